@@ -0,0 +1,61 @@
+//! Compares scanning complete response bodies for brouter's error messages with the bounded
+//! scan done by `Error::from_response_body`.
+//!
+//! Run with `cargo bench --bench error_sniffing`.
+
+use brouter_client::Error;
+use lazy_regex::regex;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 50;
+
+/// A GPX document of roughly the size brouter returns for a long route.
+fn large_gpx(points: usize) -> Vec<u8> {
+    let mut gpx = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<gpx version=\"1.1\" creator=\"BRouter\">\n<trk>\n<trkseg>\n",
+    );
+    for i in 0..points {
+        gpx.push_str(&format!(
+            "<trkpt lon=\"{:.6}\" lat=\"{:.6}\"><ele>{:.1}</ele></trkpt>\n",
+            5.0 + i as f64 * 1e-5,
+            52.0 + i as f64 * 1e-5,
+            (i % 300) as f64
+        ));
+    }
+    gpx.push_str("</trkseg>\n</trk>\n</gpx>\n");
+    gpx.into_bytes()
+}
+
+/// The scan done before bodies were sniffed: every regex over the whole body.
+fn full_scan(body: &[u8]) -> bool {
+    regex!("datafile (.*) not found\n"B).is_match(body)
+        || regex!("no track found at pass=([0-9]+)\n"B).is_match(body)
+        || regex!("pass([0-9]) timeout after ([0-9]+) seconds\n"B).is_match(body)
+}
+
+fn time(f: impl Fn()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    for points in [1_000, 10_000, 100_000] {
+        let body = large_gpx(points);
+        let full = time(|| {
+            black_box(full_scan(black_box(&body)));
+        });
+        let sniffed = time(|| {
+            black_box(Error::from_response_body(black_box(&body)));
+        });
+        println!(
+            "{:>9} bytes: full scan {:>12?}, sniffing {:>12?}",
+            body.len(),
+            full,
+            sniffed
+        );
+    }
+}
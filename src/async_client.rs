@@ -0,0 +1,310 @@
+//! An asynchronous client, for applications that run on an async runtime such as web services.
+//!
+//! [`AsyncBrouter`] offers routing and profile uploads as `async fn`s on top of
+//! [`reqwest::Client`], which needs a tokio runtime. Requests are built, long URLs sent as
+//! POST requests, responses classified and retries timed by the same code as for the blocking
+//! [`crate::Brouter`], so both send the same requests and report the same errors.
+//!
+//! The async client does not have all features of the blocking one: there is no failover to
+//! other servers, no [`crate::Middleware`], no [`crate::RoutePipeline`] and no request
+//! logging.
+//!
+//! A local server can be started without blocking with
+//! [`crate::server::BrouterServerBuilder::start_async`].
+
+use crate::response::{self, ResponseHandling, ResponseInfo};
+use crate::{
+    add_gpx_params, check_profile_id, map_deadline_err, name_exported_waypoints,
+    parse_profile_upload, retry_backoff, route_url, routing_request, Error, Nogo, Point,
+    RetryPolicy, Route, RouteRequest, TurnInstructionMode, DEFAULT_MAX_URL_LENGTH, DEFAULT_TIMEOUT,
+    FORM_CONTENT_TYPE,
+};
+use log::{debug, info};
+use reqwest::{Client, Url};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Builder for [`AsyncBrouter`].
+pub struct AsyncBrouterBuilder {
+    base_url: String,
+    timeout: Duration,
+    max_url_length: usize,
+    max_profile_size: Option<u64>,
+    response_handling: ResponseHandling,
+    retry: Option<RetryPolicy>,
+    client: Option<Client>,
+}
+
+impl AsyncBrouterBuilder {
+    pub fn new(base_url: &str) -> Self {
+        AsyncBrouterBuilder {
+            base_url: base_url.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            max_url_length: DEFAULT_MAX_URL_LENGTH,
+            max_profile_size: None,
+            response_handling: ResponseHandling::default(),
+            retry: None,
+            client: None,
+        }
+    }
+
+    /// How long to wait for a response; see [`crate::BrouterBuilder::timeout`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send routing requests with longer URLs as POST requests; see
+    /// [`crate::BrouterBuilder::max_url_length`].
+    pub fn max_url_length(mut self, length: usize) -> Self {
+        self.max_url_length = length;
+        self
+    }
+
+    /// Refuse to upload profiles larger than `limit` bytes.
+    pub fn max_profile_size(mut self, limit: u64) -> Self {
+        self.max_profile_size = Some(limit);
+        self
+    }
+
+    /// How to treat responses whose status disagrees with their body.
+    pub fn response_handling(mut self, handling: ResponseHandling) -> Self {
+        self.response_handling = handling;
+        self
+    }
+
+    /// Retry routing requests that fail with a transient error; see
+    /// [`crate::BrouterBuilder::retry`].
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Use a preconfigured HTTP client, e.g. one with a proxy.
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Build the client.
+    ///
+    /// # Panics
+    ///
+    /// If the server URL is invalid; see [`AsyncBrouterBuilder::try_build`].
+    pub fn build(self) -> AsyncBrouter {
+        self.try_build().unwrap()
+    }
+
+    /// Build the client, failing with [`Error::InvalidUrl`] if the server URL is invalid.
+    pub fn try_build(self) -> Result<AsyncBrouter, Error> {
+        let base_url = Url::parse(&self.base_url)
+            .map_err(|e| Error::InvalidUrl(format!("{}: {}", self.base_url, e)))?;
+        Ok(AsyncBrouter {
+            inner: Arc::new(Shared {
+                client: self.client.unwrap_or_default(),
+                base_url,
+                timeout: self.timeout,
+                max_url_length: self.max_url_length,
+                max_profile_size: self.max_profile_size,
+                response_handling: self.response_handling,
+                retry: self.retry,
+            }),
+        })
+    }
+}
+
+/// An asynchronous client for a brouter server.
+///
+/// Like [`crate::Brouter`], clones share their HTTP client and configuration.
+#[derive(Clone)]
+pub struct AsyncBrouter {
+    inner: Arc<Shared>,
+}
+
+struct Shared {
+    client: Client,
+    base_url: Url,
+    timeout: Duration,
+    max_url_length: usize,
+    max_profile_size: Option<u64>,
+    response_handling: ResponseHandling,
+    retry: Option<RetryPolicy>,
+}
+
+impl Default for AsyncBrouter {
+    fn default() -> Self {
+        AsyncBrouterBuilder::new("http://localhost:17777").build()
+    }
+}
+
+impl AsyncBrouter {
+    /// A client for the server at `base_url`, failing with [`Error::InvalidUrl`] if it is
+    /// invalid.
+    pub fn new(base_url: &str) -> Result<Self, Error> {
+        AsyncBrouterBuilder::new(base_url).try_build()
+    }
+
+    pub fn builder(base_url: &str) -> AsyncBrouterBuilder {
+        AsyncBrouterBuilder::new(base_url)
+    }
+
+    /// The URL of the server.
+    pub fn base_url(&self) -> &str {
+        self.inner.base_url.as_str()
+    }
+
+    /// Calculate a route; see [`crate::Brouter::request_route`].
+    pub async fn request_route(&self, request: &RouteRequest) -> Result<Route, Error> {
+        request.validate()?;
+        response::check_parseable(request.format)?;
+        info!("Planning route along {} points", request.points.len());
+        let mut url = route_url(
+            &self.inner.base_url,
+            &request.points,
+            &request.point_names,
+            &request.nogos,
+            &request.profile,
+            request.alternative,
+            request.format.name(),
+        );
+        add_gpx_params(&mut url, request);
+
+        let (content_type, text, info) = self.fetch(url, request.deadline).await?;
+        let mut route = response::parse_route(&text, content_type.as_deref(), request.format)?;
+        route.response_info = Some(info);
+        name_exported_waypoints(&mut route, request);
+        Ok(route)
+    }
+
+    /// Calculate a route and return it as GPX; see [`crate::Brouter::broute`].
+    #[allow(clippy::too_many_arguments)]
+    pub async fn broute(
+        &self,
+        points: &[Point],
+        nogos: &[Nogo],
+        profile: &str,
+        alternativeidx: Option<u8>,
+        timode: Option<TurnInstructionMode>,
+        name: Option<&str>,
+        export_waypoints: bool,
+    ) -> Result<gpx::Gpx, Error> {
+        let mut request = RouteRequest::new(points, profile)
+            .nogos(nogos)
+            .export_waypoints(export_waypoints);
+        request.alternative = alternativeidx;
+        request.turn_instructions = timode;
+        request.track_name = name.map(|n| n.to_string());
+        self.request_route(&request).await.map(Route::into_gpx)
+    }
+
+    /// Upload a profile under the name `profile`; see [`crate::Brouter::upload_profile`].
+    pub async fn upload_profile(&self, profile: &str, data: Vec<u8>) -> Result<(), Error> {
+        self.check_profile_size(data.len() as u64)?;
+        let url = self
+            .inner
+            .base_url
+            .join("brouter/profile/")
+            .unwrap()
+            .join(profile)
+            .unwrap();
+        self.profile_upload(url, data)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())?;
+        Ok(())
+    }
+
+    /// Upload a profile and return the id the server assigned to it; see
+    /// [`crate::Brouter::upload_custom_profile`].
+    pub async fn upload_custom_profile(&self, data: Vec<u8>) -> Result<String, Error> {
+        self.check_profile_size(data.len() as u64)?;
+        let url = self.inner.base_url.join("brouter/profile").unwrap();
+        let text = self
+            .profile_upload(url, data)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())?
+            .text()
+            .await?;
+        parse_profile_upload(&text)
+    }
+
+    /// Replace the uploaded profile `id`, keeping its id.
+    pub async fn replace_profile(&self, id: &str, data: Vec<u8>) -> Result<(), Error> {
+        check_profile_id(id)?;
+        self.upload_profile(id, data).await
+    }
+
+    fn check_profile_size(&self, size: u64) -> Result<(), Error> {
+        match self.inner.max_profile_size {
+            Some(limit) if size > limit => Err(Error::ProfileTooLarge { size, limit }),
+            _ => Ok(()),
+        }
+    }
+
+    fn profile_upload(&self, url: Url, data: Vec<u8>) -> reqwest::RequestBuilder {
+        self.inner
+            .client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(data)
+    }
+
+    /// Fetch `url`, retrying as the retry policy allows; see [`crate::Brouter`]'s `fetch`.
+    async fn fetch(
+        &self,
+        url: Url,
+        deadline: Option<Duration>,
+    ) -> Result<(Option<String>, Vec<u8>, ResponseInfo), Error> {
+        let start = std::time::Instant::now();
+        let mut retry = 0;
+        loop {
+            let e = match self.fetch_once(&url, deadline).await {
+                Err(e) => e,
+                result => return result,
+            };
+            match retry_backoff(self.inner.retry, &e, retry, start, deadline) {
+                Some(backoff) => {
+                    debug!("Retrying in {:?} after: {}", backoff, e);
+                    tokio::time::sleep(backoff).await;
+                    retry += 1;
+                }
+                None => return Err(e),
+            }
+        }
+    }
+
+    async fn fetch_once(
+        &self,
+        url: &Url,
+        deadline: Option<Duration>,
+    ) -> Result<(Option<String>, Vec<u8>, ResponseInfo), Error> {
+        let start = std::time::Instant::now();
+        let (method, url, form) = routing_request(url, self.inner.max_url_length);
+        let mut request = self.inner.client.request(method, url);
+        if let Some(body) = form {
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, FORM_CONTENT_TYPE)
+                .body(body);
+        }
+        let response = request
+            .timeout(deadline.unwrap_or(self.inner.timeout))
+            .send()
+            .await
+            .map_err(|e| map_deadline_err(e, deadline))?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| map_deadline_err(e, deadline))?
+            .to_vec();
+        response::check_response(
+            status,
+            &headers,
+            body,
+            start.elapsed(),
+            self.inner.response_handling,
+        )
+    }
+}
@@ -0,0 +1,250 @@
+//! Checks for the problems people most often run into when setting up brouter.
+
+use brouter_client::segments::tiles_for;
+use brouter_client::Point;
+use lazy_regex::regex_captures;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Segment files older than this are reported as outdated.
+const MAX_SEGMENT_AGE: Duration = Duration::from_secs(90 * 24 * 3600);
+
+const PUBLIC_SERVER: &str = "https://brouter.de/";
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    /// URL of the brouter server
+    #[arg(long, default_value = "http://localhost:17777")]
+    server: String,
+
+    /// Path to the brouter jar
+    #[arg(long)]
+    jar: Option<PathBuf>,
+
+    /// Directory with the segment files of the server
+    #[arg(long)]
+    segments_dir: Option<PathBuf>,
+
+    /// Points (lon,lat) to check segment coverage for
+    #[arg(name = "POINTS")]
+    points: Vec<String>,
+}
+
+#[derive(Default)]
+struct Report {
+    failed: bool,
+}
+
+impl Report {
+    fn ok(&mut self, message: &str) {
+        println!("[ok]   {}", message);
+    }
+
+    fn warn(&mut self, message: &str, fix: &str) {
+        println!("[warn] {}", message);
+        println!("       fix: {}", fix);
+    }
+
+    fn fail(&mut self, message: &str, fix: &str) {
+        println!("[fail] {}", message);
+        println!("       fix: {}", fix);
+        self.failed = true;
+    }
+}
+
+/// Run all checks, returning whether none of them failed.
+pub fn run(args: &Args) -> bool {
+    let mut report = Report::default();
+    check_java(&mut report);
+    check_jar(&mut report, args.jar.as_deref());
+    if let Some(dir) = &args.segments_dir {
+        let points = args
+            .points
+            .iter()
+            .filter_map(|p| {
+                let (lon, lat) = p.split_once(',')?;
+                Some(Point::new(lat.parse().ok()?, lon.parse().ok()?))
+            })
+            .collect::<Vec<_>>();
+        check_segments(&mut report, dir, &points);
+    }
+    check_server(&mut report, &args.server);
+    check_public_server(&mut report);
+    !report.failed
+}
+
+fn check_java(report: &mut Report) {
+    let output =
+        match std::process::Command::new("java").arg("-version").output() {
+            Ok(output) => output,
+            Err(_) => return report.fail(
+                "java not found",
+                "install a Java runtime, e.g. OpenJDK 11 or later, and make sure `java` is on PATH",
+            ),
+        };
+    // java prints its version to stderr, e.g. `openjdk version "17.0.2" 2022-01-18`.
+    let text = String::from_utf8_lossy(&output.stderr);
+    match regex_captures!(r#"version "(\d+)(?:\.(\d+))?"#, &text) {
+        Some((_, major, minor)) => {
+            // Before Java 9, versions were numbered 1.x.
+            let version = match major {
+                "1" => minor.parse::<u32>().unwrap_or(0),
+                major => major.parse::<u32>().unwrap_or(0),
+            };
+            if version < 8 {
+                report.fail(
+                    &format!("java {} is too old", version),
+                    "install Java 8 or later",
+                );
+            } else {
+                report.ok(&format!("java {}", version));
+            }
+        }
+        None => report.warn(
+            "unable to determine the java version",
+            "check the output of `java -version`",
+        ),
+    }
+}
+
+fn check_jar(report: &mut Report, jar: Option<&Path>) {
+    let jar = match jar {
+        Some(jar) => jar.to_path_buf(),
+        None => {
+            let found = std::fs::read_dir(".").ok().and_then(|entries| {
+                entries.filter_map(|e| e.ok()).map(|e| e.path()).find(|p| {
+                    p.file_name()
+                        .and_then(|n| n.to_str())
+                        .is_some_and(|n| n.starts_with("brouter") && n.ends_with(".jar"))
+                })
+            });
+            match found {
+                Some(jar) => jar,
+                None => {
+                    return report.warn(
+                        "no brouter jar found in the current directory",
+                        "pass its location with --jar, or download brouter from https://github.com/abrensch/brouter/releases",
+                    )
+                }
+            }
+        }
+    };
+    if !jar.is_file() {
+        return report.fail(
+            &format!("{} does not exist", jar.display()),
+            "download brouter from https://github.com/abrensch/brouter/releases",
+        );
+    }
+    let name = jar.file_name().unwrap_or_default().to_string_lossy();
+    match regex_captures!(r"(\d+\.\d+(?:\.\d+)?)", &name) {
+        Some((_, version)) => report.ok(&format!("{} (version {})", jar.display(), version)),
+        None => report.ok(&jar.display().to_string()),
+    }
+}
+
+fn check_segments(report: &mut Report, dir: &Path, points: &[Point]) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            return report.fail(
+                &format!("unable to read {}: {}", dir.display(), e),
+                "pass the segments directory of the server with --segments-dir",
+            )
+        }
+    };
+    let mut count = 0;
+    let mut oldest: Option<SystemTime> = None;
+    for entry in entries.filter_map(|e| e.ok()) {
+        if entry.path().extension().is_some_and(|e| e == "rd5") {
+            count += 1;
+            if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+                oldest = Some(oldest.map_or(modified, |o| o.min(modified)));
+            }
+        }
+    }
+    if count == 0 {
+        return report.fail(
+            &format!("no segment files in {}", dir.display()),
+            "download the .rd5 files for your area from https://brouter.de/brouter/segments4/",
+        );
+    }
+    report.ok(&format!("{} segment files in {}", count, dir.display()));
+
+    let age = oldest.and_then(|o| o.elapsed().ok());
+    if let Some(age) = age.filter(|age| *age > MAX_SEGMENT_AGE) {
+        report.warn(
+            &format!("oldest segment file is {} days old", age.as_secs() / 86400),
+            "download fresh segment files from https://brouter.de/brouter/segments4/",
+        );
+    }
+
+    let missing = tiles_for(points)
+        .into_iter()
+        .filter(|name| !dir.join(name).is_file())
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        report.fail(
+            &format!("missing segment files: {}", missing.join(", ")),
+            "download them from https://brouter.de/brouter/segments4/",
+        );
+    } else if !points.is_empty() {
+        report.ok("segment files cover the given points");
+    }
+}
+
+fn check_server(report: &mut Report, server: &str) {
+    let url = match reqwest::Url::parse(server) {
+        Ok(url) => url,
+        Err(e) => return report.fail(&format!("invalid server URL: {}", e), "check --server"),
+    };
+    let host = url.host_str().unwrap_or("localhost").to_string();
+    let port = url.port_or_known_default().unwrap_or(17777);
+    let addrs = match (host.as_str(), port).to_socket_addrs() {
+        Ok(addrs) => addrs.collect::<Vec<_>>(),
+        Err(e) => {
+            return report.fail(
+                &format!("unable to resolve {}: {}", host, e),
+                "check the host name in --server",
+            )
+        }
+    };
+    let listening = addrs
+        .iter()
+        .any(|addr| TcpStream::connect_timeout(addr, Duration::from_secs(3)).is_ok());
+    if listening {
+        return report.ok(&format!("server listening on {}:{}", host, port));
+    }
+    let local = addrs.iter().any(|addr| addr.ip().is_loopback());
+    if local && TcpListener::bind(("127.0.0.1", port)).is_err() {
+        report.fail(
+            &format!("port {} is in use, but not accepting connections", port),
+            "stop the process using the port, or run brouter on another port",
+        );
+    } else {
+        report.warn(
+            &format!("no server listening on {}:{}", host, port),
+            "start brouter, e.g. with the server.sh script that comes with it",
+        );
+    }
+}
+
+fn check_public_server(report: &mut Report) {
+    let result = reqwest::blocking::Client::new()
+        .get(PUBLIC_SERVER)
+        .timeout(Duration::from_secs(10))
+        .send();
+    match result {
+        Ok(response) if response.status().is_success() => {
+            report.ok(&format!("{} is reachable", PUBLIC_SERVER))
+        }
+        Ok(response) => report.warn(
+            &format!("{} returned {}", PUBLIC_SERVER, response.status()),
+            "try again later; the public server may be down",
+        ),
+        Err(e) => report.warn(
+            &format!("unable to reach {}: {}", PUBLIC_SERVER, e),
+            "check your network connection and proxy settings (HTTPS_PROXY)",
+        ),
+    }
+}
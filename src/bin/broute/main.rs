@@ -0,0 +1,525 @@
+use brouter_client::export::{FitWriter, GeoJsonWriter, GpxWriter, KmlWriter, TcxWriter};
+use brouter_client::geocode::{Geocoder, Nominatim};
+use brouter_client::naming::{endpoint_name, unique_path, NameTemplate};
+use brouter_client::Brouter;
+use brouter_client::Point;
+use brouter_client::Nogo;
+use brouter_client::profile::RouteStats;
+use brouter_client::tour::TourPlanner;
+use brouter_client::{
+    Route, RoutePreset, RouteRequest, RouteSummary, SegmentMessage, TurnInstructionMode,
+};
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+mod doctor;
+
+#[derive(Parser, Clone, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    route: RouteArgs,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum Command {
+    /// Calculate a route; this is the default
+    Route(RouteArgs),
+
+    /// Check the local setup for common problems
+    Doctor(doctor::Args),
+
+    /// Print statistics of a GPX track
+    Stats(StatsArgs),
+
+    /// Convert a GPX route to another format
+    Convert(ConvertArgs),
+
+    /// Split a GPX route into parts
+    Split(SplitArgs),
+
+    /// Merge GPX routes into one
+    Merge(MergeArgs),
+}
+
+#[derive(clap::Args, Clone, Debug)]
+#[group(required = true, multiple = false)]
+struct SplitBy {
+    /// Maximum length of each part, in km
+    #[arg(long)]
+    distance: Option<f64>,
+
+    /// Number of parts of roughly equal length
+    #[arg(long)]
+    parts: Option<usize>,
+
+    /// Maximum number of track points in each part
+    #[arg(long)]
+    points: Option<usize>,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct SplitArgs {
+    #[command(flatten)]
+    by: SplitBy,
+
+    /// Directory to write the parts to
+    #[arg(long, default_value = ".")]
+    output_dir: PathBuf,
+
+    #[arg(name = "GPX")]
+    path: PathBuf,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct MergeArgs {
+    /// File to write the merged route to
+    #[arg(long)]
+    output: PathBuf,
+
+    #[arg(name = "GPX", required = true)]
+    paths: Vec<PathBuf>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    Gpx,
+    Geojson,
+    Kml,
+    /// Garmin TCX course
+    Tcx,
+    /// FIT course, as used by Garmin and Wahoo devices
+    Fit,
+    /// Encoded polyline with 5 decimal places, as used by Google and OSRM
+    Polyline,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct ConvertArgs {
+    #[arg(long, value_enum)]
+    to: Format,
+
+    /// Write to this file rather than standard output
+    #[arg(long)]
+    output: Option<PathBuf>,
+
+    #[arg(name = "GPX")]
+    path: PathBuf,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct StatsArgs {
+    /// brouter's messages for the track, as returned in CSV format, for a breakdown by surface
+    #[arg(long)]
+    messages: Option<PathBuf>,
+
+    /// Minimum elevation gain of climbs to list, in meters
+    #[arg(long, default_value_t = 50.0)]
+    min_climb: f64,
+
+    #[arg(name = "GPX")]
+    path: PathBuf,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct RouteArgs {
+    // Optional only so that the arguments can be left out when a subcommand is used.
+    #[arg(long, required_unless_present_any = ["replay", "watch", "preset"])]
+    profile: Option<String>,
+
+    /// Use a preset: commute, touring, gravel, or one defined in the presets file
+    #[arg(long, conflicts_with = "profile")]
+    preset: Option<String>,
+
+    #[arg(long)]
+    export_waypoints: bool,
+
+    /// Style of turn instructions: none, auto, locus, osmand, comment, gpsies, orux or
+    /// locus-old; overrides that of the preset
+    #[arg(long)]
+    turn_instructions: Option<TurnInstructionMode>,
+
+    /// Name of the route; may contain placeholders such as {date}, {profile}, {from} and {to}
+    #[arg(long)]
+    name: Option<String>,
+
+    /// Without --name, name the route after the places it starts and ends at
+    #[arg(long, conflicts_with = "name")]
+    auto_name: bool,
+
+    /// Write the route to a GPX file in this directory, named after the route
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+
+    /// Nogo points
+    #[arg(long)]
+    nogos: Option<Vec<String>>,
+
+    /// Read nogos from a GeoJSON file; points need a "radius" property, and a "weight"
+    /// property makes a nogo passable at that cost
+    #[arg(long)]
+    nogo_file: Option<Vec<PathBuf>>,
+
+    /// Calculate the route for a request saved with --save-request instead
+    #[arg(long, conflicts_with_all = ["profile", "preset", "nogos", "nogo_file", "name", "export_waypoints", "turn_instructions", "POINTS"])]
+    replay: Option<PathBuf>,
+
+    /// Upload this profile and route again whenever it changes, printing how the route changed
+    #[arg(long, conflicts_with = "profile")]
+    watch: Option<PathBuf>,
+
+    /// Save the request in canonical form, so that it can be replayed with --replay
+    #[arg(long)]
+    save_request: Option<PathBuf>,
+
+    /// Points, as "lon,lat" or the name of a place to look up
+    #[arg(name = "POINTS")]
+    points: Vec<String>,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Route(args)) => route(args),
+        Some(Command::Doctor(args)) => {
+            if !doctor::run(&args) {
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Stats(args)) => stats(args),
+        Some(Command::Convert(args)) => convert(args),
+        Some(Command::Split(args)) => split(args),
+        Some(Command::Merge(args)) => merge(args),
+        None => route(cli.route),
+    }
+}
+
+fn convert(args: ConvertArgs) {
+    let route = Route::from_gpx_bytes(&std::fs::read(&args.path).unwrap()).unwrap();
+    let data = match args.to {
+        Format::Gpx => GpxWriter::new().to_vec(&route).unwrap(),
+        Format::Geojson => GeoJsonWriter::new().to_geojson(&route).into_bytes(),
+        Format::Kml => KmlWriter::new().to_kml(&route).into_bytes(),
+        Format::Tcx => TcxWriter::new().to_tcx(&route).into_bytes(),
+        Format::Fit => FitWriter::new().to_vec(&route),
+        Format::Polyline => {
+            let points = route.points().map(|p| p.point().into()).collect::<Vec<_>>();
+            Point::to_polyline(&points, 5).into_bytes()
+        }
+    };
+    match args.output {
+        Some(path) => std::fs::write(path, data).unwrap(),
+        None => std::io::Write::write_all(&mut std::io::stdout(), &data).unwrap(),
+    }
+}
+
+fn split(args: SplitArgs) {
+    let route = Route::from_gpx_bytes(&std::fs::read(&args.path).unwrap()).unwrap();
+    let parts = match args.by.points {
+        Some(max_points) => {
+            assert!(max_points >= 2, "parts need at least two points");
+            let points = route.points().cloned().collect::<Vec<_>>();
+            // Consecutive parts share a point, so that they join up.
+            let mut parts = vec![];
+            let mut first = 0;
+            while first + 1 < points.len() {
+                let last = (first + max_points - 1).min(points.len() - 1);
+                let mut track = gpx::Track::new();
+                track.segments.push(gpx::TrackSegment {
+                    points: points[first..=last].to_vec(),
+                });
+                parts.push(gpx::Gpx {
+                    version: gpx::GpxVersion::Gpx11,
+                    tracks: vec![track],
+                    ..Default::default()
+                });
+                first = last;
+            }
+            parts
+        }
+        None => {
+            let planner = match (args.by.distance, args.by.parts) {
+                (Some(km), _) => TourPlanner::new().max_daily_distance_m(km * 1000.0),
+                (None, Some(parts)) => TourPlanner::new().days(parts),
+                (None, None) => unreachable!("clap requires one of the options"),
+            };
+            planner.split(&route)
+        }
+    };
+
+    let stem = args.path.file_stem().unwrap_or_default().to_string_lossy();
+    for (i, mut part) in parts.into_iter().enumerate() {
+        let name = format!("{} {}", stem, i + 1);
+        for track in &mut part.tracks {
+            track.name = Some(name.clone());
+        }
+        let path = unique_path(&args.output_dir, &name, "gpx");
+        gpx::write(&part, std::fs::File::create(&path).unwrap()).unwrap();
+        println!("{}", path.display());
+    }
+}
+
+fn merge(args: MergeArgs) {
+    let mut merged = gpx::Gpx {
+        version: gpx::GpxVersion::Gpx11,
+        ..Default::default()
+    };
+    let mut track = gpx::Track::new();
+    let mut points: Vec<gpx::Waypoint> = vec![];
+    for path in &args.paths {
+        let route = Route::from_gpx_bytes(&std::fs::read(path).unwrap()).unwrap();
+        let mut new = route.points().cloned().peekable();
+        // Parts split from one route share their end points; don't repeat them.
+        if let (Some(last), Some(first)) = (points.last(), new.peek()) {
+            if last.point() == first.point() {
+                new.next();
+            }
+        }
+        points.extend(new);
+        merged
+            .waypoints
+            .extend(route.gpx().waypoints.iter().cloned());
+    }
+    track.segments.push(gpx::TrackSegment { points });
+    merged.tracks.push(track);
+    gpx::write(&merged, std::fs::File::create(&args.output).unwrap()).unwrap();
+}
+
+fn stats(args: StatsArgs) {
+    let route = Route::from_gpx_bytes(&std::fs::read(&args.path).unwrap()).unwrap();
+    let summary = route.summary();
+    println!("distance: {:.2} km", summary.distance_m / 1000.0);
+    println!("ascent:   {:.0} m", summary.ascent_m);
+    println!("descent:  {:.0} m", summary.descent_m);
+    if let Some(duration_s) = summary.duration_s {
+        let duration_s = duration_s.round() as u64;
+        println!(
+            "duration: {}:{:02}:{:02}",
+            duration_s / 3600,
+            duration_s / 60 % 60,
+            duration_s % 60
+        );
+    }
+    println!("points:   {}", summary.points);
+
+    let climbs = route.climbs(args.min_climb);
+    if !climbs.is_empty() {
+        println!("climbs:");
+        for climb in climbs {
+            println!(
+                "  {:>7.2} km {:>6.2} km {:>5.0} m {:>5.1}%",
+                climb.start_m / 1000.0,
+                climb.length_m() / 1000.0,
+                climb.ascent_m(),
+                climb.gradient()
+            );
+        }
+    }
+
+    if let Some(path) = args.messages {
+        let messages = SegmentMessage::parse_csv(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let stats = RouteStats::from_messages(&messages);
+        let mut surfaces = stats.surfaces.into_iter().collect::<Vec<_>>();
+        surfaces.sort_by(|a, b| b.1.total_cmp(&a.1));
+        println!("surfaces:");
+        for (surface, distance_m) in surfaces {
+            println!(
+                "  {:<16} {:>8.2} km {:>5.1}%",
+                surface,
+                distance_m / 1000.0,
+                100.0 * distance_m / stats.distance_m
+            );
+        }
+    }
+}
+
+fn route(args: RouteArgs) {
+    let router = Brouter::default();
+    let geocoder = Nominatim::default();
+    let request = match &args.replay {
+        Some(path) => {
+            RouteRequest::from_canonical_string(&std::fs::read_to_string(path).unwrap()).unwrap()
+        }
+        None => {
+            let points = args
+                .points
+                .iter()
+                .map(|p| parse_point(p, &geocoder))
+                .collect::<Vec<_>>();
+            let mut nogos = args
+                .nogos
+                .unwrap_or_default()
+                .iter()
+                .map(|p| parse_nogo(p))
+                .collect::<Vec<_>>();
+            for path in args.nogo_file.unwrap_or_default() {
+                let file = std::fs::File::open(&path)
+                    .unwrap_or_else(|e| panic!("Cannot open {}: {}", path.display(), e));
+                nogos.extend(Nogo::from_geojson(std::io::BufReader::new(file)).unwrap());
+            }
+            // With --watch, the profile is replaced by the uploaded one.
+            let profile = args.profile.unwrap_or_default();
+            let mut request = RouteRequest::new(&points, &profile)
+                .nogos(&nogos)
+                .export_waypoints(args.export_waypoints);
+            if let Some(name) = &args.preset {
+                let custom = match RoutePreset::default_config_path() {
+                    Some(path) => RoutePreset::load(&path).unwrap(),
+                    None => vec![],
+                };
+                let preset = RoutePreset::find(name, &custom)
+                    .unwrap_or_else(|| panic!("Unknown preset {}", name));
+                request = request.preset(&preset);
+            }
+            if let Some(mode) = args.turn_instructions {
+                request = request.turn_instructions(mode);
+            }
+            match args.name.as_deref() {
+                Some(name) => request.track_name_template(&NameTemplate::new(name)),
+                None if args.auto_name => match endpoint_name(&request, &geocoder).unwrap() {
+                    Some(name) => request.track_name(&name),
+                    None => request,
+                },
+                None => request,
+            }
+        }
+    };
+
+    if let Some(path) = &args.save_request {
+        std::fs::write(path, request.canonical_string()).unwrap();
+    }
+
+    if let Some(path) = &args.watch {
+        watch(&router, request, path);
+    }
+
+    let gpx = router.request_route(&request).unwrap().into_gpx();
+
+    match args.output_dir {
+        Some(dir) => {
+            let name = request.track_name.as_deref().unwrap_or("route");
+            let path = unique_path(&dir, name, "gpx");
+            let file = std::fs::File::create(&path).unwrap();
+            gpx::write(&gpx, file).unwrap();
+            println!("{}", path.display());
+        }
+        None => println!("{:?}", gpx),
+    }
+}
+
+/// Route `request` with the profile at `path` each time the profile changes.
+fn watch(router: &Brouter, mut request: RouteRequest, path: &std::path::Path) -> ! {
+    let mut modified = None;
+    let mut previous: Option<RouteSummary> = None;
+    loop {
+        let current = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        if current.is_none() || current == modified {
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            continue;
+        }
+        modified = current;
+
+        let result = std::fs::read(path)
+            .map_err(brouter_client::Error::from)
+            .and_then(|data| router.upload_custom_profile(data))
+            .and_then(|id| {
+                request.profile = id;
+                router.request_route(&request)
+            });
+        match result {
+            Ok(route) => {
+                let summary = route.summary();
+                print!(
+                    "distance {:.2} km, ascent {:.0} m",
+                    summary.distance_m / 1000.0,
+                    summary.ascent_m
+                );
+                if let Some(previous) = &previous {
+                    print!(
+                        " ({:+.2} km, {:+.0} m)",
+                        (summary.distance_m - previous.distance_m) / 1000.0,
+                        summary.ascent_m - previous.ascent_m
+                    );
+                }
+                println!();
+                previous = Some(summary);
+            }
+            Err(e) => println!("error: {}", e),
+        }
+    }
+}
+
+/// Parse a point given as "lon,lat", or look it up by name.
+fn parse_point(text: &str, geocoder: &dyn Geocoder) -> Point {
+    let coordinates = text
+        .split_once(',')
+        .and_then(|(lon, lat)| Some(Point::new(lat.parse().ok()?, lon.parse().ok()?)));
+    match coordinates {
+        Some(point) => point,
+        None => geocoder
+            .geocode(text)
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| panic!("No place found for {}", text)),
+    }
+}
+
+fn parse_nogo(p: &str) -> Nogo {
+    let p = p.split_once(':').unwrap();
+    let mut parts = p.1.split(',').collect::<Vec<_>>();
+    match p.0 {
+        "point" => {
+            let mut parts = parts.into_iter();
+            let lon = parts.next().unwrap().parse::<f64>().unwrap();
+            let lat = parts.next().unwrap().parse::<f64>().unwrap();
+            let radius = parts.next().unwrap().parse::<f64>().unwrap();
+            let weight = parts.next().map(|p| p.parse::<f64>().unwrap());
+            Nogo::Point{
+                point: Point::new(lat, lon),
+                radius,
+                weight
+            }
+        }
+        "line" => {
+            // if the number of items in parts is odd, then the last entry is the
+            // weight
+            let weight = if parts.len() % 2 == 1 {
+                Some(parts.pop().unwrap().parse::<f64>().unwrap())
+            } else {
+                None
+            };
+            let points = parts
+                .chunks(2)
+                .map(|p| {
+                    let lat = p[1].parse::<f64>().unwrap();
+                    let lon = p[0].parse::<f64>().unwrap();
+                    Point::new(lat, lon)
+                })
+                .collect::<Vec<_>>();
+            Nogo::Line{ points, weight}
+        }
+        "polygon" => {
+            // if the number of items in parts is odd, then the last entry is the
+            // weight
+            let weight = if parts.len() % 2 == 1 {
+                Some(parts.pop().unwrap().parse::<f64>().unwrap())
+            } else {
+                None
+            };
+            let points = parts
+                .chunks(2)
+                .map(|p| {
+                    let lat = p[1].parse::<f64>().unwrap();
+                    let lon = p[0].parse::<f64>().unwrap();
+                    Point::new(lat, lon)
+                })
+                .collect::<Vec<_>>();
+            Nogo::Polygon{ points, weight}
+        }
+        _ => panic!("Unknown nogo type"),
+    }
+}
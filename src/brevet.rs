@@ -0,0 +1,359 @@
+//! Planning controls for brevets.
+//!
+//! Controls are placed along a route, and get opening and closing times computed from the
+//! maximum and minimum speeds allowed for the distance ridden so far. The default speeds are
+//! those of the Audax Club Parisien (ACP).
+//!
+//! As in the ACP rules, the finish closes at the fixed time limit of the brevet's distance,
+//! e.g. after 13:30 hours for a 200 km brevet, rather than at the time the minimum speed
+//! gives. Other special cases, such as the longer closing times of controls in the first
+//! 60 km that some calculators use, are not applied.
+
+use crate::{Point, Route};
+use std::fmt;
+use std::io::Write;
+use time::{Duration, OffsetDateTime};
+
+/// Invalid settings for a [`BrevetPlanner`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrevetError {
+    pub message: String,
+}
+
+impl std::error::Error for BrevetError {}
+
+impl fmt::Display for BrevetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid brevet settings: {}", self.message)
+    }
+}
+
+fn error<T>(message: impl Into<String>) -> Result<T, BrevetError> {
+    Err(BrevetError {
+        message: message.into(),
+    })
+}
+
+fn is_positive(value: f64) -> bool {
+    value.is_finite() && value > 0.0
+}
+
+/// The allowed speeds up to a given distance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpeedLimit {
+    /// Distance from the start up to which these speeds apply, in kilometers.
+    pub until_km: f64,
+
+    /// Minimum speed in km/h; determines when a control closes.
+    pub min_kmh: f64,
+
+    /// Maximum speed in km/h; determines when a control opens.
+    pub max_kmh: f64,
+}
+
+/// The ACP speed limits.
+pub fn acp_speed_limits() -> Vec<SpeedLimit> {
+    vec![
+        SpeedLimit {
+            until_km: 200.0,
+            min_kmh: 15.0,
+            max_kmh: 34.0,
+        },
+        SpeedLimit {
+            until_km: 400.0,
+            min_kmh: 15.0,
+            max_kmh: 32.0,
+        },
+        SpeedLimit {
+            until_km: 600.0,
+            min_kmh: 15.0,
+            max_kmh: 30.0,
+        },
+        SpeedLimit {
+            until_km: 1000.0,
+            min_kmh: 11.428,
+            max_kmh: 28.0,
+        },
+        SpeedLimit {
+            until_km: 1300.0,
+            min_kmh: 13.333,
+            max_kmh: 26.0,
+        },
+    ]
+}
+
+/// The overall time limit of brevets of a distance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimeLimit {
+    /// The nominal distance of the brevet, in kilometers.
+    pub distance_km: f64,
+
+    /// When the finish closes, counted from the start.
+    pub limit: Duration,
+}
+
+/// The ACP time limits of brevets of 200 to 1000 km.
+pub fn acp_time_limits() -> Vec<TimeLimit> {
+    [
+        (200.0, 13 * 60 + 30),
+        (300.0, 20 * 60),
+        (400.0, 27 * 60),
+        (600.0, 40 * 60),
+        (1000.0, 75 * 60),
+    ]
+    .into_iter()
+    .map(|(distance_km, minutes)| TimeLimit {
+        distance_km,
+        limit: Duration::minutes(minutes),
+    })
+    .collect()
+}
+
+/// A control on a brevet.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Control {
+    pub name: String,
+
+    pub point: Point,
+
+    /// Distance from the start, in meters.
+    pub distance_m: f64,
+
+    #[cfg_attr(feature = "serde", serde(with = "time::serde::rfc3339"))]
+    pub open: OffsetDateTime,
+
+    #[cfg_attr(feature = "serde", serde(with = "time::serde::rfc3339"))]
+    pub close: OffsetDateTime,
+}
+
+impl Control {
+    /// A GPX waypoint for this control, with the opening times in its description.
+    pub fn to_waypoint(&self) -> gpx::Waypoint {
+        let mut waypoint = gpx::Waypoint::new(self.point.clone().into());
+        waypoint.name = Some(self.name.clone());
+        waypoint.description = Some(format!(
+            "{:.1} km, open {} - {}",
+            self.distance_m / 1000.0,
+            format_time(self.open),
+            format_time(self.close)
+        ));
+        waypoint.symbol = Some("Flag".to_string());
+        waypoint
+    }
+}
+
+fn format_time(t: OffsetDateTime) -> String {
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        t.year(),
+        t.month() as u8,
+        t.day(),
+        t.hour(),
+        t.minute()
+    )
+}
+
+/// Places controls along a route.
+#[derive(Debug, Clone)]
+pub struct BrevetPlanner {
+    start: OffsetDateTime,
+    limits: Vec<SpeedLimit>,
+    time_limits: Vec<TimeLimit>,
+    start_close: Duration,
+}
+
+impl BrevetPlanner {
+    /// Create a planner for a brevet starting at `start`, using the ACP speed limits.
+    pub fn new(start: OffsetDateTime) -> Self {
+        BrevetPlanner {
+            start,
+            limits: acp_speed_limits(),
+            time_limits: acp_time_limits(),
+            start_close: Duration::hours(1),
+        }
+    }
+
+    /// Set the speed limits, ordered by distance.
+    ///
+    /// Beyond the last limit, its speeds continue to apply. There has to be at least one
+    /// limit, the speeds have to be positive and the distances increasing.
+    pub fn speed_limits(mut self, limits: Vec<SpeedLimit>) -> Result<Self, BrevetError> {
+        if limits.is_empty() {
+            return error("at least one speed limit is required");
+        }
+        let mut from = 0.0;
+        for limit in &limits {
+            if !is_positive(limit.min_kmh) || !is_positive(limit.max_kmh) {
+                return error(format!(
+                    "speeds of {} and {} km/h up to {} km",
+                    limit.min_kmh, limit.max_kmh, limit.until_km
+                ));
+            }
+            if limit.until_km.is_nan() || limit.until_km <= from {
+                return error(format!(
+                    "limits not ordered by distance at {} km",
+                    limit.until_km
+                ));
+            }
+            from = limit.until_km;
+        }
+        self.limits = limits;
+        Ok(self)
+    }
+
+    /// Set the overall time limits of brevets by distance, which determine when the finish
+    /// closes; without any, it closes at the time the minimum speeds give.
+    ///
+    /// A route gets the limit of the longest distance that it covers, so a 205 km route has
+    /// the limit of a 200 km brevet.
+    pub fn time_limits(mut self, limits: Vec<TimeLimit>) -> Self {
+        self.time_limits = limits;
+        self
+    }
+
+    /// Set how long the start control stays open.
+    pub fn start_close(mut self, duration: Duration) -> Self {
+        self.start_close = duration;
+        self
+    }
+
+    /// Hours needed to cover `km` at the speeds selected by `speed`.
+    fn hours(&self, km: f64, speed: impl Fn(&SpeedLimit) -> f64) -> f64 {
+        let mut hours = 0.0;
+        let mut from = 0.0;
+        for (i, limit) in self.limits.iter().enumerate() {
+            let until = if i == self.limits.len() - 1 {
+                f64::INFINITY
+            } else {
+                limit.until_km
+            };
+            if km <= from {
+                break;
+            }
+            hours += (km.min(until) - from) / speed(limit);
+            from = until;
+        }
+        hours
+    }
+
+    /// Opening and closing times of a control `distance_m` meters from the start, rounded to
+    /// the minute.
+    pub fn times(&self, distance_m: f64) -> (OffsetDateTime, OffsetDateTime) {
+        let km = distance_m / 1000.0;
+        let minutes = |hours: f64| Duration::minutes((hours * 60.0).round() as i64);
+        let open = self.start + minutes(self.hours(km, |l| l.max_kmh));
+        let close = self.start + minutes(self.hours(km, |l| l.min_kmh)).max(self.start_close);
+        (open, close)
+    }
+
+    /// Opening and closing times of the finish of a brevet of `distance_m` meters: the
+    /// finish closes at the time limit of the brevet if there is one.
+    pub fn finish_times(&self, distance_m: f64) -> (OffsetDateTime, OffsetDateTime) {
+        let (open, close) = self.times(distance_m);
+        let km = distance_m / 1000.0;
+        let limit = self
+            .time_limits
+            .iter()
+            .filter(|l| l.distance_km <= km)
+            .max_by(|a, b| a.distance_km.total_cmp(&b.distance_km));
+        match limit {
+            Some(limit) => (open, self.start + limit.limit),
+            None => (open, close),
+        }
+    }
+
+    /// A control `distance_m` meters along `route`, which is the finish if it is at the end.
+    fn control(&self, route: &Route, name: String, distance_m: f64) -> Option<Control> {
+        let length = route.summary().distance_m;
+        let (open, close) = if distance_m >= length {
+            self.finish_times(length)
+        } else {
+            self.times(distance_m)
+        };
+        Some(Control {
+            name,
+            point: route.point_at(distance_m)?,
+            distance_m,
+            open,
+            close,
+        })
+    }
+
+    /// Controls at the given distances from the start, in meters.
+    ///
+    /// Controls are named "Control 1", "Control 2", etc.
+    pub fn controls_at(&self, route: &Route, distances_m: &[f64]) -> Vec<Control> {
+        distances_m
+            .iter()
+            .enumerate()
+            .filter_map(|(i, d)| self.control(route, format!("Control {}", i + 1), *d))
+            .collect()
+    }
+
+    /// Controls at the start, every `interval_m` meters, and at the finish.
+    pub fn controls_every(
+        &self,
+        route: &Route,
+        interval_m: f64,
+    ) -> Result<Vec<Control>, BrevetError> {
+        if !is_positive(interval_m) {
+            return error(format!("control interval of {} m", interval_m));
+        }
+        let length = route.summary().distance_m;
+        let mut distances = vec![];
+        let mut d = 0.0;
+        while d < length {
+            distances.push(d);
+            d += interval_m;
+        }
+        distances.push(length);
+        Ok(self.controls_at(route, &distances))
+    }
+
+    /// Controls at named places, which are snapped to the route.
+    ///
+    /// The controls are ordered by their distance along the route.
+    pub fn controls_near(&self, route: &Route, places: &[(&str, Point)]) -> Vec<Control> {
+        let mut controls = places
+            .iter()
+            .filter_map(|(name, point)| {
+                let (_, _, along) = route.nearest_point(point)?;
+                self.control(route, name.to_string(), along)
+            })
+            .collect::<Vec<_>>();
+        controls.sort_by(|a, b| a.distance_m.total_cmp(&b.distance_m));
+        controls
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Write a control card as CSV.
+pub fn write_control_card<W: Write>(controls: &[Control], mut writer: W) -> std::io::Result<()> {
+    writeln!(
+        writer,
+        "Control,Distance (km),Latitude,Longitude,Open,Close"
+    )?;
+    for control in controls {
+        writeln!(
+            writer,
+            "{},{:.1},{:.6},{:.6},{},{}",
+            csv_field(&control.name),
+            control.distance_m / 1000.0,
+            control.point.lat(),
+            control.point.lon(),
+            format_time(control.open),
+            format_time(control.close)
+        )?;
+    }
+    Ok(())
+}
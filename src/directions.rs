@@ -0,0 +1,110 @@
+use crate::instructions::{TurnCommand, TurnInstruction};
+
+/// Templates for rendering turn instructions as text in a particular language.
+pub trait Locale {
+    /// Describe a maneuver, e.g. "turn left".
+    fn maneuver(&self, command: &TurnCommand) -> String;
+
+    /// Combine a formatted distance, a maneuver and an optional way name into a sentence.
+    fn sentence(&self, distance: &str, maneuver: &str, way_name: Option<&str>) -> String;
+
+    /// Format a distance given in meters.
+    fn distance(&self, meters: f64) -> String {
+        if meters < 1000.0 {
+            format!("{} m", (meters / 10.0).round() * 10.0)
+        } else {
+            format!("{:.1} km", meters / 1000.0)
+        }
+    }
+
+    /// Render a single instruction, `distance_m` meters ahead.
+    fn render(&self, distance_m: f64, instruction: &TurnInstruction) -> String {
+        self.sentence(
+            &self.distance(distance_m),
+            &self.maneuver(&instruction.command),
+            instruction.way_name.as_deref(),
+        )
+    }
+}
+
+/// English directions.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct English;
+
+impl Locale for English {
+    fn maneuver(&self, command: &TurnCommand) -> String {
+        match command {
+            TurnCommand::Continue => "continue straight".to_string(),
+            TurnCommand::TurnLeft => "turn left".to_string(),
+            TurnCommand::TurnSlightLeft => "turn slightly left".to_string(),
+            TurnCommand::TurnSharpLeft => "turn sharp left".to_string(),
+            TurnCommand::TurnRight => "turn right".to_string(),
+            TurnCommand::TurnSlightRight => "turn slightly right".to_string(),
+            TurnCommand::TurnSharpRight => "turn sharp right".to_string(),
+            TurnCommand::KeepLeft => "keep left".to_string(),
+            TurnCommand::KeepRight => "keep right".to_string(),
+            TurnCommand::UTurnLeft | TurnCommand::UTurn | TurnCommand::UTurnRight => {
+                "make a u-turn".to_string()
+            }
+            TurnCommand::OffRoute => "leave the road network".to_string(),
+            TurnCommand::Roundabout(exit) | TurnCommand::RoundaboutLeft(exit) => {
+                format!("take exit {} at the roundabout", exit)
+            }
+            TurnCommand::Beeline => "continue in a straight line".to_string(),
+            TurnCommand::ExitLeft => "take the exit on the left".to_string(),
+            TurnCommand::ExitRight => "take the exit on the right".to_string(),
+        }
+    }
+
+    fn sentence(&self, distance: &str, maneuver: &str, way_name: Option<&str>) -> String {
+        match way_name {
+            Some(name) => format!("In {}, {} onto {}", distance, maneuver, name),
+            None => format!("In {}, {}", distance, maneuver),
+        }
+    }
+}
+
+/// German directions.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct German;
+
+impl Locale for German {
+    fn maneuver(&self, command: &TurnCommand) -> String {
+        match command {
+            TurnCommand::Continue => "geradeaus weiterfahren".to_string(),
+            TurnCommand::TurnLeft => "links abbiegen".to_string(),
+            TurnCommand::TurnSlightLeft => "leicht links abbiegen".to_string(),
+            TurnCommand::TurnSharpLeft => "scharf links abbiegen".to_string(),
+            TurnCommand::TurnRight => "rechts abbiegen".to_string(),
+            TurnCommand::TurnSlightRight => "leicht rechts abbiegen".to_string(),
+            TurnCommand::TurnSharpRight => "scharf rechts abbiegen".to_string(),
+            TurnCommand::KeepLeft => "links halten".to_string(),
+            TurnCommand::KeepRight => "rechts halten".to_string(),
+            TurnCommand::UTurnLeft | TurnCommand::UTurn | TurnCommand::UTurnRight => {
+                "wenden".to_string()
+            }
+            TurnCommand::OffRoute => "das Straßennetz verlassen".to_string(),
+            TurnCommand::Roundabout(exit) | TurnCommand::RoundaboutLeft(exit) => {
+                format!("im Kreisverkehr die {}. Ausfahrt nehmen", exit)
+            }
+            TurnCommand::Beeline => "in gerader Linie weiterfahren".to_string(),
+            TurnCommand::ExitLeft => "die Ausfahrt links nehmen".to_string(),
+            TurnCommand::ExitRight => "die Ausfahrt rechts nehmen".to_string(),
+        }
+    }
+
+    fn sentence(&self, distance: &str, maneuver: &str, way_name: Option<&str>) -> String {
+        match way_name {
+            Some(name) => format!("Nach {} {} auf {}", distance, maneuver, name),
+            None => format!("Nach {} {}", distance, maneuver),
+        }
+    }
+
+    fn distance(&self, meters: f64) -> String {
+        if meters < 1000.0 {
+            format!("{} m", (meters / 10.0).round() * 10.0)
+        } else {
+            format!("{:.1} km", meters / 1000.0).replace('.', ",")
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! Default locations for files, following the XDG base directory specification.
+//!
+//! Data that can't be recreated, such as stored profiles, goes in the data directory;
+//! anything that can be downloaded again goes in the cache directory, so that backup and
+//! cleanup tools can treat it accordingly.
+//!
+//! Applications that embed this crate can keep its files with their own using
+//! [`data_dir_for`] and [`cache_dir_for`], and pass the result to e.g.
+//! [`crate::profile::store::ProfileStore::new`].
+
+use std::path::PathBuf;
+
+const APP_NAME: &str = "brouter-client";
+
+/// `$<var>`, or `$HOME/<fallback>` if it is unset or empty.
+fn base_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback)))
+}
+
+/// The directory for configuration of this crate, e.g. `~/.config/brouter-client`.
+pub fn config_dir() -> Option<PathBuf> {
+    Some(base_dir("XDG_CONFIG_HOME", ".config")?.join(APP_NAME))
+}
+
+/// The directory for data of this crate, e.g. `~/.local/share/brouter-client`.
+pub fn data_dir() -> Option<PathBuf> {
+    Some(base_dir("XDG_DATA_HOME", ".local/share")?.join(APP_NAME))
+}
+
+/// The directory for downloads of this crate, e.g. `~/.cache/brouter-client`.
+pub fn cache_dir() -> Option<PathBuf> {
+    Some(base_dir("XDG_CACHE_HOME", ".cache")?.join(APP_NAME))
+}
+
+/// The directory for data of this crate within that of `app_name`, e.g.
+/// `~/.local/share/myapp/brouter`.
+pub fn data_dir_for(app_name: &str) -> Option<PathBuf> {
+    Some(
+        base_dir("XDG_DATA_HOME", ".local/share")?
+            .join(app_name)
+            .join("brouter"),
+    )
+}
+
+/// The directory for downloads of this crate within that of `app_name`, e.g.
+/// `~/.cache/myapp/brouter`.
+pub fn cache_dir_for(app_name: &str) -> Option<PathBuf> {
+    Some(
+        base_dir("XDG_CACHE_HOME", ".cache")?
+            .join(app_name)
+            .join("brouter"),
+    )
+}
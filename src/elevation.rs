@@ -0,0 +1,222 @@
+//! Elevation data from digital elevation model (DEM) tiles.
+//!
+//! Tiles are in the SRTM `.hgt` format: one tile per 1°×1° cell, stored as a square grid of
+//! big-endian 16-bit samples. By default they are downloaded from the gzipped "skadi" tile set
+//! on AWS, which combines SRTM and other sources; any mirror with the same layout can be used.
+
+use crate::Point;
+use flate2::read::GzDecoder;
+use reqwest::blocking::Client;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub const DEFAULT_SOURCE: &str = "https://elevation-tiles-prod.s3.amazonaws.com/skadi";
+
+/// Sample value used for voids.
+const VOID: i16 = -32768;
+
+#[derive(Debug)]
+pub enum Error {
+    Http(reqwest::Error),
+    Io(std::io::Error),
+    InvalidTile(String),
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "HTTP error: {}", e),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::InvalidTile(s) => write!(f, "Invalid DEM tile: {}", s),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+#[derive(Debug)]
+struct Tile {
+    size: usize,
+    samples: Vec<i16>,
+}
+
+impl Tile {
+    fn parse(name: &str, data: &[u8]) -> Result<Self, Error> {
+        let count = data.len() / 2;
+        let size = (count as f64).sqrt() as usize;
+        if size < 2 || size * size * 2 != data.len() {
+            return Err(Error::InvalidTile(format!(
+                "{}: unexpected size {}",
+                name,
+                data.len()
+            )));
+        }
+        Ok(Tile {
+            size,
+            samples: data
+                .chunks_exact(2)
+                .map(|c| i16::from_be_bytes([c[0], c[1]]))
+                .collect(),
+        })
+    }
+
+    fn sample(&self, row: usize, col: usize) -> Option<f64> {
+        match self.samples[row * self.size + col] {
+            VOID => None,
+            v => Some(v as f64),
+        }
+    }
+
+    /// Bilinearly interpolated elevation, given the offset within the tile in degrees from the
+    /// south-west corner.
+    fn elevation(&self, dlat: f64, dlon: f64) -> Option<f64> {
+        let max = (self.size - 1) as f64;
+        let y = ((1.0 - dlat) * max).clamp(0.0, max);
+        let x = (dlon * max).clamp(0.0, max);
+        let (row, col) = (y.floor() as usize, x.floor() as usize);
+        let (row1, col1) = ((row + 1).min(self.size - 1), (col + 1).min(self.size - 1));
+        let (fy, fx) = (y - row as f64, x - col as f64);
+
+        let corners = [
+            (self.sample(row, col), (1.0 - fx) * (1.0 - fy)),
+            (self.sample(row, col1), fx * (1.0 - fy)),
+            (self.sample(row1, col), (1.0 - fx) * fy),
+            (self.sample(row1, col1), fx * fy),
+        ];
+
+        // Skip voids, reweighting the remaining samples.
+        let (sum, weight) = corners
+            .iter()
+            .filter_map(|(v, w)| v.map(|v| (v * w, *w)))
+            .fold((0.0, 0.0), |(s, t), (v, w)| (s + v, t + w));
+        if weight > 0.0 {
+            Some(sum / weight)
+        } else {
+            corners.iter().find_map(|(v, _)| *v)
+        }
+    }
+}
+
+/// A loaded tile, or `None` for a cell without data, e.g. over oceans.
+type TileSlot = Option<Arc<Tile>>;
+
+/// Name of the tile containing the given south-west corner, e.g. "N52E013".
+pub fn tile_name(lat: i32, lon: i32) -> String {
+    format!(
+        "{}{:02}{}{:03}",
+        if lat < 0 { 'S' } else { 'N' },
+        lat.abs(),
+        if lon < 0 { 'W' } else { 'E' },
+        lon.abs()
+    )
+}
+
+/// A local cache of DEM tiles, downloading missing tiles on demand.
+pub struct DemCache {
+    dir: PathBuf,
+    source: String,
+    client: Client,
+    download: bool,
+    tiles: Mutex<HashMap<(i32, i32), TileSlot>>,
+}
+
+impl DemCache {
+    /// Create a cache that stores tiles in `dir`.
+    pub fn new(dir: &Path) -> Self {
+        DemCache {
+            dir: dir.to_path_buf(),
+            source: DEFAULT_SOURCE.to_string(),
+            client: Client::new(),
+            download: true,
+            tiles: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The default location of the cache: `brouter-client/dem` in the XDG cache directory.
+    pub fn default_dir() -> Option<PathBuf> {
+        Some(crate::dirs::cache_dir()?.join("dem"))
+    }
+
+    /// Set the base URL to download tiles from.
+    ///
+    /// Tiles are fetched from `{source}/{N52}/{N52E013}.hgt.gz`.
+    pub fn with_source(mut self, source: &str) -> Self {
+        self.source = source.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Only use tiles that are already present in the cache directory.
+    pub fn offline(mut self) -> Self {
+        self.download = false;
+        self
+    }
+
+    fn fetch(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        let path = self.dir.join(format!("{}.hgt", name));
+        if path.exists() {
+            return Ok(Some(std::fs::read(path)?));
+        }
+        if !self.download {
+            return Ok(None);
+        }
+
+        let url = format!("{}/{}/{}.hgt.gz", self.source, &name[..3], name);
+        let response = self.client.get(&url).send().map_err(Error::Http)?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND
+            || response.status() == reqwest::StatusCode::FORBIDDEN
+        {
+            // No tile for this cell.
+            return Ok(None);
+        }
+        let compressed = response
+            .error_for_status()
+            .map_err(Error::Http)?
+            .bytes()
+            .map_err(Error::Http)?;
+
+        let mut data = Vec::new();
+        GzDecoder::new(compressed.as_ref()).read_to_end(&mut data)?;
+
+        std::fs::create_dir_all(&self.dir)?;
+        let tmp = path.with_extension("hgt.tmp");
+        std::fs::write(&tmp, &data)?;
+        std::fs::rename(&tmp, &path)?;
+
+        Ok(Some(data))
+    }
+
+    fn tile(&self, lat: i32, lon: i32) -> Result<Option<Arc<Tile>>, Error> {
+        if let Some(tile) = self.tiles.lock().unwrap().get(&(lat, lon)) {
+            return Ok(tile.clone());
+        }
+        let name = tile_name(lat, lon);
+        let tile = self
+            .fetch(&name)?
+            .map(|data| Tile::parse(&name, &data))
+            .transpose()?
+            .map(Arc::new);
+        self.tiles.lock().unwrap().insert((lat, lon), tile.clone());
+        Ok(tile)
+    }
+
+    /// The elevation at `point` in meters, or `None` if there is no data for it.
+    pub fn elevation(&self, point: &Point) -> Result<Option<f64>, Error> {
+        let (lat, lon) = (point.lat().floor(), point.lon().floor());
+        Ok(self
+            .tile(lat as i32, lon as i32)?
+            .and_then(|tile| tile.elevation(point.lat() - lat, point.lon() - lon)))
+    }
+}
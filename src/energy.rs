@@ -0,0 +1,204 @@
+//! Estimating the battery energy an e-bike needs for a route.
+//!
+//! The energy needed to overcome rolling resistance, air drag and climbs is taken from
+//! brouter's kinematic model if the route has messages with energy, and otherwise calculated
+//! from the elevation profile of the track. Whatever the rider doesn't supply comes from the
+//! battery.
+
+use crate::profile::KinematicParameters;
+use crate::Route;
+use geo::{Distance, Haversine};
+
+const GRAVITY: f64 = 9.81;
+
+/// Density of air at sea level, in kg/m³.
+const AIR_DENSITY: f64 = 1.2;
+
+const JOULES_PER_WH: f64 = 3600.0;
+
+/// The parameters of an e-bike and its rider.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EBikeParameters {
+    /// The physical model; `max_speed` is taken as the cruising speed and `biker_power` is
+    /// ignored in favor of `rider_power`.
+    pub kinematic: KinematicParameters,
+
+    /// Power the rider contributes, in watts.
+    pub rider_power: f64,
+
+    /// Energy left in the battery, in Wh.
+    pub battery_wh: f64,
+
+    /// Fraction of the battery energy that reaches the wheel.
+    pub efficiency: f64,
+}
+
+impl Default for EBikeParameters {
+    fn default() -> Self {
+        EBikeParameters {
+            kinematic: KinematicParameters::e_bike(),
+            rider_power: 100.0,
+            battery_wh: 500.0,
+            efficiency: 0.75,
+        }
+    }
+}
+
+impl EBikeParameters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn kinematic(mut self, kinematic: KinematicParameters) -> Self {
+        self.kinematic = kinematic;
+        self
+    }
+
+    pub fn rider_power(mut self, watts: f64) -> Self {
+        assert!(watts >= 0.0);
+        self.rider_power = watts;
+        self
+    }
+
+    pub fn battery_wh(mut self, wh: f64) -> Self {
+        assert!(wh >= 0.0);
+        self.battery_wh = wh;
+        self
+    }
+
+    pub fn efficiency(mut self, efficiency: f64) -> Self {
+        assert!(efficiency > 0.0 && efficiency <= 1.0);
+        self.efficiency = efficiency;
+        self
+    }
+}
+
+/// The result of [`Route::energy_estimate`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EnergyEstimate {
+    /// Energy drawn from the battery over the whole route, in Wh.
+    pub consumed_wh: f64,
+
+    /// Energy left in the battery at the end of the route, in Wh; negative if the battery
+    /// runs out.
+    pub remaining_wh: f64,
+
+    /// How far the remaining energy lasts beyond the end of the route at the average
+    /// consumption of the route, in meters.
+    pub remaining_range_m: f64,
+
+    /// Distance along the route at which the battery runs out, if it does.
+    pub runs_out_at_m: Option<f64>,
+}
+
+impl EnergyEstimate {
+    /// Whether the battery lasts for the whole route.
+    pub fn is_sufficient(&self) -> bool {
+        self.runs_out_at_m.is_none()
+    }
+}
+
+/// A stretch of the route: its length in meters, the mechanical energy it takes in joules and
+/// the time it takes in seconds.
+struct Stretch {
+    distance_m: f64,
+    energy_j: f64,
+    time_s: f64,
+}
+
+impl Route {
+    /// Estimate how much of the battery of an e-bike the route takes.
+    pub fn energy_estimate(&self, params: &EBikeParameters) -> EnergyEstimate {
+        let stretches = match self.message_stretches(params) {
+            Some(stretches) => stretches,
+            None => self.track_stretches(params),
+        };
+
+        let mut consumed_j = 0.0;
+        let mut along_m = 0.0;
+        let mut runs_out_at_m = None;
+        let capacity_j = params.battery_wh * JOULES_PER_WH;
+        for stretch in &stretches {
+            let motor_j = (stretch.energy_j - params.rider_power * stretch.time_s).max(0.0)
+                / params.efficiency;
+            if runs_out_at_m.is_none() && consumed_j + motor_j > capacity_j {
+                let fraction = (capacity_j - consumed_j) / motor_j;
+                runs_out_at_m = Some(along_m + fraction * stretch.distance_m);
+            }
+            consumed_j += motor_j;
+            along_m += stretch.distance_m;
+        }
+
+        let consumed_wh = consumed_j / JOULES_PER_WH;
+        let remaining_wh = params.battery_wh - consumed_wh;
+        let remaining_range_m = if remaining_wh <= 0.0 {
+            0.0
+        } else if consumed_wh > 0.0 {
+            remaining_wh / consumed_wh * along_m
+        } else {
+            f64::INFINITY
+        };
+        EnergyEstimate {
+            consumed_wh,
+            remaining_wh,
+            remaining_range_m,
+            runs_out_at_m,
+        }
+    }
+
+    /// Stretches from brouter's kinematic model, if the messages have energy and time.
+    fn message_stretches(&self, params: &EBikeParameters) -> Option<Vec<Stretch>> {
+        let messages = self.messages();
+        if messages.is_empty() || messages.iter().any(|m| m.energy_j.is_none()) {
+            return None;
+        }
+        let speed = params.kinematic.max_speed / 3.6;
+        let (mut energy, mut time) = (0.0, 0.0);
+        Some(
+            messages
+                .iter()
+                .map(|m| {
+                    // Energy and time are totals since the start of the route.
+                    let energy_j = m.energy_j.unwrap_or(energy);
+                    let time_s = m.time_s.unwrap_or(time + m.distance_m / speed);
+                    let stretch = Stretch {
+                        distance_m: m.distance_m,
+                        energy_j: energy_j - energy,
+                        time_s: time_s - time,
+                    };
+                    (energy, time) = (energy_j, time_s);
+                    stretch
+                })
+                .collect(),
+        )
+    }
+
+    /// Stretches between track points, with the energy from the physical model.
+    fn track_stretches(&self, params: &EBikeParameters) -> Vec<Stretch> {
+        let k = &params.kinematic;
+        let speed = k.max_speed / 3.6;
+        // Force of rolling resistance and air drag at cruising speed, in newtons.
+        let resistance = k.rolling_resistance * k.total_mass * GRAVITY
+            + 0.5 * AIR_DENSITY * k.drag_area * speed * speed;
+        let points = self.points().collect::<Vec<_>>();
+        points
+            .windows(2)
+            .map(|w| {
+                let distance_m = Haversine.distance(w[0].point(), w[1].point());
+                let climb = match (w[0].elevation, w[1].elevation) {
+                    (Some(a), Some(b)) => b - a,
+                    _ => 0.0,
+                };
+                // Descents make up for resistance, but the rider brakes rather than store energy.
+                let energy_j = (resistance * distance_m + k.total_mass * GRAVITY * climb).max(0.0);
+                Stretch {
+                    distance_m,
+                    energy_j,
+                    time_s: distance_m / speed,
+                }
+            })
+            .collect()
+    }
+}
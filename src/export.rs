@@ -0,0 +1,1010 @@
+use crate::geojson::{feature, feature_collection, number, position, positions, property, string};
+use crate::instructions::format_voice_hints;
+use crate::{Point, Route, TurnCommand, TurnInstructionMode};
+use geo::{Distance, Haversine};
+use lazy_regex::regex;
+use std::io::Write;
+
+/// Writes routes as GPX, with control over the version and metadata.
+///
+/// Extensions are only written for turn instructions in a style that requires them: the `gpx`
+/// crate does not retain them when parsing, so by default the output is safe to hand to
+/// services that choke on brouter's extension namespaces.
+#[derive(Debug, Clone)]
+pub struct GpxWriter {
+    version: gpx::GpxVersion,
+    creator: Option<String>,
+    name: Option<String>,
+    description: Option<String>,
+    time: Option<gpx::Time>,
+    author: Option<gpx::Person>,
+    links: Vec<gpx::Link>,
+    turn_instructions: Option<TurnInstructionMode>,
+}
+
+impl Default for GpxWriter {
+    fn default() -> Self {
+        GpxWriter {
+            version: gpx::GpxVersion::Gpx11,
+            creator: None,
+            name: None,
+            description: None,
+            time: None,
+            author: None,
+            links: vec![],
+            turn_instructions: None,
+        }
+    }
+}
+
+impl GpxWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the GPX version to write; either GPX 1.0 or GPX 1.1.
+    pub fn version(mut self, version: gpx::GpxVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Set the `creator` attribute, overriding the one set by brouter.
+    pub fn creator(mut self, creator: &str) -> Self {
+        self.creator = Some(creator.to_string());
+        self
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// Set the metadata time.
+    pub fn time(mut self, time: gpx::Time) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    pub fn author(mut self, author: gpx::Person) -> Self {
+        self.author = Some(author);
+        self
+    }
+
+    /// Add a link to the metadata.
+    pub fn link(mut self, link: gpx::Link) -> Self {
+        self.links.push(link);
+        self
+    }
+
+    /// Re-emit the route's turn instructions in the given style, replacing the ones brouter
+    /// produced.
+    pub fn turn_instructions(mut self, mode: TurnInstructionMode) -> Self {
+        self.turn_instructions = Some(mode);
+        self
+    }
+
+    /// Build the GPX document that would be written for `route`.
+    pub fn to_gpx(&self, route: &Route) -> gpx::Gpx {
+        let mut gpx = route.gpx().clone();
+        gpx.version = self.version;
+        if let Some(creator) = &self.creator {
+            gpx.creator = Some(creator.clone());
+        }
+
+        if self.name.is_some()
+            || self.description.is_some()
+            || self.time.is_some()
+            || self.author.is_some()
+            || !self.links.is_empty()
+        {
+            let metadata = gpx.metadata.get_or_insert_with(Default::default);
+            if let Some(name) = &self.name {
+                metadata.name = Some(name.clone());
+            }
+            if let Some(description) = &self.description {
+                metadata.description = Some(description.clone());
+            }
+            if let Some(time) = self.time {
+                metadata.time = Some(time);
+            }
+            if let Some(author) = &self.author {
+                metadata.author = Some(author.clone());
+            }
+            metadata.links.extend(self.links.iter().cloned());
+        }
+
+        if self.turn_instructions.is_some() {
+            let points = route
+                .turn_instructions()
+                .into_iter()
+                .map(|i| geo_types::Point::from(i.point))
+                .collect::<Vec<_>>();
+            gpx.waypoints.retain(|w| !points.contains(&w.point()));
+            gpx.routes.retain(|r| {
+                !r.points.iter().any(|p| {
+                    p.description
+                        .as_deref()
+                        .and_then(TurnCommand::from_message)
+                        .is_some()
+                })
+            });
+        }
+
+        gpx
+    }
+
+    pub fn write<W: Write>(&self, route: &Route, mut writer: W) -> std::io::Result<()> {
+        let mode = match self.turn_instructions {
+            Some(mode) => mode,
+            None => return gpx::write(&self.to_gpx(route), writer).map_err(std::io::Error::other),
+        };
+
+        let mut buf = Vec::new();
+        gpx::write(&self.to_gpx(route), &mut buf).map_err(std::io::Error::other)?;
+        let mut text = String::from_utf8_lossy(&buf).into_owned();
+
+        // Waypoints and routes precede tracks in a GPX document.
+        let hints = format_voice_hints(&route.turn_instructions(), mode);
+        let offset = regex!("<trk[ >]|</gpx>")
+            .find(&text)
+            .map_or(text.len(), |m| m.start());
+        text.insert_str(offset, &hints);
+
+        writer.write_all(text.as_bytes())
+    }
+
+    pub fn to_vec(&self, route: &Route) -> std::io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.write(route, &mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// What [`GeoJsonWriter`] colors the route by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorBy {
+    /// The gradient between track points, from blue for steep descents to red for steep
+    /// climbs.
+    Gradient,
+
+    /// The `surface` tag of the ways, which requires the route to have messages (see
+    /// [`Route::with_messages`]).
+    Surface,
+}
+
+/// Upper bounds, labels and colors of the gradient classes, in percent.
+const GRADIENT_CLASSES: &[(f64, &str, &str)] = &[
+    (-8.0, "steep descent", "#2166ac"),
+    (-3.0, "descent", "#67a9cf"),
+    (3.0, "flat", "#1a9850"),
+    (8.0, "climb", "#fdae61"),
+    (f64::INFINITY, "steep climb", "#d73027"),
+];
+
+const UNKNOWN_COLOR: &str = "#999999";
+
+fn surface_color(surface: &str) -> &'static str {
+    match surface {
+        "asphalt" | "paved" | "concrete" | "concrete:plates" | "concrete:lanes"
+        | "paving_stones" | "chipseal" => "#555555",
+        "sett" | "cobblestone" | "unhewn_cobblestone" | "bricks" => "#7570b3",
+        "compacted" | "fine_gravel" => "#a6761d",
+        "gravel" | "pebblestone" | "rock" => "#e6ab02",
+        "unpaved" | "dirt" | "ground" | "earth" | "mud" | "sand" | "grass" | "woodchips" => {
+            "#8c510a"
+        }
+        _ => UNKNOWN_COLOR,
+    }
+}
+
+/// A stretch of track points with the same style.
+struct Piece {
+    points: Vec<Point>,
+    properties: Vec<String>,
+}
+
+/// Writes routes as GeoJSON, with style properties following the
+/// [simplestyle spec](https://github.com/mapbox/simplestyle-spec), so that the output renders
+/// as-is in e.g. geojson.io or Leaflet.
+///
+/// By default each track segment becomes a single `LineString` feature. When coloring by
+/// gradient or surface, the track is split into a feature per stretch of the same class, with
+/// the class in a `gradient` or `surface` property.
+#[derive(Debug, Clone)]
+pub struct GeoJsonWriter {
+    color: String,
+    stroke_width: f64,
+    color_by: Option<ColorBy>,
+    markers: bool,
+}
+
+impl Default for GeoJsonWriter {
+    fn default() -> Self {
+        GeoJsonWriter {
+            color: "#3388ff".to_string(),
+            stroke_width: 4.0,
+            color_by: None,
+            markers: false,
+        }
+    }
+}
+
+impl GeoJsonWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the color of the route, as a CSS color, when it isn't colored by class.
+    pub fn color(mut self, color: &str) -> Self {
+        self.color = color.to_string();
+        self
+    }
+
+    pub fn stroke_width(mut self, stroke_width: f64) -> Self {
+        self.stroke_width = stroke_width;
+        self
+    }
+
+    pub fn color_by(mut self, color_by: ColorBy) -> Self {
+        self.color_by = Some(color_by);
+        self
+    }
+
+    /// Add markers for the start, the end and the via points of the route.
+    ///
+    /// Via points are taken from the route's waypoints other than turn instructions, so they
+    /// are only present if waypoints were exported (see
+    /// [`crate::RouteRequest::export_waypoints`]).
+    pub fn markers(mut self, markers: bool) -> Self {
+        self.markers = markers;
+        self
+    }
+
+    fn pieces(&self, route: &Route) -> Vec<Piece> {
+        // End of each message along the route, for looking up the message of a track point.
+        let message_ends = route
+            .segment_costs()
+            .iter()
+            .map(|c| c.distance_m)
+            .collect::<Vec<_>>();
+
+        let mut pieces: Vec<Piece> = vec![];
+        let mut along = 0.0;
+        for segment in route.gpx().tracks.iter().flat_map(|t| t.segments.iter()) {
+            let points = &segment.points;
+            let color_by = match self.color_by {
+                Some(color_by) if points.len() >= 2 => color_by,
+                _ => {
+                    pieces.push(Piece {
+                        points: points.iter().map(|p| p.point().into()).collect(),
+                        properties: vec![property("stroke", string(&self.color))],
+                    });
+                    continue;
+                }
+            };
+
+            let mut current: Option<Piece> = None;
+            for pair in points.windows(2) {
+                let distance = Haversine.distance(pair[0].point(), pair[1].point());
+                let properties = match color_by {
+                    ColorBy::Gradient => {
+                        let gradient = match (pair[0].elevation, pair[1].elevation) {
+                            (Some(a), Some(b)) if distance > 0.0 => {
+                                Some((b - a) / distance * 100.0)
+                            }
+                            _ => None,
+                        };
+                        let (label, color) = match gradient {
+                            Some(gradient) => GRADIENT_CLASSES
+                                .iter()
+                                .find(|(bound, _, _)| gradient < *bound)
+                                .map_or(("unknown", UNKNOWN_COLOR), |(_, l, c)| (*l, *c)),
+                            None => ("unknown", UNKNOWN_COLOR),
+                        };
+                        vec![
+                            property("stroke", string(color)),
+                            property("gradient", string(label)),
+                        ]
+                    }
+                    ColorBy::Surface => {
+                        let middle = along + distance / 2.0;
+                        let surface = route
+                            .messages()
+                            .get(message_ends.partition_point(|end| *end < middle))
+                            .and_then(|m| m.way_tag("surface"));
+                        vec![
+                            property(
+                                "stroke",
+                                string(surface.map_or(UNKNOWN_COLOR, surface_color)),
+                            ),
+                            property(
+                                "surface",
+                                surface.map_or_else(|| "null".to_string(), string),
+                            ),
+                        ]
+                    }
+                };
+                along += distance;
+
+                match &mut current {
+                    Some(piece) if piece.properties == properties => {
+                        piece.points.push(pair[1].point().into());
+                    }
+                    _ => {
+                        pieces.extend(current.take());
+                        current = Some(Piece {
+                            points: vec![pair[0].point().into(), pair[1].point().into()],
+                            properties,
+                        });
+                    }
+                }
+            }
+            pieces.extend(current);
+        }
+        pieces
+    }
+
+    fn markers_features(&self, route: &Route) -> Vec<String> {
+        let marker = |point: &Point, title: &str, symbol: &str, color: &str| {
+            feature(
+                &format!(r#"{{"type":"Point","coordinates":{}}}"#, position(point)),
+                &[
+                    property("title", string(title)),
+                    property("marker-symbol", string(symbol)),
+                    property("marker-color", string(color)),
+                ],
+            )
+        };
+
+        let (first, last) = match (route.points().next(), route.points().last()) {
+            (Some(first), Some(last)) => (first.point().into(), last.point().into()),
+            _ => return vec![],
+        };
+        let mut features = vec![marker(&first, "Start", "s", "#1a9850")];
+        let vias = route.gpx().waypoints.iter().filter(|w| {
+            let point = Point::from(w.point());
+            w.name
+                .as_deref()
+                .and_then(TurnCommand::from_message)
+                .is_none()
+                && point != first
+                && point != last
+        });
+        for (i, via) in vias.enumerate() {
+            let title = via.name.clone().unwrap_or_else(|| format!("Via {}", i + 1));
+            features.push(marker(
+                &via.point().into(),
+                &title,
+                &(i + 1).to_string(),
+                "#3388ff",
+            ));
+        }
+        features.push(marker(&last, "End", "e", "#d73027"));
+        features
+    }
+
+    /// Build the GeoJSON FeatureCollection for `route`.
+    pub fn to_geojson(&self, route: &Route) -> String {
+        let mut features = self
+            .pieces(route)
+            .into_iter()
+            .map(|mut piece| {
+                piece
+                    .properties
+                    .push(property("stroke-width", number(self.stroke_width)));
+                feature(
+                    &format!(
+                        r#"{{"type":"LineString","coordinates":{}}}"#,
+                        positions(&piece.points)
+                    ),
+                    &piece.properties,
+                )
+            })
+            .collect::<Vec<_>>();
+        if self.markers {
+            features.extend(self.markers_features(route));
+        }
+        feature_collection(features)
+    }
+
+    pub fn write<W: Write>(&self, route: &Route, mut writer: W) -> std::io::Result<()> {
+        writer.write_all(self.to_geojson(route).as_bytes())
+    }
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Truncate `s` to at most `max` bytes, at a character boundary.
+fn truncate(s: &str, max: usize) -> &str {
+    let mut end = s.len().min(max);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+fn format_time(t: time::OffsetDateTime) -> String {
+    let t = t.to_offset(time::UtcOffset::UTC);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        t.year(),
+        t.month() as u8,
+        t.day(),
+        t.hour(),
+        t.minute(),
+        t.second()
+    )
+}
+
+/// The name of the first track, if any.
+fn track_name(route: &Route) -> Option<&str> {
+    route.gpx().tracks.iter().find_map(|t| t.name.as_deref())
+}
+
+/// Writes routes as KML, with a `LineString` placemark per track segment.
+#[derive(Debug, Clone, Default)]
+pub struct KmlWriter {
+    name: Option<String>,
+    turn_instructions: bool,
+}
+
+impl KmlWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the name of the document, overriding the name of the track.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Add a point placemark for each turn instruction.
+    pub fn turn_instructions(mut self, turn_instructions: bool) -> Self {
+        self.turn_instructions = turn_instructions;
+        self
+    }
+
+    /// Build the KML document for `route`.
+    pub fn to_kml(&self, route: &Route) -> String {
+        let mut ret = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<kml xmlns=\"http://www.opengis.net/kml/2.2\"><Document>",
+        );
+        if let Some(name) = self.name.as_deref().or_else(|| track_name(route)) {
+            ret.push_str(&format!("<name>{}</name>", escape_xml(name)));
+        }
+        for segment in route.gpx().tracks.iter().flat_map(|t| t.segments.iter()) {
+            let coordinates = segment
+                .points
+                .iter()
+                .map(|p| {
+                    let point = p.point();
+                    match p.elevation {
+                        Some(elevation) => format!("{},{},{}", point.x(), point.y(), elevation),
+                        None => format!("{},{}", point.x(), point.y()),
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            ret.push_str(&format!(
+                "<Placemark><LineString><tessellate>1</tessellate><coordinates>{}</coordinates></LineString></Placemark>",
+                coordinates
+            ));
+        }
+        if self.turn_instructions {
+            for instruction in route.turn_instructions() {
+                ret.push_str(&format!(
+                    "<Placemark><name>{}</name><Point><coordinates>{},{}</coordinates></Point></Placemark>",
+                    escape_xml(&instruction.command.message()),
+                    instruction.point.lon(),
+                    instruction.point.lat()
+                ));
+            }
+        }
+        ret.push_str("</Document></kml>\n");
+        ret
+    }
+
+    pub fn write<W: Write>(&self, route: &Route, mut writer: W) -> std::io::Result<()> {
+        writer.write_all(self.to_kml(route).as_bytes())
+    }
+}
+
+/// A track point with the distance along the route and time that course formats require.
+struct CoursePoint {
+    point: Point,
+    elevation: Option<f64>,
+    distance_m: f64,
+    time: time::OffsetDateTime,
+}
+
+/// Speed assumed for track points without a known time, in meters per second.
+const DEFAULT_COURSE_SPEED: f64 = 5.0;
+
+/// The track points of `route` with their times.
+///
+/// Times are relative to `start`, or the time of the first track point if not set. Where
+/// [`Route::set_timestamps`] can't determine the time of a point, it is extrapolated at
+/// `speed_m_s` from the last known time.
+fn course_points(
+    route: &Route,
+    start: Option<time::OffsetDateTime>,
+    speed_m_s: f64,
+) -> Vec<CoursePoint> {
+    let start = start
+        .or_else(|| route.points().find_map(|p| p.time).map(Into::into))
+        .unwrap_or_else(time::OffsetDateTime::now_utc);
+    let mut route = route.clone();
+    route.set_timestamps(start);
+
+    let mut ret: Vec<CoursePoint> = vec![];
+    let mut known = (0.0, start);
+    for p in route.points() {
+        let distance_m = ret.last().map_or(0.0, |prev| {
+            prev.distance_m
+                + Haversine.distance(geo_types::Point::from(prev.point.clone()), p.point())
+        });
+        let time = match p.time {
+            Some(time) => {
+                known = (distance_m, time.into());
+                known.1
+            }
+            None => {
+                known.1 + std::time::Duration::from_secs_f64((distance_m - known.0) / speed_m_s)
+            }
+        };
+        ret.push(CoursePoint {
+            point: p.point().into(),
+            elevation: p.elevation,
+            distance_m,
+            time,
+        });
+    }
+    ret
+}
+
+/// The time at which `distance_m` is reached, i.e. that of the first point at or beyond it.
+fn time_at(points: &[CoursePoint], distance_m: f64) -> Option<time::OffsetDateTime> {
+    let i = points.partition_point(|p| p.distance_m < distance_m);
+    points.get(i).or(points.last()).map(|p| p.time)
+}
+
+fn tcx_point_type(command: &TurnCommand) -> &'static str {
+    match command {
+        TurnCommand::Continue => "Straight",
+        TurnCommand::TurnLeft
+        | TurnCommand::TurnSlightLeft
+        | TurnCommand::TurnSharpLeft
+        | TurnCommand::KeepLeft
+        | TurnCommand::ExitLeft => "Left",
+        TurnCommand::TurnRight
+        | TurnCommand::TurnSlightRight
+        | TurnCommand::TurnSharpRight
+        | TurnCommand::KeepRight
+        | TurnCommand::ExitRight => "Right",
+        _ => "Generic",
+    }
+}
+
+/// Writes routes as Garmin TCX courses, with a course point for each turn instruction.
+///
+/// TCX requires a time for every track point; see [`TcxWriter::start`] and
+/// [`TcxWriter::speed`] for how they are filled in.
+#[derive(Debug, Clone)]
+pub struct TcxWriter {
+    name: Option<String>,
+    start: Option<time::OffsetDateTime>,
+    speed_m_s: f64,
+}
+
+impl Default for TcxWriter {
+    fn default() -> Self {
+        TcxWriter {
+            name: None,
+            start: None,
+            speed_m_s: DEFAULT_COURSE_SPEED,
+        }
+    }
+}
+
+impl TcxWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the name of the course, overriding the name of the track.
+    ///
+    /// TCX limits course names to 15 characters; longer names are truncated.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Set the start time of the course.
+    ///
+    /// Defaults to the time of the first track point, or the current time if the route has
+    /// no times.
+    pub fn start(mut self, start: time::OffsetDateTime) -> Self {
+        self.start = Some(start);
+        self
+    }
+
+    /// Set the speed assumed for stretches without known times, in meters per second.
+    pub fn speed(mut self, speed_m_s: f64) -> Self {
+        self.speed_m_s = speed_m_s;
+        self
+    }
+
+    /// Build the TCX document for `route`.
+    pub fn to_tcx(&self, route: &Route) -> String {
+        let points = course_points(route, self.start, self.speed_m_s);
+        let position = |point: &Point| {
+            format!(
+                "<LatitudeDegrees>{}</LatitudeDegrees><LongitudeDegrees>{}</LongitudeDegrees>",
+                point.lat(),
+                point.lon()
+            )
+        };
+
+        let mut ret = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<TrainingCenterDatabase xmlns=\"http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2\"><Courses><Course>",
+        );
+        let name = self
+            .name
+            .as_deref()
+            .or_else(|| track_name(route))
+            .unwrap_or("Route");
+        ret.push_str(&format!("<Name>{}</Name>", escape_xml(truncate(name, 15))));
+        if let (Some(first), Some(last)) = (points.first(), points.last()) {
+            ret.push_str(&format!(
+                "<Lap><TotalTimeSeconds>{}</TotalTimeSeconds><DistanceMeters>{:.1}</DistanceMeters><BeginPosition>{}</BeginPosition><EndPosition>{}</EndPosition><Intensity>Active</Intensity></Lap>",
+                (last.time - first.time).as_seconds_f64().round(),
+                last.distance_m,
+                position(&first.point),
+                position(&last.point)
+            ));
+        }
+        ret.push_str("<Track>");
+        for p in &points {
+            ret.push_str(&format!(
+                "<Trackpoint><Time>{}</Time><Position>{}</Position>",
+                format_time(p.time),
+                position(&p.point)
+            ));
+            if let Some(elevation) = p.elevation {
+                ret.push_str(&format!("<AltitudeMeters>{}</AltitudeMeters>", elevation));
+            }
+            ret.push_str(&format!(
+                "<DistanceMeters>{:.1}</DistanceMeters></Trackpoint>",
+                p.distance_m
+            ));
+        }
+        ret.push_str("</Track>");
+        for instruction in route.turn_instructions() {
+            let Some(time) = time_at(&points, instruction.distance_m) else {
+                break;
+            };
+            ret.push_str(&format!(
+                "<CoursePoint><Name>{}</Name><Time>{}</Time><Position>{}</Position><PointType>{}</PointType><Notes>{}</Notes></CoursePoint>",
+                escape_xml(truncate(&instruction.command.message(), 10)),
+                format_time(time),
+                position(&instruction.point),
+                tcx_point_type(&instruction.command),
+                escape_xml(&instruction.command.message())
+            ));
+        }
+        ret.push_str("</Course></Courses></TrainingCenterDatabase>\n");
+        ret
+    }
+
+    pub fn write<W: Write>(&self, route: &Route, mut writer: W) -> std::io::Result<()> {
+        writer.write_all(self.to_tcx(route).as_bytes())
+    }
+}
+
+/// Seconds between the Unix epoch and the FIT epoch, 1989-12-31T00:00:00Z.
+const FIT_EPOCH: i64 = 631_065_600;
+
+const FIT_ENUM: u8 = 0x00;
+const FIT_STRING: u8 = 0x07;
+const FIT_UINT16: u8 = 0x84;
+const FIT_SINT32: u8 = 0x85;
+const FIT_UINT32: u8 = 0x86;
+
+/// Maximum length of course point names, including the terminating NUL.
+const FIT_COURSE_POINT_NAME_SIZE: u8 = 16;
+
+/// The CRC-16 used by FIT files.
+fn fit_crc(data: &[u8]) -> u16 {
+    const TABLE: [u16; 16] = [
+        0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401, 0xA001, 0x6C00, 0x7800,
+        0xB401, 0x5000, 0x9C01, 0x8801, 0x4400,
+    ];
+    data.iter().fold(0, |crc, &byte| {
+        let crc = (crc >> 4) ^ TABLE[(crc & 0xf) as usize] ^ TABLE[(byte & 0xf) as usize];
+        (crc >> 4) ^ TABLE[(crc & 0xf) as usize] ^ TABLE[(byte >> 4) as usize]
+    })
+}
+
+fn fit_timestamp(t: time::OffsetDateTime) -> u32 {
+    (t.unix_timestamp() - FIT_EPOCH).clamp(0, u32::MAX as i64) as u32
+}
+
+fn fit_semicircles(degrees: f64) -> i32 {
+    (degrees * (2f64.powi(31) / 180.0)).round() as i32
+}
+
+/// A string field of `size` bytes, truncated or padded with NULs.
+fn fit_string(s: &str, size: u8) -> Vec<u8> {
+    let mut ret = truncate(s, size as usize - 1).as_bytes().to_vec();
+    ret.resize(size as usize, 0);
+    ret
+}
+
+fn fit_course_point_type(command: &TurnCommand) -> u8 {
+    match command {
+        TurnCommand::Continue => 8,
+        TurnCommand::TurnLeft | TurnCommand::ExitLeft => 6,
+        TurnCommand::TurnRight | TurnCommand::ExitRight => 7,
+        TurnCommand::KeepLeft => 16,
+        TurnCommand::KeepRight => 17,
+        TurnCommand::TurnSlightLeft => 19,
+        TurnCommand::TurnSharpLeft => 20,
+        TurnCommand::TurnSlightRight => 21,
+        TurnCommand::TurnSharpRight => 22,
+        TurnCommand::UTurn | TurnCommand::UTurnLeft | TurnCommand::UTurnRight => 23,
+        _ => 0,
+    }
+}
+
+/// The data records of a FIT file.
+#[derive(Default)]
+struct FitRecords(Vec<u8>);
+
+impl FitRecords {
+    /// Define local message type `local` as global message `global`, with fields given as
+    /// number, size and base type.
+    fn define(&mut self, local: u8, global: u16, fields: &[(u8, u8, u8)]) {
+        self.0.push(0x40 | local);
+        // Reserved, then little endian architecture.
+        self.0.extend([0, 0]);
+        self.0.extend(global.to_le_bytes());
+        self.0.push(fields.len() as u8);
+        for (number, size, base_type) in fields {
+            self.0.extend([*number, *size, *base_type]);
+        }
+    }
+
+    fn data(&mut self, local: u8, values: &[&[u8]]) {
+        self.0.push(local);
+        for value in values {
+            self.0.extend_from_slice(value);
+        }
+    }
+}
+
+/// Writes routes as FIT course files, as used by Garmin and Wahoo devices, with a course point
+/// for each turn instruction.
+///
+/// Like TCX, FIT courses need a time for every track point; see [`FitWriter::start`] and
+/// [`FitWriter::speed`].
+#[derive(Debug, Clone)]
+pub struct FitWriter {
+    name: Option<String>,
+    start: Option<time::OffsetDateTime>,
+    speed_m_s: f64,
+}
+
+impl Default for FitWriter {
+    fn default() -> Self {
+        FitWriter {
+            name: None,
+            start: None,
+            speed_m_s: DEFAULT_COURSE_SPEED,
+        }
+    }
+}
+
+impl FitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the name of the course, overriding the name of the track.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Set the start time of the course.
+    ///
+    /// Defaults to the time of the first track point, or the current time if the route has
+    /// no times.
+    pub fn start(mut self, start: time::OffsetDateTime) -> Self {
+        self.start = Some(start);
+        self
+    }
+
+    /// Set the speed assumed for stretches without known times, in meters per second.
+    pub fn speed(mut self, speed_m_s: f64) -> Self {
+        self.speed_m_s = speed_m_s;
+        self
+    }
+
+    /// Build the FIT file for `route`.
+    pub fn to_vec(&self, route: &Route) -> Vec<u8> {
+        let points = course_points(route, self.start, self.speed_m_s);
+        let created = points
+            .first()
+            .map_or_else(time::OffsetDateTime::now_utc, |p| p.time);
+        let name = self
+            .name
+            .as_deref()
+            .or_else(|| track_name(route))
+            .unwrap_or("Route");
+        let position = |point: &Point| {
+            [
+                fit_semicircles(point.lat()).to_le_bytes(),
+                fit_semicircles(point.lon()).to_le_bytes(),
+            ]
+        };
+
+        let mut records = FitRecords::default();
+        // file_id, with type course and the development manufacturer.
+        records.define(
+            0,
+            0,
+            &[
+                (0, 1, FIT_ENUM),
+                (1, 2, FIT_UINT16),
+                (2, 2, FIT_UINT16),
+                (4, 4, FIT_UINT32),
+            ],
+        );
+        records.data(
+            0,
+            &[
+                &[6],
+                &255u16.to_le_bytes(),
+                &0u16.to_le_bytes(),
+                &fit_timestamp(created).to_le_bytes(),
+            ],
+        );
+
+        let name = fit_string(name, (name.len() + 1).min(64) as u8);
+        records.define(1, 31, &[(5, name.len() as u8, FIT_STRING)]);
+        records.data(1, &[&name]);
+
+        if let (Some(first), Some(last)) = (points.first(), points.last()) {
+            let [start_lat, start_lon] = position(&first.point);
+            let [end_lat, end_lon] = position(&last.point);
+            let elapsed =
+                (((last.time - first.time).as_seconds_f64() * 1000.0).round() as u32).to_le_bytes();
+            records.define(
+                2,
+                19,
+                &[
+                    (253, 4, FIT_UINT32),
+                    (2, 4, FIT_UINT32),
+                    (3, 4, FIT_SINT32),
+                    (4, 4, FIT_SINT32),
+                    (5, 4, FIT_SINT32),
+                    (6, 4, FIT_SINT32),
+                    (7, 4, FIT_UINT32),
+                    (8, 4, FIT_UINT32),
+                    (9, 4, FIT_UINT32),
+                ],
+            );
+            records.data(
+                2,
+                &[
+                    &fit_timestamp(last.time).to_le_bytes(),
+                    &fit_timestamp(first.time).to_le_bytes(),
+                    &start_lat,
+                    &start_lon,
+                    &end_lat,
+                    &end_lon,
+                    &elapsed,
+                    &elapsed,
+                    &((last.distance_m * 100.0).round() as u32).to_le_bytes(),
+                ],
+            );
+
+            // Timer start and stop events around the records.
+            records.define(
+                3,
+                21,
+                &[(253, 4, FIT_UINT32), (0, 1, FIT_ENUM), (1, 1, FIT_ENUM)],
+            );
+            records.data(3, &[&fit_timestamp(first.time).to_le_bytes(), &[0], &[0]]);
+
+            records.define(
+                4,
+                20,
+                &[
+                    (253, 4, FIT_UINT32),
+                    (0, 4, FIT_SINT32),
+                    (1, 4, FIT_SINT32),
+                    (2, 2, FIT_UINT16),
+                    (5, 4, FIT_UINT32),
+                ],
+            );
+            for p in &points {
+                let [lat, lon] = position(&p.point);
+                // Altitude is stored with a scale of 5 and an offset of 500 m.
+                let altitude = p.elevation.map_or(u16::MAX, |e| {
+                    ((e + 500.0) * 5.0)
+                        .round()
+                        .clamp(0.0, (u16::MAX - 1) as f64) as u16
+                });
+                records.data(
+                    4,
+                    &[
+                        &fit_timestamp(p.time).to_le_bytes(),
+                        &lat,
+                        &lon,
+                        &altitude.to_le_bytes(),
+                        &((p.distance_m * 100.0).round() as u32).to_le_bytes(),
+                    ],
+                );
+            }
+
+            records.define(
+                5,
+                32,
+                &[
+                    (1, 4, FIT_UINT32),
+                    (2, 4, FIT_SINT32),
+                    (3, 4, FIT_SINT32),
+                    (4, 4, FIT_UINT32),
+                    (5, 1, FIT_ENUM),
+                    (6, FIT_COURSE_POINT_NAME_SIZE, FIT_STRING),
+                ],
+            );
+            for instruction in route.turn_instructions() {
+                let Some(time) = time_at(&points, instruction.distance_m) else {
+                    break;
+                };
+                let [lat, lon] = position(&instruction.point);
+                records.data(
+                    5,
+                    &[
+                        &fit_timestamp(time).to_le_bytes(),
+                        &lat,
+                        &lon,
+                        &((instruction.distance_m * 100.0).round() as u32).to_le_bytes(),
+                        &[fit_course_point_type(&instruction.command)],
+                        &fit_string(&instruction.command.message(), FIT_COURSE_POINT_NAME_SIZE),
+                    ],
+                );
+            }
+
+            records.data(3, &[&fit_timestamp(last.time).to_le_bytes(), &[0], &[4]]);
+        }
+
+        // A 14 byte header: header size, protocol version 2.0, profile version 21.32, data size
+        // and the file type, followed by the CRC of the header.
+        let mut ret = vec![14, 0x20];
+        ret.extend(2132u16.to_le_bytes());
+        ret.extend((records.0.len() as u32).to_le_bytes());
+        ret.extend(b".FIT");
+        ret.extend(fit_crc(&ret).to_le_bytes());
+        ret.extend(records.0);
+        ret.extend(fit_crc(&ret).to_le_bytes());
+        ret
+    }
+
+    pub fn write<W: Write>(&self, route: &Route, mut writer: W) -> std::io::Result<()> {
+        writer.write_all(&self.to_vec(route))
+    }
+}
@@ -0,0 +1,148 @@
+//! Looking up places by name.
+//!
+//! [`Geocoder`] abstracts over geocoding services; with the `nominatim` feature,
+//! [`Nominatim`] implements it with OpenStreetMap's Nominatim service.
+
+use crate::Point;
+
+#[derive(Debug)]
+pub enum Error {
+    Http(reqwest::Error),
+    /// The response could not be understood.
+    InvalidResponse(String),
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "HTTP error: {}", e),
+            Error::InvalidResponse(e) => write!(f, "Invalid response: {}", e),
+        }
+    }
+}
+
+/// A service that finds places by name.
+pub trait Geocoder {
+    /// The places matching `query`, best match first.
+    fn geocode(&self, query: &str) -> Result<Vec<Point>, Error>;
+
+    /// A short name for the place at `point`, such as the name of the town; `None` if there
+    /// is none or the service doesn't support reverse geocoding.
+    fn reverse(&self, _point: &Point) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+}
+
+#[cfg(feature = "nominatim")]
+pub use nominatim::{Nominatim, NOMINATIM_ENDPOINT};
+
+#[cfg(feature = "nominatim")]
+mod nominatim {
+    use super::{Error, Geocoder};
+    use crate::Point;
+    use reqwest::blocking::Client;
+    use serde_json::Value;
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    pub const NOMINATIM_ENDPOINT: &str = "https://nominatim.openstreetmap.org/";
+
+    /// The usage policy of the public Nominatim server allows one request per second.
+    const DEFAULT_MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Maximum number of results to ask for.
+    const LIMIT: usize = 5;
+
+    /// A client for a Nominatim server.
+    ///
+    /// Requests are spaced out by at least a second, as the usage policy of the public server
+    /// requires; clients can be shared between threads to respect the limit across them.
+    pub struct Nominatim {
+        client: Client,
+        endpoint: String,
+        min_interval: Duration,
+        last_request: Mutex<Option<Instant>>,
+    }
+
+    impl Default for Nominatim {
+        fn default() -> Self {
+            Self::new(NOMINATIM_ENDPOINT)
+        }
+    }
+
+    impl Nominatim {
+        pub fn new(endpoint: &str) -> Self {
+            Nominatim {
+                client: Client::builder()
+                    .user_agent(concat!("brouter-client/", env!("CARGO_PKG_VERSION")))
+                    .build()
+                    .unwrap(),
+                endpoint: endpoint.trim_end_matches('/').to_string(),
+                min_interval: DEFAULT_MIN_INTERVAL,
+                last_request: Mutex::new(None),
+            }
+        }
+
+        /// Set the minimum time between requests, e.g. for a self-hosted server.
+        pub fn min_interval(mut self, interval: Duration) -> Self {
+            self.min_interval = interval;
+            self
+        }
+
+        /// Send a request to `path`, waiting for the rate limit first.
+        pub(super) fn get(&self, path: &str, params: &[(&str, &str)]) -> Result<Value, Error> {
+            {
+                let mut last_request = self.last_request.lock().unwrap();
+                if let Some(last) = *last_request {
+                    let elapsed = last.elapsed();
+                    if elapsed < self.min_interval {
+                        std::thread::sleep(self.min_interval - elapsed);
+                    }
+                }
+                *last_request = Some(Instant::now());
+            }
+            let text = self
+                .client
+                .get(format!("{}/{}", self.endpoint, path))
+                .query(params)
+                .query(&[("format", "jsonv2")])
+                .send()
+                .and_then(|r| r.error_for_status())
+                .and_then(|r| r.text())
+                .map_err(Error::Http)?;
+            serde_json::from_str(&text).map_err(|e| Error::InvalidResponse(e.to_string()))
+        }
+    }
+
+    /// The coordinates of a Nominatim result, which are given as strings.
+    pub(super) fn point(place: &Value) -> Option<Point> {
+        let coordinate = |key: &str| place.get(key)?.as_str()?.parse::<f64>().ok();
+        Some(Point::new(coordinate("lat")?, coordinate("lon")?))
+    }
+
+    impl Geocoder for Nominatim {
+        fn geocode(&self, query: &str) -> Result<Vec<Point>, Error> {
+            let limit = LIMIT.to_string();
+            let places = self.get("search", &[("q", query), ("limit", &limit)])?;
+            let places = places
+                .as_array()
+                .ok_or_else(|| Error::InvalidResponse("expected a list of places".to_string()))?;
+            Ok(places.iter().filter_map(point).collect())
+        }
+
+        fn reverse(&self, point: &Point) -> Result<Option<String>, Error> {
+            let (lat, lon) = (point.lat().to_string(), point.lon().to_string());
+            // Zoom level 10 asks for the city rather than the street.
+            let place = self.get("reverse", &[("lat", &lat), ("lon", &lon), ("zoom", "10")])?;
+            let address = place.get("address");
+            let name = ["city", "town", "village", "municipality", "county"]
+                .iter()
+                .find_map(|key| address?.get(*key)?.as_str())
+                .or_else(|| place.get("name")?.as_str())
+                .filter(|name| !name.is_empty());
+            Ok(name.map(|name| name.to_string()))
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Geohash conversions.
+//!
+//! See <https://en.wikipedia.org/wiki/Geohash>.
+
+use crate::{Error, Point};
+
+const BASE32: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+impl Point {
+    /// The center of the cell identified by `geohash`.
+    pub fn from_geohash(geohash: &str) -> Result<Point, Error> {
+        if geohash.is_empty() {
+            return Err(Error::InvalidGeohash("empty geohash".to_string()));
+        }
+        let (mut lat, mut lon) = ((-90.0, 90.0), (-180.0, 180.0));
+        let mut even = true;
+        for c in geohash.chars() {
+            let bits = BASE32
+                .iter()
+                .position(|b| *b as char == c.to_ascii_lowercase())
+                .ok_or_else(|| Error::InvalidGeohash(format!("invalid character '{}'", c)))?;
+            for shift in (0..5).rev() {
+                // Bits alternate between longitude and latitude, starting with longitude.
+                let range: &mut (f64, f64) = if even { &mut lon } else { &mut lat };
+                let mid = (range.0 + range.1) / 2.0;
+                if bits & (1 << shift) != 0 {
+                    range.0 = mid;
+                } else {
+                    range.1 = mid;
+                }
+                even = !even;
+            }
+        }
+        Ok(Point::new((lat.0 + lat.1) / 2.0, (lon.0 + lon.1) / 2.0))
+    }
+
+    /// The geohash of the cell containing this point, with `precision` characters.
+    pub fn geohash(&self, precision: usize) -> String {
+        let (mut lat, mut lon) = ((-90.0, 90.0), (-180.0, 180.0));
+        let mut even = true;
+        let mut geohash = String::with_capacity(precision);
+        let mut bits = 0;
+        let mut count = 0;
+        while geohash.len() < precision {
+            let (range, value): (&mut (f64, f64), f64) = if even {
+                (&mut lon, self.lon())
+            } else {
+                (&mut lat, self.lat())
+            };
+            let mid = (range.0 + range.1) / 2.0;
+            bits <<= 1;
+            if value >= mid {
+                bits |= 1;
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+            even = !even;
+            count += 1;
+            if count == 5 {
+                geohash.push(BASE32[bits] as char);
+                bits = 0;
+                count = 0;
+            }
+        }
+        geohash
+    }
+}
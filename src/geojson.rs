@@ -0,0 +1,309 @@
+//! Minimal GeoJSON writing, and reading of nogos.
+
+use crate::{Nogo, Point};
+use geo::{Destination, Haversine};
+
+/// Number of vertices of the polygons that approximate circles.
+const CIRCLE_VERTICES: usize = 32;
+
+/// Quote a string as a JSON string literal.
+pub(crate) fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Write a number as JSON, which has no representation for NaN or infinity.
+pub(crate) fn number(n: f64) -> String {
+    if n.is_finite() {
+        n.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+pub(crate) fn position(p: &Point) -> String {
+    format!("[{},{}]", number(p.lon()), number(p.lat()))
+}
+
+pub(crate) fn positions(points: &[Point]) -> String {
+    let positions = points.iter().map(position).collect::<Vec<_>>();
+    format!("[{}]", positions.join(","))
+}
+
+/// A closed ring approximating the circle of `radius_m` meters around `center`.
+fn circle(center: &Point, radius_m: f64) -> Vec<Point> {
+    let center = geo_types::Point::from(center.clone());
+    let mut ring = (0..CIRCLE_VERTICES)
+        .map(|i| {
+            // Counterclockwise, as GeoJSON prefers for exterior rings.
+            let bearing = 360.0 - 360.0 * i as f64 / CIRCLE_VERTICES as f64;
+            Point::from(Haversine.destination(center, bearing, radius_m))
+        })
+        .collect::<Vec<_>>();
+    ring.push(ring[0].clone());
+    ring
+}
+
+/// A closed ring of `points`, as GeoJSON requires for polygons.
+fn ring(points: &[Point]) -> Vec<Point> {
+    let mut ring = points.to_vec();
+    if let (Some(first), Some(last)) = (points.first(), points.last()) {
+        if first != last {
+            ring.push(first.clone());
+        }
+    }
+    ring
+}
+
+/// A GeoJSON feature for a nogo.
+pub(crate) fn nogo_feature(nogo: &Nogo) -> String {
+    let (kind, geometry, radius, weight) = match nogo {
+        Nogo::Point {
+            point,
+            radius,
+            weight,
+        } => (
+            "point",
+            format!(
+                r#"{{"type":"Polygon","coordinates":[{}]}}"#,
+                positions(&circle(point, *radius))
+            ),
+            Some(*radius),
+            weight,
+        ),
+        Nogo::Line { points, weight } => (
+            "line",
+            format!(
+                r#"{{"type":"LineString","coordinates":{}}}"#,
+                positions(points)
+            ),
+            None,
+            weight,
+        ),
+        Nogo::Polygon { points, weight } => (
+            "polygon",
+            format!(
+                r#"{{"type":"Polygon","coordinates":[{}]}}"#,
+                positions(&ring(points))
+            ),
+            None,
+            weight,
+        ),
+    };
+
+    let mut properties = vec![format!(r#""nogo":{}"#, string(kind))];
+    if let Nogo::Point { point, .. } = nogo {
+        properties.push(format!(r#""center":{}"#, position(point)));
+    }
+    if let Some(radius) = radius {
+        properties.push(format!(r#""radius":{}"#, number(radius)));
+    }
+    properties.push(format!(
+        r#""weight":{}"#,
+        weight.map(number).unwrap_or_else(|| "null".to_string())
+    ));
+
+    feature(&geometry, &properties)
+}
+
+/// A GeoJSON feature; `properties` are `"key":value` pairs that are already encoded.
+pub(crate) fn feature(geometry: &str, properties: &[String]) -> String {
+    format!(
+        r#"{{"type":"Feature","geometry":{},"properties":{{{}}}}}"#,
+        geometry,
+        properties.join(",")
+    )
+}
+
+pub(crate) fn property(key: &str, value: String) -> String {
+    format!("{}:{}", string(key), value)
+}
+
+pub(crate) fn feature_collection(features: impl IntoIterator<Item = String>) -> String {
+    let features = features.into_iter().collect::<Vec<_>>();
+    format!(
+        r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+        features.join(",")
+    )
+}
+
+#[cfg(feature = "geojson")]
+pub(crate) use reading::parse_nogos;
+
+/// Reading nogos, which needs a JSON parser.
+#[cfg(feature = "geojson")]
+mod reading {
+    use crate::{Error, Nogo, Point};
+    use serde_json::Value;
+
+    /// Read the nogos in a GeoJSON document; see [`Nogo::from_geojson`].
+    pub(crate) fn parse_nogos(reader: impl std::io::Read) -> Result<Vec<Nogo>, Error> {
+        let root: Value =
+            serde_json::from_reader(reader).map_err(|e| Error::InvalidGeoJson(e.to_string()))?;
+        let mut nogos = vec![];
+        match root.get("type").and_then(|t| t.as_str()) {
+            Some("FeatureCollection") => {
+                let features = root
+                    .get("features")
+                    .and_then(|f| f.as_array())
+                    .ok_or_else(|| invalid("feature collection without features"))?;
+                for feature in features {
+                    feature_nogos(feature, &mut nogos)?;
+                }
+            }
+            Some("Feature") => feature_nogos(&root, &mut nogos)?,
+            Some(_) => geometry_nogos(&root, &NogoProperties::default(), &mut nogos)?,
+            None => return Err(invalid("object without type")),
+        }
+        Ok(nogos)
+    }
+
+    fn invalid(message: &str) -> Error {
+        Error::InvalidGeoJson(message.to_string())
+    }
+
+    /// The properties of a feature that describe its nogos.
+    #[derive(Default)]
+    struct NogoProperties {
+        radius: Option<f64>,
+        weight: Option<f64>,
+        /// The center of a point nogo written by [`super::nogo_feature`].
+        center: Option<Point>,
+    }
+
+    fn feature_nogos(feature: &Value, nogos: &mut Vec<Nogo>) -> Result<(), Error> {
+        let property = |key: &str| feature.get("properties").and_then(|p| p.get(key));
+        let properties = NogoProperties {
+            radius: property("radius").and_then(|r| r.as_f64()),
+            weight: property("weight").and_then(|w| w.as_f64()),
+            center: match property("nogo").and_then(|n| n.as_str()) {
+                Some("point") => property("center").map(parse_position).transpose()?,
+                _ => None,
+            },
+        };
+        match feature.get("geometry") {
+            Some(Value::Null) | None => Err(invalid("feature without geometry")),
+            Some(geometry) => geometry_nogos(geometry, &properties, nogos),
+        }
+    }
+
+    fn geometry_nogos(
+        geometry: &Value,
+        properties: &NogoProperties,
+        nogos: &mut Vec<Nogo>,
+    ) -> Result<(), Error> {
+        let kind = geometry
+            .get("type")
+            .and_then(|t| t.as_str())
+            .ok_or_else(|| invalid("geometry without type"))?;
+        if kind == "GeometryCollection" {
+            let geometries = geometry
+                .get("geometries")
+                .and_then(|g| g.as_array())
+                .ok_or_else(|| invalid("geometry collection without geometries"))?;
+            return geometries
+                .iter()
+                .try_for_each(|g| geometry_nogos(g, properties, nogos));
+        }
+
+        let coordinates = geometry
+            .get("coordinates")
+            .ok_or_else(|| invalid("geometry without coordinates"))?;
+        let radius = || {
+            properties
+                .radius
+                .ok_or_else(|| invalid("point without a radius property"))
+        };
+        let start = nogos.len();
+        match kind {
+            // A circle written by nogo_feature: read back the circle rather than its outline.
+            "Polygon" if properties.center.is_some() => nogos.push(Nogo::Point {
+                point: properties.center.clone().unwrap(),
+                radius: radius()?,
+                weight: None,
+            }),
+            "Point" => nogos.push(Nogo::Point {
+                point: parse_position(coordinates)?,
+                radius: radius()?,
+                weight: None,
+            }),
+            "MultiPoint" => {
+                for position in array(coordinates)? {
+                    nogos.push(Nogo::Point {
+                        point: parse_position(position)?,
+                        radius: radius()?,
+                        weight: None,
+                    });
+                }
+            }
+            "LineString" => nogos.push(Nogo::Line {
+                points: parse_positions(coordinates)?,
+                weight: None,
+            }),
+            "MultiLineString" => {
+                for line in array(coordinates)? {
+                    nogos.push(Nogo::Line {
+                        points: parse_positions(line)?,
+                        weight: None,
+                    });
+                }
+            }
+            "Polygon" => nogos.push(parse_polygon(coordinates)?),
+            "MultiPolygon" => {
+                for polygon in array(coordinates)? {
+                    nogos.push(parse_polygon(polygon)?);
+                }
+            }
+            kind => return Err(invalid(&format!("unsupported geometry type {}", kind))),
+        }
+        if let Some(weight) = properties.weight {
+            for nogo in &mut nogos[start..] {
+                *nogo = nogo.clone().penalize(weight)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn array(value: &Value) -> Result<&Vec<Value>, Error> {
+        value
+            .as_array()
+            .ok_or_else(|| invalid("coordinates are not an array"))
+    }
+
+    fn parse_position(position: &Value) -> Result<Point, Error> {
+        let position = array(position)?;
+        let number = |i: usize| position.get(i).and_then(|v| v.as_f64());
+        let (lon, lat) = number(0)
+            .zip(number(1))
+            .ok_or_else(|| invalid("position without longitude and latitude"))?;
+        Ok(Point::new(lat, lon))
+    }
+
+    fn parse_positions(positions: &Value) -> Result<Vec<Point>, Error> {
+        array(positions)?.iter().map(parse_position).collect()
+    }
+
+    fn parse_polygon(rings: &Value) -> Result<Nogo, Error> {
+        match array(rings)?.as_slice() {
+            [exterior] => Ok(Nogo::Polygon {
+                points: parse_positions(exterior)?,
+                weight: None,
+            }),
+            [] => Err(invalid("polygon without rings")),
+            _ => Err(invalid("polygons with holes are not supported")),
+        }
+    }
+}
@@ -0,0 +1,523 @@
+use crate::{Point, TurnInstructionMode};
+use xml::reader::{EventReader, XmlEvent};
+
+// See https://github.com/abrensch/brouter/blob/master/brouter-core/src/main/java/btools/router/VoiceHint.java
+
+/// A turn command, as used by brouter's voice hints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TurnCommand {
+    Continue,
+    TurnLeft,
+    TurnSlightLeft,
+    TurnSharpLeft,
+    TurnRight,
+    TurnSlightRight,
+    TurnSharpRight,
+    KeepLeft,
+    KeepRight,
+    UTurnLeft,
+    UTurn,
+    UTurnRight,
+    OffRoute,
+    /// Take the given exit of a (counter-clockwise) roundabout.
+    Roundabout(u8),
+    /// Take the given exit of a clockwise roundabout.
+    RoundaboutLeft(u8),
+    Beeline,
+    ExitLeft,
+    ExitRight,
+}
+
+impl TurnCommand {
+    /// The numeric code brouter uses for this command.
+    pub fn code(&self) -> i32 {
+        match self {
+            TurnCommand::Continue => 1,
+            TurnCommand::TurnLeft => 2,
+            TurnCommand::TurnSlightLeft => 3,
+            TurnCommand::TurnSharpLeft => 4,
+            TurnCommand::TurnRight => 5,
+            TurnCommand::TurnSlightRight => 6,
+            TurnCommand::TurnSharpRight => 7,
+            TurnCommand::KeepLeft => 8,
+            TurnCommand::KeepRight => 9,
+            TurnCommand::UTurnLeft => 10,
+            TurnCommand::UTurn => 11,
+            TurnCommand::UTurnRight => 12,
+            TurnCommand::OffRoute => 13,
+            TurnCommand::Roundabout(_) => 14,
+            TurnCommand::RoundaboutLeft(_) => 15,
+            TurnCommand::Beeline => 16,
+            TurnCommand::ExitLeft => 17,
+            TurnCommand::ExitRight => 18,
+        }
+    }
+
+    /// Create a command from brouter's numeric code and roundabout exit number.
+    pub fn from_code(code: i32, exit: u8) -> Option<Self> {
+        Some(match code {
+            1 => TurnCommand::Continue,
+            2 => TurnCommand::TurnLeft,
+            3 => TurnCommand::TurnSlightLeft,
+            4 => TurnCommand::TurnSharpLeft,
+            5 => TurnCommand::TurnRight,
+            6 => TurnCommand::TurnSlightRight,
+            7 => TurnCommand::TurnSharpRight,
+            8 => TurnCommand::KeepLeft,
+            9 => TurnCommand::KeepRight,
+            10 => TurnCommand::UTurnLeft,
+            11 => TurnCommand::UTurn,
+            12 => TurnCommand::UTurnRight,
+            13 => TurnCommand::OffRoute,
+            14 => TurnCommand::Roundabout(exit),
+            15 => TurnCommand::RoundaboutLeft(exit),
+            16 => TurnCommand::Beeline,
+            17 => TurnCommand::ExitLeft,
+            18 => TurnCommand::ExitRight,
+            _ => return None,
+        })
+    }
+
+    /// The command string brouter uses in OsmAnd-style output, e.g. "TSLL" or "RNDB2".
+    pub fn command_string(&self) -> String {
+        match self {
+            TurnCommand::Continue => "C".to_string(),
+            TurnCommand::TurnLeft => "TL".to_string(),
+            TurnCommand::TurnSlightLeft => "TSLL".to_string(),
+            TurnCommand::TurnSharpLeft => "TSHL".to_string(),
+            TurnCommand::TurnRight => "TR".to_string(),
+            TurnCommand::TurnSlightRight => "TSLR".to_string(),
+            TurnCommand::TurnSharpRight => "TSHR".to_string(),
+            TurnCommand::KeepLeft => "KL".to_string(),
+            TurnCommand::KeepRight => "KR".to_string(),
+            TurnCommand::UTurnLeft => "TLU".to_string(),
+            TurnCommand::UTurn => "TU".to_string(),
+            TurnCommand::UTurnRight => "TRU".to_string(),
+            TurnCommand::OffRoute => "OFFR".to_string(),
+            TurnCommand::Roundabout(exit) => format!("RNDB{}", exit),
+            TurnCommand::RoundaboutLeft(exit) => format!("RNLB{}", exit),
+            TurnCommand::Beeline => "BL".to_string(),
+            TurnCommand::ExitLeft => "EL".to_string(),
+            TurnCommand::ExitRight => "ER".to_string(),
+        }
+    }
+
+    /// Parse a command string, as produced by [`TurnCommand::command_string`].
+    pub fn from_command_string(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if let Some(exit) = s.strip_prefix("RNDB") {
+            return exit.parse().ok().map(TurnCommand::Roundabout);
+        }
+        if let Some(exit) = s.strip_prefix("RNLB") {
+            return exit.parse().ok().map(TurnCommand::RoundaboutLeft);
+        }
+        Some(match s {
+            "C" => TurnCommand::Continue,
+            "TL" => TurnCommand::TurnLeft,
+            "TSLL" => TurnCommand::TurnSlightLeft,
+            "TSHL" => TurnCommand::TurnSharpLeft,
+            "TR" => TurnCommand::TurnRight,
+            "TSLR" => TurnCommand::TurnSlightRight,
+            "TSHR" => TurnCommand::TurnSharpRight,
+            "KL" => TurnCommand::KeepLeft,
+            "KR" => TurnCommand::KeepRight,
+            "TLU" => TurnCommand::UTurnLeft,
+            "TU" => TurnCommand::UTurn,
+            "TRU" => TurnCommand::UTurnRight,
+            "OFFR" => TurnCommand::OffRoute,
+            "BL" => TurnCommand::Beeline,
+            "EL" => TurnCommand::ExitLeft,
+            "ER" => TurnCommand::ExitRight,
+            _ => return None,
+        })
+    }
+
+    /// The human-readable message brouter attaches to voice hint waypoints.
+    pub fn message(&self) -> String {
+        match self {
+            TurnCommand::Continue => "straight".to_string(),
+            TurnCommand::TurnLeft => "left".to_string(),
+            TurnCommand::TurnSlightLeft => "slight left".to_string(),
+            TurnCommand::TurnSharpLeft => "sharp left".to_string(),
+            TurnCommand::TurnRight => "right".to_string(),
+            TurnCommand::TurnSlightRight => "slight right".to_string(),
+            TurnCommand::TurnSharpRight => "sharp right".to_string(),
+            TurnCommand::KeepLeft => "keep left".to_string(),
+            TurnCommand::KeepRight => "keep right".to_string(),
+            TurnCommand::UTurnLeft => "u-turn left".to_string(),
+            TurnCommand::UTurn => "u-turn".to_string(),
+            TurnCommand::UTurnRight => "u-turn right".to_string(),
+            TurnCommand::OffRoute => "offroad".to_string(),
+            TurnCommand::Roundabout(exit) => format!("Take exit {}", exit),
+            TurnCommand::RoundaboutLeft(exit) => format!("Take exit -{}", exit),
+            TurnCommand::Beeline => "beeline".to_string(),
+            TurnCommand::ExitLeft => "exit left".to_string(),
+            TurnCommand::ExitRight => "exit right".to_string(),
+        }
+    }
+
+    /// The `rtePointAction` code used by Locus.
+    pub fn locus_action(&self) -> i32 {
+        match self {
+            TurnCommand::Continue => 1,
+            TurnCommand::TurnSlightLeft => 3,
+            TurnCommand::TurnLeft => 4,
+            TurnCommand::TurnSharpLeft => 5,
+            TurnCommand::TurnSlightRight => 6,
+            TurnCommand::TurnRight => 7,
+            TurnCommand::TurnSharpRight => 8,
+            TurnCommand::KeepLeft => 9,
+            TurnCommand::KeepRight => 10,
+            TurnCommand::UTurn => 12,
+            TurnCommand::UTurnLeft => 13,
+            TurnCommand::UTurnRight => 14,
+            TurnCommand::ExitLeft => 15,
+            TurnCommand::ExitRight => 16,
+            TurnCommand::Roundabout(exit) | TurnCommand::RoundaboutLeft(exit) => 26 + *exit as i32,
+            TurnCommand::OffRoute | TurnCommand::Beeline => 1,
+        }
+    }
+
+    pub fn from_locus_action(action: i32) -> Option<Self> {
+        Some(match action {
+            1 => TurnCommand::Continue,
+            3 => TurnCommand::TurnSlightLeft,
+            4 => TurnCommand::TurnLeft,
+            5 => TurnCommand::TurnSharpLeft,
+            6 => TurnCommand::TurnSlightRight,
+            7 => TurnCommand::TurnRight,
+            8 => TurnCommand::TurnSharpRight,
+            9 => TurnCommand::KeepLeft,
+            10 => TurnCommand::KeepRight,
+            12 => TurnCommand::UTurn,
+            13 => TurnCommand::UTurnLeft,
+            14 => TurnCommand::UTurnRight,
+            15 => TurnCommand::ExitLeft,
+            16 => TurnCommand::ExitRight,
+            27..=34 => TurnCommand::Roundabout((action - 26) as u8),
+            _ => return None,
+        })
+    }
+
+    /// The icon code used by OruxMaps.
+    pub fn orux_action(&self) -> i32 {
+        match self {
+            TurnCommand::TurnLeft => 1000,
+            TurnCommand::TurnRight | TurnCommand::OffRoute | TurnCommand::Beeline => 1001,
+            TurnCommand::Continue => 1002,
+            TurnCommand::UTurn | TurnCommand::UTurnLeft | TurnCommand::UTurnRight => 1003,
+            TurnCommand::Roundabout(exit) | TurnCommand::RoundaboutLeft(exit) => {
+                1008 + *exit as i32
+            }
+            TurnCommand::KeepRight | TurnCommand::ExitRight => 1014,
+            TurnCommand::KeepLeft | TurnCommand::ExitLeft => 1015,
+            TurnCommand::TurnSlightRight => 1016,
+            TurnCommand::TurnSlightLeft => 1017,
+            TurnCommand::TurnSharpRight => 1018,
+            TurnCommand::TurnSharpLeft => 1019,
+        }
+    }
+
+    pub fn from_orux_action(action: i32) -> Option<Self> {
+        Some(match action {
+            1000 => TurnCommand::TurnLeft,
+            1001 => TurnCommand::TurnRight,
+            1002 => TurnCommand::Continue,
+            1003 => TurnCommand::UTurn,
+            1009..=1013 => TurnCommand::Roundabout((action - 1008) as u8),
+            1014 => TurnCommand::KeepRight,
+            1015 => TurnCommand::KeepLeft,
+            1016 => TurnCommand::TurnSlightRight,
+            1017 => TurnCommand::TurnSlightLeft,
+            1018 => TurnCommand::TurnSharpRight,
+            1019 => TurnCommand::TurnSharpLeft,
+            _ => return None,
+        })
+    }
+
+    /// Parse the human-readable message brouter attaches to voice hint waypoints, e.g.
+    /// "slight left" or "Take exit 2".
+    pub fn from_message(message: &str) -> Option<Self> {
+        let message = message.trim().to_lowercase();
+        if let Some(exit) = message
+            .strip_prefix("take exit ")
+            .and_then(|e| e.trim().parse::<i32>().ok())
+        {
+            return Some(if exit < 0 {
+                TurnCommand::RoundaboutLeft(exit.unsigned_abs() as u8)
+            } else {
+                TurnCommand::Roundabout(exit as u8)
+            });
+        }
+        Some(match message.as_str() {
+            "straight" | "continue" => TurnCommand::Continue,
+            "left" => TurnCommand::TurnLeft,
+            "slight left" => TurnCommand::TurnSlightLeft,
+            "sharp left" => TurnCommand::TurnSharpLeft,
+            "right" => TurnCommand::TurnRight,
+            "slight right" => TurnCommand::TurnSlightRight,
+            "sharp right" => TurnCommand::TurnSharpRight,
+            "keep left" => TurnCommand::KeepLeft,
+            "keep right" => TurnCommand::KeepRight,
+            "u-turn left" => TurnCommand::UTurnLeft,
+            "u-turn" => TurnCommand::UTurn,
+            "u-turn right" => TurnCommand::UTurnRight,
+            "offroad" | "off route" => TurnCommand::OffRoute,
+            "beeline" => TurnCommand::Beeline,
+            "exit left" => TurnCommand::ExitLeft,
+            "exit right" => TurnCommand::ExitRight,
+            _ => return None,
+        })
+    }
+}
+
+/// A single turn instruction along a route.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TurnInstruction {
+    /// Where the maneuver takes place.
+    pub point: Point,
+
+    pub command: TurnCommand,
+
+    /// Distance along the route at which the maneuver takes place, in meters.
+    pub distance_m: f64,
+
+    /// Name of the way to turn onto, if known.
+    pub way_name: Option<String>,
+
+    /// Turn angle in degrees, negative for left turns, if known.
+    pub angle: Option<f64>,
+}
+
+/// The mode of transport brouter assumes when generating voice hints, which affects e.g.
+/// whether turns onto footpaths are announced.
+///
+/// brouter derives this from the `validForCars`, `validForBikes` and `validForFoot` variables
+/// of the profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TransportMode {
+    Car,
+    Bike,
+    Foot,
+}
+
+/// Options for the turn instructions brouter generates, on top of their
+/// [`TurnInstructionMode`].
+///
+/// Options that are `None` are left to the profile, or to brouter's defaults.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TurnInstructionOptions {
+    pub mode: Option<TurnInstructionMode>,
+
+    /// Distance within which consecutive turns are combined into one instruction, in meters;
+    /// brouter's default is 40.
+    pub catching_range: Option<f64>,
+
+    /// Whether to give instructions for roundabouts rather than for each turn in them.
+    pub roundabouts: Option<bool>,
+
+    pub transport_mode: Option<TransportMode>,
+}
+
+impl TurnInstructionOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mode(mut self, mode: TurnInstructionMode) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    pub fn catching_range(mut self, meters: f64) -> Self {
+        assert!(meters >= 0.0);
+        self.catching_range = Some(meters);
+        self
+    }
+
+    pub fn roundabouts(mut self, roundabouts: bool) -> Self {
+        self.roundabouts = Some(roundabouts);
+        self
+    }
+
+    pub fn transport_mode(mut self, mode: TransportMode) -> Self {
+        self.transport_mode = Some(mode);
+        self
+    }
+
+    /// The profile variables that implement these options.
+    pub fn profile_params(&self) -> Vec<(String, String)> {
+        let flag = |b: bool| if b { "1" } else { "0" }.to_string();
+        let mut params = vec![];
+        if let Some(range) = self.catching_range {
+            params.push((
+                "turnInstructionCatchingRange".to_string(),
+                range.to_string(),
+            ));
+        }
+        if let Some(roundabouts) = self.roundabouts {
+            params.push(("turnInstructionRoundabouts".to_string(), flag(roundabouts)));
+        }
+        if let Some(mode) = self.transport_mode {
+            for (name, m) in [
+                ("validForCars", TransportMode::Car),
+                ("validForBikes", TransportMode::Bike),
+                ("validForFoot", TransportMode::Foot),
+            ] {
+                params.push((name.to_string(), flag(mode == m)));
+            }
+        }
+        params
+    }
+}
+
+/// A voice hint as found in brouter's GPX output, independent of the turn instruction style.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct VoiceHint {
+    pub point: Point,
+    pub command: TurnCommand,
+    pub angle: Option<f64>,
+}
+
+/// Parse the voice hints from a GPX document produced by brouter.
+///
+/// This understands the Locus (`rtePointAction`), OsmAnd (`<rte>` with `<turn>`), Orux and
+/// Gpsies styles. The `gpx` crate does not retain extensions, so this works on the raw XML.
+pub(crate) fn parse_voice_hints(data: &[u8]) -> Result<Vec<VoiceHint>, xml::reader::Error> {
+    let mut hints = vec![];
+    let mut point: Option<(Point, bool)> = None;
+    let mut fields: Vec<(String, String)> = vec![];
+    let mut current: Option<String> = None;
+
+    for event in EventReader::new(data) {
+        match event? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => match name.local_name.as_str() {
+                "wpt" | "rtept" => {
+                    let attr = |n: &str| {
+                        attributes
+                            .iter()
+                            .find(|a| a.name.local_name == n)
+                            .and_then(|a| a.value.parse::<f64>().ok())
+                    };
+                    if let (Some(lat), Some(lon)) = (attr("lat"), attr("lon")) {
+                        point = Some((Point::new(lat, lon), name.local_name == "rtept"));
+                        fields.clear();
+                    }
+                }
+                n if point.is_some() => current = Some(n.to_string()),
+                _ => {}
+            },
+            XmlEvent::Characters(text) => {
+                if let Some(current) = current.as_ref() {
+                    fields.push((current.clone(), text.trim().to_string()));
+                }
+            }
+            XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                "wpt" | "rtept" => {
+                    if let Some((point, is_rtept)) = point.take() {
+                        let field =
+                            |n: &str| fields.iter().find(|(k, _)| k == n).map(|(_, v)| v.as_str());
+                        let command = if is_rtept {
+                            field("turn").and_then(TurnCommand::from_command_string)
+                        } else if let Some(action) = field("rtePointAction") {
+                            action.parse().ok().and_then(TurnCommand::from_locus_action)
+                        } else if let Some(action) = field("ext") {
+                            action.parse().ok().and_then(TurnCommand::from_orux_action)
+                        } else {
+                            field("name").and_then(TurnCommand::from_message)
+                        };
+                        if let Some(command) = command {
+                            hints.push(VoiceHint {
+                                point,
+                                command,
+                                angle: field("turn-angle").and_then(|a| a.parse().ok()),
+                            });
+                        }
+                    }
+                }
+                _ => current = None,
+            },
+            _ => {}
+        }
+    }
+
+    Ok(hints)
+}
+
+fn format_point(tag: &str, point: &Point) -> String {
+    format!("<{} lat=\"{}\" lon=\"{}\">", tag, point.lat(), point.lon())
+}
+
+/// Render turn instructions as GPX elements in the given brouter style.
+///
+/// Comment style and automatic selection are rendered as plain Gpsies-style waypoints, which
+/// need no extensions.
+pub(crate) fn format_voice_hints(
+    instructions: &[TurnInstruction],
+    mode: TurnInstructionMode,
+) -> String {
+    let mut ret = String::new();
+    match mode {
+        TurnInstructionMode::None => {}
+        TurnInstructionMode::LocusStyle | TurnInstructionMode::LocusOldStyle => {
+            for (i, instruction) in instructions.iter().enumerate() {
+                let next = instructions
+                    .get(i + 1)
+                    .map_or(instruction.distance_m, |n| n.distance_m);
+                ret.push_str(&format_point("wpt", &instruction.point));
+                ret.push_str(&format!(
+                    "<name>{}</name><extensions xmlns:locus=\"http://www.locusmap.eu\"><locus:rteDistance>{}</locus:rteDistance><locus:rtePointAction>{}</locus:rtePointAction></extensions></wpt>",
+                    instruction.command.message(),
+                    (next - instruction.distance_m).round(),
+                    instruction.command.locus_action()
+                ));
+            }
+        }
+        TurnInstructionMode::OsmandStyle => {
+            ret.push_str("<rte>");
+            for instruction in instructions {
+                ret.push_str(&format_point("rtept", &instruction.point));
+                ret.push_str(&format!(
+                    "<desc>{}</desc><extensions><turn>{}</turn>",
+                    instruction.command.message(),
+                    instruction.command.command_string()
+                ));
+                if let Some(angle) = instruction.angle {
+                    ret.push_str(&format!("<turn-angle>{}</turn-angle>", angle));
+                }
+                ret.push_str("</extensions></rtept>");
+            }
+            ret.push_str("</rte>");
+        }
+        TurnInstructionMode::OruxStyle => {
+            for instruction in instructions {
+                ret.push_str(&format_point("wpt", &instruction.point));
+                ret.push_str(&format!(
+                    "<extensions><om:oruxmapsextensions xmlns:om=\"http://www.oruxmaps.com/oruxmapsextensions/1/0\"><om:ext type=\"ICON\" subtype=\"0\">{}</om:ext></om:oruxmapsextensions></extensions></wpt>",
+                    instruction.command.orux_action()
+                ));
+            }
+        }
+        TurnInstructionMode::AutoChoose
+        | TurnInstructionMode::CommentStyle
+        | TurnInstructionMode::GpsiesStyle => {
+            for instruction in instructions {
+                let symbol = instruction.command.command_string();
+                ret.push_str(&format_point("wpt", &instruction.point));
+                ret.push_str(&format!(
+                    "<name>{}</name><sym>{}</sym><type>{}</type></wpt>",
+                    instruction.command.message(),
+                    symbol.to_lowercase(),
+                    symbol
+                ));
+            }
+        }
+    }
+    ret
+}
@@ -1,12 +1,94 @@
-use lazy_regex::regex;
-use log::info;
-use reqwest::blocking::Client;
+//! A client for the [brouter](https://brouter.de/) routing engine.
+//!
+//! # Features
+//!
+//! Without any features, the crate is just the blocking HTTP client and the route, profile
+//! and GPX handling it needs; see the README for the optional features. APIs that need a
+//! feature don't exist without it, e.g. writing nogos as WKT requires `wkt`:
+//!
+#![cfg_attr(feature = "wkt", doc = "```")]
+#![cfg_attr(not(feature = "wkt"), doc = "```compile_fail")]
+//! use brouter_client::{Nogo, Point};
+//!
+//! let wkt = Nogo::circle(&Point::new(52.5, 13.4), 100.0).to_wkt();
+//! ```
+//!
+//! reading nogos from GeoJSON requires `geojson`:
+//!
+#![cfg_attr(feature = "geojson", doc = "```")]
+#![cfg_attr(not(feature = "geojson"), doc = "```compile_fail")]
+//! let nogos = brouter_client::Nogo::from_geojson(std::io::empty());
+//! ```
+//!
+//! and the asynchronous client requires `async`:
+//!
+#![cfg_attr(feature = "async", doc = "```")]
+#![cfg_attr(not(feature = "async"), doc = "```compile_fail")]
+//! let client = brouter_client::async_client::AsyncBrouter::new("http://localhost:17777");
+//! ```
+
+use lazy_regex::{regex, regex_captures, regex_is_match};
+use log::{debug, info};
+use reqwest::blocking::{Client, RequestBuilder, Response};
 use reqwest::Url;
-use std::io::BufReader;
+
+#[cfg(feature = "async")]
+pub mod async_client;
+pub mod brevet;
+pub mod directions;
+pub mod dirs;
+#[cfg(feature = "elevation")]
+pub mod elevation;
+pub mod energy;
+pub mod export;
+pub mod geocode;
+mod geohash;
+mod geojson;
+pub mod instructions;
+pub mod matching;
+pub mod messages;
+pub mod middleware;
+pub mod naming;
+#[cfg(feature = "overpass")]
+pub mod overpass;
+pub mod pipeline;
+mod polyline;
+pub mod preset;
+pub mod profile;
+pub mod request;
+pub mod response;
+pub mod route;
+pub mod segments;
+pub mod server;
+#[cfg(feature = "storage")]
+pub mod storage;
+pub mod sun;
+pub mod tour;
+mod transfer;
+#[cfg(feature = "wkt")]
+pub mod wkt;
+#[cfg(feature = "download")]
+mod zip;
+pub use instructions::{TransportMode, TurnCommand, TurnInstruction, TurnInstructionOptions};
+pub use messages::{CuePoint, SegmentMessage};
+pub use middleware::Middleware;
+pub use pipeline::RoutePipeline;
+pub use preset::RoutePreset;
+pub use request::{RouteBuilder, RouteRequest};
+pub use response::{RawResponse, ResponseHandling, ResponseInfo};
+pub use route::{Climb, Route, RouteSummary, RouteTotals};
 
 // See https://github.com/abrensch/brouter/blob/77977677db5fe78593c6a55afec6a251e69b3449/brouter-server/src/main/java/btools/server/request/ServerHandler.java#L17
 
-#[derive(Debug, Clone)]
+/// An area for routes to avoid.
+///
+/// Without a weight, a nogo blocks the area entirely. With a weight, routes may still pass
+/// through it, but the weight is added to the cost of the route, in the same units as the
+/// costs of the profile: with the standard profiles, a weight of 1000 is roughly a detour of
+/// a kilometer on a good road. Use [`Nogo::block`] and [`Nogo::penalize`] rather than setting
+/// the weight directly, since the latter checks that the weight is valid.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Nogo {
     Point {
         point: Point,
@@ -23,7 +105,195 @@ pub enum Nogo {
     },
 }
 
-#[derive(Debug, Clone)]
+impl Nogo {
+    /// A circle of `radius_m` meters around `center` that routes can't pass through.
+    pub fn circle(center: &Point, radius_m: f64) -> Nogo {
+        Nogo::Point {
+            point: center.clone(),
+            radius: radius_m,
+            weight: None,
+        }
+    }
+
+    /// A line that routes can't cross.
+    pub fn line(points: &[Point]) -> Nogo {
+        Nogo::Line {
+            points: points.to_vec(),
+            weight: None,
+        }
+    }
+
+    /// A polygon that routes can't pass through.
+    pub fn polygon(points: &[Point]) -> Nogo {
+        Nogo::Polygon {
+            points: points.to_vec(),
+            weight: None,
+        }
+    }
+
+    pub fn weight(&self) -> Option<f64> {
+        match self {
+            Nogo::Point { weight, .. }
+            | Nogo::Line { weight, .. }
+            | Nogo::Polygon { weight, .. } => *weight,
+        }
+    }
+
+    fn weight_mut(&mut self) -> &mut Option<f64> {
+        match self {
+            Nogo::Point { weight, .. }
+            | Nogo::Line { weight, .. }
+            | Nogo::Polygon { weight, .. } => weight,
+        }
+    }
+
+    /// Whether routes can't pass through the nogo at all.
+    pub fn is_blocking(&self) -> bool {
+        self.weight().is_none()
+    }
+
+    /// The same area, blocked entirely.
+    pub fn block(mut self) -> Nogo {
+        *self.weight_mut() = None;
+        self
+    }
+
+    /// The same area, passable at an extra cost of `weight`.
+    ///
+    /// The weight has to be positive and finite; a weight of zero would make the nogo
+    /// meaningless.
+    pub fn penalize(mut self, weight: f64) -> Result<Nogo, Error> {
+        check_nogo_weight(weight)?;
+        *self.weight_mut() = Some(weight);
+        Ok(self)
+    }
+
+    /// Check that the weight of the nogo, if any, is valid.
+    pub fn validate(&self) -> Result<(), Error> {
+        match self.weight() {
+            Some(weight) => check_nogo_weight(weight),
+            None => Ok(()),
+        }
+    }
+
+    /// A GeoJSON FeatureCollection of `nogos`, for rendering them on a map.
+    ///
+    /// Point nogos are approximated by polygons, with their center and radius as properties.
+    /// All features have a `nogo` property with the kind of nogo and a `weight` property,
+    /// which is null for impassable nogos.
+    pub fn to_geojson(nogos: &[Nogo]) -> String {
+        geojson::feature_collection(nogos.iter().map(geojson::nogo_feature))
+    }
+
+    /// A polygon nogo that covers everything outside `area`.
+    ///
+    /// Brouter polygons can't have holes, so the nogo is the bounding box of `area` grown by
+    /// `margin_m` meters, with `area` cut out through a zero-width slit. Holes in `area` itself
+    /// are not part of the nogo.
+    ///
+    /// Combined with [`Route::buffer`], this keeps a new route within a corridor around an
+    /// existing one.
+    ///
+    /// Returns `None` if `area` is degenerate.
+    pub fn outside(
+        area: &geo_types::Polygon<f64>,
+        margin_m: f64,
+        weight: Option<f64>,
+    ) -> Option<Nogo> {
+        use geo::{BoundingRect, Winding};
+
+        let mut ring = area.exterior().clone();
+        ring.close();
+        if ring.0.len() < 4 {
+            return None;
+        }
+        let bounds = ring.bounding_rect()?;
+
+        // Traverse the cut-out clockwise, starting from its westernmost vertex: the slit runs
+        // due west from there to the edge of the box, so it never crosses the area.
+        if ring.is_ccw() {
+            ring.0.reverse();
+        }
+        let mut coords = ring.0[..ring.0.len() - 1].to_vec();
+        let start = coords
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.x.total_cmp(&b.x))
+            .map(|(i, _)| i)?;
+        coords.rotate_left(start);
+
+        let margin_lat = margin_m / geo::Haversine.radius().to_radians();
+        let margin_lon = margin_lat / bounds.center().y.to_radians().cos().max(0.01);
+        let (min_x, max_x) = (bounds.min().x - margin_lon, bounds.max().x + margin_lon);
+        let (min_y, max_y) = (bounds.min().y - margin_lat, bounds.max().y + margin_lat);
+        let slit_y = coords[0].y;
+
+        let mut points = vec![
+            Point::new(slit_y, min_x),
+            Point::new(min_y, min_x),
+            Point::new(min_y, max_x),
+            Point::new(max_y, max_x),
+            Point::new(max_y, min_x),
+            Point::new(slit_y, min_x),
+        ];
+        points.extend(coords.iter().map(|c| Point::new(c.y, c.x)));
+        points.push(Point::new(coords[0].y, coords[0].x));
+
+        Some(Nogo::Polygon { points, weight })
+    }
+
+    /// Read nogos from a GeoJSON document, e.g. a file of areas to avoid drawn in a map editor.
+    ///
+    /// The document may be a FeatureCollection, a single Feature or a bare geometry.
+    /// Polygons, line strings and their multi variants become polygon and line nogos; points
+    /// become circles and need a `radius` property in meters. A `weight` property makes a nogo
+    /// passable at that cost; without it, nogos are impassable. Polygons with holes are not
+    /// supported, since brouter can't represent them.
+    ///
+    /// Features written by [`Nogo::to_geojson`] are read back as the nogos they came from.
+    #[cfg(feature = "geojson")]
+    pub fn from_geojson(reader: impl std::io::Read) -> Result<Vec<Nogo>, Error> {
+        geojson::parse_nogos(reader)
+    }
+}
+
+/// An impassable polygon nogo along the exterior of `polygon`.
+///
+/// Brouter polygons can't have holes, so polygons with holes are rejected rather than
+/// covering the holes as well.
+impl TryFrom<geo_types::Polygon<f64>> for Nogo {
+    type Error = Error;
+
+    fn try_from(polygon: geo_types::Polygon<f64>) -> Result<Self, Error> {
+        let (exterior, interiors) = polygon.into_inner();
+        if !interiors.is_empty() {
+            return Err(Error::InvalidRequest(
+                "polygons with holes are not supported".to_string(),
+            ));
+        }
+        Ok(Nogo::Polygon {
+            points: exterior
+                .into_points()
+                .into_iter()
+                .map(Point::from)
+                .collect(),
+            weight: None,
+        })
+    }
+}
+
+/// An impassable line nogo; use [`Nogo::penalize`] to make it passable.
+impl From<geo_types::LineString<f64>> for Nogo {
+    fn from(line: geo_types::LineString<f64>) -> Self {
+        Nogo::Line {
+            points: line.into_points().into_iter().map(Point::from).collect(),
+            weight: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Point {
     lat: f64,
     lon: f64,
@@ -46,11 +316,67 @@ impl From<Point> for geo_types::Point<f64> {
 
 #[derive(Debug)]
 pub enum Error {
-    InvalidGpx(String),
+    /// A response could not be parsed as GPX.
+    InvalidGpx {
+        /// The error reported by the parser.
+        message: String,
+        /// The content type of the response, if known.
+        content_type: Option<String>,
+        /// The start of the response.
+        head: String,
+    },
+    InvalidMessages(String),
+    InvalidPolyline(String),
+    InvalidGeohash(String),
+    InvalidGeoJson(String),
+    InvalidCoordinates(String),
+    InvalidRequest(String),
+    /// A server URL could not be parsed.
+    InvalidUrl(String),
+    /// The server could not be reached, e.g. because it isn't running.
+    Connect(reqwest::Error),
+    /// The host name of the server could not be resolved.
+    Dns(reqwest::Error),
+    /// The TLS handshake failed, e.g. because of an invalid certificate.
+    Tls(reqwest::Error),
+    /// The request timed out.
+    Timeout(reqwest::Error),
+    /// The server answered with an error status and a body that isn't one of brouter's
+    /// error messages, e.g. an error page of a proxy; holds the start of the body.
+    HttpStatus {
+        status: reqwest::StatusCode,
+        body: String,
+    },
+    /// Any other HTTP error.
     Http(reqwest::Error),
+    Io(std::io::Error),
     MissingDataFile(String),
     NoRouteFound(isize),
-    PassTimeout { pass: String, timeout: String },
+    /// A point is too far from the road network; holds the name brouter uses for the point,
+    /// e.g. "from" or "via1".
+    PositionNotMapped(String),
+    /// The client-side deadline of a request expired before brouter returned a route.
+    Deadline(std::time::Duration),
+    PassTimeout {
+        pass: u8,
+        /// The server's time limit, in seconds.
+        timeout: u64,
+    },
+    /// A profile is larger than the upload limit set with
+    /// [`BrouterBuilder::max_profile_size`].
+    ProfileTooLarge {
+        size: u64,
+        limit: u64,
+    },
+    /// The server rejected a profile.
+    InvalidProfile {
+        line: Option<usize>,
+        message: String,
+    },
+    /// A step of the [`RoutePipeline`] failed.
+    PostProcessing(Box<dyn std::error::Error + Send + Sync>),
+    /// Setting up or running a local server failed.
+    Server(server::Error),
     Other(String),
 }
 
@@ -59,234 +385,459 @@ impl std::error::Error for Error {}
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Error::InvalidGpx(s) => write!(f, "Invalid GPX: {}", s),
+            Error::InvalidGpx {
+                message,
+                content_type,
+                head,
+            } => {
+                write!(f, "Invalid GPX: {}", message)?;
+                if let Some(content_type) = content_type {
+                    write!(f, " (content type {})", content_type)?;
+                }
+                write!(f, "; response starts with {:?}", head)
+            }
+            Error::InvalidMessages(s) => write!(f, "Invalid messages: {}", s),
+            Error::InvalidPolyline(s) => write!(f, "Invalid polyline: {}", s),
+            Error::InvalidGeohash(s) => write!(f, "Invalid geohash: {}", s),
+            Error::InvalidGeoJson(s) => write!(f, "Invalid GeoJSON: {}", s),
+            Error::InvalidCoordinates(s) => write!(f, "Invalid coordinates: {}", s),
+            Error::InvalidRequest(s) => write!(f, "Invalid request: {}", s),
+            Error::InvalidUrl(s) => write!(f, "Invalid URL: {}", s),
             Error::Other(e) => write!(f, "Error: {}", e),
+            Error::Connect(e) => write!(f, "Unable to connect to server: {}", e),
+            Error::Dns(e) => write!(f, "Unable to resolve server host name: {}", e),
+            Error::Tls(e) => write!(f, "TLS error (certificate problem?): {}", e),
+            Error::Timeout(e) => write!(f, "Request timed out: {}", e),
+            Error::HttpStatus { status, body } => write!(f, "HTTP error: {}: {}", status, body),
             Error::Http(e) => write!(f, "HTTP error: {}", e),
             Error::MissingDataFile(s) => write!(f, "Missing data file: {}", s),
             Error::PassTimeout { pass, timeout } => {
                 write!(f, "Pass {} timeout after {} seconds", pass, timeout)
             }
             Error::NoRouteFound(i) => write!(f, "No route found: {}", i),
+            Error::PositionNotMapped(p) => {
+                write!(f, "Position {} not mapped: too far from a road?", p)
+            }
+            Error::Deadline(d) => write!(f, "No route within deadline of {:?}", d),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::PostProcessing(e) => write!(f, "Post-processing failed: {}", e),
+            Error::Server(e) => write!(f, "Local server: {}", e),
+            Error::ProfileTooLarge { size, limit } => write!(
+                f,
+                "Profile of {} bytes exceeds the upload limit of {} bytes",
+                size, limit
+            ),
+            Error::InvalidProfile {
+                line: Some(line),
+                message,
+            } => write!(f, "Invalid profile (line {}): {}", line, message),
+            Error::InvalidProfile {
+                line: None,
+                message,
+            } => write!(f, "Invalid profile: {}", message),
         }
     }
 }
 
-impl Point {
-    pub fn new(lat: f64, lon: f64) -> Self {
-        Point { lat, lon }
+impl Error {
+    /// Whether the error may go away if the request is sent again, e.g. because the server
+    /// is still starting or briefly overloaded.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Connect(_) | Error::Timeout(_) => true,
+            Error::HttpStatus { status, .. } => status.is_server_error(),
+            _ => false,
+        }
     }
 
-    pub fn lat(&self) -> f64 {
-        self.lat
+    /// Whether the server couldn't be connected to, as opposed to failing to answer.
+    fn is_unreachable(&self) -> bool {
+        match self {
+            Error::Connect(_) | Error::Dns(_) | Error::Tls(_) => true,
+            // A timeout after connecting means the server is busy with the request.
+            Error::Timeout(e) => e.is_connect(),
+            _ => false,
+        }
     }
+}
 
-    pub fn lon(&self) -> f64 {
-        self.lon
+/// Check that `alternative` is one of the alternative routes brouter offers, 0 to 3.
+pub(crate) fn check_alternative(alternative: u8) -> Result<(), Error> {
+    if alternative <= 3 {
+        Ok(())
+    } else {
+        Err(Error::InvalidRequest(format!(
+            "invalid alternative {}; brouter offers alternatives 0 to 3",
+            alternative
+        )))
     }
 }
 
-pub struct Brouter {
-    client: Client,
-    base_url: Url,
+fn check_nogo_weight(weight: f64) -> Result<(), Error> {
+    if weight.is_finite() && weight > 0.0 {
+        Ok(())
+    } else {
+        Err(Error::InvalidRequest(format!(
+            "invalid nogo weight {}; weights must be positive",
+            weight
+        )))
+    }
 }
 
-impl Default for Brouter {
-    fn default() -> Self {
-        Self::new("http://localhost:17777")
+/// Check that `id` is a profile id rather than e.g. a path.
+pub(crate) fn check_profile_id(id: &str) -> Result<(), Error> {
+    if regex_is_match!(r"^[A-Za-z0-9_\-]+$", id) {
+        Ok(())
+    } else {
+        Err(Error::InvalidRequest(format!(
+            "invalid profile id '{}'",
+            id
+        )))
     }
 }
 
-#[derive(Debug, Clone, Copy, Default)]
-pub enum TurnInstructionMode {
-    #[default]
-    None = 0,
-    AutoChoose = 1,
-    LocusStyle = 2,
-    OsmandStyle = 3,
-    CommentStyle = 4,
-    GpsiesStyle = 5,
-    OruxStyle = 6,
-    LocusOldStyle = 7,
+/// If `url` is longer than `max_length`, split it into the URL without its query and the
+/// query, to send as the body of a POST request.
+fn split_long_url(url: &Url, max_length: usize) -> Option<(Url, String)> {
+    if url.as_str().len() <= max_length {
+        return None;
+    }
+    let body = url.query()?.to_string();
+    let mut url = url.clone();
+    url.set_query(None);
+    Some((url, body))
 }
 
-impl Brouter {
-    pub fn new(base_url: &str) -> Self {
-        Brouter {
-            client: Client::new(),
-            base_url: Url::parse(base_url).unwrap(),
-        }
-    }
+/// The content type of the bodies of routing requests sent as POST requests.
+pub(crate) const FORM_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
 
-    pub fn upload_profile(&self, profile: &str, data: Vec<u8>) -> Result<(), Error> {
-        let url = self
-            .base_url
-            .join("brouter/profile")
-            .unwrap()
-            .join(profile)
-            .unwrap();
+/// The method, URL and form body, if any, to send a routing request for `url` with: a GET
+/// request, or a POST request if the URL is longer than `max_length`.
+pub(crate) fn routing_request(
+    url: &Url,
+    max_length: usize,
+) -> (reqwest::Method, Url, Option<String>) {
+    match split_long_url(url, max_length) {
+        Some((url, body)) => (reqwest::Method::POST, url, Some(body)),
+        None => (reqwest::Method::GET, url.clone(), None),
+    }
+}
 
-        let response = self
-            .client
-            .post(url)
-            .body(data)
-            .send()
-            .map_err(Error::Http)?;
+/// How long to wait before retrying a request that failed with `e` after `retry` retries,
+/// or `None` if it shouldn't be retried: because `e` isn't transient, the retries of `policy`
+/// are used up or the wait would run past `deadline`, counted from `start`.
+pub(crate) fn retry_backoff(
+    policy: Option<RetryPolicy>,
+    e: &Error,
+    retry: u32,
+    start: std::time::Instant,
+    deadline: Option<std::time::Duration>,
+) -> Option<std::time::Duration> {
+    if !e.is_transient() {
+        return None;
+    }
+    policy?
+        .backoff(retry)
+        .filter(|backoff| deadline.is_none_or(|d| start.elapsed() + *backoff < d))
+}
 
-        response.error_for_status().map_err(Error::Http).map(|_| ())
+/// The names of the profiles in `dir`, sorted.
+fn list_profile_files(dir: &std::path::Path) -> Result<Vec<String>, Error> {
+    let mut names = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "brf") {
+            if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+                names.push(name.to_string());
+            }
+        }
     }
+    names.sort();
+    Ok(names)
+}
 
-    pub fn broute(
-        &self,
-        points: &[Point],
-        nogos: &[Nogo],
-        profile: &str,
-        alternativeidx: Option<u8>,
-        timode: Option<TurnInstructionMode>,
-        name: Option<&str>,
-        export_waypoints: bool,
-    ) -> Result<gpx::Gpx, Error> {
-        let lon_lat_strings: Vec<String> = points
-            .iter()
-            .map(|p| format!("{},{}", p.lon(), p.lat()))
-            .collect();
+/// Lock `path` against other processes writing it, waiting until they are done, by taking an
+/// exclusive lock on `.{name}.lock` next to it. The lock is released when the returned file is
+/// dropped.
+pub(crate) fn lock_for(path: &std::path::Path) -> Result<std::fs::File, std::io::Error> {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default();
+    let lock_path = path.with_file_name(format!(".{}.lock", name));
+    if let Some(parent) = lock_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = std::fs::File::options()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(lock_path)?;
+    file.lock()?;
+    Ok(file)
+}
 
-        info!("Planning route along {:?}", points);
+/// The URL of a routing request to the server at `base_url`.
+pub(crate) fn route_url(
+    base_url: &Url,
+    points: &[Point],
+    names: &[Option<String>],
+    nogos: &[Nogo],
+    profile: &str,
+    alternativeidx: Option<u8>,
+    format: &str,
+) -> Url {
+    let lon_lat_strings: Vec<String> = points
+        .iter()
+        .enumerate()
+        .map(|(i, p)| match names.get(i).and_then(|n| n.as_deref()) {
+            // The separators of the lonlats parameter can't appear in names.
+            Some(name) => format!("{},{},{}", p.lon(), p.lat(), name.replace([',', '|'], " ")),
+            None => format!("{},{}", p.lon(), p.lat()),
+        })
+        .collect();
 
-        let lonlats = lon_lat_strings.join("|");
+    let lonlats = lon_lat_strings.join("|");
 
-        let nogos_string: String = nogos
-            .iter()
-            .filter_map(|nogo| match nogo {
-                Nogo::Point {
-                    point,
-                    radius,
-                    weight,
-                } => {
-                    let mut v = vec![point.lon(), point.lat(), *radius];
-                    if let Some(weight) = weight {
-                        v.push(*weight);
-                    }
-                    Some(
-                        v.iter()
-                            .map(|f| f.to_string())
-                            .collect::<Vec<_>>()
-                            .join(","),
-                    )
+    let nogos_string: String = nogos
+        .iter()
+        .filter_map(|nogo| match nogo {
+            Nogo::Point {
+                point,
+                radius,
+                weight,
+            } => {
+                let mut v = vec![point.lon(), point.lat(), *radius];
+                if let Some(weight) = weight {
+                    v.push(*weight);
                 }
-                Nogo::Polygon { .. } => None,
-                Nogo::Line { .. } => None,
-            })
-            .collect::<Vec<_>>()
-            .join("|");
+                Some(
+                    v.iter()
+                        .map(|f| f.to_string())
+                        .collect::<Vec<_>>()
+                        .join(","),
+                )
+            }
+            Nogo::Polygon { .. } => None,
+            Nogo::Line { .. } => None,
+        })
+        .collect::<Vec<_>>()
+        .join("|");
 
-        let polylines = nogos
-            .iter()
-            .filter_map(|nogo| match nogo {
-                Nogo::Point { .. } => None,
-                Nogo::Polygon { .. } => None,
-                Nogo::Line { points, weight } => {
-                    let mut v = points
-                        .iter()
-                        .flat_map(|p| vec![p.lon(), p.lat()])
-                        .collect::<Vec<_>>();
-                    if let Some(weight) = weight {
-                        v.push(*weight);
-                    }
-                    Some(
-                        v.iter()
-                            .map(|f| f.to_string())
-                            .collect::<Vec<_>>()
-                            .join(","),
-                    )
+    let polylines = nogos
+        .iter()
+        .filter_map(|nogo| match nogo {
+            Nogo::Point { .. } => None,
+            Nogo::Polygon { .. } => None,
+            Nogo::Line { points, weight } => {
+                let mut v = points
+                    .iter()
+                    .flat_map(|p| vec![p.lon(), p.lat()])
+                    .collect::<Vec<_>>();
+                if let Some(weight) = weight {
+                    v.push(*weight);
                 }
-            })
-            .collect::<Vec<_>>()
-            .join("|");
+                Some(
+                    v.iter()
+                        .map(|f| f.to_string())
+                        .collect::<Vec<_>>()
+                        .join(","),
+                )
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("|");
 
-        let polygons = nogos
-            .iter()
-            .filter_map(|nogo| match nogo {
-                Nogo::Point { .. } => None,
-                Nogo::Line { .. } => None,
-                Nogo::Polygon { points, weight } => {
-                    let mut v = points
-                        .iter()
-                        .flat_map(|p| vec![p.lon(), p.lat()])
-                        .collect::<Vec<_>>();
-                    if let Some(weight) = weight {
-                        v.push(*weight);
-                    }
-                    Some(
-                        v.iter()
-                            .map(|f| f.to_string())
-                            .collect::<Vec<_>>()
-                            .join(","),
-                    )
+    let polygons = nogos
+        .iter()
+        .filter_map(|nogo| match nogo {
+            Nogo::Point { .. } => None,
+            Nogo::Line { .. } => None,
+            Nogo::Polygon { points, weight } => {
+                let mut v = points
+                    .iter()
+                    .flat_map(|p| vec![p.lon(), p.lat()])
+                    .collect::<Vec<_>>();
+                if let Some(weight) = weight {
+                    v.push(*weight);
                 }
-            })
-            .collect::<Vec<_>>()
-            .join("|");
+                Some(
+                    v.iter()
+                        .map(|f| f.to_string())
+                        .collect::<Vec<_>>()
+                        .join(","),
+                )
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("|");
+
+    let mut url = base_url.join("brouter").unwrap();
 
-        let mut url = self.base_url.join("brouter").unwrap();
+    url.query_pairs_mut()
+        .append_pair("lonlats", &lonlats)
+        .append_pair("profile", profile)
+        .append_pair("format", format);
 
+    if let Some(alternativeidx) = alternativeidx {
         url.query_pairs_mut()
-            .append_pair("lonlats", &lonlats)
-            .append_pair("profile", profile)
-            .append_pair("format", "gpx");
+            .append_pair("alternativeidx", alternativeidx.to_string().as_str());
+    }
 
-        if let Some(alternativeidx) = alternativeidx {
-            assert!((0..=3).contains(&alternativeidx));
+    if !polygons.is_empty() {
+        url.query_pairs_mut().append_pair("polygons", &polygons);
+    }
 
-            url.query_pairs_mut()
-                .append_pair("alternativeidx", alternativeidx.to_string().as_str());
-        }
+    if !nogos_string.is_empty() {
+        url.query_pairs_mut().append_pair("nogos", &nogos_string);
+    }
 
-        if let Some(timode) = timode {
-            url.query_pairs_mut()
-                .append_pair("timode", (timode as i32).to_string().as_str());
-        }
+    if !polylines.is_empty() {
+        url.query_pairs_mut().append_pair("polylines", &polylines);
+    }
 
-        if !polygons.is_empty() {
-            url.query_pairs_mut().append_pair("polygons", &polygons);
-        }
+    url
+}
 
-        if !nogos_string.is_empty() {
-            url.query_pairs_mut().append_pair("nogos", &nogos_string);
-        }
+/// Add the parameters of `request` that only apply to GPX output to `url`.
+pub(crate) fn add_gpx_params(url: &mut Url, request: &RouteRequest) {
+    if let Some(timode) = request.turn_instructions {
+        url.query_pairs_mut()
+            .append_pair("timode", (timode as i32).to_string().as_str());
+    }
 
-        if !polylines.is_empty() {
-            url.query_pairs_mut().append_pair("polylines", &polylines);
-        }
+    if request.export_waypoints {
+        url.query_pairs_mut().append_pair("exportWaypoints", "1");
+    }
 
-        if export_waypoints {
-            url.query_pairs_mut().append_pair("exportWaypoints", "1");
-        }
+    add_straight_param(url, request);
+
+    if let Some(name) = request.track_name.as_deref() {
+        url.query_pairs_mut().append_pair("trackname", name);
+    }
+
+    for (name, value) in &request.profile_params {
+        url.query_pairs_mut()
+            .append_pair(&format!("profile:{}", name), value);
+    }
+    for (key, value) in &request.extra_params {
+        url.query_pairs_mut().append_pair(key, value);
+    }
+}
 
-        if let Some(name) = name {
-            url.query_pairs_mut().append_pair("trackname", name);
+/// Add the `straight` parameter for the straight segments of `request`, if any, to `url`.
+pub(crate) fn add_straight_param(url: &mut Url, request: &RouteRequest) {
+    if !request.straight_segments.is_empty() {
+        let indices = request
+            .straight_segments
+            .iter()
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>();
+        url.query_pairs_mut()
+            .append_pair("straight", &indices.join(","));
+    }
+}
+
+/// The id of an uploaded profile, from the response to the upload.
+pub(crate) fn parse_profile_upload(text: &str) -> Result<String, Error> {
+    if let Some((_, id)) = regex_captures!(r#""profileid"\s*:\s*"([^"]+)""#, text) {
+        return Ok(id.to_string());
+    }
+    match regex_captures!(r#""error"\s*:\s*"([^"]*)""#, text) {
+        Some((_, error)) => Err(Error::InvalidProfile {
+            line: regex_captures!(r"line (\d+)", error).and_then(|(_, l)| l.parse().ok()),
+            message: error.to_string(),
+        }),
+        None => Err(Error::Other(format!("unexpected response: {}", text))),
+    }
+}
+
+/// Name the waypoints brouter exported for `request` after its points.
+pub(crate) fn name_exported_waypoints(route: &mut Route, request: &RouteRequest) {
+    if request.export_waypoints {
+        // Not all brouter versions use the names passed in the lonlats parameter.
+        let named = request
+            .points
+            .iter()
+            .zip(&request.point_names)
+            .filter_map(|(point, name)| Some((point.clone(), name.clone()?)))
+            .collect::<Vec<_>>();
+        route.name_waypoints(&named);
+    }
+}
+
+/// Map timeouts to [`Error::Deadline`] for requests with a deadline.
+pub(crate) fn map_deadline_err(e: reqwest::Error, deadline: Option<std::time::Duration>) -> Error {
+    match deadline {
+        Some(deadline) if e.is_timeout() => Error::Deadline(deadline),
+        _ => Error::from(e),
+    }
+}
+
+/// Whether `e` was caused by reusing a pooled connection that the server has since closed, e.g.
+/// because it was restarted.
+fn is_stale_connection(e: &reqwest::Error) -> bool {
+    if !e.is_request() || e.is_timeout() {
+        return false;
+    }
+    let mut source = std::error::Error::source(e);
+    while let Some(cause) = source {
+        let message = cause.to_string().to_lowercase();
+        if [
+            "connection closed",
+            "connection reset",
+            "broken pipe",
+            "incomplete message",
+        ]
+        .iter()
+        .any(|m| message.contains(m))
+        {
+            return true;
         }
+        source = cause.source();
+    }
+    false
+}
 
-        let response = self
-            .client
-            .get(url)
-            .timeout(std::time::Duration::from_secs(3600))
-            .send()
-            .map_err(Error::Http)?
-            .error_for_status()
-            .map_err(Error::Http)?;
+/// How far from the points of a request [`Brouter::alternative_avoiding`] leaves the previous
+/// route out of the nogos, in meters.
+const AVOID_WAYPOINT_CLEARANCE_M: f64 = 200.0;
 
-        let status = response.status();
+/// Tolerance for simplifying routes that are turned into nogos, in degrees (about 5 m).
+const AVOID_SIMPLIFY_EPSILON: f64 = 0.00005;
+
+/// brouter's default for how far points may be from the road network, in meters.
+const DEFAULT_WAYPOINT_CATCHING_RANGE_M: f64 = 250.0;
+
+/// How much of a response body is scanned for error messages.
+pub(crate) const ERROR_SNIFF_LIMIT: usize = 4096;
 
-        let text = response.bytes().map_err(Error::Http)?.to_vec();
+impl Error {
+    /// Recognize the plain text error messages brouter returns instead of a route.
+    ///
+    /// Only the start of `body` is scanned, and bodies that look like GPX, GeoJSON or other
+    /// structured output are skipped, so this is cheap even for large routes.
+    pub fn from_response_body(body: &[u8]) -> Option<Error> {
+        let head = &body[..body.len().min(ERROR_SNIFF_LIMIT)];
+        let first = head.iter().find(|b| !b.is_ascii_whitespace());
+        if matches!(first, Some(b'<') | Some(b'{') | Some(b'[')) {
+            return None;
+        }
+
+        if let Some(m) = regex!("datafile (.*) not found\n"B).captures(head) {
+            return Some(Error::MissingDataFile(
+                String::from_utf8_lossy(m.get(1).unwrap().as_bytes()).to_string(),
+            ));
+        }
 
-        if let Some(m) = regex!("datafile (.*) not found\n"B).captures(text.as_slice()) {
-            return Err(Error::MissingDataFile(
+        if let Some(m) = regex!("([A-Za-z0-9]+)-position not mapped"B).captures(head) {
+            return Some(Error::PositionNotMapped(
                 String::from_utf8_lossy(m.get(1).unwrap().as_bytes()).to_string(),
             ));
         }
 
-        if let Some(m) = regex!("no track found at pass=([0-9]+)\n"B).captures(text.as_slice()) {
-            return Err(Error::NoRouteFound(
+        if let Some(m) = regex!("no track found at pass=([0-9]+)\n"B).captures(head) {
+            return Some(Error::NoRouteFound(
                 String::from_utf8_lossy(m.get(1).unwrap().as_bytes())
                     .to_string()
                     .parse()
@@ -294,29 +845,1483 @@ impl Brouter {
             ));
         }
 
-        if let Some(m) =
-            regex!("pass([0-9]) timeout after ([0-9]+) seconds\n"B).captures(text.as_slice())
-        {
+        if let Some(m) = regex!("pass([0-9]) timeout after ([0-9]+) seconds\n"B).captures(head) {
             let pass = String::from_utf8_lossy(m.get(1).unwrap().as_bytes())
-                .to_string()
                 .parse()
-                .unwrap();
+                .ok()?;
 
             let timeout = String::from_utf8_lossy(m.get(2).unwrap().as_bytes())
-                .to_string()
                 .parse()
-                .unwrap();
-            return Err(Error::PassTimeout { pass, timeout });
+                .ok()?;
+            return Some(Error::PassTimeout { pass, timeout });
+        }
+
+        None
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<server::Error> for Error {
+    fn from(e: server::Error) -> Self {
+        Error::Server(e)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            return Error::Timeout(e);
+        }
+        if !e.is_connect() {
+            return Error::Http(e);
         }
 
-        if status == reqwest::StatusCode::BAD_REQUEST {
-            return Err(Error::Other(format!("HTTP error: {}", status)));
+        // reqwest doesn't expose why connecting failed, other than in the underlying errors.
+        let mut causes = vec![];
+        let mut source = std::error::Error::source(&e);
+        while let Some(cause) = source {
+            causes.push(cause.to_string().to_lowercase());
+            source = cause.source();
+        }
+        if causes.iter().any(|c| c.contains("dns error")) {
+            Error::Dns(e)
+        } else if causes
+            .iter()
+            .any(|c| c.contains("certificate") || c.contains("tls") || c.contains("ssl"))
+        {
+            Error::Tls(e)
+        } else {
+            Error::Connect(e)
         }
+    }
+}
 
-        let gpx: gpx::Gpx = gpx::read(BufReader::new(text.as_slice())).map_err(|_e| {
-            Error::InvalidGpx(String::from_utf8_lossy(text.as_slice()).to_string())
-        })?;
+impl Point {
+    pub fn new(lat: f64, lon: f64) -> Self {
+        Point { lat, lon }
+    }
+
+    /// Same as [`Point::new`], but explicit about the argument order.
+    pub fn from_lat_lon(lat: f64, lon: f64) -> Self {
+        Point::new(lat, lon)
+    }
+
+    /// Create a point from longitude and latitude, the order used by GeoJSON, WKT and
+    /// `geo_types`.
+    pub fn from_lon_lat(lon: f64, lat: f64) -> Self {
+        Point::new(lat, lon)
+    }
+
+    /// Create a point, checking that the coordinates are in range.
+    ///
+    /// A latitude beyond ±90° usually means latitude and longitude were swapped; the error
+    /// says so if swapping them would give a valid point.
+    pub fn try_new(lat: f64, lon: f64) -> Result<Self, Error> {
+        if !lat.is_finite() || !lon.is_finite() {
+            return Err(Error::InvalidCoordinates(format!(
+                "{}, {} is not a finite point",
+                lat, lon
+            )));
+        }
+        if lat.abs() > 90.0 {
+            let hint = if lon.abs() <= 90.0 {
+                "; are latitude and longitude swapped?"
+            } else {
+                ""
+            };
+            return Err(Error::InvalidCoordinates(format!(
+                "latitude {} is out of range{}",
+                lat, hint
+            )));
+        }
+        if lon.abs() > 180.0 {
+            return Err(Error::InvalidCoordinates(format!(
+                "longitude {} is out of range",
+                lon
+            )));
+        }
+        Ok(Point::new(lat, lon))
+    }
+
+    pub fn lat(&self) -> f64 {
+        self.lat
+    }
+
+    pub fn lon(&self) -> f64 {
+        self.lon
+    }
+}
+
+/// A client for a brouter server.
+///
+/// Clones share the HTTP client, configuration and failover state, so they are cheap and a
+/// single client can be shared between threads, e.g. the handlers of a web server.
+#[derive(Clone)]
+pub struct Brouter {
+    inner: std::sync::Arc<Shared>,
+}
+
+struct Shared {
+    client: Client,
+    base_url: Url,
+    fallback_urls: Vec<Url>,
+    recheck_primary_after: std::time::Duration,
+    timeout: std::time::Duration,
+    max_url_length: usize,
+    max_profile_size: Option<u64>,
+    custom_profiles_dir: Option<std::path::PathBuf>,
+    profiles_dir: Option<std::path::PathBuf>,
+    segments_dir: Option<std::path::PathBuf>,
+    segment_manager: Option<segments::SegmentManager>,
+    response_handling: ResponseHandling,
+    pipeline: RoutePipeline,
+    retry: Option<RetryPolicy>,
+    /// The server requests currently go to, as an index into the base URL and fallbacks, and
+    /// when requests started going there.
+    active: std::sync::Mutex<(usize, std::time::Instant)>,
+    log_requests: Option<LogCoordinates>,
+    middleware: Vec<Box<dyn Middleware>>,
+}
+
+/// How long requests stay with a fallback server before the primary server is tried again.
+const DEFAULT_RECHECK_PRIMARY_AFTER: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// Length of the longest URL sent as a GET request: no limit, since brouter's own server only
+/// reads the parameters of routing requests from the query.
+pub(crate) const DEFAULT_MAX_URL_LENGTH: usize = usize::MAX;
+
+/// Timeout for requests when the server's running time limit is unknown.
+pub(crate) const DEFAULT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3600);
+
+/// How much longer than the server's running time limit the client waits, to allow for the
+/// server to report the timeout.
+const SERVER_TIMEOUT_MARGIN: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How to retry requests that failed with a transient error, see [`Error::is_transient`].
+///
+/// The delay before each retry doubles, from `initial_backoff` up to `max_backoff`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt.
+    pub max_retries: u32,
+
+    pub initial_backoff: std::time::Duration,
+
+    pub max_backoff: std::time::Duration,
+}
+
+impl RetryPolicy {
+    /// Retry up to `max_retries` times, starting with a delay of half a second.
+    pub fn new(max_retries: u32) -> Self {
+        RetryPolicy {
+            max_retries,
+            initial_backoff: std::time::Duration::from_millis(500),
+            max_backoff: std::time::Duration::from_secs(10),
+        }
+    }
+
+    pub fn initial_backoff(mut self, backoff: std::time::Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    pub fn max_backoff(mut self, backoff: std::time::Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// The delay before retry number `retry`, counting from 0, or `None` if there are no
+    /// retries left.
+    pub fn backoff(&self, retry: u32) -> Option<std::time::Duration> {
+        if retry >= self.max_retries {
+            return None;
+        }
+        Some(
+            self.initial_backoff
+                .saturating_mul(2u32.saturating_pow(retry))
+                .min(self.max_backoff),
+        )
+    }
+}
+
+/// How coordinates appear in logged requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogCoordinates {
+    Exact,
+    /// Rounded to the given number of decimal places; 2 decimals is roughly 1 km.
+    Rounded(u8),
+    /// Coordinates and point names are left out entirely.
+    Redacted,
+}
+
+impl LogCoordinates {
+    /// Parameters of routing requests that contain coordinates.
+    const PARAMS: &'static [&'static str] = &["lonlats", "nogos", "polylines", "polygons"];
+
+    fn apply(&self, value: &str) -> String {
+        match self {
+            LogCoordinates::Exact => value.to_string(),
+            LogCoordinates::Rounded(decimals) => regex!(r"-?\d+\.\d+")
+                .replace_all(value, |c: &lazy_regex::Captures| {
+                    let n: f64 = c[0].parse().unwrap();
+                    format!("{:.*}", *decimals as usize, n)
+                })
+                .into_owned(),
+            LogCoordinates::Redacted => "redacted".to_string(),
+        }
+    }
+
+    /// `url` with the coordinates in its query adjusted.
+    fn url(&self, url: &Url) -> Url {
+        let mut url = url.clone();
+        let pairs = url
+            .query_pairs()
+            .map(|(k, v)| {
+                let v = if Self::PARAMS.contains(&k.as_ref()) {
+                    self.apply(&v)
+                } else {
+                    v.into_owned()
+                };
+                (k.into_owned(), v)
+            })
+            .collect::<Vec<_>>();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+        url
+    }
+}
+
+/// Builder for a [`Brouter`] client with non-default settings.
+pub struct BrouterBuilder {
+    base_url: String,
+    fallback_urls: Vec<String>,
+    recheck_primary_after: std::time::Duration,
+    timeout: std::time::Duration,
+    max_url_length: usize,
+    max_profile_size: Option<u64>,
+    custom_profiles_dir: Option<std::path::PathBuf>,
+    profiles_dir: Option<std::path::PathBuf>,
+    segments_dir: Option<std::path::PathBuf>,
+    segment_manager: Option<segments::SegmentManager>,
+    response_handling: ResponseHandling,
+    pipeline: RoutePipeline,
+    retry: Option<RetryPolicy>,
+    connect_timeout: Option<std::time::Duration>,
+    client: Option<Client>,
+    log_requests: Option<LogCoordinates>,
+    middleware: Vec<Box<dyn Middleware>>,
+}
+
+impl BrouterBuilder {
+    pub fn new(base_url: &str) -> Self {
+        BrouterBuilder {
+            base_url: base_url.to_string(),
+            fallback_urls: vec![],
+            recheck_primary_after: DEFAULT_RECHECK_PRIMARY_AFTER,
+            max_url_length: DEFAULT_MAX_URL_LENGTH,
+            timeout: DEFAULT_TIMEOUT,
+            max_profile_size: None,
+            custom_profiles_dir: None,
+            profiles_dir: None,
+            segments_dir: None,
+            segment_manager: None,
+            response_handling: ResponseHandling::default(),
+            pipeline: RoutePipeline::new(),
+            retry: None,
+            connect_timeout: None,
+            client: None,
+            log_requests: None,
+            middleware: vec![],
+        }
+    }
+
+    /// Send requests to the server at `base_url` when the servers before it can't be reached,
+    /// e.g. a public server as fallback for a self-hosted one.
+    ///
+    /// Fallbacks are tried in the order they were added, when connecting to a server fails or
+    /// times out. Servers that were connected to are not failed over from, even if they answer
+    /// with an error, e.g. that no route was found, or take too long to answer: the next
+    /// server would likely take as long to calculate the same route.
+    ///
+    /// Servers are not health-checked. Requests keep going to the server that last answered,
+    /// and the primary server is only tried again after
+    /// [`BrouterBuilder::recheck_primary_after`].
+    pub fn fallback(mut self, base_url: &str) -> Self {
+        self.fallback_urls.push(base_url.to_string());
+        self
+    }
+
+    /// Send routing requests whose URL would be longer than `length` as POST requests, with
+    /// the parameters in the body. By default all routing requests are sent as GET requests.
+    ///
+    /// This keeps requests with many points or large nogo areas from being rejected for
+    /// their URL length, e.g. with a limit of 8000. Only set it if the server, or the proxy in
+    /// front of it, accepts routing requests sent as POST: brouter's own server ignores the
+    /// body and only reads the query.
+    pub fn max_url_length(mut self, length: usize) -> Self {
+        self.max_url_length = length;
+        self
+    }
+
+    /// How long to keep using a fallback server before trying the primary server again; five
+    /// minutes by default.
+    pub fn recheck_primary_after(mut self, interval: std::time::Duration) -> Self {
+        self.recheck_primary_after = interval;
+        self
+    }
+
+    /// How long to wait for a response; an hour by default.
+    ///
+    /// Deadlines set on requests take precedence.
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How long to wait for a connection to the server. This doesn't apply to clients passed
+    /// to [`BrouterBuilder::client`].
+    pub fn connect_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Retry routing requests that fail with a transient error, e.g. while a local server is
+    /// starting, according to `policy`.
+    ///
+    /// Retries stop once the deadline of a request would pass.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// The `maxRunningTime` the server is configured with.
+    ///
+    /// Requests then time out slightly later than on the server, so that slow routes fail
+    /// with the server's [`Error::PassTimeout`] rather than a client-side [`Error::Timeout`].
+    /// Deadlines set on requests still apply.
+    pub fn server_max_running_time(mut self, max_running_time: std::time::Duration) -> Self {
+        self.timeout = max_running_time + SERVER_TIMEOUT_MARGIN;
+        self
+    }
+
+    /// Refuse to upload profiles larger than `limit` bytes with [`Error::ProfileTooLarge`],
+    /// rather than leaving it to the server.
+    pub fn max_profile_size(mut self, limit: u64) -> Self {
+        self.max_profile_size = Some(limit);
+        self
+    }
+
+    /// The directory a local server stores uploaded profiles in, i.e. its `customprofiles`
+    /// directory.
+    ///
+    /// brouter has no way to delete profiles over HTTP, so
+    /// [`Brouter::delete_custom_profile`] needs access to this directory.
+    pub fn custom_profiles_dir(mut self, dir: impl Into<std::path::PathBuf>) -> Self {
+        self.custom_profiles_dir = Some(dir.into());
+        self
+    }
+
+    /// The directory a local server reads its standard profiles from, i.e. its `profiles2`
+    /// directory.
+    ///
+    /// brouter has no way to list or fetch these over HTTP, so [`Brouter::list_profiles`] and
+    /// related methods need access to this directory.
+    pub fn profiles_dir(mut self, dir: impl Into<std::path::PathBuf>) -> Self {
+        self.profiles_dir = Some(dir.into());
+        self
+    }
+
+    /// The directory a local server reads its segment files from, i.e. its `segments4`
+    /// directory.
+    ///
+    /// [`Brouter::check_coverage`] needs access to this directory, unless a
+    /// [`BrouterBuilder::segment_manager`] is set.
+    pub fn segments_dir(mut self, dir: impl Into<std::path::PathBuf>) -> Self {
+        self.segments_dir = Some(dir.into());
+        self
+    }
+
+    /// Download the segment files routes need into the segment directory of a local server
+    /// with `manager`, rather than failing with [`Error::MissingDataFile`].
+    ///
+    /// Before each route is calculated, the files for the area around its points are
+    /// downloaded; should brouter still miss a file, it is downloaded and the request retried.
+    pub fn segment_manager(mut self, manager: segments::SegmentManager) -> Self {
+        self.segment_manager = Some(manager);
+        self
+    }
+
+    /// How to treat responses whose status disagrees with their body, e.g. a route sent with
+    /// status 500. Defaults to [`ResponseHandling::Lenient`].
+    pub fn response_handling(mut self, handling: ResponseHandling) -> Self {
+        self.response_handling = handling;
+        self
+    }
+
+    /// Post-process every route returned by [`Brouter::request_route`] with `pipeline`.
+    pub fn pipeline(mut self, pipeline: RoutePipeline) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+
+    /// Use a preconfigured HTTP client, e.g. one with a proxy.
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Log each routing request at debug level, with the profile, the response status and the
+    /// time taken.
+    ///
+    /// `coordinates` controls how the points and nogos of the request are logged, since they
+    /// may reveal where users live or work.
+    pub fn log_requests(mut self, coordinates: LogCoordinates) -> Self {
+        self.log_requests = Some(coordinates);
+        self
+    }
+
+    /// Run `middleware` around every request.
+    pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Build the client.
+    ///
+    /// # Panics
+    ///
+    /// If a server URL is invalid; see [`BrouterBuilder::try_build`].
+    pub fn build(self) -> Brouter {
+        self.try_build().unwrap()
+    }
+
+    /// Build the client, failing with [`Error::InvalidUrl`] if a server URL is invalid.
+    pub fn try_build(self) -> Result<Brouter, Error> {
+        let parse = |u: &str| Url::parse(u).map_err(|e| Error::InvalidUrl(format!("{}: {}", u, e)));
+        let client = match (self.client, self.connect_timeout) {
+            (Some(client), _) => client,
+            (None, Some(timeout)) => Client::builder().connect_timeout(timeout).build()?,
+            (None, None) => Client::new(),
+        };
+        Ok(Brouter {
+            inner: std::sync::Arc::new(Shared {
+                client,
+                base_url: parse(&self.base_url)?,
+                fallback_urls: self
+                    .fallback_urls
+                    .iter()
+                    .map(|u| parse(u))
+                    .collect::<Result<_, _>>()?,
+                recheck_primary_after: self.recheck_primary_after,
+                max_url_length: self.max_url_length,
+                timeout: self.timeout,
+                max_profile_size: self.max_profile_size,
+                custom_profiles_dir: self.custom_profiles_dir,
+                profiles_dir: self.profiles_dir,
+                segments_dir: self.segments_dir,
+                segment_manager: self.segment_manager,
+                response_handling: self.response_handling,
+                pipeline: self.pipeline,
+                retry: self.retry,
+                active: std::sync::Mutex::new((0, std::time::Instant::now())),
+                log_requests: self.log_requests,
+                middleware: self.middleware,
+            }),
+        })
+    }
+}
+
+// Sharing a client between threads is the point of making it cheap to clone.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Brouter>();
+};
+
+impl Default for Brouter {
+    fn default() -> Self {
+        BrouterBuilder::new("http://localhost:17777").build()
+    }
+}
+
+/// The style of turn instructions brouter adds to routes, i.e. its `timode` parameter.
+///
+/// Modes can be parsed from their code (`"3"`) or name (`"osmand"`), see
+/// [`TurnInstructionMode::name`]. With the `serde` feature they are serialized as their name,
+/// and deserialized from either.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "TurnInstructionModeDef", try_from = "TurnInstructionModeDef")
+)]
+pub enum TurnInstructionMode {
+    #[default]
+    None = 0,
+    AutoChoose = 1,
+    LocusStyle = 2,
+    OsmandStyle = 3,
+    CommentStyle = 4,
+    GpsiesStyle = 5,
+    OruxStyle = 6,
+    LocusOldStyle = 7,
+}
+
+/// Whether one point can be reached from another, see [`Brouter::is_reachable`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Reachability {
+    Reachable,
+    /// There is no route, or one of the points is too far from the network.
+    NoRoute,
+    /// The server lacks the data to tell; holds the missing data file.
+    Unknown(String),
+}
+
+/// Information about a server, see [`Brouter::server_info`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServerInfo {
+    /// The URL of the server that answered, which may be a fallback server.
+    pub url: String,
+    /// The brouter version, e.g. "1.7.3", if the server reports it.
+    pub version: Option<String>,
+}
+
+impl TurnInstructionMode {
+    /// All modes, in the order of their codes.
+    pub const ALL: [TurnInstructionMode; 8] = [
+        TurnInstructionMode::None,
+        TurnInstructionMode::AutoChoose,
+        TurnInstructionMode::LocusStyle,
+        TurnInstructionMode::OsmandStyle,
+        TurnInstructionMode::CommentStyle,
+        TurnInstructionMode::GpsiesStyle,
+        TurnInstructionMode::OruxStyle,
+        TurnInstructionMode::LocusOldStyle,
+    ];
+
+    /// The mode with the given value of brouter's `timode` parameter.
+    pub fn from_code(code: i32) -> Option<Self> {
+        Self::ALL.into_iter().find(|m| m.code() == code)
+    }
+
+    /// The value of brouter's `timode` parameter for this mode.
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    /// The name of the mode, as accepted by [`str::parse`].
+    pub fn name(self) -> &'static str {
+        match self {
+            TurnInstructionMode::None => "none",
+            TurnInstructionMode::AutoChoose => "auto",
+            TurnInstructionMode::LocusStyle => "locus",
+            TurnInstructionMode::OsmandStyle => "osmand",
+            TurnInstructionMode::CommentStyle => "comment",
+            TurnInstructionMode::GpsiesStyle => "gpsies",
+            TurnInstructionMode::OruxStyle => "orux",
+            TurnInstructionMode::LocusOldStyle => "locus-old",
+        }
+    }
+}
+
+impl std::fmt::Display for TurnInstructionMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl From<TurnInstructionMode> for i32 {
+    fn from(mode: TurnInstructionMode) -> i32 {
+        mode.code()
+    }
+}
+
+impl TryFrom<i32> for TurnInstructionMode {
+    type Error = String;
+
+    fn try_from(code: i32) -> Result<Self, Self::Error> {
+        Self::from_code(code).ok_or_else(|| format!("invalid turn instruction mode {}", code))
+    }
+}
+
+impl std::str::FromStr for TurnInstructionMode {
+    type Err = String;
+
+    /// Parse a mode from its code or its name, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(code) = s.parse::<i32>() {
+            return Self::try_from(code);
+        }
+        Self::ALL
+            .into_iter()
+            .find(|m| m.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("invalid turn instruction mode '{}'", s))
+    }
+}
+
+/// The serialized form of a [`TurnInstructionMode`]: its name, or its code.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+enum TurnInstructionModeDef {
+    Code(i32),
+    Name(String),
+}
+
+#[cfg(feature = "serde")]
+impl From<TurnInstructionMode> for TurnInstructionModeDef {
+    fn from(mode: TurnInstructionMode) -> Self {
+        TurnInstructionModeDef::Name(mode.name().to_string())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<TurnInstructionModeDef> for TurnInstructionMode {
+    type Error = String;
+
+    fn try_from(def: TurnInstructionModeDef) -> Result<Self, Self::Error> {
+        match def {
+            TurnInstructionModeDef::Code(code) => code.try_into(),
+            // Earlier versions serialized modes by their variant names.
+            TurnInstructionModeDef::Name(name) => match name.as_str() {
+                "None" => Ok(TurnInstructionMode::None),
+                "AutoChoose" => Ok(TurnInstructionMode::AutoChoose),
+                "LocusStyle" => Ok(TurnInstructionMode::LocusStyle),
+                "OsmandStyle" => Ok(TurnInstructionMode::OsmandStyle),
+                "CommentStyle" => Ok(TurnInstructionMode::CommentStyle),
+                "GpsiesStyle" => Ok(TurnInstructionMode::GpsiesStyle),
+                "OruxStyle" => Ok(TurnInstructionMode::OruxStyle),
+                "LocusOldStyle" => Ok(TurnInstructionMode::LocusOldStyle),
+                name => name.parse(),
+            },
+        }
+    }
+}
+
+impl Brouter {
+    /// A client for the server at `base_url`, failing with [`Error::InvalidUrl`] if it is
+    /// invalid.
+    pub fn new(base_url: &str) -> Result<Self, Error> {
+        BrouterBuilder::new(base_url).try_build()
+    }
+
+    pub fn builder(base_url: &str) -> BrouterBuilder {
+        BrouterBuilder::new(base_url)
+    }
+
+    /// The URL of the server.
+    pub fn base_url(&self) -> &str {
+        self.inner.base_url.as_str()
+    }
+
+    /// The URL of the server requests currently go to, which differs from
+    /// [`Brouter::base_url`] after failing over to a fallback server.
+    pub fn active_url(&self) -> &str {
+        let (index, _) = *self.inner.active.lock().unwrap();
+        self.server_url(index).as_str()
+    }
+
+    fn server_url(&self, index: usize) -> &Url {
+        match index {
+            0 => &self.inner.base_url,
+            i => &self.inner.fallback_urls[i - 1],
+        }
+    }
+
+    /// Run `f` with `url` rebased onto each server in turn, starting with the active one, until
+    /// one can be connected to.
+    ///
+    /// The server that answers becomes the active one; see [`BrouterBuilder::fallback`].
+    fn with_failover<T>(&self, url: Url, f: impl Fn(Url) -> Result<T, Error>) -> Result<T, Error> {
+        let path = match url.as_str().strip_prefix(self.inner.base_url.as_str()) {
+            Some(path) if !self.inner.fallback_urls.is_empty() => path.to_string(),
+            _ => return f(url),
+        };
+        let start = {
+            let (index, since) = *self.inner.active.lock().unwrap();
+            if index != 0 && since.elapsed() >= self.inner.recheck_primary_after {
+                0
+            } else {
+                index
+            }
+        };
+        let count = self.inner.fallback_urls.len() + 1;
+        let mut result = None;
+        for index in (start..count).chain(0..start) {
+            let url = self.server_url(index).join(&path).unwrap();
+            match f(url) {
+                Err(e) if e.is_unreachable() => {
+                    debug!("Server {} unavailable: {}", self.server_url(index), e);
+                    result = Some(Err(e));
+                }
+                r => {
+                    let mut active = self.inner.active.lock().unwrap();
+                    if active.0 != index {
+                        info!("Switching to server {}", self.server_url(index));
+                        *active = (index, std::time::Instant::now());
+                    } else if index != 0 && start == 0 {
+                        active.1 = std::time::Instant::now();
+                    }
+                    return r;
+                }
+            }
+        }
+        result.unwrap()
+    }
+
+    /// Upload a profile, letting the server choose its id.
+    ///
+    /// Returns the id the server stored the profile under, which can be used as the profile
+    /// name in routing requests.
+    pub fn upload_custom_profile(&self, data: Vec<u8>) -> Result<String, Error> {
+        self.check_profile_size(data.len() as u64)?;
+        let url = self.inner.base_url.join("brouter/profile").unwrap();
+
+        let text = self.with_failover(url, |url| {
+            self.send(self.profile_upload(url, data.clone()))
+                .and_then(|r| r.error_for_status())
+                .and_then(|r| r.text())
+                .map_err(Error::from)
+        })?;
+
+        parse_profile_upload(&text)
+    }
+
+    /// Check a profile by having the server compile it, without routing.
+    ///
+    /// Syntax errors are returned as [`Error::InvalidProfile`], with the line number if the
+    /// server reports one. brouter has no way to remove uploaded profiles over HTTP; the server
+    /// discards the uploaded copy when it cleans up its custom profiles, or see
+    /// [`Brouter::delete_custom_profile`] for local servers.
+    pub fn validate_profile(&self, data: Vec<u8>) -> Result<(), Error> {
+        self.upload_custom_profile(data).map(|_| ())
+    }
+
+    /// Check whether `b` can be reached from `a` with `profile`.
+    ///
+    /// This is cheaper than [`Brouter::request_route`], since the returned route isn't parsed.
+    /// Errors other than those that answer the question, e.g. connection failures, are
+    /// returned as is.
+    pub fn is_reachable(&self, a: &Point, b: &Point, profile: &str) -> Result<Reachability, Error> {
+        let url = self.route_url(&[a.clone(), b.clone()], &[], &[], profile, None, "gpx");
+        match self.fetch(url, None) {
+            Ok(_) => Ok(Reachability::Reachable),
+            Err(Error::NoRouteFound(_)) | Err(Error::PositionNotMapped(_)) => {
+                Ok(Reachability::NoRoute)
+            }
+            Err(Error::MissingDataFile(file)) => Ok(Reachability::Unknown(file)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Find where `point` joins the network of ways usable with `profile`.
+    ///
+    /// This routes from `point` to a point a few meters away and takes the start of the route.
+    /// Returns the snapped point and its distance from `point` in meters, which is useful to
+    /// check user-placed markers before routing. Points that are too far from any way give
+    /// [`Error::PositionNotMapped`].
+    pub fn snap(&self, point: &Point, profile: &str) -> Result<(Point, f64), Error> {
+        use geo::{Destination, Distance};
+
+        let from: geo_types::Point<f64> = point.clone().into();
+        let to = geo::Haversine.destination(from, 0.0, 10.0);
+        let route = self.request_route(&RouteRequest::new(&[point.clone(), to.into()], profile))?;
+        let snapped = route
+            .points()
+            .next()
+            .map(|p| p.point())
+            .ok_or_else(|| Error::Other("route without points".to_string()))?;
+        Ok((snapped.into(), geo::Haversine.distance(from, snapped)))
+    }
+
+    /// Route a short distance near `point`, so that a freshly started server loads its classes
+    /// and maps the segment file before the first real request.
+    ///
+    /// Returns how long the request took. Not finding a route still warms the server up, so
+    /// that is not an error.
+    pub fn warm_up(&self, point: &Point, profile: &str) -> Result<std::time::Duration, Error> {
+        use geo::Destination;
+
+        let start = std::time::Instant::now();
+        let to = geo::Haversine.destination(point.clone().into(), 90.0, 100.0);
+        match self.request_route(&RouteRequest::new(&[point.clone(), to.into()], profile)) {
+            Ok(_) | Err(Error::NoRouteFound(_)) => Ok(start.elapsed()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Find out which version of brouter the server runs.
+    ///
+    /// brouter only reports its version in the routes it returns, so this routes a short
+    /// distance from `point`, which has to be covered by the server's segment files. For a
+    /// server started by this crate, [`server::BrouterServer::version`] needs no route.
+    pub fn server_info(&self, point: &Point, profile: &str) -> Result<ServerInfo, Error> {
+        use geo::Destination;
+
+        let to = geo::Haversine.destination(point.clone().into(), 90.0, 100.0);
+        let route = self.request_route(&RouteRequest::new(&[point.clone(), to.into()], profile))?;
+        Ok(ServerInfo {
+            url: self.active_url().to_string(),
+            version: route.brouter_version().map(|v| v.to_string()),
+        })
+    }
+
+    pub fn upload_profile(&self, profile: &str, data: Vec<u8>) -> Result<(), Error> {
+        self.check_profile_size(data.len() as u64)?;
+        let url = self
+            .inner
+            .base_url
+            .join("brouter/profile/")
+            .unwrap()
+            .join(profile)
+            .unwrap();
+
+        self.with_failover(url, |url| {
+            self.send(self.profile_upload(url, data.clone()))
+                .and_then(|r| r.error_for_status())
+                .map_err(Error::from)
+                .map(|_| ())
+        })
+    }
+
+    /// Replace the uploaded profile `id`, e.g. one returned by
+    /// [`Brouter::upload_custom_profile`], keeping its id.
+    pub fn replace_profile(&self, id: &str, data: Vec<u8>) -> Result<(), Error> {
+        check_profile_id(id)?;
+        self.upload_profile(id, data)
+    }
+
+    /// The directory a local server stores uploaded profiles in, if configured with
+    /// [`BrouterBuilder::custom_profiles_dir`].
+    pub fn custom_profiles_dir(&self) -> Option<&std::path::Path> {
+        self.inner.custom_profiles_dir.as_deref()
+    }
+
+    fn required_custom_profiles_dir(&self) -> Result<&std::path::Path, Error> {
+        self.custom_profiles_dir().ok_or_else(|| {
+            Error::Other("the custom profiles directory of the server is not set".to_string())
+        })
+    }
+
+    /// The directory a local server reads its segment files from, if configured with
+    /// [`BrouterBuilder::segments_dir`] or [`BrouterBuilder::segment_manager`].
+    pub fn segments_dir(&self) -> Option<&std::path::Path> {
+        self.inner.segments_dir.as_deref().or_else(|| {
+            self.inner
+                .segment_manager
+                .as_ref()
+                .map(|manager| manager.dir())
+        })
+    }
+
+    /// Check which of the segment files needed for `request` are missing from the segment
+    /// directory of a local server; see [`segments::check_coverage`].
+    pub fn check_coverage(&self, request: &RouteRequest) -> Result<segments::Coverage, Error> {
+        let dir = self.segments_dir().ok_or_else(|| {
+            Error::Other("the segments directory of the server is not set".to_string())
+        })?;
+        Ok(segments::check_coverage(request, dir))
+    }
+
+    /// The ids of the profiles uploaded to a local server, sorted.
+    pub fn list_custom_profiles(&self) -> Result<Vec<String>, Error> {
+        list_profile_files(self.required_custom_profiles_dir()?)
+    }
+
+    /// The directory a local server reads its standard profiles from, if configured with
+    /// [`BrouterBuilder::profiles_dir`].
+    pub fn profiles_dir(&self) -> Option<&std::path::Path> {
+        self.inner.profiles_dir.as_deref()
+    }
+
+    fn required_profiles_dir(&self) -> Result<&std::path::Path, Error> {
+        self.profiles_dir().ok_or_else(|| {
+            Error::Other("the profiles directory of the server is not set".to_string())
+        })
+    }
+
+    /// The names of the profiles a local server offers, sorted.
+    pub fn list_profiles(&self) -> Result<Vec<String>, Error> {
+        list_profile_files(self.required_profiles_dir()?)
+    }
+
+    /// The source of the profile `name` of a local server, e.g. to modify it and upload the
+    /// result with [`Brouter::upload_custom_profile`].
+    pub fn get_profile(&self, name: &str) -> Result<String, Error> {
+        check_profile_id(name)?;
+        let dir = self.required_profiles_dir()?;
+        Ok(std::fs::read_to_string(dir.join(format!("{}.brf", name)))?)
+    }
+
+    /// Install the profile file at `path`, e.g. `my-bike.brf`, on a local server, named after
+    /// the file.
+    ///
+    /// An installed profile with the same name is replaced. Returns the name.
+    pub fn install_profile(&self, path: &std::path::Path) -> Result<String, Error> {
+        let name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default()
+            .to_string();
+        check_profile_id(&name)?;
+        let target = self.required_profiles_dir()?.join(format!("{}.brf", name));
+        // Copying a file onto itself would truncate it.
+        if target.canonicalize().ok() != Some(path.canonicalize()?) {
+            std::fs::copy(path, target)?;
+        }
+        Ok(name)
+    }
+
+    /// The uploaded profile `id` as stored by a local server.
+    pub fn get_custom_profile(&self, id: &str) -> Result<String, Error> {
+        check_profile_id(id)?;
+        let dir = self.required_custom_profiles_dir()?;
+        Ok(std::fs::read_to_string(dir.join(format!("{}.brf", id)))?)
+    }
+
+    /// Delete the uploaded profile `id` from a local server.
+    ///
+    /// This requires [`BrouterBuilder::custom_profiles_dir`] to be set. Deleting a profile that
+    /// doesn't exist is not an error.
+    pub fn delete_custom_profile(&self, id: &str) -> Result<(), Error> {
+        check_profile_id(id)?;
+        let dir = self.required_custom_profiles_dir()?;
+        match std::fs::remove_file(dir.join(format!("{}.brf", id))) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Upload a profile of `size` bytes read from `reader`, without loading it into memory.
+    ///
+    /// Since the body can only be read once, the profile goes to the active server without
+    /// failing over.
+    pub fn upload_profile_stream(
+        &self,
+        profile: &str,
+        reader: impl std::io::Read + Send + 'static,
+        size: u64,
+    ) -> Result<(), Error> {
+        self.check_profile_size(size)?;
+        let url = Url::parse(self.active_url())
+            .unwrap()
+            .join("brouter/profile/")
+            .unwrap()
+            .join(profile)
+            .unwrap();
+        let body = reqwest::blocking::Body::sized(reader, size);
+
+        self.send(self.profile_upload(url, body))
+            .and_then(|r| r.error_for_status())
+            .map_err(Error::from)
+            .map(|_| ())
+    }
+
+    fn check_profile_size(&self, size: u64) -> Result<(), Error> {
+        match self.inner.max_profile_size {
+            Some(limit) if size > limit => Err(Error::ProfileTooLarge { size, limit }),
+            _ => Ok(()),
+        }
+    }
+
+    fn profile_upload(&self, url: Url, body: impl Into<reqwest::blocking::Body>) -> RequestBuilder {
+        self.inner
+            .client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(body)
+    }
+
+    fn route_url(
+        &self,
+        points: &[Point],
+        names: &[Option<String>],
+        nogos: &[Nogo],
+        profile: &str,
+        alternativeidx: Option<u8>,
+        format: &str,
+    ) -> Url {
+        match self.inner.log_requests {
+            Some(LogCoordinates::Exact) | None => info!("Planning route along {:?}", points),
+            Some(_) => info!("Planning route along {} points", points.len()),
+        }
+
+        route_url(
+            &self.inner.base_url,
+            points,
+            names,
+            nogos,
+            profile,
+            alternativeidx,
+            format,
+        )
+    }
+
+    /// Send a request, running the middleware around it.
+    fn send(&self, request: RequestBuilder) -> Result<Response, reqwest::Error> {
+        let mut request = request.build()?;
+        for middleware in &self.inner.middleware {
+            middleware.before(&mut request);
+        }
+        let response = self.inner.client.execute(request)?;
+        for middleware in &self.inner.middleware {
+            middleware.after(&response);
+        }
+        Ok(response)
+    }
+
+    /// Fetch `url`, returning the content type, body and details of the response.
+    fn fetch(
+        &self,
+        url: Url,
+        deadline: Option<std::time::Duration>,
+    ) -> Result<(Option<String>, Vec<u8>, ResponseInfo), Error> {
+        self.with_retry(deadline, || {
+            self.with_failover(url.clone(), |url| self.fetch_from(url, deadline))
+        })
+    }
+
+    /// Run `f` until it succeeds or fails with an error that isn't transient, as long as the
+    /// retry policy and `deadline` allow.
+    fn with_retry<T>(
+        &self,
+        deadline: Option<std::time::Duration>,
+        f: impl Fn() -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let start = std::time::Instant::now();
+        let mut retry = 0;
+        loop {
+            let e = match f() {
+                Err(e) => e,
+                result => return result,
+            };
+            match retry_backoff(self.inner.retry, &e, retry, start, deadline) {
+                Some(backoff) => {
+                    debug!("Retrying in {:?} after: {}", backoff, e);
+                    std::thread::sleep(backoff);
+                    retry += 1;
+                }
+                None => return Err(e),
+            }
+        }
+    }
+
+    /// Send a GET request for `url`, retrying once on a stale connection.
+    ///
+    /// URLs longer than [`BrouterBuilder::max_url_length`] are sent as POST requests instead.
+    fn get(&self, url: &Url, deadline: Option<std::time::Duration>) -> Result<Response, Error> {
+        let start = std::time::Instant::now();
+        if let Some(coordinates) = self.inner.log_requests {
+            let profile = url
+                .query_pairs()
+                .find(|(k, _)| k == "profile")
+                .map(|(_, v)| v.into_owned())
+                .unwrap_or_default();
+            debug!(
+                "brouter request: profile={} url={}",
+                profile,
+                coordinates.url(url)
+            );
+        }
+        let (method, url, form) = routing_request(url, self.inner.max_url_length);
+        let request = || {
+            let request = self.inner.client.request(method.clone(), url.clone());
+            match &form {
+                Some(body) => request
+                    .header(reqwest::header::CONTENT_TYPE, FORM_CONTENT_TYPE)
+                    .body(body.clone()),
+                None => request,
+            }
+            .timeout(deadline.unwrap_or(self.inner.timeout))
+        };
+        let response = match self.send(request()) {
+            // Routing requests are idempotent, so they can safely be sent again on a new
+            // connection.
+            Err(e) if is_stale_connection(&e) => {
+                debug!("Connection closed by server; retrying request");
+                self.send(request())
+            }
+            response => response,
+        }
+        .map_err(|e| map_deadline_err(e, deadline))?;
+        if self.inner.log_requests.is_some() {
+            debug!(
+                "brouter response: status={} time={:?}",
+                response.status(),
+                start.elapsed()
+            );
+        }
+        Ok(response)
+    }
+
+    fn fetch_from(
+        &self,
+        url: Url,
+        deadline: Option<std::time::Duration>,
+    ) -> Result<(Option<String>, Vec<u8>, ResponseInfo), Error> {
+        let start = std::time::Instant::now();
+        let response = self.get(&url, deadline)?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response
+            .bytes()
+            .map_err(|e| map_deadline_err(e, deadline))?
+            .to_vec();
+        response::check_response(
+            status,
+            &headers,
+            body,
+            start.elapsed(),
+            self.inner.response_handling,
+        )
+    }
+
+    /// Start a request for a route along `points`, using the "trekking" profile unless another
+    /// is set.
+    ///
+    /// ```no_run
+    /// use brouter_client::{Brouter, Point, TurnInstructionMode};
+    ///
+    /// let router = Brouter::default();
+    /// let route = router
+    ///     .route(&[Point::new(52.3676, 4.9041), Point::new(52.0907, 5.1214)])
+    ///     .profile("fastbike")
+    ///     .turn_instructions(TurnInstructionMode::OsmandStyle)
+    ///     .profile_param("avoid_unsafe", "1")
+    ///     .send()?;
+    /// # Ok::<(), brouter_client::Error>(())
+    /// ```
+    pub fn route(&self, points: &[Point]) -> RouteBuilder<'_> {
+        RouteBuilder::new(self, RouteRequest::new(points, "trekking"))
+    }
+
+    /// Calculate a route.
+    pub fn request_route(&self, request: &RouteRequest) -> Result<Route, Error> {
+        request.validate()?;
+        response::check_parseable(request.format)?;
+        let url = self.request_url(request);
+        let (content_type, text, info) = match &self.inner.segment_manager {
+            Some(manager) => {
+                manager.ensure_for(request)?;
+                let mut attempted = vec![];
+                loop {
+                    match self.fetch(url.clone(), request.deadline) {
+                        Err(Error::MissingDataFile(name)) if !attempted.contains(&name) => {
+                            if !manager.download(&name)? {
+                                return Err(Error::MissingDataFile(name));
+                            }
+                            attempted.push(name);
+                        }
+                        result => break result?,
+                    }
+                }
+            }
+            None => self.fetch(url, request.deadline)?,
+        };
+
+        let mut route =
+            response::parse_route(text.as_slice(), content_type.as_deref(), request.format)?;
+        route.response_info = Some(info);
+        name_exported_waypoints(&mut route, request);
+        self.inner.pipeline.apply(&mut route)?;
+        Ok(route)
+    }
+
+    /// Calculate a route and write the document brouter returns to `path`, in the format of
+    /// the request, without holding it in memory.
+    ///
+    /// The response is checked for errors before anything is written, and it is written to a
+    /// temporary file next to `path` that only replaces `path` once complete. Unlike
+    /// [`Brouter::request_route`], waypoints are written as brouter names them.
+    pub fn broute_to_file(
+        &self,
+        request: &RouteRequest,
+        path: &std::path::Path,
+    ) -> Result<ResponseInfo, Error> {
+        request.validate()?;
+        let url = self.request_url(request);
+        self.with_retry(request.deadline, || {
+            self.with_failover(url.clone(), |url| {
+                self.fetch_to_file(url, request.deadline, path)
+            })
+        })
+    }
+
+    /// Send a routing request with the given query parameters, and return the response without
+    /// interpreting it.
+    ///
+    /// The request goes through the same failover, middleware and retries as the other
+    /// requests, which makes this useful for debugging and for server features this crate
+    /// doesn't support yet. Only failures to get a response at all are errors, and only those
+    /// are retried: a response with an error status is returned as is.
+    pub fn send_raw<K, V>(
+        &self,
+        query_pairs: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<RawResponse, Error>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut url = self.inner.base_url.join("brouter").unwrap();
+        url.query_pairs_mut().extend_pairs(query_pairs);
+        self.with_retry(None, || {
+            self.with_failover(url.clone(), |url| {
+                let start = std::time::Instant::now();
+                let response = self.get(&url, None)?;
+                let status = response.status();
+                let headers = response.headers().clone();
+                let body = response.bytes()?.to_vec();
+                let info = ResponseInfo::new(&headers, body.len(), start.elapsed());
+                Ok(RawResponse {
+                    status,
+                    headers,
+                    body,
+                    info,
+                })
+            })
+        })
+    }
+
+    fn fetch_to_file(
+        &self,
+        url: Url,
+        deadline: Option<std::time::Duration>,
+        path: &std::path::Path,
+    ) -> Result<ResponseInfo, Error> {
+        use std::io::{Read, Write};
+
+        let start = std::time::Instant::now();
+        let mut response = self.get(&url, deadline)?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let content_type = headers
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok());
+
+        let mut head = Vec::with_capacity(ERROR_SNIFF_LIMIT);
+        (&mut response)
+            .take(ERROR_SNIFF_LIMIT as u64)
+            .read_to_end(&mut head)?;
+        response::classify(status, content_type, &head, self.inner.response_handling)?;
+
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".part");
+        let partial = std::path::PathBuf::from(partial);
+        let mut write = || -> Result<u64, std::io::Error> {
+            let mut file = std::fs::File::create(&partial)?;
+            file.write_all(&head)?;
+            let rest = std::io::copy(&mut response, &mut file)?;
+            file.sync_all()?;
+            Ok(head.len() as u64 + rest)
+        };
+        let size = match write() {
+            Ok(size) => size,
+            Err(e) => {
+                let _ = std::fs::remove_file(&partial);
+                return Err(e.into());
+            }
+        };
+        std::fs::rename(&partial, path)?;
+        Ok(ResponseInfo::new(&headers, size as usize, start.elapsed()))
+    }
+
+    /// The URL to request the route for `request` from.
+    fn request_url(&self, request: &RouteRequest) -> Url {
+        let mut url = self.route_url(
+            &request.points,
+            &request.point_names,
+            &request.nogos,
+            &request.profile,
+            request.alternative,
+            request.format.name(),
+        );
+        add_gpx_params(&mut url, request);
+        url
+    }
+
+    /// Calculate a route for `request` that avoids the roads of `route`, e.g. one calculated
+    /// for the same request before.
+    ///
+    /// The route is added to the nogos of the request as weighted lines, so it is still used
+    /// where there is no other way. Stretches close to the points of the request are left
+    /// out, since brouter ignores nogos that contain a waypoint. Repeating this with each new
+    /// route yields more alternatives than the server's four alternative indexes.
+    pub fn alternative_avoiding(
+        &self,
+        request: &RouteRequest,
+        route: &Route,
+        weight: f64,
+    ) -> Result<Route, Error> {
+        use geo::{Distance, Simplify};
+
+        let near_waypoint = |p: geo_types::Point<f64>| {
+            request
+                .points
+                .iter()
+                .any(|w| geo::Haversine.distance(p, w.clone().into()) < AVOID_WAYPOINT_CLEARANCE_M)
+        };
+
+        let mut request = request.clone();
+        let mut stretch: Vec<geo_types::Point<f64>> = vec![];
+        let points = route.points().map(|p| Some(p.point())).chain([None]);
+        for point in points {
+            match point {
+                Some(p) if !near_waypoint(p) => stretch.push(p),
+                _ if stretch.len() >= 2 => {
+                    // Simplify to keep the request URL short.
+                    let line = geo_types::LineString::from(std::mem::take(&mut stretch))
+                        .simplify(AVOID_SIMPLIFY_EPSILON);
+                    request.nogos.push(Nogo::Line {
+                        points: line.points().map(Point::from).collect(),
+                        weight: Some(weight),
+                    });
+                }
+                _ => stretch.clear(),
+            }
+        }
+        self.request_route(&request)
+    }
+
+    /// Calculate a new route from `position`, e.g. after leaving `route`.
+    ///
+    /// `via_points` are the points `route` was meant to pass through, including its
+    /// destination. Those that lie along `route` before the point nearest to `position` have
+    /// been passed and are dropped; the new route leads through the others.
+    pub fn reroute(
+        &self,
+        route: &Route,
+        position: &Point,
+        via_points: &[Point],
+        profile: &str,
+    ) -> Result<Route, Error> {
+        let along = |p: &Point| route.nearest_point(p).map_or(0.0, |(_, _, along)| along);
+        let current = along(position);
+        let mut points = vec![position.clone()];
+        points.extend(via_points.iter().filter(|p| along(p) > current).cloned());
+        if points.len() < 2 {
+            // Past every via point; head for the destination.
+            points.extend(via_points.last().cloned());
+        }
+        if points.len() < 2 {
+            return Err(Error::Other("no via points to route to".to_string()));
+        }
+        self.request_route(&RouteRequest::new(&points, profile))
+    }
+
+    /// Calculate a route, retrying with a larger `waypointCatchingRange` if a point is too far
+    /// from the road network.
+    ///
+    /// The range, i.e. how far brouter looks for a road near each point, is doubled from the
+    /// one set in the request or brouter's default of 250 m, up to `max_range_m`. Returns the
+    /// route and the range that succeeded, or `None` if the request succeeded unchanged.
+    pub fn request_route_catching(
+        &self,
+        request: &RouteRequest,
+        max_range_m: f64,
+    ) -> Result<(Route, Option<f64>), Error> {
+        const PARAM: &str = "waypointCatchingRange";
+
+        let mut range = request
+            .profile_params
+            .iter()
+            .rev()
+            .find(|(name, _)| name == PARAM)
+            .and_then(|(_, value)| value.parse().ok())
+            .unwrap_or(DEFAULT_WAYPOINT_CATCHING_RANGE_M);
+        let mut result = self.request_route(request).map(|route| (route, None));
+        while let Err(Error::PositionNotMapped(_)) = result {
+            range *= 2.0;
+            if range > max_range_m {
+                break;
+            }
+            let mut request = request.clone();
+            request.profile_params.retain(|(name, _)| name != PARAM);
+            let request = request.profile_param(PARAM, &range.to_string());
+            result = self
+                .request_route(&request)
+                .map(|route| (route, Some(range)));
+        }
+        result
+    }
+
+    /// Calculate a route and return it as GPX.
+    ///
+    /// [`Brouter::route`] supports all options of a request and is easier to read.
+    #[allow(clippy::too_many_arguments)]
+    pub fn broute(
+        &self,
+        points: &[Point],
+        nogos: &[Nogo],
+        profile: &str,
+        alternativeidx: Option<u8>,
+        timode: Option<TurnInstructionMode>,
+        name: Option<&str>,
+        export_waypoints: bool,
+    ) -> Result<gpx::Gpx, Error> {
+        let mut request = RouteRequest::new(points, profile)
+            .nogos(nogos)
+            .export_waypoints(export_waypoints);
+        request.alternative = alternativeidx;
+        request.turn_instructions = timode;
+        request.track_name = name.map(|n| n.to_string());
+
+        self.request_route(&request).map(Route::into_gpx)
+    }
+
+    /// Retrieve the turns of a route from brouter's CSV output.
+    ///
+    /// The turn instruction mode of the request selects the turns, as for GPX output; see
+    /// [`CuePoint::parse_csv`].
+    pub fn broute_cues(&self, request: &RouteRequest) -> Result<Vec<CuePoint>, Error> {
+        request.validate()?;
+        let mut url = self.route_url(
+            &request.points,
+            &[],
+            &request.nogos,
+            &request.profile,
+            request.alternative,
+            "csv",
+        );
+        if let Some(timode) = request.turn_instructions {
+            url.query_pairs_mut()
+                .append_pair("timode", (timode as i32).to_string().as_str());
+        }
+        add_straight_param(&mut url, request);
+        for (name, value) in &request.profile_params {
+            url.query_pairs_mut()
+                .append_pair(&format!("profile:{}", name), value);
+        }
+        for (key, value) in &request.extra_params {
+            url.query_pairs_mut().append_pair(key, value);
+        }
+
+        let (_, text, _) = self.fetch(url, request.deadline)?;
+
+        CuePoint::parse_csv(&String::from_utf8_lossy(text.as_slice()))
+    }
+
+    /// Retrieve the per-segment messages for a route, using brouter's CSV output.
+    pub fn broute_messages(
+        &self,
+        points: &[Point],
+        nogos: &[Nogo],
+        profile: &str,
+        alternativeidx: Option<u8>,
+    ) -> Result<Vec<SegmentMessage>, Error> {
+        if let Some(alternative) = alternativeidx {
+            check_alternative(alternative)?;
+        }
+        nogos.iter().try_for_each(Nogo::validate)?;
+        let url = self.route_url(points, &[], nogos, profile, alternativeidx, "csv");
+
+        let (_, text, _) = self.fetch(url, None)?;
 
-        Ok(gpx)
+        SegmentMessage::parse_csv(&String::from_utf8_lossy(text.as_slice()))
     }
 }
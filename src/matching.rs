@@ -0,0 +1,132 @@
+//! Snapping recorded tracks to the road network.
+//!
+//! A recorded track is reduced to a small number of via points, which are then routed through.
+//! Via points are placed at corners, so that the route follows the same roads as the
+//! recording, and at regular intervals in between.
+
+use crate::{Brouter, Error, Point, Route, RouteRequest};
+use geo::{Bearing, Distance, Haversine};
+
+/// Options for reducing a track to via points.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViaPointOptions {
+    /// Maximum distance between consecutive via points, in meters.
+    pub max_spacing_m: f64,
+
+    /// Minimum distance between consecutive via points, in meters.
+    ///
+    /// This also determines how far ahead the direction of the track is measured, which keeps
+    /// GPS jitter from being mistaken for corners.
+    pub min_spacing_m: f64,
+
+    /// Minimum change of direction for a point to be considered a corner, in degrees.
+    pub corner_angle: f64,
+}
+
+impl Default for ViaPointOptions {
+    fn default() -> Self {
+        ViaPointOptions {
+            max_spacing_m: 2000.0,
+            min_spacing_m: 50.0,
+            corner_angle: 30.0,
+        }
+    }
+}
+
+/// The absolute difference between two bearings, in degrees (0-180).
+fn bearing_change(a: f64, b: f64) -> f64 {
+    let d = (b - a).rem_euclid(360.0);
+    if d > 180.0 {
+        360.0 - d
+    } else {
+        d
+    }
+}
+
+/// Reduce a track to a sequence of via points.
+///
+/// The first and last point of the track are always included.
+pub fn via_points(track: &[Point], options: &ViaPointOptions) -> Vec<Point> {
+    let points: Vec<geo_types::Point<f64>> = track.iter().map(|p| p.clone().into()).collect();
+    if points.len() < 2 {
+        return track.to_vec();
+    }
+
+    // Cumulative distance along the track.
+    let mut along = Vec::with_capacity(points.len());
+    let mut total = 0.0;
+    along.push(total);
+    for w in points.windows(2) {
+        total += Haversine.distance(w[0], w[1]);
+        along.push(total);
+    }
+
+    // Index of the first point at least `min_spacing_m` beyond `i`.
+    let ahead = |i: usize| {
+        (i + 1..points.len())
+            .find(|&j| along[j] - along[i] >= options.min_spacing_m)
+            .unwrap_or(points.len() - 1)
+    };
+
+    // Change of direction at `i`, coming from `last`.
+    let turn = |last: usize, i: usize| {
+        bearing_change(
+            Haversine.bearing(points[last], points[i]),
+            Haversine.bearing(points[i], points[ahead(i)]),
+        )
+    };
+
+    let mut result = vec![track[0].clone()];
+    let mut last = 0;
+    let mut i = 1;
+    while i < points.len() - 1 {
+        let since_last = along[i] - along[last];
+        if since_last >= options.max_spacing_m {
+            result.push(track[i].clone());
+            last = i;
+        } else if since_last >= options.min_spacing_m && turn(last, i) >= options.corner_angle {
+            // The turn is first noticed up to `min_spacing_m` before the actual corner; pick
+            // the sharpest point in that window.
+            let end = ahead(i).min(points.len() - 2);
+            let corner = (i..=end)
+                .max_by(|&a, &b| turn(last, a).total_cmp(&turn(last, b)))
+                .unwrap_or(i);
+            result.push(track[corner].clone());
+            last = corner;
+            i = corner;
+        }
+        i += 1;
+    }
+
+    result.push(track[points.len() - 1].clone());
+    result
+}
+
+impl Brouter {
+    /// Route along a recorded track, snapping it to the road network.
+    ///
+    /// All track points in `recording` are concatenated, reduced to via points with
+    /// [`via_points`] and routed through using `profile`.
+    pub fn match_track(
+        &self,
+        recording: &gpx::Gpx,
+        profile: &str,
+        options: &ViaPointOptions,
+    ) -> Result<Route, Error> {
+        let track = recording
+            .tracks
+            .iter()
+            .flat_map(|t| t.segments.iter())
+            .flat_map(|s| s.points.iter())
+            .map(|p| Point::from(p.point()))
+            .collect::<Vec<_>>();
+
+        if track.len() < 2 {
+            return Err(Error::Other(
+                "recording needs at least two track points".to_string(),
+            ));
+        }
+
+        self.request_route(&RouteRequest::new(&via_points(&track, options), profile))
+    }
+}
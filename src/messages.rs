@@ -0,0 +1,223 @@
+use crate::{Error, Point, TurnCommand};
+
+/// A row of brouter's per-segment messages table.
+///
+/// Each message describes the stretch of the route leading up to `point`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SegmentMessage {
+    pub point: Point,
+
+    pub elevation: Option<f64>,
+
+    /// Length of the segment, in meters.
+    pub distance_m: f64,
+
+    pub cost_per_km: f64,
+
+    pub elev_cost: f64,
+
+    pub turn_cost: f64,
+
+    pub node_cost: f64,
+
+    pub initial_cost: f64,
+
+    pub way_tags: Vec<(String, String)>,
+
+    pub node_tags: Vec<(String, String)>,
+
+    /// Time since the start of the route, in seconds; only set for kinematic profiles.
+    pub time_s: Option<f64>,
+
+    /// Energy spent since the start of the route, in joules; only set for kinematic profiles.
+    pub energy_j: Option<f64>,
+}
+
+fn parse_tags(s: &str) -> Vec<(String, String)> {
+    s.split_whitespace()
+        .map(|kv| match kv.split_once('=') {
+            Some((k, v)) => (k.to_string(), v.to_string()),
+            None => (kv.to_string(), String::new()),
+        })
+        .collect()
+}
+
+impl SegmentMessage {
+    /// The total cost brouter assigned to this segment.
+    pub fn cost(&self) -> f64 {
+        self.cost_per_km * self.distance_m / 1000.0
+            + self.elev_cost
+            + self.turn_cost
+            + self.node_cost
+            + self.initial_cost
+    }
+
+    pub fn way_tag(&self, key: &str) -> Option<&str> {
+        self.way_tags
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn node_tag(&self, key: &str) -> Option<&str> {
+        self.node_tags
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Parse a messages table, given as a header row followed by data rows.
+    pub fn parse_rows<R, C>(mut rows: R) -> Result<Vec<SegmentMessage>, Error>
+    where
+        R: Iterator<Item = Vec<C>>,
+        C: AsRef<str>,
+    {
+        let header = match rows.next() {
+            Some(header) => header
+                .iter()
+                .map(|c| c.as_ref().trim().to_string())
+                .collect::<Vec<_>>(),
+            None => return Ok(vec![]),
+        };
+
+        let column = |name: &str| header.iter().position(|c| c == name);
+        let required = |name: &str| {
+            column(name).ok_or_else(|| Error::InvalidMessages(format!("missing column {}", name)))
+        };
+
+        let lon = required("Longitude")?;
+        let lat = required("Latitude")?;
+        let distance = required("Distance")?;
+        let cost_per_km = required("CostPerKm")?;
+        let elevation = column("Elevation");
+        let elev_cost = column("ElevCost");
+        let turn_cost = column("TurnCost");
+        let node_cost = column("NodeCost");
+        let initial_cost = column("InitialCost");
+        let way_tags = column("WayTags");
+        let node_tags = column("NodeTags");
+        let time = column("Time");
+        let energy = column("Energy");
+
+        rows.filter(|row| !row.iter().all(|c| c.as_ref().trim().is_empty()))
+            .map(|row| {
+                let get = |i: Option<usize>| {
+                    i.and_then(|i| row.get(i))
+                        .map(|c| c.as_ref().trim())
+                        .filter(|c| !c.is_empty())
+                };
+                let number = |i: Option<usize>| -> Result<Option<f64>, Error> {
+                    get(i)
+                        .map(|c| {
+                            c.parse::<f64>().map_err(|_| {
+                                Error::InvalidMessages(format!("invalid number: {}", c))
+                            })
+                        })
+                        .transpose()
+                };
+                let required_number = |i: usize| -> Result<f64, Error> {
+                    number(Some(i))?.ok_or_else(|| {
+                        Error::InvalidMessages(format!("missing value for {}", header[i]))
+                    })
+                };
+
+                Ok(SegmentMessage {
+                    // Coordinates are in micro-degrees.
+                    point: Point::new(
+                        required_number(lat)? / 1_000_000.0,
+                        required_number(lon)? / 1_000_000.0,
+                    ),
+                    elevation: number(elevation)?,
+                    distance_m: required_number(distance)?,
+                    cost_per_km: required_number(cost_per_km)?,
+                    elev_cost: number(elev_cost)?.unwrap_or(0.0),
+                    turn_cost: number(turn_cost)?.unwrap_or(0.0),
+                    node_cost: number(node_cost)?.unwrap_or(0.0),
+                    initial_cost: number(initial_cost)?.unwrap_or(0.0),
+                    way_tags: get(way_tags).map(parse_tags).unwrap_or_default(),
+                    node_tags: get(node_tags).map(parse_tags).unwrap_or_default(),
+                    time_s: number(time)?,
+                    energy_j: number(energy)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Parse the tab-separated messages table brouter returns for `format=csv`.
+    pub fn parse_csv(text: &str) -> Result<Vec<SegmentMessage>, Error> {
+        Self::parse_rows(text.lines().map(|l| l.split('\t').collect::<Vec<_>>()))
+    }
+}
+
+/// A turn from brouter's CSV output.
+///
+/// Some brouter versions only include turns in the CSV output and others only in the voice
+/// hints of the GPX output, so this is kept separate from [`crate::TurnInstruction`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CuePoint {
+    pub point: Point,
+
+    /// Distance along the route, in meters.
+    pub distance_m: f64,
+
+    pub command: TurnCommand,
+
+    pub way_name: Option<String>,
+}
+
+/// Names of the columns that brouter has used for turns in its CSV output.
+const TURN_COLUMNS: &[&str] = &["VoiceHint", "Turn", "Command"];
+
+impl CuePoint {
+    /// Parse the turns from the tab-separated messages table brouter returns for `format=csv`.
+    ///
+    /// Returns an empty list if the table has no turn column, e.g. because no turn
+    /// instruction mode was requested.
+    pub fn parse_csv(text: &str) -> Result<Vec<CuePoint>, Error> {
+        let mut rows = text
+            .lines()
+            .map(|l| l.split('\t').map(|c| c.trim()).collect::<Vec<_>>())
+            .filter(|row| !row.iter().all(|c| c.is_empty()));
+        let header = match rows.next() {
+            Some(header) => header,
+            None => return Ok(vec![]),
+        };
+        let turn = match header.iter().position(|c| TURN_COLUMNS.contains(c)) {
+            Some(turn) => turn,
+            None => return Ok(vec![]),
+        };
+        let way_name = header.iter().position(|c| *c == "WayName" || *c == "Name");
+
+        let messages = SegmentMessage::parse_csv(text)?;
+        let mut distance_m = 0.0;
+        let mut cues = vec![];
+        for (row, message) in rows.zip(&messages) {
+            distance_m += message.distance_m;
+            let command = match row.get(turn).filter(|c| !c.is_empty()) {
+                Some(c) => TurnCommand::from_command_string(c)
+                    .or_else(|| TurnCommand::from_message(c))
+                    .or_else(|| {
+                        c.parse()
+                            .ok()
+                            .and_then(|code| TurnCommand::from_code(code, 0))
+                    }),
+                None => None,
+            };
+            if let Some(command) = command {
+                cues.push(CuePoint {
+                    point: message.point.clone(),
+                    distance_m,
+                    command,
+                    way_name: way_name
+                        .and_then(|i| row.get(i))
+                        .filter(|n| !n.is_empty())
+                        .map(|n| n.to_string())
+                        .or_else(|| message.way_tag("name").map(|n| n.to_string())),
+                });
+            }
+        }
+        Ok(cues)
+    }
+}
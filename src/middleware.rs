@@ -0,0 +1,16 @@
+//! Hooks around the HTTP requests made to a brouter server.
+
+use reqwest::blocking::{Request, Response};
+
+/// Code that runs around every HTTP request a [`crate::Brouter`] makes.
+///
+/// This makes it possible to sign requests, add headers or record responses without changing
+/// the client. Middleware added with [`crate::BrouterBuilder::middleware`] runs in the order it
+/// was added.
+pub trait Middleware: Send + Sync {
+    /// Called before the request is sent.
+    fn before(&self, _request: &mut Request) {}
+
+    /// Called when a response has been received, including error responses.
+    fn after(&self, _response: &Response) {}
+}
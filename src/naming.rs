@@ -0,0 +1,115 @@
+//! Templates for naming routes and the files they are written to.
+//!
+//! Templates contain placeholders in braces, e.g. `"{date} {profile} {from}-{to}"`:
+//!
+//! * `{date}` and `{time}`: when the route was requested, as `2024-05-01` and `14:30`
+//! * `{profile}`: the profile name
+//! * `{from}`, `{to}`: names of the first and last point, or their coordinates
+//! * `{points}`: the number of points
+//! * `{alternative}`: the index of the alternative route, 0 by default
+//!
+//! Unknown placeholders are kept as they are.
+
+use crate::geocode::{self, Geocoder};
+use crate::{Point, RouteRequest};
+use lazy_regex::regex;
+use std::path::{Path, PathBuf};
+use time::OffsetDateTime;
+
+/// A template for names of routes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTemplate {
+    template: String,
+}
+
+fn point_name(request: &RouteRequest, index: usize) -> String {
+    match request.point_names.get(index) {
+        Some(Some(name)) => name.clone(),
+        _ => request
+            .points
+            .get(index)
+            .map(|p: &Point| format!("{:.4},{:.4}", p.lat(), p.lon()))
+            .unwrap_or_default(),
+    }
+}
+
+impl NameTemplate {
+    pub fn new(template: &str) -> Self {
+        NameTemplate {
+            template: template.to_string(),
+        }
+    }
+
+    /// The name for `request`, requested at `time`.
+    pub fn render(&self, request: &RouteRequest, time: OffsetDateTime) -> String {
+        regex!(r"\{([a-z]+)\}")
+            .replace_all(&self.template, |c: &lazy_regex::Captures| match &c[1] {
+                "date" => format!(
+                    "{:04}-{:02}-{:02}",
+                    time.year(),
+                    time.month() as u8,
+                    time.day()
+                ),
+                "time" => format!("{:02}:{:02}", time.hour(), time.minute()),
+                "profile" => request.profile.clone(),
+                "from" => point_name(request, 0),
+                "to" => point_name(request, request.points.len().saturating_sub(1)),
+                "points" => request.points.len().to_string(),
+                "alternative" => request.alternative.unwrap_or(0).to_string(),
+                _ => c[0].to_string(),
+            })
+            .into_owned()
+    }
+}
+
+/// A name for the route of `request` from the places it starts and ends at, e.g.
+/// "Berlin → Potsdam", or just "Berlin" for round trips.
+///
+/// Named points keep their names; the others are looked up with `geocoder`. Returns `None`
+/// if either end can't be named.
+pub fn endpoint_name(
+    request: &RouteRequest,
+    geocoder: &dyn Geocoder,
+) -> Result<Option<String>, geocode::Error> {
+    let name = |index: usize| -> Result<Option<String>, geocode::Error> {
+        match request.point_names.get(index) {
+            Some(Some(name)) => Ok(Some(name.clone())),
+            _ => match request.points.get(index) {
+                Some(point) => geocoder.reverse(point),
+                None => Ok(None),
+            },
+        }
+    };
+    let last = request.points.len().saturating_sub(1);
+    let (from, to) = match (name(0)?, name(last)?) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return Ok(None),
+    };
+    Ok(Some(if from == to {
+        from
+    } else {
+        format!("{} → {}", from, to)
+    }))
+}
+
+/// A path in `dir` for a file named `name` with `extension` that doesn't exist yet.
+///
+/// Characters that aren't allowed in file names are replaced, and if the file exists a number
+/// is appended, e.g. `route (2).gpx`.
+pub fn unique_path(dir: &Path, name: &str, extension: &str) -> PathBuf {
+    let name = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>();
+    let mut path = dir.join(format!("{}.{}", name, extension));
+    let mut n = 2;
+    while path.exists() {
+        path = dir.join(format!("{} ({}).{}", name, n, extension));
+        n += 1;
+    }
+    path
+}
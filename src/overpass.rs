@@ -0,0 +1,208 @@
+//! Generating nogos from Overpass queries.
+//!
+//! This makes it possible to avoid features that change frequently, such as construction
+//! sites or flooded areas, by querying OpenStreetMap through the Overpass API at routing time.
+//!
+//! Queries have to request JSON output with geometry, i.e. start with `[out:json]` and end
+//! with `out geom;`; [`bbox_query`] builds such a query for a single tag filter.
+
+use crate::{Nogo, Point};
+use geo_types::Rect;
+use reqwest::blocking::Client;
+use serde_json::Value;
+
+pub const DEFAULT_ENDPOINT: &str = "https://overpass-api.de/api/interpreter";
+
+#[derive(Debug)]
+pub enum Error {
+    Http(reqwest::Error),
+    Json(serde_json::Error),
+    /// The server reported an error, e.g. because the query is invalid or timed out.
+    Overpass(String),
+    /// The response is valid JSON, but not an Overpass result with geometry.
+    InvalidResponse(String),
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "HTTP error: {}", e),
+            Error::Json(e) => write!(f, "JSON error: {}", e),
+            Error::Overpass(e) => write!(f, "Overpass error: {}", e),
+            Error::InvalidResponse(e) => write!(f, "Invalid response: {}", e),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+/// How Overpass elements are turned into nogos.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NogoOptions {
+    /// Radius of the nogos created for nodes, in meters.
+    pub node_radius_m: f64,
+
+    /// Weight of the nogos; `None` makes them impassable.
+    pub weight: Option<f64>,
+}
+
+impl Default for NogoOptions {
+    fn default() -> Self {
+        NogoOptions {
+            node_radius_m: 20.0,
+            weight: None,
+        }
+    }
+}
+
+/// A query for the nodes and ways matching `filter` (e.g. `[highway=construction]`) within
+/// `bbox`, in degrees.
+pub fn bbox_query(filter: &str, bbox: &Rect<f64>) -> String {
+    let bbox = format!(
+        "{},{},{},{}",
+        bbox.min().y,
+        bbox.min().x,
+        bbox.max().y,
+        bbox.max().x
+    );
+    format!(
+        "[out:json][timeout:60];(node{filter}({bbox});way{filter}({bbox}););out geom;",
+        filter = filter,
+        bbox = bbox
+    )
+}
+
+fn point(value: &Value) -> Option<Point> {
+    Some(Point::new(value["lat"].as_f64()?, value["lon"].as_f64()?))
+}
+
+fn geometry(value: &Value) -> Option<Vec<Point>> {
+    value.as_array()?.iter().map(point).collect()
+}
+
+/// Whether a closed way describes an area rather than a loop of road or path.
+fn is_area(tags: &Value) -> bool {
+    match tags["area"].as_str() {
+        Some("yes") => true,
+        Some("no") => false,
+        _ => tags.get("highway").is_none() && tags.get("barrier").is_none(),
+    }
+}
+
+/// Convert an Overpass JSON result, as returned for queries ending in `out geom;`, to nogos.
+///
+/// Nodes become point nogos, closed ways that describe areas become polygons and other ways
+/// lines. The member ways of relations, such as the rings of multipolygons, become lines.
+pub fn nogos_from_json(text: &str, options: &NogoOptions) -> Result<Vec<Nogo>, Error> {
+    let result: Value = serde_json::from_str(text)?;
+    if let Some(remark) = result["remark"].as_str() {
+        if remark.contains("error") {
+            return Err(Error::Overpass(remark.to_string()));
+        }
+    }
+    let elements = result["elements"]
+        .as_array()
+        .ok_or_else(|| Error::InvalidResponse("no elements".to_string()))?;
+
+    let mut nogos = vec![];
+    for element in elements {
+        match element["type"].as_str() {
+            Some("node") => {
+                if let Some(point) = point(element) {
+                    nogos.push(Nogo::Point {
+                        point,
+                        radius: options.node_radius_m,
+                        weight: options.weight,
+                    });
+                }
+            }
+            Some("way") => {
+                let points = geometry(&element["geometry"]).ok_or_else(|| {
+                    Error::InvalidResponse("way without geometry; use 'out geom'".to_string())
+                })?;
+                if points.len() < 2 {
+                    continue;
+                }
+                if points.len() >= 4 && points.first() == points.last() && is_area(&element["tags"])
+                {
+                    nogos.push(Nogo::Polygon {
+                        points,
+                        weight: options.weight,
+                    });
+                } else {
+                    nogos.push(Nogo::Line {
+                        points,
+                        weight: options.weight,
+                    });
+                }
+            }
+            Some("relation") => {
+                for member in element["members"].as_array().into_iter().flatten() {
+                    if let Some(points) = geometry(&member["geometry"]) {
+                        if points.len() >= 2 {
+                            nogos.push(Nogo::Line {
+                                points,
+                                weight: options.weight,
+                            });
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(nogos)
+}
+
+/// A client for an Overpass API server.
+pub struct Overpass {
+    client: Client,
+    endpoint: String,
+}
+
+impl Default for Overpass {
+    fn default() -> Self {
+        Self::new(DEFAULT_ENDPOINT)
+    }
+}
+
+impl Overpass {
+    pub fn new(endpoint: &str) -> Self {
+        Overpass {
+            client: Client::builder()
+                .user_agent(concat!("brouter-client/", env!("CARGO_PKG_VERSION")))
+                .build()
+                .unwrap(),
+            endpoint: endpoint.to_string(),
+        }
+    }
+
+    /// Run an Overpass QL query, returning the raw response.
+    pub fn query(&self, query: &str) -> Result<String, Error> {
+        let response = self
+            .client
+            .post(&self.endpoint)
+            .form(&[("data", query)])
+            .send()
+            .map_err(Error::Http)?;
+        let status = response.status();
+        let text = response.text().map_err(Error::Http)?;
+        if !status.is_success() {
+            return Err(Error::Overpass(format!("{}: {}", status, text.trim())));
+        }
+        Ok(text)
+    }
+
+    /// Run an Overpass QL query and convert the result to nogos.
+    ///
+    /// See [`nogos_from_json`].
+    pub fn nogos(&self, query: &str, options: &NogoOptions) -> Result<Vec<Nogo>, Error> {
+        nogos_from_json(&self.query(query)?, options)
+    }
+}
@@ -0,0 +1,90 @@
+//! Post-processing applied to every calculated route.
+//!
+//! A [`RoutePipeline`] is configured once, e.g. with [`crate::BrouterBuilder::pipeline`], and
+//! then runs its steps in order on each route:
+//!
+//! ```no_run
+//! use brouter_client::{Brouter, RoutePipeline};
+//!
+//! let router = Brouter::builder("http://localhost:17777")
+//!     .pipeline(RoutePipeline::new().simplify(5.0))
+//!     .build();
+//! ```
+
+use crate::{Error, Route};
+use time::OffsetDateTime;
+
+type StepError = Box<dyn std::error::Error + Send + Sync>;
+
+type Step = Box<dyn Fn(&mut Route) -> Result<(), StepError> + Send + Sync>;
+
+/// A sequence of transformations of routes.
+#[derive(Default)]
+pub struct RoutePipeline {
+    steps: Vec<Step>,
+}
+
+impl std::fmt::Debug for RoutePipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RoutePipeline")
+            .field("steps", &self.steps.len())
+            .finish()
+    }
+}
+
+impl RoutePipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a custom step.
+    pub fn then<F, E>(mut self, step: F) -> Self
+    where
+        F: Fn(&mut Route) -> Result<(), E> + Send + Sync + 'static,
+        E: Into<StepError>,
+    {
+        self.steps
+            .push(Box::new(move |route| step(route).map_err(Into::into)));
+        self
+    }
+
+    /// Drop track points within `tolerance_m` meters of the line through their neighbours,
+    /// see [`Route::simplify`].
+    pub fn simplify(self, tolerance_m: f64) -> Self {
+        self.then(move |route| {
+            route.simplify(tolerance_m);
+            Ok::<_, StepError>(())
+        })
+    }
+
+    /// Fill in missing elevations from `dem`, see [`Route::enrich_elevation`].
+    #[cfg(feature = "elevation")]
+    pub fn enrich_elevation(
+        self,
+        dem: impl Into<std::sync::Arc<crate::elevation::DemCache>>,
+    ) -> Self {
+        let dem = dem.into();
+        self.then(move |route| route.enrich_elevation(&dem).map(|_| ()))
+    }
+
+    /// Set the times of the track points for a departure at `start`, see
+    /// [`Route::set_timestamps`].
+    pub fn with_timestamps(self, start: OffsetDateTime) -> Self {
+        self.then(move |route| {
+            route.set_timestamps(start);
+            Ok::<_, StepError>(())
+        })
+    }
+
+    /// Whether the pipeline has no steps.
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Run the steps on `route`, stopping at the first that fails.
+    pub fn apply(&self, route: &mut Route) -> Result<(), Error> {
+        self.steps
+            .iter()
+            .try_for_each(|step| step(route).map_err(Error::PostProcessing))
+    }
+}
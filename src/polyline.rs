@@ -0,0 +1,78 @@
+//! Encoded polylines, as used by Google, OSRM and Valhalla.
+//!
+//! See <https://developers.google.com/maps/documentation/utilities/polylinealgorithm>. Google
+//! and OSRM use a precision of 5 decimal places; Valhalla uses 6.
+
+use crate::{Error, Point};
+
+fn decode_value(bytes: &mut impl Iterator<Item = u8>) -> Result<Option<i64>, Error> {
+    let mut result = 0i64;
+    let mut shift = 0;
+    loop {
+        let byte = match bytes.next() {
+            Some(byte) => byte,
+            None if shift == 0 => return Ok(None),
+            None => return Err(Error::InvalidPolyline("truncated value".to_string())),
+        };
+        if !(63..127).contains(&byte) || shift > 60 {
+            return Err(Error::InvalidPolyline(format!(
+                "invalid character '{}'",
+                byte as char
+            )));
+        }
+        let chunk = (byte - 63) as i64;
+        result |= (chunk & 0x1f) << shift;
+        shift += 5;
+        if chunk < 0x20 {
+            break;
+        }
+    }
+    Ok(Some(if result & 1 != 0 {
+        !(result >> 1)
+    } else {
+        result >> 1
+    }))
+}
+
+fn encode_value(value: i64, out: &mut String) {
+    let mut value = if value < 0 { !(value << 1) } else { value << 1 };
+    while value >= 0x20 {
+        out.push((((value & 0x1f) | 0x20) as u8 + 63) as char);
+        value >>= 5;
+    }
+    out.push((value as u8 + 63) as char);
+}
+
+impl Point {
+    /// Decode an encoded polyline with `precision` decimal places.
+    pub fn from_polyline(polyline: &str, precision: u32) -> Result<Vec<Point>, Error> {
+        let factor = 10f64.powi(precision as i32);
+        let mut bytes = polyline.bytes();
+        let mut points = vec![];
+        let (mut lat, mut lon) = (0i64, 0i64);
+        while let Some(dlat) = decode_value(&mut bytes)? {
+            let dlon = decode_value(&mut bytes)?
+                .ok_or_else(|| Error::InvalidPolyline("missing longitude".to_string()))?;
+            let overflow = || Error::InvalidPolyline("coordinate out of range".to_string());
+            lat = lat.checked_add(dlat).ok_or_else(overflow)?;
+            lon = lon.checked_add(dlon).ok_or_else(overflow)?;
+            points.push(Point::new(lat as f64 / factor, lon as f64 / factor));
+        }
+        Ok(points)
+    }
+
+    /// Encode `points` as a polyline with `precision` decimal places.
+    pub fn to_polyline(points: &[Point], precision: u32) -> String {
+        let factor = 10f64.powi(precision as i32);
+        let mut out = String::new();
+        let (mut prev_lat, mut prev_lon) = (0i64, 0i64);
+        for point in points {
+            let lat = (point.lat() * factor).round() as i64;
+            let lon = (point.lon() * factor).round() as i64;
+            encode_value(lat - prev_lat, &mut out);
+            encode_value(lon - prev_lon, &mut out);
+            (prev_lat, prev_lon) = (lat, lon);
+        }
+        out
+    }
+}
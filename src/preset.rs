@@ -0,0 +1,174 @@
+//! Named bundles of a profile, profile parameters and turn instruction mode.
+//!
+//! Besides the built-in presets, users can define their own in a configuration file with a
+//! section per preset:
+//!
+//! ```text
+//! # Comments start with '#'.
+//! [audax]
+//! profile = fastbike-lowtraffic
+//! turn-instructions = 3
+//! profile:avoid_unsafe = 1
+//! ```
+//!
+//! `turn-instructions` takes the name of a mode, e.g. `osmand`, or the value of brouter's `timode`
+//! parameter.
+
+use crate::{Error, RouteRequest, TurnInstructionMode};
+use std::path::{Path, PathBuf};
+
+/// A preset defined by the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CustomPreset {
+    pub name: String,
+    pub profile: String,
+    pub profile_params: Vec<(String, String)>,
+    pub turn_instructions: Option<TurnInstructionMode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RoutePreset {
+    /// Fast and direct, with the `fastbike` profile.
+    Commute,
+    /// Bicycle touring, with the `trekking` profile.
+    Touring,
+    /// The `trekking` profile kept off busy roads. None of brouter's standard profiles prefer
+    /// unpaved roads; define a custom preset to use a dedicated gravel profile.
+    Gravel,
+    Custom(CustomPreset),
+}
+
+impl RoutePreset {
+    pub const BUILTIN: [RoutePreset; 3] = [
+        RoutePreset::Commute,
+        RoutePreset::Touring,
+        RoutePreset::Gravel,
+    ];
+
+    pub fn name(&self) -> &str {
+        match self {
+            RoutePreset::Commute => "commute",
+            RoutePreset::Touring => "touring",
+            RoutePreset::Gravel => "gravel",
+            RoutePreset::Custom(preset) => &preset.name,
+        }
+    }
+
+    pub fn profile(&self) -> &str {
+        match self {
+            RoutePreset::Commute => "fastbike",
+            RoutePreset::Touring | RoutePreset::Gravel => "trekking",
+            RoutePreset::Custom(preset) => &preset.profile,
+        }
+    }
+
+    pub fn profile_params(&self) -> Vec<(String, String)> {
+        match self {
+            RoutePreset::Commute | RoutePreset::Touring => vec![],
+            RoutePreset::Gravel => vec![("avoid_unsafe".to_string(), "1".to_string())],
+            RoutePreset::Custom(preset) => preset.profile_params.clone(),
+        }
+    }
+
+    pub fn turn_instructions(&self) -> Option<TurnInstructionMode> {
+        match self {
+            RoutePreset::Custom(preset) => preset.turn_instructions,
+            _ => Some(TurnInstructionMode::OsmandStyle),
+        }
+    }
+
+    /// The preset called `name`, looking in `custom` first so that users can redefine the
+    /// built-in presets.
+    pub fn find(name: &str, custom: &[RoutePreset]) -> Option<RoutePreset> {
+        custom
+            .iter()
+            .chain(Self::BUILTIN.iter())
+            .find(|p| p.name() == name)
+            .cloned()
+    }
+
+    /// The default location of the presets file: `brouter-client/presets` in the XDG config
+    /// directory.
+    pub fn default_config_path() -> Option<PathBuf> {
+        Some(crate::dirs::config_dir()?.join("presets"))
+    }
+
+    /// Read custom presets from a file; a missing file has none.
+    pub fn load(path: &Path) -> Result<Vec<RoutePreset>, Error> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse_config(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Parse custom presets in the format described in the [module documentation](self).
+    pub fn parse_config(text: &str) -> Result<Vec<RoutePreset>, Error> {
+        let error = |line: usize, message: &str| {
+            Error::InvalidRequest(format!("presets line {}: {}", line + 1, message))
+        };
+        let mut presets: Vec<CustomPreset> = vec![];
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                presets.push(CustomPreset {
+                    name: name.trim().to_string(),
+                    profile: String::new(),
+                    profile_params: vec![],
+                    turn_instructions: None,
+                });
+                continue;
+            }
+            let preset = presets
+                .last_mut()
+                .ok_or_else(|| error(i, "setting outside of a preset"))?;
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error(i, "expected 'key = value'"))?;
+            let (key, value) = (key.trim(), value.trim());
+            match key {
+                "profile" => preset.profile = value.to_string(),
+                "turn-instructions" => {
+                    preset.turn_instructions = Some(
+                        value
+                            .parse::<TurnInstructionMode>()
+                            .map_err(|e| error(i, &e))?,
+                    )
+                }
+                key => match key.strip_prefix("profile:") {
+                    Some(name) => preset
+                        .profile_params
+                        .push((name.to_string(), value.to_string())),
+                    None => return Err(error(i, &format!("unknown setting '{}'", key))),
+                },
+            }
+        }
+        if let Some(preset) = presets.iter().find(|p| p.profile.is_empty()) {
+            return Err(Error::InvalidRequest(format!(
+                "preset '{}' has no profile",
+                preset.name
+            )));
+        }
+        Ok(presets.into_iter().map(RoutePreset::Custom).collect())
+    }
+}
+
+impl RouteRequest {
+    /// Use the profile, profile parameters and turn instruction mode of `preset`.
+    ///
+    /// Profile parameters set before are kept, unless the preset overrides them.
+    pub fn preset(mut self, preset: &RoutePreset) -> Self {
+        self.profile = preset.profile().to_string();
+        for (name, value) in preset.profile_params() {
+            self.profile_params.retain(|(n, _)| *n != name);
+            self.profile_params.push((name, value));
+        }
+        self.turn_instructions = preset.turn_instructions();
+        self
+    }
+}
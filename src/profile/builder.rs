@@ -0,0 +1,139 @@
+use super::{Assign, Context, ContextKind, Expr, Profile, Statement};
+use lazy_regex::regex_is_match;
+
+/// Builds the statements of a single context.
+#[derive(Debug, Clone, Default)]
+pub struct ContextBuilder {
+    statements: Vec<Statement>,
+}
+
+impl ContextBuilder {
+    /// Assign `value` to the variable `name`.
+    ///
+    /// # Panics
+    ///
+    /// If `name` is not a valid variable name.
+    pub fn assign(self, name: &str, value: impl Into<Expr>) -> Self {
+        self.assign_with_comment(name, value, None)
+    }
+
+    /// Assign `value` to the variable `name`, with a comment on the same line.
+    ///
+    /// # Panics
+    ///
+    /// If `name` is not a valid variable name.
+    pub fn assign_with_comment(
+        mut self,
+        name: &str,
+        value: impl Into<Expr>,
+        comment: Option<&str>,
+    ) -> Self {
+        assert!(
+            regex_is_match!(r"^[A-Za-z_][A-Za-z0-9_]*$", name),
+            "invalid variable name: {:?}",
+            name
+        );
+        self.statements.push(Statement::Assign(Assign {
+            name: name.to_string(),
+            value: value.into(),
+            comment: comment.map(|c| format!(" {}", c)),
+        }));
+        self
+    }
+
+    /// Add a comment on a line of its own.
+    pub fn comment(mut self, comment: &str) -> Self {
+        for line in comment.lines() {
+            self.statements
+                .push(Statement::Comment(format!(" {}", line)));
+        }
+        self
+    }
+
+    pub fn blank(mut self) -> Self {
+        self.statements.push(Statement::Blank);
+        self
+    }
+}
+
+/// Builds a profile.
+///
+/// Contexts are always written in the order global, way, node, regardless of the order in
+/// which they are built.
+#[derive(Debug, Clone, Default)]
+pub struct ProfileBuilder {
+    comment: Option<String>,
+    model: Option<String>,
+    global: Option<ContextBuilder>,
+    way: Option<ContextBuilder>,
+    node: Option<ContextBuilder>,
+}
+
+impl ProfileBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set a comment at the top of the profile.
+    pub fn comment(mut self, comment: &str) -> Self {
+        self.comment = Some(comment.to_string());
+        self
+    }
+
+    /// Set the cost model, e.g. `btools.router.KinematicModel`.
+    pub fn model(mut self, model: &str) -> Self {
+        self.model = Some(model.to_string());
+        self
+    }
+
+    fn extend(
+        context: Option<ContextBuilder>,
+        f: impl FnOnce(ContextBuilder) -> ContextBuilder,
+    ) -> Option<ContextBuilder> {
+        Some(f(context.unwrap_or_default()))
+    }
+
+    pub fn global(mut self, f: impl FnOnce(ContextBuilder) -> ContextBuilder) -> Self {
+        self.global = Self::extend(self.global, f);
+        self
+    }
+
+    pub fn way(mut self, f: impl FnOnce(ContextBuilder) -> ContextBuilder) -> Self {
+        self.way = Self::extend(self.way, f);
+        self
+    }
+
+    pub fn node(mut self, f: impl FnOnce(ContextBuilder) -> ContextBuilder) -> Self {
+        self.node = Self::extend(self.node, f);
+        self
+    }
+
+    /// Build the profile.
+    ///
+    /// The global, way and node contexts are always present, since brouter requires them.
+    pub fn build(self) -> Profile {
+        let contexts = [
+            (ContextKind::Global, self.global),
+            (ContextKind::Way, self.way),
+            (ContextKind::Node, self.node),
+        ]
+        .into_iter()
+        .map(|(kind, builder)| Context {
+            kind,
+            comment: None,
+            statements: builder.unwrap_or_default().statements,
+        })
+        .collect();
+
+        Profile {
+            preamble: self
+                .comment
+                .iter()
+                .flat_map(|c| c.lines())
+                .map(|line| Statement::Comment(format!(" {}", line)))
+                .collect(),
+            model: self.model,
+            contexts,
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{Brouter, Error, Point, SegmentMessage};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Statistics of a route, derived from brouter's segment messages.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RouteStats {
+    pub distance_m: f64,
+
+    pub ascent_m: f64,
+
+    pub cost: f64,
+
+    /// Distance in meters by value of the `surface` tag; "unknown" for ways without one.
+    pub surfaces: BTreeMap<String, f64>,
+}
+
+impl RouteStats {
+    pub fn from_messages(messages: &[SegmentMessage]) -> Self {
+        let mut stats = RouteStats::default();
+        let mut elevation = None;
+        for message in messages {
+            stats.distance_m += message.distance_m;
+            stats.cost += message.cost();
+            if let (Some(prev), Some(e)) = (elevation, message.elevation) {
+                if e > prev {
+                    stats.ascent_m += e - prev;
+                }
+            }
+            elevation = message.elevation.or(elevation);
+            *stats
+                .surfaces
+                .entry(message.way_tag("surface").unwrap_or("unknown").to_string())
+                .or_default() += message.distance_m;
+        }
+        stats
+    }
+}
+
+/// The result of routing a pair of points with two profiles.
+#[derive(Debug)]
+pub struct RouteComparison {
+    pub from: Point,
+    pub to: Point,
+    pub a: Result<RouteStats, Error>,
+    pub b: Result<RouteStats, Error>,
+}
+
+impl RouteComparison {
+    fn delta(&self, f: impl Fn(&RouteStats) -> f64) -> Option<f64> {
+        match (&self.a, &self.b) {
+            (Ok(a), Ok(b)) => Some(f(b) - f(a)),
+            _ => None,
+        }
+    }
+
+    /// Change in distance from profile a to profile b, if both found a route.
+    pub fn distance_delta_m(&self) -> Option<f64> {
+        self.delta(|s| s.distance_m)
+    }
+
+    pub fn ascent_delta_m(&self) -> Option<f64> {
+        self.delta(|s| s.ascent_m)
+    }
+
+    pub fn cost_delta(&self) -> Option<f64> {
+        self.delta(|s| s.cost)
+    }
+
+    /// Change in distance per surface, leaving out surfaces whose distance didn't change.
+    pub fn surface_deltas_m(&self) -> BTreeMap<String, f64> {
+        let (Ok(a), Ok(b)) = (&self.a, &self.b) else {
+            return BTreeMap::new();
+        };
+        let mut deltas = b.surfaces.clone();
+        for (surface, distance) in &a.surfaces {
+            *deltas.entry(surface.clone()).or_default() -= distance;
+        }
+        deltas.retain(|_, delta| delta.abs() >= 0.5);
+        deltas
+    }
+}
+
+impl fmt::Display for RouteComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{},{} -> {},{}: ",
+            self.from.lat(),
+            self.from.lon(),
+            self.to.lat(),
+            self.to.lon()
+        )?;
+        match (&self.a, &self.b) {
+            (Err(e), _) => return write!(f, "profile a failed: {}", e),
+            (_, Err(e)) => return write!(f, "profile b failed: {}", e),
+            _ => {}
+        }
+        write!(
+            f,
+            "distance {:+.2} km, ascent {:+.0} m, cost {:+.0}",
+            self.distance_delta_m().unwrap() / 1000.0,
+            self.ascent_delta_m().unwrap(),
+            self.cost_delta().unwrap()
+        )?;
+        let surfaces = self.surface_deltas_m();
+        if !surfaces.is_empty() {
+            f.write_str(", surfaces:")?;
+            for (surface, delta) in surfaces {
+                write!(f, " {} {:+.2} km", surface, delta / 1000.0)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Routes a set of representative point pairs with two profiles, to see how a change to a
+/// profile affects the routes it produces.
+pub struct ProfileComparison<'a> {
+    brouter: &'a Brouter,
+    pairs: Vec<(Point, Point)>,
+}
+
+impl<'a> ProfileComparison<'a> {
+    pub fn new(brouter: &'a Brouter) -> Self {
+        ProfileComparison {
+            brouter,
+            pairs: vec![],
+        }
+    }
+
+    pub fn pair(mut self, from: Point, to: Point) -> Self {
+        self.pairs.push((from, to));
+        self
+    }
+
+    pub fn pairs(mut self, pairs: impl IntoIterator<Item = (Point, Point)>) -> Self {
+        self.pairs.extend(pairs);
+        self
+    }
+
+    fn stats(&self, from: &Point, to: &Point, profile: &str) -> Result<RouteStats, Error> {
+        self.brouter
+            .broute_messages(&[from.clone(), to.clone()], &[], profile, None)
+            .map(|messages| RouteStats::from_messages(&messages))
+    }
+
+    /// Route every pair with the profiles named `a` and `b`, which have to be known to the
+    /// server.
+    pub fn compare(&self, a: &str, b: &str) -> Vec<RouteComparison> {
+        self.pairs
+            .iter()
+            .map(|(from, to)| RouteComparison {
+                from: from.clone(),
+                to: to.clone(),
+                a: self.stats(from, to, a),
+                b: self.stats(from, to, b),
+            })
+            .collect()
+    }
+
+    /// Upload two versions of a profile and route every pair with both.
+    pub fn compare_texts(&self, a: &str, b: &str) -> Result<Vec<RouteComparison>, Error> {
+        let a = self.brouter.upload_custom_profile(a.as_bytes().to_vec())?;
+        let b = self.brouter.upload_custom_profile(b.as_bytes().to_vec())?;
+        Ok(self.compare(&a, &b))
+    }
+}
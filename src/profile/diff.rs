@@ -0,0 +1,180 @@
+use super::{Context, ContextKind, Expr, Profile};
+use std::fmt;
+
+/// A difference between two profiles.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    ModelChanged {
+        old: Option<String>,
+        new: Option<String>,
+    },
+    ContextAdded(ContextKind),
+    ContextRemoved(ContextKind),
+    Added {
+        context: ContextKind,
+        name: String,
+        value: Expr,
+    },
+    Removed {
+        context: ContextKind,
+        name: String,
+        value: Expr,
+    },
+    Changed {
+        context: ContextKind,
+        name: String,
+        old: Expr,
+        new: Expr,
+    },
+    /// Assignments present in both profiles appear in a different order.
+    Reordered {
+        context: ContextKind,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::ModelChanged { old, new } => write!(
+                f,
+                "model changed from {} to {}",
+                old.as_deref().unwrap_or("(default)"),
+                new.as_deref().unwrap_or("(default)")
+            ),
+            Change::ContextAdded(kind) => write!(f, "context {} added", kind),
+            Change::ContextRemoved(kind) => write!(f, "context {} removed", kind),
+            Change::Added {
+                context,
+                name,
+                value,
+            } => write!(f, "{}: + {} = {}", context, name, value),
+            Change::Removed {
+                context,
+                name,
+                value,
+            } => write!(f, "{}: - {} = {}", context, name, value),
+            Change::Changed {
+                context,
+                name,
+                old,
+                new,
+            } => write!(f, "{}: {} = {} -> {}", context, name, old, new),
+            Change::Reordered { context } => write!(f, "{}: assignments reordered", context),
+        }
+    }
+}
+
+/// Whether two expressions are equivalent, regardless of whether switches are written with
+/// `switch` or `if`.
+fn equivalent(a: &Expr, b: &Expr) -> bool {
+    match (a, b) {
+        (
+            Expr::Switch {
+                condition: c1,
+                then: t1,
+                otherwise: o1,
+                ..
+            },
+            Expr::Switch {
+                condition: c2,
+                then: t2,
+                otherwise: o2,
+                ..
+            },
+        ) => equivalent(c1, c2) && equivalent(t1, t2) && equivalent(o1, o2),
+        (Expr::Op { op: op1, args: a1 }, Expr::Op { op: op2, args: a2 }) => {
+            op1 == op2 && a1.iter().zip(a2).all(|(a, b)| equivalent(a, b))
+        }
+        _ => a == b,
+    }
+}
+
+/// The final value of each variable in a context, in order of first assignment.
+fn values(context: &Context) -> Vec<(&str, &Expr)> {
+    let mut values: Vec<(&str, &Expr)> = vec![];
+    for assign in context.assignments() {
+        match values.iter_mut().find(|(name, _)| *name == assign.name) {
+            Some(entry) => entry.1 = &assign.value,
+            None => values.push((&assign.name, &assign.value)),
+        }
+    }
+    values
+}
+
+fn diff_context(kind: &ContextKind, a: &Context, b: &Context, changes: &mut Vec<Change>) {
+    let old = values(a);
+    let new = values(b);
+
+    for (name, value) in &old {
+        match new.iter().find(|(n, _)| n == name) {
+            None => changes.push(Change::Removed {
+                context: kind.clone(),
+                name: name.to_string(),
+                value: (*value).clone(),
+            }),
+            Some((_, new_value)) if !equivalent(new_value, value) => {
+                changes.push(Change::Changed {
+                    context: kind.clone(),
+                    name: name.to_string(),
+                    old: (*value).clone(),
+                    new: (*new_value).clone(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+
+    for (name, value) in &new {
+        if !old.iter().any(|(n, _)| n == name) {
+            changes.push(Change::Added {
+                context: kind.clone(),
+                name: name.to_string(),
+                value: (*value).clone(),
+            });
+        }
+    }
+
+    let common_old = old
+        .iter()
+        .map(|(n, _)| *n)
+        .filter(|n| new.iter().any(|(m, _)| m == n));
+    let common_new = new
+        .iter()
+        .map(|(n, _)| *n)
+        .filter(|n| old.iter().any(|(m, _)| m == n));
+    if !common_old.eq(common_new) {
+        changes.push(Change::Reordered {
+            context: kind.clone(),
+        });
+    }
+}
+
+/// Compare two profiles.
+///
+/// Assignments are compared by the final value of each variable; comments, formatting and the
+/// choice between `switch` and `if` are ignored.
+pub fn diff(a: &Profile, b: &Profile) -> Vec<Change> {
+    let mut changes = vec![];
+
+    if a.model != b.model {
+        changes.push(Change::ModelChanged {
+            old: a.model.clone(),
+            new: b.model.clone(),
+        });
+    }
+
+    for context in &a.contexts {
+        match b.context(&context.kind) {
+            Some(other) => diff_context(&context.kind, context, other, &mut changes),
+            None => changes.push(Change::ContextRemoved(context.kind.clone())),
+        }
+    }
+
+    for context in &b.contexts {
+        if a.context(&context.kind).is_none() {
+            changes.push(Change::ContextAdded(context.kind.clone()));
+        }
+    }
+
+    changes
+}
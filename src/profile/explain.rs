@@ -0,0 +1,222 @@
+use super::{ContextKind, Expr, Operator, Profile};
+use crate::Route;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// A switch whose condition was evaluated while calculating a variable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision {
+    pub condition: Expr,
+    /// Whether the condition held, i.e. the first branch was taken.
+    pub taken: bool,
+}
+
+/// The value of a variable of the way context and the decisions that led to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    pub name: String,
+    pub value: f64,
+    pub decisions: Vec<Decision>,
+}
+
+/// An explanation of the cost brouter assigned to a segment of a route.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CostExplanation {
+    pub segment_idx: usize,
+
+    pub way_tags: Vec<(String, String)>,
+
+    /// The cost per kilometer brouter reported for the segment.
+    pub cost_per_km: f64,
+
+    /// The variables `costfactor` depends on, in the order they are assigned, ending with
+    /// `costfactor` itself.
+    pub evaluations: Vec<Evaluation>,
+}
+
+impl CostExplanation {
+    /// The cost factor according to the profile.
+    pub fn costfactor(&self) -> Option<f64> {
+        self.evaluations
+            .iter()
+            .rev()
+            .find(|e| e.name == "costfactor")
+            .map(|e| e.value)
+    }
+}
+
+impl fmt::Display for CostExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "segment {}:", self.segment_idx)?;
+        for (key, value) in &self.way_tags {
+            write!(f, " {}={}", key, value)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "cost per km: {}", self.cost_per_km)?;
+        for evaluation in &self.evaluations {
+            writeln!(f, "{} = {}", evaluation.name, evaluation.value)?;
+            for decision in &evaluation.decisions {
+                writeln!(
+                    f,
+                    "  {} {}",
+                    if decision.taken { "yes:" } else { "no: " },
+                    decision.condition
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn flag(b: bool) -> f64 {
+    if b {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Evaluates expressions against the tags of a way, the way brouter does.
+struct Evaluator<'a> {
+    tags: &'a [(String, String)],
+    variables: HashMap<String, f64>,
+    decisions: Vec<Decision>,
+}
+
+impl Evaluator<'_> {
+    fn eval(&mut self, expr: &Expr) -> f64 {
+        match expr {
+            Expr::Number(n) => *n,
+            Expr::Bool(b) => flag(*b),
+            Expr::Variable(name) => self.variables.get(name).copied().unwrap_or(0.0),
+            Expr::Lookup { key, values } => {
+                let value = self
+                    .tags
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, v)| v.as_str())
+                    .unwrap_or("");
+                flag(values.iter().any(|v| v == value))
+            }
+            Expr::Switch {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                let taken = self.eval(condition) != 0.0;
+                self.decisions.push(Decision {
+                    condition: (**condition).clone(),
+                    taken,
+                });
+                if taken {
+                    self.eval(then)
+                } else {
+                    self.eval(otherwise)
+                }
+            }
+            Expr::Op { op, args } => {
+                let args = args.iter().map(|a| self.eval(a)).collect::<Vec<_>>();
+                match op {
+                    Operator::And => flag(args[0] != 0.0 && args[1] != 0.0),
+                    Operator::Or => flag(args[0] != 0.0 || args[1] != 0.0),
+                    Operator::Xor => flag((args[0] != 0.0) != (args[1] != 0.0)),
+                    Operator::Not => flag(args[0] == 0.0),
+                    Operator::Add => args[0] + args[1],
+                    Operator::Sub => args[0] - args[1],
+                    Operator::Multiply => args[0] * args[1],
+                    Operator::Div => args[0] / args[1],
+                    Operator::Max => args[0].max(args[1]),
+                    Operator::Min => args[0].min(args[1]),
+                    Operator::Equal => flag(args[0] == args[1]),
+                    Operator::Greater => flag(args[0] > args[1]),
+                    Operator::Lesser => flag(args[0] < args[1]),
+                }
+            }
+        }
+    }
+}
+
+fn referenced(expr: &Expr, names: &mut HashSet<String>) {
+    match expr {
+        Expr::Variable(name) => {
+            names.insert(name.clone());
+        }
+        Expr::Switch {
+            condition,
+            then,
+            otherwise,
+            ..
+        } => {
+            referenced(condition, names);
+            referenced(then, names);
+            referenced(otherwise, names);
+        }
+        Expr::Op { args, .. } => args.iter().for_each(|a| referenced(a, names)),
+        Expr::Number(_) | Expr::Bool(_) | Expr::Lookup { .. } => {}
+    }
+}
+
+/// Explain which rules of `profile` determined the cost of a segment of `route`.
+///
+/// The route needs the segment messages brouter returned for it (see
+/// [`Route::with_messages`]); `segment_idx` is an index into them. Returns `None` if there is
+/// no such segment.
+///
+/// Variables overridden through `profile:` parameters are not taken into account.
+pub fn explain_cost(
+    route: &Route,
+    profile: &Profile,
+    segment_idx: usize,
+) -> Option<CostExplanation> {
+    let message = route.messages().get(segment_idx)?;
+
+    let mut evaluator = Evaluator {
+        tags: &[],
+        variables: HashMap::new(),
+        decisions: vec![],
+    };
+    let global = profile
+        .contexts
+        .iter()
+        .filter(|c| c.kind == ContextKind::Global);
+    for assign in global.flat_map(|c| c.assignments()) {
+        let value = evaluator.eval(&assign.value);
+        evaluator.variables.insert(assign.name.clone(), value);
+    }
+
+    evaluator.tags = &message.way_tags;
+    let assignments = profile
+        .contexts
+        .iter()
+        .filter(|c| c.kind == ContextKind::Way)
+        .flat_map(|c| c.assignments())
+        .collect::<Vec<_>>();
+    let mut evaluations = vec![];
+    for assign in &assignments {
+        evaluator.decisions.clear();
+        let value = evaluator.eval(&assign.value);
+        evaluator.variables.insert(assign.name.clone(), value);
+        evaluations.push(Evaluation {
+            name: assign.name.clone(),
+            value,
+            decisions: std::mem::take(&mut evaluator.decisions),
+        });
+    }
+
+    // Keep only what costfactor depends on, directly or indirectly.
+    let mut needed = HashSet::from(["costfactor".to_string()]);
+    for assign in assignments.iter().rev() {
+        if needed.contains(&assign.name) {
+            referenced(&assign.value, &mut needed);
+        }
+    }
+    evaluations.retain(|e| needed.contains(&e.name));
+
+    Some(CostExplanation {
+        segment_idx,
+        way_tags: message.way_tags.clone(),
+        cost_per_km: message.cost_per_km,
+        evaluations,
+    })
+}
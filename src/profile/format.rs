@@ -0,0 +1,146 @@
+use super::{Assign, Expr, ParseError, Profile, Statement};
+
+/// Assignments longer than this are broken over multiple lines, if they are switch chains.
+const MAX_WIDTH: usize = 100;
+
+const INDENT: &str = "    ";
+
+/// Write an operand, parenthesizing `if` expressions as [`Expr`]'s `Display` does.
+fn operand(expr: &Expr) -> String {
+    if expr.is_if_then_else() {
+        format!("( {} )", expr)
+    } else {
+        expr.to_string()
+    }
+}
+
+fn format_assign(out: &mut String, assign: &Assign) {
+    let head = format!("assign {} =", assign.name);
+    let comment = assign
+        .comment
+        .as_ref()
+        .map(|c| format!(" #{}", c))
+        .unwrap_or_default();
+    let line = format!("{} {}", head, assign.value);
+    if line.len() + comment.len() <= MAX_WIDTH || !matches!(assign.value, Expr::Switch { .. }) {
+        out.push_str(&line);
+        out.push_str(&comment);
+        out.push('\n');
+        return;
+    }
+
+    // A chain of switches is written with one case per line:
+    //
+    //     assign costfactor =
+    //         switch highway=motorway 10000
+    //         switch highway=cycleway 1
+    //         2
+    out.push_str(&head);
+    out.push_str(&comment);
+    out.push('\n');
+    let mut expr = &assign.value;
+    let mut after_if = false;
+    loop {
+        out.push_str(INDENT);
+        if after_if {
+            out.push_str("else ");
+        }
+        match expr {
+            Expr::Switch {
+                condition,
+                then,
+                otherwise,
+                if_then_else,
+            } => {
+                if *if_then_else {
+                    out.push_str(&format!("if {} then {}", operand(condition), operand(then)));
+                } else {
+                    out.push_str(&format!("switch {} {}", operand(condition), operand(then)));
+                }
+                out.push('\n');
+                after_if = *if_then_else;
+                expr = otherwise;
+            }
+            _ => {
+                out.push_str(&operand(expr));
+                out.push('\n');
+                return;
+            }
+        }
+    }
+}
+
+/// Collapse runs of blank lines and remove those at the start and end.
+fn normalize(statements: &[Statement]) -> Vec<&Statement> {
+    let mut result: Vec<&Statement> = vec![];
+    for statement in statements {
+        if statement == &Statement::Blank
+            && result.last().is_none_or(|last| *last == &Statement::Blank)
+        {
+            continue;
+        }
+        result.push(statement);
+    }
+    if result.last() == Some(&&Statement::Blank) {
+        result.pop();
+    }
+    result
+}
+
+fn format_statements(out: &mut String, statements: &[Statement]) {
+    for statement in normalize(statements) {
+        match statement {
+            Statement::Assign(assign) => format_assign(out, assign),
+            other => {
+                out.push_str(&other.to_string());
+                out.push('\n');
+            }
+        }
+    }
+}
+
+/// Reformat profile text consistently.
+///
+/// Comments are preserved; blank lines are kept, but runs of them are collapsed and contexts
+/// are separated by exactly one. Long switch chains are written with one case per line.
+pub fn format(text: &str) -> Result<String, ParseError> {
+    let profile = Profile::parse(text)?;
+    let mut out = String::new();
+
+    format_statements(&mut out, &profile.preamble);
+    if let Some(model) = &profile.model {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&format!("---model:{}\n", model));
+    }
+    for context in &profile.contexts {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&format!("---context:{}", context.kind));
+        if let Some(comment) = &context.comment {
+            out.push_str(&format!(" #{}", comment));
+        }
+        out.push('\n');
+        format_statements(&mut out, &context.statements);
+    }
+    Ok(out)
+}
+
+/// Strip comments, blank lines and optional syntax from profile text, to make uploads smaller.
+pub fn minify(text: &str) -> Result<String, ParseError> {
+    let profile = Profile::parse(text)?;
+    let mut out = String::new();
+
+    if let Some(model) = &profile.model {
+        out.push_str(&format!("---model:{}\n", model));
+    }
+    for context in &profile.contexts {
+        out.push_str(&format!("---context:{}\n", context.kind));
+        for assign in context.assignments() {
+            out.push_str(&format!("assign {} {}\n", assign.name, assign.value));
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,195 @@
+use super::{ContextBuilder, Expr, Profile, ProfileBuilder};
+use crate::Brouter;
+
+/// The `sac_scale` values, from easiest to hardest.
+const SAC_SCALE: [&str; 6] = [
+    "hiking",
+    "mountain_hiking",
+    "demanding_mountain_hiking",
+    "alpine_hiking",
+    "demanding_alpine_hiking",
+    "difficult_alpine_hiking",
+];
+
+/// The `trail_visibility` values, from best to worst.
+const TRAIL_VISIBILITY: [&str; 6] = ["excellent", "good", "intermediate", "bad", "horrible", "no"];
+
+/// Surfaces that are unpleasant to walk on.
+const ROUGH_SURFACES: &[&str] = &["mud", "sand", "rock", "scree", "shingle", "pebblestone"];
+
+/// Ready-made hiking profiles, from easy walks to alpine routes.
+///
+/// Each preset excludes paths above a difficulty on the SAC scale and paths whose trail
+/// visibility is too poor, and prefers paths over roads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HikingPreset {
+    /// Well-marked paths without any difficulty, avoiding rough surfaces.
+    EasyWalking,
+    /// Paths up to `sac_scale=mountain_hiking` (T2).
+    Hiking,
+    /// Paths up to `sac_scale=demanding_mountain_hiking` (T3).
+    MountainHiking,
+    /// Paths up to `sac_scale=demanding_alpine_hiking` (T5).
+    Alpine,
+}
+
+impl HikingPreset {
+    pub const ALL: [HikingPreset; 4] = [
+        HikingPreset::EasyWalking,
+        HikingPreset::Hiking,
+        HikingPreset::MountainHiking,
+        HikingPreset::Alpine,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HikingPreset::EasyWalking => "easy-walking",
+            HikingPreset::Hiking => "hiking",
+            HikingPreset::MountainHiking => "mountain-hiking",
+            HikingPreset::Alpine => "alpine",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name() == name)
+    }
+
+    /// The hardest `sac_scale` value that is allowed.
+    pub fn max_sac_scale(&self) -> &'static str {
+        match self {
+            HikingPreset::EasyWalking => "hiking",
+            HikingPreset::Hiking => "mountain_hiking",
+            HikingPreset::MountainHiking => "demanding_mountain_hiking",
+            HikingPreset::Alpine => "demanding_alpine_hiking",
+        }
+    }
+
+    /// The worst `trail_visibility` value that is allowed.
+    pub fn min_trail_visibility(&self) -> &'static str {
+        match self {
+            HikingPreset::EasyWalking => "good",
+            HikingPreset::Hiking => "intermediate",
+            HikingPreset::MountainHiking => "bad",
+            HikingPreset::Alpine => "horrible",
+        }
+    }
+
+    /// The name under which the generated profile should be uploaded.
+    pub fn profile_name(&self) -> String {
+        format!("hiking-preset-{}", self.name())
+    }
+
+    pub fn profile(&self) -> Profile {
+        let preset = *self;
+        ProfileBuilder::new()
+            .comment(&format!(
+                "Generated by brouter-client: hiking preset '{}'.",
+                preset
+            ))
+            .global(|g| {
+                g.assign("validForFoot", true)
+                    .assign("uphillcutoff", 0)
+                    .assign("uphillcost", 0)
+                    .assign("downhillcutoff", 0)
+                    .assign("downhillcost", 0)
+            })
+            .way(|w| way(w, preset))
+            .node(|n| n.assign("initialcost", 0))
+            .build()
+    }
+
+    /// Upload the generated profile to a brouter server.
+    pub fn upload(&self, brouter: &Brouter) -> Result<(), crate::Error> {
+        brouter.upload_profile(
+            &self.profile_name(),
+            self.profile().to_string().into_bytes(),
+        )
+    }
+}
+
+impl std::fmt::Display for HikingPreset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The values of `scale` that come after `limit`.
+fn beyond<'a>(scale: &[&'a str], limit: &str) -> Vec<&'a str> {
+    let position = scale.iter().position(|v| *v == limit).unwrap();
+    scale[position + 1..].to_vec()
+}
+
+fn way(w: ContextBuilder, preset: HikingPreset) -> ContextBuilder {
+    let highway = |values: &[&str]| Expr::lookup("highway", values);
+
+    let mut too_difficult = Expr::or(
+        Expr::lookup("sac_scale", &beyond(&SAC_SCALE, preset.max_sac_scale())),
+        Expr::lookup(
+            "trail_visibility",
+            &beyond(&TRAIL_VISIBILITY, preset.min_trail_visibility()),
+        ),
+    );
+    if preset == HikingPreset::EasyWalking {
+        too_difficult = Expr::or(too_difficult, highway(&["via_ferrata"]));
+    }
+
+    w.assign(
+        "accessdenied",
+        Expr::or(
+            Expr::lookup("foot", &["no", "private", "use_sidepath"]),
+            Expr::and(
+                Expr::lookup("access", &["no", "private"]),
+                Expr::not(Expr::lookup("foot", &["yes", "designated", "permissive"])),
+            ),
+        ),
+    )
+    .assign("toodifficult", too_difficult)
+    .assign("isrough", Expr::lookup("surface", ROUGH_SURFACES))
+    .blank()
+    .assign(
+        "basecost",
+        Expr::cases(
+            vec![
+                (Expr::lookup("route", &["ferry"]), 5.7.into()),
+                (highway(&["path", "footway", "bridleway"]), 1.into()),
+                (highway(&["track", "pedestrian", "steps"]), 1.1.into()),
+                (
+                    highway(&["living_street", "residential", "service"]),
+                    1.3.into(),
+                ),
+                (highway(&["unclassified", "tertiary"]), 1.5.into()),
+                (
+                    highway(&["secondary", "primary", "secondary_link", "primary_link"]),
+                    3.into(),
+                ),
+                (
+                    highway(&["motorway", "motorway_link", "trunk", "trunk_link"]),
+                    10000.into(),
+                ),
+            ],
+            2,
+        ),
+    )
+    .assign(
+        "costfactor",
+        Expr::cases(
+            vec![
+                (Expr::var("accessdenied"), 10000.into()),
+                (Expr::var("toodifficult"), 10000.into()),
+                (
+                    Expr::var("isrough"),
+                    Expr::multiply(
+                        Expr::var("basecost"),
+                        if preset == HikingPreset::EasyWalking {
+                            3.0
+                        } else {
+                            1.2
+                        },
+                    ),
+                ),
+            ],
+            Expr::var("basecost"),
+        ),
+    )
+}
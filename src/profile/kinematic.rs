@@ -0,0 +1,156 @@
+use super::{Assign, Context, ContextBuilder, ContextKind, Expr, Profile, Statement};
+
+/// The parameters of brouter's physical model of a bike ride.
+///
+/// brouter uses these global variables to estimate the time and energy needed for a route,
+/// which are reported in the `Time` and `Energy` columns of the segment messages (see
+/// [`crate::SegmentMessage::time_s`]).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KinematicParameters {
+    /// Total mass of bike, rider and luggage in kg (`totalMass`).
+    pub total_mass: f64,
+
+    /// Maximum speed in km/h (`maxSpeed`); brouter brakes rather than go faster downhill.
+    pub max_speed: f64,
+
+    /// Drag area in m², i.e. the frontal area times the drag coefficient (`S_C_x`).
+    pub drag_area: f64,
+
+    /// Rolling resistance coefficient (`defaultC_r`).
+    pub rolling_resistance: f64,
+
+    /// Average power output of the rider in watts (`bikerPower`).
+    pub biker_power: f64,
+}
+
+impl Default for KinematicParameters {
+    /// The defaults of brouter's trekking profile.
+    fn default() -> Self {
+        KinematicParameters {
+            total_mass: 90.0,
+            max_speed: 45.0,
+            drag_area: 0.225,
+            rolling_resistance: 0.01,
+            biker_power: 100.0,
+        }
+    }
+}
+
+impl KinematicParameters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Typical parameters for a pedelec, whose motor assists up to 25 km/h.
+    ///
+    /// brouter doesn't model the motor separately; it is approximated by a higher power and
+    /// a maximum speed at the assistance limit.
+    pub fn e_bike() -> Self {
+        KinematicParameters {
+            total_mass: 110.0,
+            max_speed: 25.0,
+            drag_area: 0.25,
+            rolling_resistance: 0.008,
+            biker_power: 250.0,
+        }
+    }
+
+    pub fn total_mass(mut self, kg: f64) -> Self {
+        assert!(kg > 0.0);
+        self.total_mass = kg;
+        self
+    }
+
+    pub fn max_speed(mut self, kmh: f64) -> Self {
+        assert!(kmh > 0.0);
+        self.max_speed = kmh;
+        self
+    }
+
+    pub fn drag_area(mut self, m2: f64) -> Self {
+        assert!(m2 >= 0.0);
+        self.drag_area = m2;
+        self
+    }
+
+    pub fn rolling_resistance(mut self, c_r: f64) -> Self {
+        assert!(c_r >= 0.0);
+        self.rolling_resistance = c_r;
+        self
+    }
+
+    pub fn biker_power(mut self, watts: f64) -> Self {
+        assert!(watts > 0.0);
+        self.biker_power = watts;
+        self
+    }
+
+    /// The profile variables and their values.
+    pub fn variables(&self) -> [(&'static str, f64); 5] {
+        [
+            ("totalMass", self.total_mass),
+            ("maxSpeed", self.max_speed),
+            ("S_C_x", self.drag_area),
+            ("defaultC_r", self.rolling_resistance),
+            ("bikerPower", self.biker_power),
+        ]
+    }
+
+    /// Add the assignments to a global context that is being built.
+    pub fn assign(&self, global: ContextBuilder) -> ContextBuilder {
+        self.variables()
+            .into_iter()
+            .fold(global, |global, (name, value)| global.assign(name, value))
+    }
+
+    /// Set the parameters in the global context of `profile`.
+    ///
+    /// Existing assignments are replaced; missing ones are added in front of the first
+    /// assignment of the global context.
+    pub fn apply(&self, profile: &mut Profile) {
+        if profile.context(&ContextKind::Global).is_none() {
+            profile
+                .contexts
+                .insert(0, Context::new(ContextKind::Global));
+        }
+        let global = profile.context_mut(&ContextKind::Global).unwrap();
+
+        let mut added = vec![];
+        for (name, value) in self.variables() {
+            let mut assigned = false;
+            for statement in &mut global.statements {
+                if let Statement::Assign(assign) = statement {
+                    if assign.name == name {
+                        assign.value = Expr::Number(value);
+                        assigned = true;
+                    }
+                }
+            }
+            if !assigned {
+                added.push(Statement::Assign(Assign {
+                    name: name.to_string(),
+                    value: Expr::Number(value),
+                    comment: None,
+                }));
+            }
+        }
+
+        let position = global
+            .statements
+            .iter()
+            .position(|s| matches!(s, Statement::Assign(_)))
+            .unwrap_or(global.statements.len());
+        global.statements.splice(position..position, added);
+    }
+
+    /// The parameters as `profile:` overrides, for profiles that already assign them.
+    ///
+    /// See [`crate::RouteRequest::profile_param`].
+    pub fn overrides(&self) -> Vec<(String, String)> {
+        self.variables()
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+}
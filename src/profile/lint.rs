@@ -0,0 +1,307 @@
+use super::parser::{parse_with_events, tokenize, Event};
+use super::ContextKind;
+use std::collections::HashSet;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// A problem found in a profile, with the 1-based position at which it occurs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.line, self.column, self.severity, self.message
+        )
+    }
+}
+
+/// Common way tags from brouter's standard lookup table.
+const WAY_TAGS: &[&str] = &[
+    "highway",
+    "surface",
+    "tracktype",
+    "smoothness",
+    "access",
+    "vehicle",
+    "motor_vehicle",
+    "motorcar",
+    "motorcycle",
+    "moped",
+    "mofa",
+    "hgv",
+    "bicycle",
+    "foot",
+    "horse",
+    "oneway",
+    "oneway:bicycle",
+    "cycleway",
+    "cycleway:left",
+    "cycleway:right",
+    "sidewalk",
+    "segregated",
+    "junction",
+    "route",
+    "railway",
+    "service",
+    "bridge",
+    "tunnel",
+    "ford",
+    "layer",
+    "lanes",
+    "maxspeed",
+    "motorroad",
+    "toll",
+    "incline",
+    "sac_scale",
+    "trail_visibility",
+    "mtb:scale",
+    "mtb:scale:uphill",
+    "lcn",
+    "rcn",
+    "ncn",
+    "icn",
+    "bicycle_road",
+    "cyclestreet",
+    "waterway",
+    "estimated_noise_class",
+    "estimated_river_class",
+    "estimated_forest_class",
+    "estimated_town_class",
+    "estimated_traffic_class",
+    "estimated_green_class",
+    "reversedirection",
+    "route_bicycle_icn",
+    "route_bicycle_ncn",
+    "route_bicycle_rcn",
+    "route_bicycle_lcn",
+    "route_bicycle_radweit",
+    "route_hiking_iwn",
+    "route_hiking_nwn",
+    "route_hiking_rwn",
+    "route_hiking_lwn",
+    "route_hiking_",
+    "route_foot_",
+    "route_mtb_",
+];
+
+/// Common node tags from brouter's standard lookup table.
+const NODE_TAGS: &[&str] = &[
+    "highway",
+    "barrier",
+    "access",
+    "vehicle",
+    "motor_vehicle",
+    "motorcar",
+    "motorcycle",
+    "moped",
+    "hgv",
+    "bicycle",
+    "foot",
+    "horse",
+    "railway",
+    "crossing",
+    "ford",
+    "traffic_calming",
+    "entrance",
+    "noexit",
+    "direction",
+    "nodeaccessgranted",
+];
+
+/// Checks profiles for common mistakes, without uploading them.
+#[derive(Debug, Clone)]
+pub struct Linter {
+    way_tags: HashSet<String>,
+    node_tags: HashSet<String>,
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Linter {
+            way_tags: WAY_TAGS.iter().map(|t| t.to_string()).collect(),
+            node_tags: NODE_TAGS.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+}
+
+impl Linter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check tags against a brouter lookup table (`lookups.dat`) rather than the built-in
+    /// list of common tags.
+    pub fn with_lookups(mut self, lookups: &str) -> Self {
+        self.way_tags.clear();
+        self.node_tags.clear();
+        let mut node = false;
+        for line in lookups.lines() {
+            if let Some(context) = line.strip_prefix("---context:") {
+                node = context.trim() == "node";
+            } else if let Some((key, _)) = line.split_once(';') {
+                let tags = if node {
+                    &mut self.node_tags
+                } else {
+                    &mut self.way_tags
+                };
+                tags.insert(key.trim().to_string());
+            }
+        }
+        // Pseudo tags that brouter sets itself.
+        self.way_tags.insert("reversedirection".to_string());
+        self.node_tags.insert("nodeaccessgranted".to_string());
+        self
+    }
+
+    fn is_known_tag(&self, context: &ContextKind, key: &str) -> bool {
+        let tags = match context {
+            ContextKind::Way => &self.way_tags,
+            ContextKind::Node => &self.node_tags,
+            _ => return true,
+        };
+        // Some entries, such as "route_hiking_", are prefixes of a family of tags.
+        tags.contains(key) || tags.iter().any(|t| t.ends_with('_') && key.starts_with(t))
+    }
+
+    fn check_parentheses(&self, text: &str) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        let mut open = vec![];
+        let unclosed = |open: &mut Vec<(usize, usize)>, diagnostics: &mut Vec<Diagnostic>| {
+            diagnostics.extend(open.drain(..).map(|(line, column)| Diagnostic {
+                line,
+                column,
+                severity: Severity::Error,
+                message: "unclosed '('".to_string(),
+            }))
+        };
+        for token in tokenize(text) {
+            if token.starts_statement() {
+                unclosed(&mut open, &mut diagnostics);
+            } else if token.is_open_paren() {
+                open.push((token.line, token.column));
+            } else if token.is_close_paren() && open.pop().is_none() {
+                diagnostics.push(Diagnostic {
+                    line: token.line,
+                    column: token.column,
+                    severity: Severity::Error,
+                    message: "unmatched ')'".to_string(),
+                });
+            }
+        }
+        unclosed(&mut open, &mut diagnostics);
+        diagnostics
+    }
+
+    /// Check the profile `text`, returning the problems found in order of position.
+    pub fn lint(&self, text: &str) -> Vec<Diagnostic> {
+        let mut diagnostics = self.check_parentheses(text);
+
+        let (profile, events) = match parse_with_events(text) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                // Syntax errors are usually caused by unbalanced parentheses, if there are any.
+                if diagnostics.is_empty() {
+                    diagnostics.push(Diagnostic {
+                        line: e.line,
+                        column: e.column,
+                        severity: Severity::Error,
+                        message: e.message,
+                    });
+                }
+                return diagnostics;
+            }
+        };
+
+        let end = text.lines().count().max(1);
+        for kind in [ContextKind::Global, ContextKind::Way, ContextKind::Node] {
+            if profile.context(&kind).is_none() {
+                diagnostics.push(Diagnostic {
+                    line: end,
+                    column: 1,
+                    severity: Severity::Error,
+                    message: format!("missing context '{}'", kind),
+                });
+            }
+        }
+
+        let mut context: Option<ContextKind> = None;
+        let mut global = HashSet::new();
+        let mut local = HashSet::new();
+        for located in events {
+            let mut report = |severity, message| {
+                diagnostics.push(Diagnostic {
+                    line: located.line,
+                    column: located.column,
+                    severity,
+                    message,
+                })
+            };
+            match located.event {
+                Event::Context(kind) => {
+                    if let ContextKind::Other(name) = &kind {
+                        report(Severity::Error, format!("unknown context '{}'", name));
+                    }
+                    local.clear();
+                    context = Some(kind);
+                }
+                Event::Assign(name) => match context {
+                    None => report(
+                        Severity::Error,
+                        format!("assignment to '{}' outside of a context", name),
+                    ),
+                    Some(ContextKind::Global) => {
+                        global.insert(name);
+                    }
+                    Some(_) => {
+                        local.insert(name);
+                    }
+                },
+                Event::Variable(name) => {
+                    if !global.contains(&name) && !local.contains(&name) {
+                        report(Severity::Error, format!("undefined variable '{}'", name));
+                    }
+                }
+                Event::Lookup(key) => match &context {
+                    Some(ContextKind::Global) => report(
+                        Severity::Warning,
+                        format!("tag lookup '{}' in the global context", key),
+                    ),
+                    Some(kind) if !self.is_known_tag(kind, &key) => {
+                        report(Severity::Warning, format!("unknown tag '{}'", key))
+                    }
+                    _ => {}
+                },
+            }
+        }
+
+        diagnostics.sort_by_key(|d| (d.line, d.column));
+        diagnostics
+    }
+}
+
+/// Check the profile `text` for common mistakes, using the built-in list of known tags.
+pub fn lint(text: &str) -> Vec<Diagnostic> {
+    Linter::new().lint(text)
+}
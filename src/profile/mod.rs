@@ -0,0 +1,507 @@
+//! BRouter routing profiles.
+//!
+//! Profiles are written in a small language: a series of contexts (`global`, `way` and
+//! `node`), each a list of `assign` statements whose values are prefix-notation expressions
+//! over variables and the tags of the way or node being considered. See
+//! <https://github.com/abrensch/brouter/blob/master/docs/developers/profile_developers_guide.md>.
+//!
+//! This module contains a syntax tree for profiles, which can be parsed from and written out
+//! as profile text.
+
+use std::fmt;
+
+mod builder;
+mod compare;
+mod diff;
+mod explain;
+mod format;
+mod hiking;
+mod kinematic;
+mod lint;
+mod parser;
+mod preferences;
+pub mod repository;
+#[cfg(feature = "serde")]
+mod serialization;
+pub mod store;
+mod template;
+mod variables;
+
+pub use builder::{ContextBuilder, ProfileBuilder};
+pub use compare::{ProfileComparison, RouteComparison, RouteStats};
+pub use diff::{diff, Change};
+pub use explain::{explain_cost, CostExplanation, Decision, Evaluation};
+pub use format::{format, minify};
+pub use hiking::HikingPreset;
+pub use kinematic::KinematicParameters;
+pub use lint::{lint, Diagnostic, Linter, Severity};
+pub use parser::ParseError;
+pub use preferences::{RoutingPreferences, Vehicle};
+pub use template::{Parameter, ParameterKind, ProfileTemplate, TemplateError, Value};
+pub use variables::{UnknownVariable, Variable};
+
+/// The section of a profile that a statement belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ContextKind {
+    /// Settings that apply to the whole route.
+    Global,
+    /// Costs of ways, evaluated against way tags.
+    Way,
+    /// Costs of nodes, evaluated against node tags.
+    Node,
+    Other(String),
+}
+
+impl ContextKind {
+    pub fn name(&self) -> &str {
+        match self {
+            ContextKind::Global => "global",
+            ContextKind::Way => "way",
+            ContextKind::Node => "node",
+            ContextKind::Other(name) => name,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "global" => ContextKind::Global,
+            "way" => ContextKind::Way,
+            "node" => ContextKind::Node,
+            other => ContextKind::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ContextKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Operators that can be used in expressions, other than `switch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operator {
+    And,
+    Or,
+    Xor,
+    Not,
+    Add,
+    Sub,
+    Multiply,
+    Div,
+    Max,
+    Min,
+    Equal,
+    Greater,
+    Lesser,
+}
+
+impl Operator {
+    pub const ALL: [Operator; 13] = [
+        Operator::And,
+        Operator::Or,
+        Operator::Xor,
+        Operator::Not,
+        Operator::Add,
+        Operator::Sub,
+        Operator::Multiply,
+        Operator::Div,
+        Operator::Max,
+        Operator::Min,
+        Operator::Equal,
+        Operator::Greater,
+        Operator::Lesser,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operator::And => "and",
+            Operator::Or => "or",
+            Operator::Xor => "xor",
+            Operator::Not => "not",
+            Operator::Add => "add",
+            Operator::Sub => "sub",
+            Operator::Multiply => "multiply",
+            Operator::Div => "div",
+            Operator::Max => "max",
+            Operator::Min => "min",
+            Operator::Equal => "equal",
+            Operator::Greater => "greater",
+            Operator::Lesser => "lesser",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|op| op.name() == name)
+    }
+
+    /// The number of operands the operator takes.
+    pub fn arity(&self) -> usize {
+        match self {
+            Operator::Not => 1,
+            _ => 2,
+        }
+    }
+}
+
+/// An expression in a profile.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "String", try_from = "String")
+)]
+pub enum Expr {
+    Number(f64),
+    Bool(bool),
+    /// A reference to a variable assigned earlier, or in the global context.
+    Variable(String),
+    /// A tag lookup, true if the tag `key` has one of `values`.
+    ///
+    /// An empty value matches if the tag is not set, e.g. `highway=`.
+    Lookup {
+        key: String,
+        values: Vec<String>,
+    },
+    Switch {
+        condition: Box<Expr>,
+        then: Box<Expr>,
+        otherwise: Box<Expr>,
+        /// Whether this is written as `if .. then .. else ..` rather than `switch`.
+        if_then_else: bool,
+    },
+    Op {
+        op: Operator,
+        args: Vec<Expr>,
+    },
+}
+
+impl Expr {
+    pub fn var(name: &str) -> Self {
+        Expr::Variable(name.to_string())
+    }
+
+    /// A tag lookup; true if the tag `key` has any of `values`.
+    pub fn lookup(key: &str, values: &[&str]) -> Self {
+        Expr::Lookup {
+            key: key.to_string(),
+            values: values.iter().map(|v| v.to_string()).collect(),
+        }
+    }
+
+    pub fn switch(
+        condition: impl Into<Expr>,
+        then: impl Into<Expr>,
+        otherwise: impl Into<Expr>,
+    ) -> Self {
+        Expr::Switch {
+            condition: Box::new(condition.into()),
+            then: Box::new(then.into()),
+            otherwise: Box::new(otherwise.into()),
+            if_then_else: false,
+        }
+    }
+
+    /// Like [`Expr::switch`], but written as `if .. then .. else ..`.
+    pub fn if_then_else(
+        condition: impl Into<Expr>,
+        then: impl Into<Expr>,
+        otherwise: impl Into<Expr>,
+    ) -> Self {
+        Expr::Switch {
+            condition: Box::new(condition.into()),
+            then: Box::new(then.into()),
+            otherwise: Box::new(otherwise.into()),
+            if_then_else: true,
+        }
+    }
+
+    /// A chain of switches: the value of the first case whose condition holds, or `default`.
+    pub fn cases(cases: Vec<(Expr, Expr)>, default: impl Into<Expr>) -> Self {
+        cases
+            .into_iter()
+            .rev()
+            .fold(default.into(), |otherwise, (condition, then)| {
+                Expr::switch(condition, then, otherwise)
+            })
+    }
+
+    /// Apply `op` to `args`.
+    ///
+    /// # Panics
+    ///
+    /// If the number of arguments doesn't match the arity of the operator.
+    pub fn op(op: Operator, args: Vec<Expr>) -> Self {
+        assert_eq!(
+            args.len(),
+            op.arity(),
+            "{} takes {} arguments",
+            op.name(),
+            op.arity()
+        );
+        Expr::Op { op, args }
+    }
+
+    pub fn and(a: impl Into<Expr>, b: impl Into<Expr>) -> Self {
+        Expr::op(Operator::And, vec![a.into(), b.into()])
+    }
+
+    pub fn or(a: impl Into<Expr>, b: impl Into<Expr>) -> Self {
+        Expr::op(Operator::Or, vec![a.into(), b.into()])
+    }
+
+    pub fn not(a: impl Into<Expr>) -> Self {
+        Expr::op(Operator::Not, vec![a.into()])
+    }
+
+    pub fn add(a: impl Into<Expr>, b: impl Into<Expr>) -> Self {
+        Expr::op(Operator::Add, vec![a.into(), b.into()])
+    }
+
+    pub fn multiply(a: impl Into<Expr>, b: impl Into<Expr>) -> Self {
+        Expr::op(Operator::Multiply, vec![a.into(), b.into()])
+    }
+
+    pub fn max(a: impl Into<Expr>, b: impl Into<Expr>) -> Self {
+        Expr::op(Operator::Max, vec![a.into(), b.into()])
+    }
+
+    pub fn min(a: impl Into<Expr>, b: impl Into<Expr>) -> Self {
+        Expr::op(Operator::Min, vec![a.into(), b.into()])
+    }
+
+    fn is_if_then_else(&self) -> bool {
+        matches!(
+            self,
+            Expr::Switch {
+                if_then_else: true,
+                ..
+            }
+        )
+    }
+}
+
+impl From<f64> for Expr {
+    fn from(n: f64) -> Self {
+        Expr::Number(n)
+    }
+}
+
+impl From<i32> for Expr {
+    fn from(n: i32) -> Self {
+        Expr::Number(n as f64)
+    }
+}
+
+impl From<bool> for Expr {
+    fn from(b: bool) -> Self {
+        Expr::Bool(b)
+    }
+}
+
+/// Write an operand, parenthesizing `if` expressions so that their `else` can't be mistaken
+/// for that of an enclosing expression.
+fn fmt_operand(f: &mut fmt::Formatter<'_>, expr: &Expr) -> fmt::Result {
+    if expr.is_if_then_else() {
+        write!(f, "( {} )", expr)
+    } else {
+        write!(f, "{}", expr)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(n) => write!(f, "{}", n),
+            Expr::Bool(b) => write!(f, "{}", b),
+            Expr::Variable(name) => f.write_str(name),
+            Expr::Lookup { key, values } => write!(f, "{}={}", key, values.join("|")),
+            Expr::Switch {
+                condition,
+                then,
+                otherwise,
+                if_then_else,
+            } => {
+                let keywords = if *if_then_else {
+                    ["if", " then", " else"]
+                } else {
+                    ["switch", "", ""]
+                };
+                for (keyword, operand) in keywords.iter().zip([condition, then, otherwise]) {
+                    write!(f, "{} ", keyword)?;
+                    fmt_operand(f, operand)?;
+                }
+                Ok(())
+            }
+            Expr::Op { op, args } => {
+                f.write_str(op.name())?;
+                for arg in args {
+                    f.write_str(" ")?;
+                    fmt_operand(f, arg)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// An assignment of an expression to a variable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assign {
+    pub name: String,
+    pub value: Expr,
+    /// Comment on the same line as the assignment, without the leading `#`.
+    pub comment: Option<String>,
+}
+
+impl fmt::Display for Assign {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "assign {} = {}", self.name, self.value)?;
+        if let Some(comment) = &self.comment {
+            write!(f, " #{}", comment)?;
+        }
+        Ok(())
+    }
+}
+
+/// A statement in a context.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Assign(Assign),
+    /// A comment on a line of its own, without the leading `#`.
+    Comment(String),
+    /// An empty line.
+    Blank,
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Statement::Assign(assign) => write!(f, "{}", assign),
+            Statement::Comment(comment) => write!(f, "#{}", comment),
+            Statement::Blank => Ok(()),
+        }
+    }
+}
+
+/// A context and its statements.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Context {
+    pub kind: ContextKind,
+    /// Comment on the same line as the context header, without the leading `#`.
+    pub comment: Option<String>,
+    pub statements: Vec<Statement>,
+}
+
+impl Context {
+    pub fn new(kind: ContextKind) -> Self {
+        Context {
+            kind,
+            comment: None,
+            statements: vec![],
+        }
+    }
+
+    /// Iterate over the assignments in this context.
+    pub fn assignments(&self) -> impl Iterator<Item = &Assign> {
+        self.statements.iter().filter_map(|s| match s {
+            Statement::Assign(assign) => Some(assign),
+            _ => None,
+        })
+    }
+
+    /// The last assignment to `name` in this context.
+    pub fn assignment(&self, name: &str) -> Option<&Assign> {
+        self.assignments().filter(|a| a.name == name).last()
+    }
+}
+
+impl fmt::Display for Context {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "---context:{}", self.kind)?;
+        if let Some(comment) = &self.comment {
+            write!(f, " #{}", comment)?;
+        }
+        writeln!(f)?;
+        for statement in &self.statements {
+            writeln!(f, "{}", statement)?;
+        }
+        Ok(())
+    }
+}
+
+/// A routing profile.
+///
+/// With the `serde` feature, profiles can be serialized to a structured form with one entry
+/// per statement and expressions in profile syntax, which keeps diffs of the serialized form
+/// aligned with the changed assignments. In TOML, for example:
+///
+/// ```toml
+/// model = "btools.router.KinematicModel"
+///
+/// [[contexts]]
+/// name = "global"
+///
+/// [[contexts.statements]]
+/// assign = "validForCars"
+/// value = "true"
+/// comment = " the profile is meant for cars"
+/// ```
+///
+/// Statements with only a `comment` are comments on a line of their own; empty statements are
+/// blank lines.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        into = "serialization::ProfileDef",
+        try_from = "serialization::ProfileDef"
+    )
+)]
+pub struct Profile {
+    /// Statements before the first context; only comments and blank lines are meaningful here.
+    pub preamble: Vec<Statement>,
+    /// The cost model to use, e.g. `btools.router.KinematicModel`.
+    pub model: Option<String>,
+    pub contexts: Vec<Context>,
+}
+
+impl Profile {
+    /// The first context of the given kind.
+    pub fn context(&self, kind: &ContextKind) -> Option<&Context> {
+        self.contexts.iter().find(|c| &c.kind == kind)
+    }
+
+    pub fn context_mut(&mut self, kind: &ContextKind) -> Option<&mut Context> {
+        self.contexts.iter_mut().find(|c| &c.kind == kind)
+    }
+
+    /// The value assigned to `name` in the given context.
+    pub fn assignment(&self, kind: &ContextKind, name: &str) -> Option<&Expr> {
+        self.contexts
+            .iter()
+            .rev()
+            .filter(|c| &c.kind == kind)
+            .find_map(|c| c.assignment(name))
+            .map(|a| &a.value)
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for statement in &self.preamble {
+            writeln!(f, "{}", statement)?;
+        }
+        if let Some(model) = &self.model {
+            writeln!(f, "---model:{}", model)?;
+        }
+        for (i, context) in self.contexts.iter().enumerate() {
+            if i > 0 && self.contexts[i - 1].statements.last() != Some(&Statement::Blank) {
+                writeln!(f)?;
+            }
+            write!(f, "{}", context)?;
+        }
+        Ok(())
+    }
+}
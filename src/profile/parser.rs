@@ -0,0 +1,409 @@
+use super::{Assign, Context, ContextKind, Expr, Operator, Profile, Statement};
+use lazy_regex::regex_is_match;
+use std::fmt;
+
+/// An error parsing a profile, with the 1-based position at which it occurred.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl std::error::Error for ParseError {}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Word(String),
+    LParen,
+    RParen,
+    Comment(String),
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct Token {
+    tok: Tok,
+    pub(super) line: usize,
+    pub(super) column: usize,
+}
+
+impl Token {
+    pub(super) fn is_open_paren(&self) -> bool {
+        self.tok == Tok::LParen
+    }
+
+    pub(super) fn is_close_paren(&self) -> bool {
+        self.tok == Tok::RParen
+    }
+
+    /// The number of characters the token spans.
+    fn width(&self) -> usize {
+        match &self.tok {
+            Tok::Word(word) => word.chars().count(),
+            Tok::LParen | Tok::RParen => 1,
+            Tok::Comment(comment) => comment.chars().count() + 1,
+        }
+    }
+
+    /// Whether this token starts a new statement.
+    pub(super) fn starts_statement(&self) -> bool {
+        matches!(&self.tok, Tok::Word(w) if w == "assign" || w.starts_with("---"))
+    }
+}
+
+/// Something of interest encountered while parsing, in source order.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Event {
+    /// An assignment to a variable; recorded after the references in its value.
+    Assign(String),
+    Variable(String),
+    Lookup(String),
+    Context(ContextKind),
+}
+
+/// An event with the position at which it occurred.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Located {
+    pub(super) event: Event,
+    pub(super) line: usize,
+    pub(super) column: usize,
+}
+
+pub(super) fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let mut chars = line.char_indices().peekable();
+        let mut column = 0;
+        while let Some((offset, c)) = chars.next() {
+            column += 1;
+            let start = column;
+            let tok = match c {
+                c if c.is_whitespace() => continue,
+                '#' => {
+                    let comment = line[offset + 1..].to_string();
+                    tokens.push(Token {
+                        tok: Tok::Comment(comment),
+                        line: i + 1,
+                        column: start,
+                    });
+                    break;
+                }
+                '(' => Tok::LParen,
+                ')' => Tok::RParen,
+                _ => {
+                    let mut end = offset + c.len_utf8();
+                    while let Some(&(o, c)) = chars.peek() {
+                        if c.is_whitespace() || c == '(' || c == ')' || c == '#' {
+                            break;
+                        }
+                        end = o + c.len_utf8();
+                        column += 1;
+                        chars.next();
+                    }
+                    Tok::Word(line[offset..end].to_string())
+                }
+            };
+            tokens.push(Token {
+                tok,
+                line: i + 1,
+                column: start,
+            });
+        }
+    }
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Comments encountered while parsing an expression, with their line numbers.
+    comments: Vec<(usize, String)>,
+    /// Line of the last token consumed.
+    line: usize,
+    events: Vec<Located>,
+}
+
+impl Parser {
+    fn error<T>(&self, message: String) -> Result<T, ParseError> {
+        // At the end of the text, point just past the last token.
+        let (line, column) = match (self.tokens.get(self.pos), self.tokens.last()) {
+            (Some(token), _) => (token.line, token.column),
+            (None, Some(last)) => (last.line, last.column + last.width()),
+            (None, None) => (1, 1),
+        };
+        Err(ParseError {
+            line,
+            column,
+            message,
+        })
+    }
+
+    /// Record an event at the position of the last token consumed.
+    fn record(&mut self, event: Event) {
+        let token = &self.tokens[self.pos - 1];
+        self.events.push(Located {
+            event,
+            line: token.line,
+            column: token.column,
+        });
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Tok> {
+        let token = self.tokens.get(self.pos)?;
+        self.line = token.line;
+        self.pos += 1;
+        Some(token.tok.clone())
+    }
+
+    /// The next token that is not a comment, collecting any comments skipped.
+    fn next_code(&mut self) -> Option<Tok> {
+        loop {
+            let line = self.peek()?.line;
+            match self.next()? {
+                Tok::Comment(comment) => self.comments.push((line, comment)),
+                tok => return Some(tok),
+            }
+        }
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<(), ParseError> {
+        match self.next_code() {
+            Some(Tok::Word(w)) if w == word => Ok(()),
+            Some(_) => {
+                self.pos -= 1;
+                self.error(format!("expected '{}'", word))
+            }
+            None => self.error(format!("expected '{}'", word)),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        let word = match self.next_code() {
+            Some(Tok::Word(word)) => word,
+            Some(Tok::LParen) => {
+                let expr = self.expr()?;
+                return match self.next_code() {
+                    Some(Tok::RParen) => Ok(expr),
+                    Some(_) => {
+                        self.pos -= 1;
+                        self.error("expected ')'".to_string())
+                    }
+                    None => self.error("expected ')'".to_string()),
+                };
+            }
+            Some(_) => {
+                self.pos -= 1;
+                return self.error("expected an expression".to_string());
+            }
+            None => return self.error("unexpected end of profile".to_string()),
+        };
+
+        match word.as_str() {
+            "switch" => Ok(Expr::switch(self.expr()?, self.expr()?, self.expr()?)),
+            "if" => {
+                let condition = self.expr()?;
+                self.expect_word("then")?;
+                let then = self.expr()?;
+                self.expect_word("else")?;
+                Ok(Expr::if_then_else(condition, then, self.expr()?))
+            }
+            "true" => Ok(Expr::Bool(true)),
+            "false" => Ok(Expr::Bool(false)),
+            _ => {
+                if let Some(op) = Operator::from_name(&word) {
+                    let args = (0..op.arity())
+                        .map(|_| self.expr())
+                        .collect::<Result<_, _>>()?;
+                    Ok(Expr::Op { op, args })
+                } else if let Some((key, values)) = word.split_once('=') {
+                    self.record(Event::Lookup(key.to_string()));
+                    Ok(Expr::Lookup {
+                        key: key.to_string(),
+                        values: values.split('|').map(|v| v.to_string()).collect(),
+                    })
+                } else if regex_is_match!(r"^-?[0-9.]", &word) {
+                    match word.parse() {
+                        Ok(n) => Ok(Expr::Number(n)),
+                        Err(_) => {
+                            self.pos -= 1;
+                            self.error(format!("invalid number '{}'", word))
+                        }
+                    }
+                } else if regex_is_match!(r"^[A-Za-z_][A-Za-z0-9_:]*$", &word) {
+                    self.record(Event::Variable(word.clone()));
+                    Ok(Expr::Variable(word))
+                } else {
+                    self.pos -= 1;
+                    self.error(format!("unexpected '{}'", word))
+                }
+            }
+        }
+    }
+
+    fn assign(&mut self) -> Result<Vec<Statement>, ParseError> {
+        let line = self.line;
+        let name = match self.next_code() {
+            Some(Tok::Word(name)) => name,
+            Some(_) => {
+                self.pos -= 1;
+                return self.error("expected a variable name".to_string());
+            }
+            None => return self.error("expected a variable name".to_string()),
+        };
+        let name_pos = self.pos - 1;
+        if matches!(self.peek(), Some(Token { tok: Tok::Word(w), .. }) if w == "=") {
+            self.next();
+        }
+        let value = self.expr()?;
+        self.events.push(Located {
+            event: Event::Assign(name.clone()),
+            line: self.tokens[name_pos].line,
+            column: self.tokens[name_pos].column,
+        });
+
+        // A comment directly following the expression.
+        if let Some(Token {
+            tok: Tok::Comment(comment),
+            line: l,
+            ..
+        }) = self.peek().cloned()
+        {
+            if l == self.line {
+                self.comments.push((l, comment));
+                self.next();
+            }
+        }
+
+        // The comment on the line of the assignment is kept with it; other comments inside
+        // the expression are moved in front of it.
+        let mut comment = None;
+        let mut statements = vec![];
+        for (l, c) in std::mem::take(&mut self.comments) {
+            if l == line && comment.is_none() {
+                comment = Some(c);
+            } else {
+                statements.push(Statement::Comment(c));
+            }
+        }
+        statements.push(Statement::Assign(Assign {
+            name,
+            value,
+            comment,
+        }));
+        Ok(statements)
+    }
+
+    fn profile(&mut self) -> Result<Profile, ParseError> {
+        let mut profile = Profile::default();
+        let mut last_line = 0;
+
+        while let Some(token) = self.peek().cloned() {
+            let statements = match profile.contexts.last_mut() {
+                Some(context) => &mut context.statements,
+                None => &mut profile.preamble,
+            };
+            for _ in last_line + 1..token.line {
+                statements.push(Statement::Blank);
+            }
+            self.next();
+
+            match token.tok {
+                Tok::Comment(comment) => statements.push(Statement::Comment(comment)),
+                Tok::Word(word) if word == "assign" => statements.extend(self.assign()?),
+                Tok::Word(word) if word.starts_with("---context:") => {
+                    let kind = ContextKind::from_name(word.trim_start_matches("---context:"));
+                    self.record(Event::Context(kind.clone()));
+                    let mut context = Context::new(kind);
+                    if let Some(Token {
+                        tok: Tok::Comment(comment),
+                        line,
+                        ..
+                    }) = self.peek().cloned()
+                    {
+                        if line == token.line {
+                            context.comment = Some(comment);
+                            self.next();
+                        }
+                    }
+                    profile.contexts.push(context);
+                }
+                Tok::Word(word) if word.starts_with("---model:") => {
+                    profile.model = Some(word.trim_start_matches("---model:").to_string());
+                }
+                Tok::Word(word) => {
+                    self.pos -= 1;
+                    return self.error(format!("unexpected '{}'", word));
+                }
+                _ => {
+                    self.pos -= 1;
+                    return self.error("expected 'assign'".to_string());
+                }
+            }
+            last_line = self.line;
+        }
+
+        Ok(profile)
+    }
+}
+
+/// Parse a profile, also returning the variable references, lookups and assignments in it.
+pub(super) fn parse_with_events(text: &str) -> Result<(Profile, Vec<Located>), ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(text),
+        pos: 0,
+        comments: vec![],
+        line: 0,
+        events: vec![],
+    };
+    let profile = parser.profile()?;
+    Ok((profile, parser.events))
+}
+
+impl Profile {
+    /// Parse profile text.
+    ///
+    /// Comments are preserved: a comment on the first line of an assignment stays attached
+    /// to it, while comments within a multi-line expression are moved in front of the
+    /// assignment.
+    pub fn parse(text: &str) -> Result<Profile, ParseError> {
+        parse_with_events(text).map(|(profile, _)| profile)
+    }
+}
+
+impl std::str::FromStr for Profile {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Profile::parse(s)
+    }
+}
+
+impl std::str::FromStr for Expr {
+    type Err = ParseError;
+
+    /// Parse a single expression, e.g. `switch highway=cycleway 1 2`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s),
+            pos: 0,
+            comments: vec![],
+            line: 0,
+            events: vec![],
+        };
+        let expr = parser.expr()?;
+        if parser.peek().is_some() {
+            return parser.error("unexpected text after expression".to_string());
+        }
+        Ok(expr)
+    }
+}
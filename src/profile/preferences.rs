@@ -0,0 +1,443 @@
+//! Routing preferences that don't require knowledge of the profile language.
+//!
+//! A [`RoutingPreferences`] is compiled to a generated profile, which only depends on the
+//! vehicle, and a set of `profile:` overrides that carry the individual preferences. The
+//! generated profile therefore only has to be uploaded once per vehicle.
+
+use super::{ContextBuilder, Expr, KinematicParameters, Profile, ProfileBuilder};
+use crate::{Brouter, Point, RouteRequest};
+
+/// Extra cost per meter of climbing on slopes steeper than the maximum slope.
+const STEEP_UPHILL_COST: f64 = 100.0;
+
+/// Cost factor multiplier for toll roads, when they are to be avoided.
+const TOLL_PENALTY: f64 = 10.0;
+
+/// Cost factor multiplier for minor roads, when primary roads are preferred.
+const MINOR_ROAD_PENALTY: f64 = 2.0;
+
+/// The cost factor brouter treats as impassable.
+const FORBIDDEN: f64 = 10000.0;
+
+/// The kind of vehicle to route for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Vehicle {
+    Bike,
+    Foot,
+    Car,
+}
+
+impl Vehicle {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Vehicle::Bike => "bike",
+            Vehicle::Foot => "foot",
+            Vehicle::Car => "car",
+        }
+    }
+
+    /// The access tag that applies to this vehicle.
+    fn access_tag(&self) -> &'static str {
+        match self {
+            Vehicle::Bike => "bicycle",
+            Vehicle::Foot => "foot",
+            Vehicle::Car => "motorcar",
+        }
+    }
+}
+
+impl std::fmt::Display for Vehicle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// High-level routing preferences.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RoutingPreferences {
+    pub vehicle: Vehicle,
+
+    pub avoid_motorways: bool,
+
+    pub avoid_ferries: bool,
+
+    pub avoid_unpaved: bool,
+
+    /// Maximum uphill slope in percent; steeper climbs are heavily penalized.
+    pub max_slope: Option<f64>,
+
+    /// Total mass of vehicle, rider and luggage in kg; used for bikes only.
+    pub total_mass: Option<f64>,
+
+    /// Average power output of the rider in watts; used for bikes only.
+    pub average_power: Option<f64>,
+
+    /// Avoid toll roads where possible; used for cars only.
+    pub avoid_tolls: bool,
+
+    /// Prefer primary and secondary roads over minor roads; used for cars only.
+    pub prefer_primary_roads: bool,
+}
+
+impl RoutingPreferences {
+    pub fn new(vehicle: Vehicle) -> Self {
+        RoutingPreferences {
+            vehicle,
+            avoid_motorways: false,
+            avoid_ferries: false,
+            avoid_unpaved: false,
+            max_slope: None,
+            total_mass: None,
+            average_power: None,
+            avoid_tolls: false,
+            prefer_primary_roads: false,
+        }
+    }
+
+    pub fn avoid_motorways(mut self, avoid: bool) -> Self {
+        self.avoid_motorways = avoid;
+        self
+    }
+
+    pub fn avoid_ferries(mut self, avoid: bool) -> Self {
+        self.avoid_ferries = avoid;
+        self
+    }
+
+    pub fn avoid_unpaved(mut self, avoid: bool) -> Self {
+        self.avoid_unpaved = avoid;
+        self
+    }
+
+    pub fn max_slope(mut self, percent: f64) -> Self {
+        assert!(percent > 0.0);
+        self.max_slope = Some(percent);
+        self
+    }
+
+    pub fn total_mass(mut self, kg: f64) -> Self {
+        assert!(kg > 0.0);
+        self.total_mass = Some(kg);
+        self
+    }
+
+    pub fn average_power(mut self, watts: f64) -> Self {
+        assert!(watts > 0.0);
+        self.average_power = Some(watts);
+        self
+    }
+
+    pub fn avoid_tolls(mut self, avoid: bool) -> Self {
+        self.avoid_tolls = avoid;
+        self
+    }
+
+    pub fn prefer_primary_roads(mut self, prefer: bool) -> Self {
+        self.prefer_primary_roads = prefer;
+        self
+    }
+
+    /// The name under which the generated profile should be uploaded.
+    pub fn profile_name(&self) -> String {
+        format!("preferences-{}", self.vehicle)
+    }
+
+    /// The generated profile.
+    ///
+    /// The profile only depends on the vehicle; the other preferences are passed as overrides
+    /// of its global variables (see [`RoutingPreferences::overrides`]).
+    pub fn profile(&self) -> Profile {
+        let vehicle = self.vehicle;
+        ProfileBuilder::new()
+            .comment(&format!(
+                "Generated by brouter-client for routing by {}.",
+                vehicle
+            ))
+            .global(|g| global(g, vehicle))
+            .way(|w| way(w, vehicle))
+            .node(|n| node(n, vehicle))
+            .build()
+    }
+
+    /// The values of the variables of the generated profile, to be passed as `profile:`
+    /// parameters.
+    pub fn overrides(&self) -> Vec<(String, String)> {
+        let flag = |b: bool| if b { "1" } else { "0" }.to_string();
+        let mut overrides = vec![
+            ("avoid_motorways".to_string(), flag(self.avoid_motorways)),
+            ("avoid_ferries".to_string(), flag(self.avoid_ferries)),
+            ("avoid_unpaved".to_string(), flag(self.avoid_unpaved)),
+        ];
+        if let Some(max_slope) = self.max_slope {
+            overrides.push(("uphillcutoff".to_string(), max_slope.to_string()));
+            overrides.push(("uphillcost".to_string(), STEEP_UPHILL_COST.to_string()));
+        }
+        if self.vehicle == Vehicle::Bike {
+            if let Some(total_mass) = self.total_mass {
+                overrides.push(("totalMass".to_string(), total_mass.to_string()));
+            }
+            if let Some(average_power) = self.average_power {
+                overrides.push(("bikerPower".to_string(), average_power.to_string()));
+            }
+        }
+        if self.vehicle == Vehicle::Car {
+            overrides.push(("avoid_tolls".to_string(), flag(self.avoid_tolls)));
+            overrides.push((
+                "prefer_primary_roads".to_string(),
+                flag(self.prefer_primary_roads),
+            ));
+        }
+        overrides
+    }
+
+    /// A request for a route along `points`, using the generated profile.
+    ///
+    /// The profile has to be uploaded first, see [`RoutingPreferences::upload`].
+    pub fn route_request(&self, points: &[Point]) -> RouteRequest {
+        self.overrides().into_iter().fold(
+            RouteRequest::new(points, &self.profile_name()),
+            |request, (name, value)| request.profile_param(&name, &value),
+        )
+    }
+
+    /// Upload the generated profile to a brouter server.
+    pub fn upload(&self, brouter: &Brouter) -> Result<(), crate::Error> {
+        brouter.upload_profile(
+            &self.profile_name(),
+            self.profile().to_string().into_bytes(),
+        )
+    }
+}
+
+fn global(g: ContextBuilder, vehicle: Vehicle) -> ContextBuilder {
+    let g = g
+        .assign("avoid_motorways", false)
+        .assign("avoid_ferries", false)
+        .assign("avoid_unpaved", false)
+        .blank()
+        .assign("validForBikes", vehicle == Vehicle::Bike)
+        .assign("validForFoot", vehicle == Vehicle::Foot)
+        .assign("validForCars", vehicle == Vehicle::Car)
+        .blank()
+        .assign_with_comment("uphillcutoff", 1.5, Some("slope in percent"))
+        .assign("uphillcost", 0)
+        .assign("downhillcutoff", 1.5)
+        .assign("downhillcost", if vehicle == Vehicle::Car { 0 } else { 60 })
+        .assign(
+            "turnInstructionMode",
+            if vehicle == Vehicle::Car { 0 } else { 1 },
+        );
+    match vehicle {
+        Vehicle::Bike => KinematicParameters::default().assign(g.blank()),
+        Vehicle::Car => g
+            .blank()
+            .assign("avoid_tolls", false)
+            .assign("prefer_primary_roads", false),
+        Vehicle::Foot => g,
+    }
+}
+
+fn way(w: ContextBuilder, vehicle: Vehicle) -> ContextBuilder {
+    let access = vehicle.access_tag();
+    let denied = Expr::or(
+        Expr::lookup(access, &["no", "private", "use_sidepath"]),
+        Expr::and(
+            Expr::lookup("access", &["no", "private", "agricultural", "forestry"]),
+            Expr::not(Expr::lookup(access, &["yes", "designated", "permissive"])),
+        ),
+    );
+    let oneway = Expr::and(
+        Expr::lookup("reversedirection", &["yes"]),
+        Expr::or(
+            Expr::lookup("oneway", &["yes", "true", "1"]),
+            Expr::lookup("junction", &["roundabout"]),
+        ),
+    );
+    let oneway = match vehicle {
+        Vehicle::Bike => Expr::and(oneway, Expr::not(Expr::lookup("oneway:bicycle", &["no"]))),
+        Vehicle::Foot => false.into(),
+        Vehicle::Car => oneway,
+    };
+    let motorways: &[&str] = match vehicle {
+        Vehicle::Car => &["motorway", "motorway_link"],
+        _ => &["motorway", "motorway_link", "trunk", "trunk_link"],
+    };
+
+    let mut costs = vec![
+        (Expr::var("accessdenied"), FORBIDDEN.into()),
+        (Expr::var("onewaydenied"), FORBIDDEN.into()),
+        (
+            Expr::and(Expr::var("avoid_ferries"), Expr::var("isferry")),
+            FORBIDDEN.into(),
+        ),
+        (
+            Expr::and(Expr::var("avoid_motorways"), Expr::var("ismotorway")),
+            FORBIDDEN.into(),
+        ),
+        (
+            Expr::and(Expr::var("avoid_unpaved"), Expr::var("isunpaved")),
+            Expr::multiply(Expr::var("basecost"), 3),
+        ),
+    ];
+    let w = if vehicle == Vehicle::Car {
+        costs.push((
+            Expr::and(Expr::var("avoid_tolls"), Expr::var("istoll")),
+            Expr::multiply(Expr::var("basecost"), TOLL_PENALTY),
+        ));
+        costs.push((
+            Expr::and(Expr::var("prefer_primary_roads"), Expr::var("isminor")),
+            Expr::multiply(Expr::var("basecost"), MINOR_ROAD_PENALTY),
+        ));
+        w.assign("istoll", Expr::lookup("toll", &["yes"])).assign(
+            "isminor",
+            Expr::lookup(
+                "highway",
+                &[
+                    "tertiary",
+                    "tertiary_link",
+                    "unclassified",
+                    "residential",
+                    "living_street",
+                    "service",
+                    "road",
+                ],
+            ),
+        )
+    } else {
+        w
+    };
+
+    w.assign("ismotorway", Expr::lookup("highway", motorways))
+        .assign("isferry", Expr::lookup("route", &["ferry"]))
+        .assign(
+            "isunpaved",
+            Expr::or(
+                Expr::lookup(
+                    "surface",
+                    &[
+                        "unpaved",
+                        "gravel",
+                        "fine_gravel",
+                        "compacted",
+                        "dirt",
+                        "earth",
+                        "ground",
+                        "grass",
+                        "mud",
+                        "sand",
+                        "pebblestone",
+                        "woodchips",
+                    ],
+                ),
+                Expr::lookup("tracktype", &["grade2", "grade3", "grade4", "grade5"]),
+            ),
+        )
+        .assign("accessdenied", denied)
+        .assign("onewaydenied", oneway)
+        .blank()
+        .assign("basecost", base_cost(vehicle))
+        .assign("costfactor", Expr::cases(costs, Expr::var("basecost")))
+        .assign(
+            "turncost",
+            match vehicle {
+                Vehicle::Bike => 90,
+                Vehicle::Foot => 0,
+                Vehicle::Car => 200,
+            },
+        )
+}
+
+/// The cost factor of a way by its highway class.
+fn base_cost(vehicle: Vehicle) -> Expr {
+    let highway = |values: &[&str]| Expr::lookup("highway", values);
+    match vehicle {
+        Vehicle::Bike => Expr::cases(
+            vec![
+                (Expr::lookup("route", &["ferry"]), 5.7.into()),
+                (highway(&["cycleway"]), 1.into()),
+                (Expr::lookup("bicycle_road", &["yes"]), 1.into()),
+                (
+                    highway(&["residential", "living_street", "unclassified", "service"]),
+                    1.2.into(),
+                ),
+                (highway(&["track", "path", "bridleway"]), 1.5.into()),
+                (highway(&["tertiary", "tertiary_link"]), 1.5.into()),
+                (highway(&["secondary", "secondary_link"]), 2.into()),
+                (highway(&["primary", "primary_link"]), 3.into()),
+                (
+                    highway(&["footway", "pedestrian"]),
+                    Expr::switch(Expr::lookup("bicycle", &["yes", "designated"]), 1.2, 3),
+                ),
+                (highway(&["steps"]), 20.into()),
+                (
+                    highway(&["motorway", "motorway_link", "trunk", "trunk_link"]),
+                    Expr::switch(Expr::lookup("bicycle", &["yes"]), 3, FORBIDDEN),
+                ),
+            ],
+            2,
+        ),
+        Vehicle::Foot => Expr::cases(
+            vec![
+                (Expr::lookup("route", &["ferry"]), 5.7.into()),
+                (
+                    highway(&[
+                        "footway",
+                        "pedestrian",
+                        "path",
+                        "steps",
+                        "track",
+                        "bridleway",
+                        "living_street",
+                        "residential",
+                        "service",
+                    ]),
+                    1.into(),
+                ),
+                (
+                    highway(&["motorway", "motorway_link", "trunk", "trunk_link"]),
+                    FORBIDDEN.into(),
+                ),
+                (highway(&["primary", "primary_link"]), 2.into()),
+            ],
+            1.3,
+        ),
+        Vehicle::Car => Expr::cases(
+            vec![
+                (Expr::lookup("route", &["ferry"]), 5.7.into()),
+                (highway(&["motorway", "motorway_link"]), 1.into()),
+                (highway(&["trunk", "trunk_link"]), 1.1.into()),
+                (highway(&["primary", "primary_link"]), 1.2.into()),
+                (highway(&["secondary", "secondary_link"]), 1.3.into()),
+                (highway(&["tertiary", "tertiary_link"]), 1.4.into()),
+                (
+                    highway(&["unclassified", "residential", "road"]),
+                    1.8.into(),
+                ),
+                (highway(&["living_street", "service"]), 3.into()),
+            ],
+            FORBIDDEN,
+        ),
+    }
+}
+
+fn node(n: ContextBuilder, vehicle: Vehicle) -> ContextBuilder {
+    let initial_cost = match vehicle {
+        Vehicle::Bike => Expr::switch(
+            Expr::lookup("barrier", &["gate", "lift_gate", "swing_gate", "stile"]),
+            100,
+            0,
+        ),
+        Vehicle::Foot => 0.into(),
+        Vehicle::Car => Expr::switch(
+            Expr::lookup(
+                "barrier",
+                &["bollard", "block", "cycle_barrier", "stile", "kissing_gate"],
+            ),
+            1000000,
+            Expr::switch(Expr::lookup("highway", &["traffic_signals"]), 100, 0),
+        ),
+    };
+    n.assign("initialcost", initial_cost)
+}
@@ -0,0 +1,271 @@
+//! Fetching profiles from well-known collections.
+//!
+//! Collections are directories of `.brf` files in GitHub repositories. Downloaded profiles
+//! are cached locally by collection and version.
+
+use crate::Brouter;
+use lazy_regex::{regex, regex_is_match};
+use reqwest::blocking::Client;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    Http(reqwest::Error),
+    Io(std::io::Error),
+    Upload(crate::Error),
+    /// The collection has no profile with this name.
+    NotFound(String),
+    /// A profile name or version that can't be used as a file name.
+    InvalidName(String),
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "HTTP error: {}", e),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Upload(e) => write!(f, "Upload failed: {}", e),
+            Error::NotFound(name) => write!(f, "No such profile: {}", name),
+            Error::InvalidName(name) => write!(f, "Invalid profile name or version: {}", name),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// A collection of profiles in a directory of a GitHub repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collection {
+    pub name: String,
+    pub owner: String,
+    pub repo: String,
+    /// Directory within the repository that contains the profiles.
+    pub path: String,
+    /// The git ref to use if no version is given.
+    pub default_version: String,
+}
+
+impl Collection {
+    pub fn github(name: &str, owner: &str, repo: &str, path: &str, default_version: &str) -> Self {
+        Collection {
+            name: name.to_string(),
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+            path: path.trim_matches('/').to_string(),
+            default_version: default_version.to_string(),
+        }
+    }
+
+    /// The profiles that ship with brouter; versions are brouter release tags, e.g. "v1.7.8".
+    ///
+    /// The crate doesn't embed these, so they always match the release a server runs.
+    pub fn brouter() -> Self {
+        Self::github("brouter", "abrensch", "brouter", "misc/profiles2", "master")
+    }
+
+    /// Poutnik's profiles.
+    pub fn poutnik() -> Self {
+        Self::github("poutnik", "poutnikl", "Brouter-profiles", "", "master")
+    }
+
+    /// Look up a well-known collection by name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "brouter" => Some(Self::brouter()),
+            "poutnik" => Some(Self::poutnik()),
+            _ => None,
+        }
+    }
+
+    fn file_url(&self, version: &str, file_name: &str) -> String {
+        let path = if self.path.is_empty() {
+            file_name.to_string()
+        } else {
+            format!("{}/{}", self.path, file_name)
+        };
+        format!(
+            "https://raw.githubusercontent.com/{}/{}/{}/{}",
+            self.owner, self.repo, version, path
+        )
+    }
+
+    fn listing_url(&self, version: &str) -> String {
+        format!(
+            "https://api.github.com/repos/{}/{}/contents/{}?ref={}",
+            self.owner, self.repo, self.path, version
+        )
+    }
+}
+
+/// Check that `name` can be used as a file name, so that profile names and versions can't
+/// point outside of the cache or profiles directory. Versions are git refs, but only those
+/// without slashes can be used.
+fn check_name(name: &str) -> Result<(), Error> {
+    if regex_is_match!(r"^[A-Za-z0-9_][A-Za-z0-9_.-]*$", name) {
+        Ok(())
+    } else {
+        Err(Error::InvalidName(name.to_string()))
+    }
+}
+
+/// Downloads profiles from collections, caching them locally.
+pub struct ProfileRepository {
+    client: Client,
+    cache_dir: PathBuf,
+}
+
+impl ProfileRepository {
+    pub fn new(cache_dir: &Path) -> Self {
+        ProfileRepository {
+            client: Client::builder()
+                .user_agent(concat!("brouter-client/", env!("CARGO_PKG_VERSION")))
+                .build()
+                .unwrap(),
+            cache_dir: cache_dir.to_path_buf(),
+        }
+    }
+
+    /// The default location of the cache: `brouter-client/profiles` in the XDG cache
+    /// directory.
+    pub fn default_cache_dir() -> Option<PathBuf> {
+        Some(crate::dirs::cache_dir()?.join("profiles"))
+    }
+
+    fn cache_path(
+        &self,
+        collection: &Collection,
+        version: &str,
+        name: &str,
+    ) -> Result<PathBuf, Error> {
+        check_name(&collection.name)?;
+        check_name(version)?;
+        check_name(name)?;
+        Ok(self
+            .cache_dir
+            .join(&collection.name)
+            .join(version)
+            .join(format!("{}.brf", name)))
+    }
+
+    /// List the names of the profiles in a collection.
+    ///
+    /// Uses the default version of the collection if `version` is `None`.
+    ///
+    /// The listing from GitHub is scanned for `"name"` keys with a `.brf` value rather than
+    /// parsed, so any such key nested in the entries would be picked up too; GitHub's listings
+    /// have none.
+    pub fn list(
+        &self,
+        collection: &Collection,
+        version: Option<&str>,
+    ) -> Result<Vec<String>, Error> {
+        let version = version.unwrap_or(&collection.default_version);
+        let listing = self
+            .client
+            .get(collection.listing_url(version))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.text())
+            .map_err(Error::Http)?;
+
+        let mut names = regex!(r#""name"\s*:\s*"([^"]+)\.brf""#)
+            .captures_iter(&listing)
+            .map(|c| c[1].to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    /// Fetch the source of a profile, from the cache if it was downloaded before.
+    ///
+    /// Cached profiles are not checked for updates; use [`ProfileRepository::refresh`] for
+    /// versions that move, such as "master".
+    pub fn fetch(
+        &self,
+        collection: &Collection,
+        version: Option<&str>,
+        name: &str,
+    ) -> Result<String, Error> {
+        let version = version.unwrap_or(&collection.default_version);
+        let path = self.cache_path(collection, version, name)?;
+        if path.exists() {
+            return Ok(std::fs::read_to_string(path)?);
+        }
+        self.download(collection, version, name)
+    }
+
+    /// Download a profile again, replacing the cached copy.
+    pub fn refresh(
+        &self,
+        collection: &Collection,
+        version: Option<&str>,
+        name: &str,
+    ) -> Result<String, Error> {
+        let version = version.unwrap_or(&collection.default_version);
+        self.download(collection, version, name)
+    }
+
+    fn download(
+        &self,
+        collection: &Collection,
+        version: &str,
+        name: &str,
+    ) -> Result<String, Error> {
+        let path = self.cache_path(collection, version, name)?;
+        let response = self
+            .client
+            .get(collection.file_url(version, &format!("{}.brf", name)))
+            .send()
+            .map_err(Error::Http)?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(Error::NotFound(name.to_string()));
+        }
+        let text = response
+            .error_for_status()
+            .and_then(|r| r.text())
+            .map_err(Error::Http)?;
+
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(&path, &text)?;
+        Ok(text)
+    }
+
+    /// Copy a profile into a local brouter profiles directory.
+    ///
+    /// Returns the path of the installed profile.
+    pub fn install(
+        &self,
+        collection: &Collection,
+        version: Option<&str>,
+        name: &str,
+        profiles_dir: &Path,
+    ) -> Result<PathBuf, Error> {
+        check_name(name)?;
+        let text = self.fetch(collection, version, name)?;
+        let path = profiles_dir.join(format!("{}.brf", name));
+        std::fs::write(&path, text)?;
+        Ok(path)
+    }
+
+    /// Upload a profile to a brouter server, under the given name.
+    pub fn upload(
+        &self,
+        collection: &Collection,
+        version: Option<&str>,
+        name: &str,
+        brouter: &Brouter,
+        upload_as: &str,
+    ) -> Result<(), Error> {
+        let text = self.fetch(collection, version, name)?;
+        brouter
+            .upload_profile(upload_as, text.into_bytes())
+            .map_err(Error::Upload)
+    }
+}
@@ -0,0 +1,131 @@
+//! The structured form of profiles used for serde; see [`Profile`].
+
+use super::{Assign, Context, ContextKind, Expr, Profile, Statement};
+use serde::{Deserialize, Serialize};
+
+impl From<Expr> for String {
+    fn from(expr: Expr) -> String {
+        expr.to_string()
+    }
+}
+
+impl TryFrom<String> for Expr {
+    type Error = super::ParseError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct StatementDef {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    assign: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    value: Option<Expr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+}
+
+impl From<Statement> for StatementDef {
+    fn from(statement: Statement) -> Self {
+        match statement {
+            Statement::Assign(assign) => StatementDef {
+                assign: Some(assign.name),
+                value: Some(assign.value),
+                comment: assign.comment,
+            },
+            Statement::Comment(comment) => StatementDef {
+                assign: None,
+                value: None,
+                comment: Some(comment),
+            },
+            Statement::Blank => StatementDef {
+                assign: None,
+                value: None,
+                comment: None,
+            },
+        }
+    }
+}
+
+impl TryFrom<StatementDef> for Statement {
+    type Error = String;
+
+    fn try_from(def: StatementDef) -> Result<Self, Self::Error> {
+        match (def.assign, def.value, def.comment) {
+            (Some(name), Some(value), comment) => Ok(Statement::Assign(Assign {
+                name,
+                value,
+                comment,
+            })),
+            (Some(name), None, _) => Err(format!("assignment to '{}' has no value", name)),
+            (None, Some(value), _) => Err(format!("value '{}' is not assigned", value)),
+            (None, None, Some(comment)) => Ok(Statement::Comment(comment)),
+            (None, None, None) => Ok(Statement::Blank),
+        }
+    }
+}
+
+fn statements(defs: Vec<StatementDef>) -> Result<Vec<Statement>, String> {
+    defs.into_iter().map(Statement::try_from).collect()
+}
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct ContextDef {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+    #[serde(default)]
+    statements: Vec<StatementDef>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct ProfileDef {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    preamble: Vec<StatementDef>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    #[serde(default)]
+    contexts: Vec<ContextDef>,
+}
+
+impl From<Profile> for ProfileDef {
+    fn from(profile: Profile) -> Self {
+        ProfileDef {
+            preamble: profile.preamble.into_iter().map(Into::into).collect(),
+            model: profile.model,
+            contexts: profile
+                .contexts
+                .into_iter()
+                .map(|context| ContextDef {
+                    name: context.kind.name().to_string(),
+                    comment: context.comment,
+                    statements: context.statements.into_iter().map(Into::into).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<ProfileDef> for Profile {
+    type Error = String;
+
+    fn try_from(def: ProfileDef) -> Result<Self, Self::Error> {
+        Ok(Profile {
+            preamble: statements(def.preamble)?,
+            model: def.model,
+            contexts: def
+                .contexts
+                .into_iter()
+                .map(|context| {
+                    Ok(Context {
+                        kind: ContextKind::from_name(&context.name),
+                        comment: context.comment,
+                        statements: statements(context.statements)?,
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+        })
+    }
+}
@@ -0,0 +1,246 @@
+//! Local storage for named profiles.
+//!
+//! Each profile is kept in a directory of its own, with every saved version, an optional
+//! description and a record of the servers it was uploaded to:
+//!
+//! ```text
+//! <dir>/<name>/1.brf
+//! <dir>/<name>/2.brf
+//! <dir>/<name>/description
+//! <dir>/<name>/uploads
+//! ```
+
+use crate::Brouter;
+use lazy_regex::regex_is_match;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Upload(crate::Error),
+    /// There is no stored profile (or version of it) with this name.
+    NotFound(String),
+    InvalidName(String),
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Upload(e) => write!(f, "Upload failed: {}", e),
+            Error::NotFound(name) => write!(f, "No such profile: {}", name),
+            Error::InvalidName(name) => write!(f, "Invalid profile name: {}", name),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// A record of a profile version having been uploaded to a server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upload {
+    pub version: usize,
+    /// Base URL of the server.
+    pub server: String,
+    /// The id the server assigned to the profile.
+    pub id: String,
+}
+
+/// Stores named profiles with their version history.
+#[derive(Debug, Clone)]
+pub struct ProfileStore {
+    dir: PathBuf,
+}
+
+impl ProfileStore {
+    pub fn new(dir: &Path) -> Self {
+        ProfileStore {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// The default location of the store: `brouter-client/profiles` in the XDG data
+    /// directory.
+    pub fn default_dir() -> Option<PathBuf> {
+        Some(crate::dirs::data_dir()?.join("profiles"))
+    }
+
+    fn profile_dir(&self, name: &str) -> Result<PathBuf, Error> {
+        if !regex_is_match!(r"^[A-Za-z0-9_][A-Za-z0-9_.-]*$", name) {
+            return Err(Error::InvalidName(name.to_string()));
+        }
+        Ok(self.dir.join(name))
+    }
+
+    /// The names of the stored profiles, sorted.
+    pub fn list(&self) -> Result<Vec<String>, Error> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut names = vec![];
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                names.extend(entry.file_name().to_str().map(|n| n.to_string()));
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// The saved versions of a profile, oldest first.
+    pub fn versions(&self, name: &str) -> Result<Vec<usize>, Error> {
+        let dir = self.profile_dir(name)?;
+        if !dir.exists() {
+            return Err(Error::NotFound(name.to_string()));
+        }
+        let mut versions = vec![];
+        for entry in std::fs::read_dir(dir)? {
+            let file_name = entry?.file_name();
+            if let Some(version) = file_name
+                .to_str()
+                .and_then(|n| n.strip_suffix(".brf"))
+                .and_then(|v| v.parse().ok())
+            {
+                versions.push(version);
+            }
+        }
+        versions.sort();
+        Ok(versions)
+    }
+
+    /// Save a new version of a profile.
+    ///
+    /// Returns the version number. If the text is unchanged from the latest version, no new
+    /// version is created and the latest version number is returned.
+    pub fn save(&self, name: &str, text: &str) -> Result<usize, Error> {
+        let dir = self.profile_dir(name)?;
+        let latest = if dir.exists() {
+            self.versions(name)?.last().copied()
+        } else {
+            None
+        };
+        if let Some(latest) = latest {
+            if std::fs::read_to_string(dir.join(format!("{}.brf", latest)))? == text {
+                return Ok(latest);
+            }
+        }
+        let version = latest.unwrap_or(0) + 1;
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join(format!("{}.brf", version)), text)?;
+        Ok(version)
+    }
+
+    /// The text of the latest version of a profile.
+    pub fn get(&self, name: &str) -> Result<String, Error> {
+        let version = self
+            .versions(name)?
+            .last()
+            .copied()
+            .ok_or_else(|| Error::NotFound(name.to_string()))?;
+        self.get_version(name, version)
+    }
+
+    pub fn get_version(&self, name: &str, version: usize) -> Result<String, Error> {
+        let path = self.profile_dir(name)?.join(format!("{}.brf", version));
+        if !path.exists() {
+            return Err(Error::NotFound(format!("{} version {}", name, version)));
+        }
+        Ok(std::fs::read_to_string(path)?)
+    }
+
+    pub fn description(&self, name: &str) -> Result<Option<String>, Error> {
+        let path = self.profile_dir(name)?.join("description");
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(std::fs::read_to_string(path)?))
+    }
+
+    pub fn set_description(&self, name: &str, description: &str) -> Result<(), Error> {
+        let dir = self.profile_dir(name)?;
+        if !dir.exists() {
+            return Err(Error::NotFound(name.to_string()));
+        }
+        Ok(std::fs::write(dir.join("description"), description)?)
+    }
+
+    /// Remove a profile with all its versions.
+    pub fn remove(&self, name: &str) -> Result<(), Error> {
+        let dir = self.profile_dir(name)?;
+        if !dir.exists() {
+            return Err(Error::NotFound(name.to_string()));
+        }
+        Ok(std::fs::remove_dir_all(dir)?)
+    }
+
+    /// Copy the latest version of a profile into the `profiles2` directory of a local brouter
+    /// installation.
+    ///
+    /// Returns the path of the installed profile.
+    pub fn install(&self, name: &str, profiles_dir: &Path) -> Result<PathBuf, Error> {
+        let text = self.get(name)?;
+        let path = profiles_dir.join(format!("{}.brf", name));
+        std::fs::write(&path, text)?;
+        Ok(path)
+    }
+
+    /// Upload the latest version of a profile to a brouter server.
+    ///
+    /// Returns the id the server assigned to the profile, which is also recorded in the
+    /// store (see [`ProfileStore::uploads`]).
+    pub fn upload(&self, name: &str, brouter: &Brouter) -> Result<String, Error> {
+        let version = self
+            .versions(name)?
+            .last()
+            .copied()
+            .ok_or_else(|| Error::NotFound(name.to_string()))?;
+        let text = self.get_version(name, version)?;
+        let id = brouter
+            .upload_custom_profile(text.into_bytes())
+            .map_err(Error::Upload)?;
+
+        use std::io::Write;
+        let mut uploads = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.profile_dir(name)?.join("uploads"))?;
+        writeln!(uploads, "{}\t{}\t{}", version, brouter.base_url(), id)?;
+        Ok(id)
+    }
+
+    /// The uploads of a profile, oldest first.
+    pub fn uploads(&self, name: &str) -> Result<Vec<Upload>, Error> {
+        let path = self.profile_dir(name)?.join("uploads");
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        Ok(std::fs::read_to_string(path)?
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split('\t');
+                Some(Upload {
+                    version: fields.next()?.parse().ok()?,
+                    server: fields.next()?.to_string(),
+                    id: fields.next()?.to_string(),
+                })
+            })
+            .collect())
+    }
+
+    /// The id of the most recent upload of a profile to the given server, if any.
+    pub fn uploaded_id(&self, name: &str, brouter: &Brouter) -> Result<Option<String>, Error> {
+        Ok(self
+            .uploads(name)?
+            .into_iter()
+            .rev()
+            .find(|u| u.server == brouter.base_url())
+            .map(|u| u.id))
+    }
+}
@@ -0,0 +1,325 @@
+use super::{Assign, Context, ContextKind, Expr, Profile, Statement};
+use std::collections::HashMap;
+use std::fmt;
+
+/// The type of a template parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParameterKind {
+    Boolean,
+    Number {
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+    /// One of a fixed set of numeric values, each with a name.
+    Choice(Vec<(f64, String)>),
+}
+
+/// A parameter of a profile template.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    pub kind: ParameterKind,
+    pub default: Value,
+    pub description: Option<String>,
+}
+
+impl Parameter {
+    pub fn boolean(name: &str, default: bool) -> Self {
+        Parameter {
+            name: name.to_string(),
+            kind: ParameterKind::Boolean,
+            default: Value::Bool(default),
+            description: None,
+        }
+    }
+
+    /// A numeric parameter, optionally limited to the range `min..=max`.
+    pub fn number(name: &str, default: f64, min: Option<f64>, max: Option<f64>) -> Self {
+        Parameter {
+            name: name.to_string(),
+            kind: ParameterKind::Number { min, max },
+            default: Value::Number(default),
+            description: None,
+        }
+    }
+
+    /// A parameter that takes one of the named `choices`.
+    pub fn choice(name: &str, default: &str, choices: &[(f64, &str)]) -> Self {
+        Parameter {
+            name: name.to_string(),
+            kind: ParameterKind::Choice(
+                choices
+                    .iter()
+                    .map(|(value, name)| (*value, name.to_string()))
+                    .collect(),
+            ),
+            default: Value::Choice(default.to_string()),
+            description: None,
+        }
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// Check `value` against the parameter, returning the expression to assign.
+    fn validate(&self, value: &Value) -> Result<Expr, TemplateError> {
+        let error = |expected: &str| TemplateError::WrongType {
+            name: self.name.clone(),
+            expected: expected.to_string(),
+        };
+        match (&self.kind, value) {
+            (ParameterKind::Boolean, Value::Bool(b)) => Ok(Expr::Bool(*b)),
+            (ParameterKind::Boolean, _) => Err(error("a boolean")),
+            (ParameterKind::Number { min, max }, Value::Number(n)) => {
+                if !n.is_finite()
+                    || min.is_some_and(|min| *n < min)
+                    || max.is_some_and(|max| *n > max)
+                {
+                    return Err(TemplateError::OutOfRange {
+                        name: self.name.clone(),
+                        value: *n,
+                        min: *min,
+                        max: *max,
+                    });
+                }
+                Ok(Expr::Number(*n))
+            }
+            (ParameterKind::Number { .. }, _) => Err(error("a number")),
+            (ParameterKind::Choice(choices), Value::Choice(choice)) => choices
+                .iter()
+                .find(|(_, name)| name == choice)
+                .map(|(value, _)| Expr::Number(*value))
+                .ok_or_else(|| TemplateError::InvalidChoice {
+                    name: self.name.clone(),
+                    value: choice.clone(),
+                }),
+            (ParameterKind::Choice(choices), Value::Number(n)) => choices
+                .iter()
+                .find(|(value, _)| value == n)
+                .map(|(value, _)| Expr::Number(*value))
+                .ok_or_else(|| TemplateError::InvalidChoice {
+                    name: self.name.clone(),
+                    value: n.to_string(),
+                }),
+            (ParameterKind::Choice(_), _) => Err(error("one of the choices")),
+        }
+    }
+}
+
+/// The value of a template parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Number(f64),
+    /// The name of a choice.
+    Choice(String),
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Choice(s.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateError {
+    UnknownParameter(String),
+    WrongType {
+        name: String,
+        expected: String,
+    },
+    OutOfRange {
+        name: String,
+        value: f64,
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+    InvalidChoice {
+        name: String,
+        value: String,
+    },
+}
+
+impl std::error::Error for TemplateError {}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::UnknownParameter(name) => write!(f, "Unknown parameter: {}", name),
+            TemplateError::WrongType { name, expected } => {
+                write!(f, "Parameter {} should be {}", name, expected)
+            }
+            TemplateError::OutOfRange {
+                name,
+                value,
+                min,
+                max,
+            } => {
+                write!(f, "Parameter {} is out of range: {}", name, value)?;
+                match (min, max) {
+                    (Some(min), Some(max)) => write!(f, " (expected {} to {})", min, max),
+                    (Some(min), None) => write!(f, " (expected at least {})", min),
+                    (None, Some(max)) => write!(f, " (expected at most {})", max),
+                    (None, None) => Ok(()),
+                }
+            }
+            TemplateError::InvalidChoice { name, value } => {
+                write!(f, "Invalid choice for parameter {}: {}", name, value)
+            }
+        }
+    }
+}
+
+/// A profile with typed parameters, from which profile text can be generated.
+#[derive(Debug, Clone)]
+pub struct ProfileTemplate {
+    profile: Profile,
+    parameters: Vec<Parameter>,
+}
+
+impl ProfileTemplate {
+    /// Create a template from a profile.
+    ///
+    /// The tunable variables of the profile (see [`Profile::variables`]) become parameters;
+    /// their types are derived from their default values and annotations.
+    pub fn new(profile: Profile) -> Self {
+        let parameters = profile
+            .variables()
+            .into_iter()
+            .map(|variable| {
+                let (kind, default) = match variable.default {
+                    Expr::Bool(b) => (ParameterKind::Boolean, Value::Bool(b)),
+                    Expr::Number(n) => {
+                        let choices = variable
+                            .choices
+                            .iter()
+                            .filter_map(|(value, name)| Some((value.parse().ok()?, name.clone())))
+                            .collect::<Vec<_>>();
+                        if choices.is_empty() {
+                            (
+                                ParameterKind::Number {
+                                    min: None,
+                                    max: None,
+                                },
+                                Value::Number(n),
+                            )
+                        } else {
+                            (ParameterKind::Choice(choices), Value::Number(n))
+                        }
+                    }
+                    _ => unreachable!("variables are always constants"),
+                };
+                Parameter {
+                    name: variable.name,
+                    kind,
+                    default,
+                    description: variable.description,
+                }
+            })
+            .collect();
+        ProfileTemplate {
+            profile,
+            parameters,
+        }
+    }
+
+    /// Add a parameter, or replace the definition of an existing one.
+    ///
+    /// Parameters that the profile doesn't assign are added to its global context.
+    pub fn parameter(mut self, parameter: Parameter) -> Self {
+        match self
+            .parameters
+            .iter_mut()
+            .find(|p| p.name == parameter.name)
+        {
+            Some(existing) => *existing = parameter,
+            None => self.parameters.push(parameter),
+        }
+        self
+    }
+
+    pub fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
+
+    /// Generate the profile for the given parameter values.
+    ///
+    /// Parameters that are not given keep their defaults.
+    pub fn render_profile(
+        &self,
+        params: &HashMap<String, Value>,
+    ) -> Result<Profile, TemplateError> {
+        if let Some(name) = params
+            .keys()
+            .find(|name| !self.parameters.iter().any(|p| &p.name == *name))
+        {
+            return Err(TemplateError::UnknownParameter(name.clone()));
+        }
+
+        let mut profile = self.profile.clone();
+        if profile.context(&ContextKind::Global).is_none() {
+            profile
+                .contexts
+                .insert(0, Context::new(ContextKind::Global));
+        }
+
+        let mut added = vec![];
+        for parameter in &self.parameters {
+            let value =
+                parameter.validate(params.get(&parameter.name).unwrap_or(&parameter.default))?;
+            let mut assigned = false;
+            for context in profile
+                .contexts
+                .iter_mut()
+                .filter(|c| c.kind == ContextKind::Global)
+            {
+                for statement in &mut context.statements {
+                    if let Statement::Assign(assign) = statement {
+                        if assign.name == parameter.name {
+                            assign.value = value.clone();
+                            assigned = true;
+                        }
+                    }
+                }
+            }
+            if !assigned {
+                added.push(Statement::Assign(Assign {
+                    name: parameter.name.clone(),
+                    value,
+                    comment: parameter.description.as_ref().map(|d| format!(" {}", d)),
+                }));
+            }
+        }
+
+        // Parameters the profile doesn't assign go in front of the first assignment, so that
+        // they are defined before they are used.
+        let global = profile.context_mut(&ContextKind::Global).unwrap();
+        let position = global
+            .statements
+            .iter()
+            .position(|s| matches!(s, Statement::Assign(_)))
+            .unwrap_or(global.statements.len());
+        global.statements.splice(position..position, added);
+
+        Ok(profile)
+    }
+
+    /// Generate the profile text for the given parameter values.
+    pub fn render(&self, params: &HashMap<String, Value>) -> Result<String, TemplateError> {
+        self.render_profile(params).map(|p| p.to_string())
+    }
+}
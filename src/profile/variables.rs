@@ -0,0 +1,118 @@
+use super::{ContextKind, Expr, Profile, Statement};
+use lazy_regex::regex_captures;
+use std::fmt;
+
+/// A tunable parameter of a profile: a global variable assigned a constant.
+///
+/// Profiles written for brouter-web annotate these with a comment of the form
+/// `# %name% | description | type`, where the type is `boolean`, `number` or a list of
+/// choices such as `[0=none, 1=auto-choose]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    /// The value assigned in the profile; either a number or a boolean.
+    pub default: Expr,
+    pub description: Option<String>,
+    /// The allowed values and their descriptions, if the annotation lists them.
+    pub choices: Vec<(String, String)>,
+}
+
+fn parse_annotation(name: &str, comment: &str) -> Option<(String, Vec<(String, String)>)> {
+    let (_, annotated, description, kind) =
+        regex_captures!(r"^\s*%([^%]+)%\s*\|([^|]*)(?:\|(.*))?$", comment)?;
+    if annotated != name {
+        return None;
+    }
+    let choices = match kind
+        .trim()
+        .strip_prefix('[')
+        .and_then(|k| k.strip_suffix(']'))
+    {
+        Some(list) => list
+            .split(',')
+            .filter_map(|choice| choice.split_once('='))
+            .map(|(value, description)| (value.trim().to_string(), description.trim().to_string()))
+            .collect(),
+        None => vec![],
+    };
+    Some((description.trim().to_string(), choices))
+}
+
+/// An override for a variable the profile doesn't define.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownVariable(pub String);
+
+impl std::error::Error for UnknownVariable {}
+
+impl fmt::Display for UnknownVariable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown profile variable: {}", self.0)
+    }
+}
+
+impl Profile {
+    /// The tunable variables of the profile, in the order they are assigned.
+    pub fn variables(&self) -> Vec<Variable> {
+        let mut variables: Vec<Variable> = vec![];
+        for context in self
+            .contexts
+            .iter()
+            .filter(|c| c.kind == ContextKind::Global)
+        {
+            for assign in context.assignments() {
+                if !matches!(assign.value, Expr::Number(_) | Expr::Bool(_)) {
+                    continue;
+                }
+                let (description, choices) = assign
+                    .comment
+                    .as_deref()
+                    .and_then(|c| parse_annotation(&assign.name, c))
+                    .map_or((None, vec![]), |(d, c)| (Some(d), c));
+                let variable = Variable {
+                    name: assign.name.clone(),
+                    default: assign.value.clone(),
+                    description,
+                    choices,
+                };
+                // A later assignment takes precedence.
+                match variables.iter_mut().find(|v| v.name == assign.name) {
+                    Some(existing) => *existing = variable,
+                    None => variables.push(variable),
+                }
+            }
+        }
+        variables
+    }
+
+    /// A copy of the profile with the values of the given variables replaced.
+    ///
+    /// Only the tunable variables listed by [`Profile::variables`] can be overridden.
+    pub fn with_overrides<K: AsRef<str>, V: Into<Expr>>(
+        &self,
+        overrides: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<Profile, UnknownVariable> {
+        let variables = self.variables();
+        let mut profile = self.clone();
+        for (name, value) in overrides {
+            let name = name.as_ref();
+            if !variables.iter().any(|v| v.name == name) {
+                return Err(UnknownVariable(name.to_string()));
+            }
+            let value = value.into();
+            for context in profile
+                .contexts
+                .iter_mut()
+                .filter(|c| c.kind == ContextKind::Global)
+            {
+                for statement in &mut context.statements {
+                    if let Statement::Assign(assign) = statement {
+                        if assign.name == name {
+                            assign.value = value.clone();
+                        }
+                    }
+                }
+            }
+        }
+        Ok(profile)
+    }
+}
@@ -0,0 +1,547 @@
+use crate::instructions::TurnInstructionOptions;
+use crate::response::ResponseFormat;
+use crate::{check_alternative, Brouter, Error, Nogo, Point, Route, TurnInstructionMode};
+use std::time::Duration;
+
+/// The parameters of a routing request.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RouteRequest {
+    pub points: Vec<Point>,
+
+    pub nogos: Vec<Nogo>,
+
+    pub profile: String,
+
+    /// Index of the alternative route to calculate (0-3).
+    pub alternative: Option<u8>,
+
+    pub turn_instructions: Option<TurnInstructionMode>,
+
+    pub track_name: Option<String>,
+
+    pub export_waypoints: bool,
+
+    /// The format to request the route in.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub format: ResponseFormat,
+
+    /// Indices of the points from which the route goes in a straight line to the next point,
+    /// instead of following roads.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub straight_segments: Vec<usize>,
+
+    /// Values for global variables of the profile, overriding those in the profile itself.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub profile_params: Vec<(String, String)>,
+
+    /// Query parameters added to the request as is, see [`RouteRequest::extra_param`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub extra_params: Vec<(String, String)>,
+
+    /// Names of the points, in the same order; brouter uses them for the waypoints it
+    /// exports (see `export_waypoints`).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub point_names: Vec<Option<String>>,
+
+    /// How long to wait for the route before giving up with [`crate::Error::Deadline`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub deadline: Option<Duration>,
+}
+
+impl RouteRequest {
+    pub fn new(points: &[Point], profile: &str) -> Self {
+        RouteRequest {
+            points: points.to_vec(),
+            nogos: vec![],
+            profile: profile.to_string(),
+            alternative: None,
+            turn_instructions: None,
+            track_name: None,
+            export_waypoints: false,
+            format: ResponseFormat::Gpx,
+            straight_segments: vec![],
+            profile_params: vec![],
+            extra_params: vec![],
+            point_names: vec![],
+            deadline: None,
+        }
+    }
+
+    /// A request for a route along the points of the first route in `gpx`, or along its
+    /// waypoints if it has no routes.
+    ///
+    /// The names of the route points or waypoints are kept as point names, and waypoint export
+    /// is enabled if there are any. Returns `None` if there are fewer than two points.
+    pub fn from_gpx(gpx: &gpx::Gpx, profile: &str) -> Option<Self> {
+        let waypoints = match gpx.routes.iter().find(|r| r.points.len() >= 2) {
+            Some(route) => &route.points,
+            None => &gpx.waypoints,
+        };
+        if waypoints.len() < 2 {
+            return None;
+        }
+        let points = waypoints
+            .iter()
+            .map(|w| w.point().into())
+            .collect::<Vec<Point>>();
+        let names = waypoints.iter().map(|w| w.name.clone()).collect::<Vec<_>>();
+        let named = names.iter().any(|n| n.is_some());
+
+        let mut request = RouteRequest::new(&points, profile).export_waypoints(named);
+        if named {
+            request.point_names = names;
+        }
+        Some(request)
+    }
+
+    pub fn profile(mut self, profile: &str) -> Self {
+        self.profile = profile.to_string();
+        self
+    }
+
+    pub fn nogos(mut self, nogos: &[Nogo]) -> Self {
+        self.nogos = nogos.to_vec();
+        self
+    }
+
+    /// Check that brouter can calculate the request: that its alternative, if any, is one
+    /// brouter offers and that the weights of its nogos are valid.
+    ///
+    /// Requests are checked when they are sent, since their fields can be set directly.
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Some(alternative) = self.alternative {
+            check_alternative(alternative)?;
+        }
+        self.nogos.iter().try_for_each(Nogo::validate)
+    }
+
+    /// Names of the segment files brouter may need for this request.
+    ///
+    /// See [`crate::segments::tiles_for`].
+    pub fn segment_tiles(&self) -> Vec<String> {
+        crate::segments::tiles_for(&self.points)
+    }
+
+    /// Keep the route inside `corridor`, e.g. a national park or a country.
+    ///
+    /// This adds an impassable nogo covering everything outside the corridor, up to
+    /// `margin_m` meters beyond its bounding box (see [`Nogo::outside`]), and one for each
+    /// hole in the corridor. The margin should be larger than the detours brouter would
+    /// consider. Corridors that are degenerate are ignored.
+    pub fn within(mut self, corridor: &geo_types::Polygon<f64>, margin_m: f64) -> Self {
+        self.nogos.extend(Nogo::outside(corridor, margin_m, None));
+        for hole in corridor.interiors() {
+            self.nogos.push(Nogo::Polygon {
+                points: hole.points().map(Point::from).collect(),
+                weight: None,
+            });
+        }
+        self
+    }
+
+    /// Calculate alternative route `alternative` rather than the best one. brouter offers
+    /// alternatives 0 to 3; others are rejected when the request is sent.
+    pub fn alternative(mut self, alternative: u8) -> Self {
+        self.alternative = Some(alternative);
+        self
+    }
+
+    pub fn turn_instructions(mut self, mode: TurnInstructionMode) -> Self {
+        self.turn_instructions = Some(mode);
+        self
+    }
+
+    /// Set the turn instruction mode and the options that go with it.
+    ///
+    /// The options are passed as profile parameters, replacing any set before.
+    pub fn turn_instruction_options(mut self, options: &TurnInstructionOptions) -> Self {
+        if let Some(mode) = options.mode {
+            self.turn_instructions = Some(mode);
+        }
+        for (name, value) in options.profile_params() {
+            self.profile_params.retain(|(n, _)| *n != name);
+            self.profile_params.push((name, value));
+        }
+        self
+    }
+
+    pub fn track_name(mut self, name: &str) -> Self {
+        self.track_name = Some(name.to_string());
+        self
+    }
+
+    /// Set the track name from a template, e.g. `"{date} {profile} {from}-{to}"`.
+    ///
+    /// See [`crate::naming`] for the placeholders.
+    pub fn track_name_template(self, template: &crate::naming::NameTemplate) -> Self {
+        let name = template.render(&self, time::OffsetDateTime::now_utc());
+        self.track_name(&name)
+    }
+
+    /// Name the points, e.g. `[Some("Start"), None, Some("Finish")]`.
+    pub fn point_names(mut self, names: &[Option<&str>]) -> Self {
+        self.point_names = names.iter().map(|n| n.map(|n| n.to_string())).collect();
+        self
+    }
+
+    /// Abort the request if brouter hasn't returned a route within `deadline`.
+    ///
+    /// Unlike the server-side timeout of a routing pass, this also covers time spent waiting
+    /// for the connection and the response.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn export_waypoints(mut self, export_waypoints: bool) -> Self {
+        self.export_waypoints = export_waypoints;
+        self
+    }
+
+    /// Request the route in `format`, e.g. GeoJSON for the totals brouter leaves out of GPX
+    /// (see [`Route::totals`]).
+    pub fn format(mut self, format: ResponseFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Go in a straight line from each of the points at `indices` to the next one, e.g. to
+    /// cross a square or a beach that isn't mapped as a way.
+    pub fn straight_segments(mut self, indices: &[usize]) -> Self {
+        self.straight_segments = indices.to_vec();
+        self
+    }
+
+    /// Override the global variable `name` of the profile.
+    pub fn profile_param(mut self, name: &str, value: &str) -> Self {
+        self.profile_params
+            .push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Add a query parameter to the request as is, e.g. one introduced by a brouter version
+    /// newer than this crate.
+    ///
+    /// Parameters are added after those set by the other options, in the order given.
+    pub fn extra_param(mut self, key: &str, value: &str) -> Self {
+        self.extra_params.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// A normalized, versioned text representation of the request, e.g. for cache keys, bug
+    /// reports or replaying requests.
+    ///
+    /// Equivalent requests have the same representation: profile parameters are sorted by
+    /// name and unnamed points have no names; extra parameters keep their order. The deadline
+    /// is left out, since it doesn't affect the route.
+    pub fn canonical_string(&self) -> String {
+        let mut lines = vec![
+            format!("brouter-request {}", CANONICAL_VERSION),
+            format!("profile {}", escape(&self.profile)),
+        ];
+        if let Some(alternative) = self.alternative {
+            lines.push(format!("alternative {}", alternative));
+        }
+        if let Some(mode) = self.turn_instructions {
+            lines.push(format!("turn-instructions {}", mode as i32));
+        }
+        if let Some(name) = &self.track_name {
+            lines.push(format!("track-name {}", escape(name)));
+        }
+        if self.export_waypoints {
+            lines.push("export-waypoints".to_string());
+        }
+        if self.format != ResponseFormat::Gpx {
+            lines.push(format!("format {}", self.format.name()));
+        }
+        if !self.straight_segments.is_empty() {
+            let indices = self
+                .straight_segments
+                .iter()
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>();
+            lines.push(format!("straight {}", indices.join(" ")));
+        }
+        let mut params = self.profile_params.iter().collect::<Vec<_>>();
+        params.sort_by(|a, b| a.0.cmp(&b.0));
+        for (name, value) in params {
+            lines.push(format!("profile-param {} {}", escape(name), escape(value)));
+        }
+        for (key, value) in &self.extra_params {
+            lines.push(format!("extra-param {} {}", escape(key), escape(value)));
+        }
+        for (i, point) in self.points.iter().enumerate() {
+            let mut line = format!("point {} {}", point.lat(), point.lon());
+            if let Some(Some(name)) = self.point_names.get(i) {
+                line.push(' ');
+                line.push_str(&escape(name));
+            }
+            lines.push(line);
+        }
+        for nogo in &self.nogos {
+            let (kind, weight, values) = match nogo {
+                Nogo::Point {
+                    point,
+                    radius,
+                    weight,
+                } => ("point", weight, vec![point.lat(), point.lon(), *radius]),
+                Nogo::Line { points, weight } => ("line", weight, coordinates(points)),
+                Nogo::Polygon { points, weight } => ("polygon", weight, coordinates(points)),
+            };
+            let weight = weight.map_or("-".to_string(), |w| w.to_string());
+            let values = values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+            lines.push(format!("nogo-{} {} {}", kind, weight, values.join(" ")));
+        }
+        lines.push(String::new());
+        lines.join("\n")
+    }
+
+    /// Parse the representation produced by [`RouteRequest::canonical_string`].
+    pub fn from_canonical_string(text: &str) -> Result<Self, Error> {
+        let invalid = |message: String| Error::InvalidRequest(message);
+        let mut lines = text.lines();
+        match lines
+            .next()
+            .and_then(|l| l.strip_prefix("brouter-request "))
+        {
+            Some(CANONICAL_VERSION) => {}
+            Some(version) => return Err(invalid(format!("unsupported version {}", version))),
+            None => return Err(invalid("missing brouter-request header".to_string())),
+        }
+
+        let mut request = RouteRequest::new(&[], "");
+        let mut names = vec![];
+        for line in lines.filter(|l| !l.is_empty()) {
+            let (key, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let number = |s: &str| {
+                s.parse::<f64>()
+                    .map_err(|_| invalid(format!("invalid number '{}' in '{}'", s, line)))
+            };
+            match key {
+                "profile" => request.profile = unescape(rest),
+                "alternative" => {
+                    let alternative = rest
+                        .parse()
+                        .map_err(|_| invalid(format!("invalid alternative '{}'", rest)))?;
+                    check_alternative(alternative)?;
+                    request.alternative = Some(alternative);
+                }
+                "turn-instructions" => {
+                    request.turn_instructions = Some(
+                        rest.parse()
+                            .ok()
+                            .and_then(TurnInstructionMode::from_code)
+                            .ok_or_else(|| invalid(format!("invalid mode '{}'", rest)))?,
+                    )
+                }
+                "track-name" => request.track_name = Some(unescape(rest)),
+                "export-waypoints" => request.export_waypoints = true,
+                "format" => {
+                    request.format = ResponseFormat::from_name(rest)
+                        .ok_or_else(|| invalid(format!("unsupported format '{}'", rest)))?
+                }
+                "straight" => {
+                    request.straight_segments = rest
+                        .split(' ')
+                        .map(|i| {
+                            i.parse().map_err(|_| {
+                                invalid(format!("invalid index '{}' in '{}'", i, line))
+                            })
+                        })
+                        .collect::<Result<_, _>>()?
+                }
+                "profile-param" => {
+                    let (name, value) = rest
+                        .split_once(' ')
+                        .ok_or_else(|| invalid(format!("missing value in '{}'", line)))?;
+                    request
+                        .profile_params
+                        .push((unescape(name), unescape(value)));
+                }
+                "extra-param" => {
+                    let (key, value) = rest
+                        .split_once(' ')
+                        .ok_or_else(|| invalid(format!("missing value in '{}'", line)))?;
+                    request.extra_params.push((unescape(key), unescape(value)));
+                }
+                "point" => {
+                    let mut parts = rest.splitn(3, ' ');
+                    let lat = number(parts.next().unwrap_or_default())?;
+                    let lon = number(parts.next().unwrap_or_default())?;
+                    request.points.push(Point::new(lat, lon));
+                    names.push(parts.next().map(unescape));
+                }
+                "nogo-point" | "nogo-line" | "nogo-polygon" => {
+                    let mut parts = rest.split(' ');
+                    let weight = match parts.next() {
+                        Some("-") => None,
+                        Some(w) => Some(number(w)?),
+                        None => return Err(invalid(format!("missing weight in '{}'", line))),
+                    };
+                    let values = parts.map(number).collect::<Result<Vec<_>, _>>()?;
+                    let points = values.chunks(2).map(|c| match c {
+                        [lat, lon] => Ok(Point::new(*lat, *lon)),
+                        _ => Err(invalid(format!("odd number of coordinates in '{}'", line))),
+                    });
+                    request.nogos.push(match (key, values.as_slice()) {
+                        ("nogo-point", [lat, lon, radius]) => Nogo::Point {
+                            point: Point::new(*lat, *lon),
+                            radius: *radius,
+                            weight,
+                        },
+                        ("nogo-point", _) => {
+                            return Err(invalid(format!("expected lat, lon, radius in '{}'", line)))
+                        }
+                        ("nogo-line", _) => Nogo::Line {
+                            points: points.collect::<Result<_, _>>()?,
+                            weight,
+                        },
+                        _ => Nogo::Polygon {
+                            points: points.collect::<Result<_, _>>()?,
+                            weight,
+                        },
+                    });
+                }
+                _ => return Err(invalid(format!("unknown key '{}'", key))),
+            }
+        }
+        if names.iter().any(|n| n.is_some()) {
+            request.point_names = names;
+        }
+        Ok(request)
+    }
+}
+
+/// A routing request for a particular server, see [`Brouter::route`].
+///
+/// The options are those of [`RouteRequest`]; [`RouteBuilder::send`] calculates the route.
+#[derive(Clone)]
+pub struct RouteBuilder<'a> {
+    brouter: &'a Brouter,
+    request: RouteRequest,
+}
+
+impl<'a> RouteBuilder<'a> {
+    pub(crate) fn new(brouter: &'a Brouter, request: RouteRequest) -> Self {
+        RouteBuilder { brouter, request }
+    }
+
+    fn with(self, f: impl FnOnce(RouteRequest) -> RouteRequest) -> Self {
+        RouteBuilder {
+            brouter: self.brouter,
+            request: f(self.request),
+        }
+    }
+
+    pub fn profile(self, profile: &str) -> Self {
+        self.with(|r| r.profile(profile))
+    }
+
+    pub fn nogos(self, nogos: &[Nogo]) -> Self {
+        self.with(|r| r.nogos(nogos))
+    }
+
+    /// See [`RouteRequest::within`].
+    pub fn within(self, corridor: &geo_types::Polygon<f64>, margin_m: f64) -> Self {
+        self.with(|r| r.within(corridor, margin_m))
+    }
+
+    pub fn alternative(self, alternative: u8) -> Self {
+        self.with(|r| r.alternative(alternative))
+    }
+
+    pub fn turn_instructions(self, mode: TurnInstructionMode) -> Self {
+        self.with(|r| r.turn_instructions(mode))
+    }
+
+    /// See [`RouteRequest::turn_instruction_options`].
+    pub fn turn_instruction_options(self, options: &TurnInstructionOptions) -> Self {
+        self.with(|r| r.turn_instruction_options(options))
+    }
+
+    pub fn track_name(self, name: &str) -> Self {
+        self.with(|r| r.track_name(name))
+    }
+
+    pub fn point_names(self, names: &[Option<&str>]) -> Self {
+        self.with(|r| r.point_names(names))
+    }
+
+    /// See [`RouteRequest::deadline`].
+    pub fn deadline(self, deadline: Duration) -> Self {
+        self.with(|r| r.deadline(deadline))
+    }
+
+    pub fn export_waypoints(self, export_waypoints: bool) -> Self {
+        self.with(|r| r.export_waypoints(export_waypoints))
+    }
+
+    /// See [`RouteRequest::format`].
+    pub fn format(self, format: ResponseFormat) -> Self {
+        self.with(|r| r.format(format))
+    }
+
+    /// See [`RouteRequest::straight_segments`].
+    pub fn straight_segments(self, indices: &[usize]) -> Self {
+        self.with(|r| r.straight_segments(indices))
+    }
+
+    /// Override the global variable `name` of the profile.
+    pub fn profile_param(self, name: &str, value: &str) -> Self {
+        self.with(|r| r.profile_param(name, value))
+    }
+
+    /// See [`RouteRequest::extra_param`].
+    pub fn extra_param(self, key: &str, value: &str) -> Self {
+        self.with(|r| r.extra_param(key, value))
+    }
+
+    /// The request as configured so far.
+    pub fn request(&self) -> &RouteRequest {
+        &self.request
+    }
+
+    pub fn into_request(self) -> RouteRequest {
+        self.request
+    }
+
+    /// Calculate the route, see [`Brouter::request_route`].
+    pub fn send(&self) -> Result<Route, Error> {
+        self.brouter.request_route(&self.request)
+    }
+}
+
+/// Version of the format produced by [`RouteRequest::canonical_string`].
+const CANONICAL_VERSION: &str = "1";
+
+fn coordinates(points: &[Point]) -> Vec<f64> {
+    points.iter().flat_map(|p| [p.lat(), p.lon()]).collect()
+}
+
+/// Escape the characters that separate fields and lines in canonical strings.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace(' ', "\\s")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('s') => out.push(' '),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
@@ -0,0 +1,378 @@
+//! Telling routes apart from errors in brouter's responses.
+//!
+//! brouter reports most errors as a short plain text message, but deployments differ in the
+//! status they send with it: the bundled server answers with 200, while some setups behind a
+//! proxy answer with 400 or 500. Some also send a route with an error status. The status alone
+//! is therefore not trusted; the body and content type are looked at as well.
+
+use crate::{Error, Route, RouteTotals, SegmentMessage, ERROR_SNIFF_LIMIT};
+use log::debug;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use std::time::Duration;
+
+/// How to treat responses whose status disagrees with their body.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResponseHandling {
+    /// Accept routes sent with an error status.
+    #[default]
+    Lenient,
+
+    /// Treat every error status as an error, whatever the body.
+    Strict,
+}
+
+/// What a response body looks like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BodyKind {
+    Empty,
+    /// A GPX, KML or GeoJSON document.
+    Route,
+    /// An HTML page, e.g. an error page of a proxy.
+    Html,
+    /// Anything else, such as CSV output or an error message.
+    Text,
+}
+
+fn body_kind(content_type: Option<&str>, body: &[u8]) -> BodyKind {
+    let head = &body[..body.len().min(ERROR_SNIFF_LIMIT)];
+    let first = match head.iter().position(|b| !b.is_ascii_whitespace()) {
+        Some(first) => first,
+        None => return BodyKind::Empty,
+    };
+    let content_type = content_type.unwrap_or_default().to_ascii_lowercase();
+    if content_type.starts_with("text/html") {
+        return BodyKind::Html;
+    }
+    match head[first] {
+        b'{' | b'[' => BodyKind::Route,
+        b'<' => {
+            let markup = String::from_utf8_lossy(&head[first..]).to_ascii_lowercase();
+            if markup.contains("<gpx") || markup.contains("<kml") {
+                BodyKind::Route
+            } else if markup.contains("<html") || markup.starts_with("<!doctype html") {
+                BodyKind::Html
+            } else {
+                BodyKind::Text
+            }
+        }
+        _ => BodyKind::Text,
+    }
+}
+
+/// The start of `body` as text, for error messages.
+fn excerpt(body: &[u8]) -> String {
+    String::from_utf8_lossy(&body[..body.len().min(ERROR_SNIFF_LIMIT)])
+        .trim()
+        .to_string()
+}
+
+/// Decide whether a response from brouter holds a result or an error.
+///
+/// brouter's error messages are recognized whatever the status (see
+/// [`Error::from_response_body`]). Successful responses are otherwise accepted, unless they are
+/// empty or an HTML page. Responses with an error status are errors, except for routes when
+/// `handling` is [`ResponseHandling::Lenient`].
+pub fn classify(
+    status: StatusCode,
+    content_type: Option<&str>,
+    body: &[u8],
+    handling: ResponseHandling,
+) -> Result<(), Error> {
+    if let Some(e) = Error::from_response_body(body) {
+        return Err(e);
+    }
+
+    let kind = body_kind(content_type, body);
+    if status.is_success() {
+        return match kind {
+            BodyKind::Empty => Err(Error::Other(format!("empty response: {}", status))),
+            BodyKind::Html => Err(Error::Other(format!(
+                "unexpected HTML response: {}: {}",
+                status,
+                excerpt(body)
+            ))),
+            BodyKind::Route | BodyKind::Text => Ok(()),
+        };
+    }
+
+    if kind == BodyKind::Route && handling == ResponseHandling::Lenient {
+        debug!("brouter sent a route with status {}; accepting it", status);
+        return Ok(());
+    }
+
+    if status.is_client_error() || status.is_server_error() {
+        return Err(Error::HttpStatus {
+            status,
+            body: excerpt(body),
+        });
+    }
+
+    Ok(())
+}
+
+/// Classify a response to a routing request, returning its content type, body and details.
+pub(crate) fn check_response(
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: Vec<u8>,
+    elapsed: Duration,
+    handling: ResponseHandling,
+) -> Result<(Option<String>, Vec<u8>, ResponseInfo), Error> {
+    let content_type = headers
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    let info = ResponseInfo::new(headers, body.len(), elapsed);
+    classify(status, content_type.as_deref(), &body, handling)?;
+    Ok((content_type, body, info))
+}
+
+/// Details of the response a result was calculated from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResponseInfo {
+    /// Time from sending the request until the whole response was received.
+    pub elapsed: Duration,
+
+    /// Size of the response body, in bytes.
+    pub size: usize,
+
+    /// The `Server` header of the response, if any.
+    pub server: Option<String>,
+
+    /// Whether the response came from an HTTP cache between client and server, according
+    /// to its `Age` or `X-Cache` header.
+    pub from_cache: bool,
+}
+
+impl ResponseInfo {
+    pub(crate) fn new(headers: &HeaderMap, size: usize, elapsed: Duration) -> ResponseInfo {
+        let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+        let from_cache = header("age").is_some()
+            || header("x-cache").is_some_and(|v| v.to_ascii_uppercase().starts_with("HIT"));
+        ResponseInfo {
+            elapsed,
+            size,
+            server: header("server").map(|v| v.to_string()),
+            from_cache,
+        }
+    }
+}
+
+/// The formats brouter can return routes in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResponseFormat {
+    #[default]
+    Gpx,
+    /// GeoJSON, with brouter's messages in the `messages` property. Parsing routes in this
+    /// format needs the `geojson` feature.
+    GeoJson,
+    /// The tab-separated messages table.
+    Csv,
+}
+
+impl ResponseFormat {
+    /// The value of brouter's `format` parameter for this format.
+    pub fn name(self) -> &'static str {
+        match self {
+            ResponseFormat::Gpx => "gpx",
+            ResponseFormat::GeoJson => "geojson",
+            ResponseFormat::Csv => "csv",
+        }
+    }
+
+    /// The format with the given [`ResponseFormat::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gpx" => Some(ResponseFormat::Gpx),
+            "geojson" => Some(ResponseFormat::GeoJson),
+            "csv" => Some(ResponseFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(not(feature = "geojson"))]
+fn geojson_unsupported() -> Error {
+    Error::InvalidRequest("parsing GeoJSON routes needs the geojson feature".to_string())
+}
+
+/// Fail if routes in `format` can't be parsed, so that requests for them aren't sent for
+/// nothing.
+pub(crate) fn check_parseable(format: ResponseFormat) -> Result<(), Error> {
+    match format {
+        #[cfg(not(feature = "geojson"))]
+        ResponseFormat::GeoJson => Err(geojson_unsupported()),
+        _ => Ok(()),
+    }
+}
+
+/// Parse a response to a request for a route in `format`, once it has been classified.
+pub(crate) fn parse_route(
+    body: &[u8],
+    content_type: Option<&str>,
+    format: ResponseFormat,
+) -> Result<Route, Error> {
+    match format {
+        ResponseFormat::Gpx => Route::from_gpx_response(body, content_type),
+        _ => parse_response(body, format),
+    }
+}
+
+/// Parse a response body from brouter, e.g. one saved earlier, as a route.
+///
+/// Error messages in the body are reported as the corresponding [`Error`]. Routes parsed from
+/// CSV consist of the end points of the segments, with the messages attached; those parsed
+/// from GeoJSON have the messages attached as well. Both have the totals (see
+/// [`Route::totals`]), though those from CSV lack the ascent.
+pub fn parse_response(body: &[u8], format: ResponseFormat) -> Result<Route, Error> {
+    if let Some(e) = Error::from_response_body(body) {
+        return Err(e);
+    }
+    match format {
+        ResponseFormat::Gpx => Route::from_gpx_bytes(body),
+        #[cfg(feature = "geojson")]
+        ResponseFormat::GeoJson => parse_geojson(body),
+        #[cfg(not(feature = "geojson"))]
+        ResponseFormat::GeoJson => Err(geojson_unsupported()),
+        ResponseFormat::Csv => {
+            let messages = SegmentMessage::parse_csv(&String::from_utf8_lossy(body))?;
+            let points = messages.iter().map(|m| {
+                let mut waypoint = gpx::Waypoint::new(m.point.clone().into());
+                waypoint.elevation = m.elevation;
+                waypoint
+            });
+            let last = messages.last();
+            let totals = RouteTotals {
+                track_length_m: Some(messages.iter().map(|m| m.distance_m).sum()),
+                total_time_s: last.and_then(|m| m.time_s),
+                total_energy_j: last.and_then(|m| m.energy_j),
+                cost: Some(messages.iter().map(SegmentMessage::cost).sum()),
+                ..Default::default()
+            };
+            let route = route_from_points(points.collect(), None, None);
+            Ok(route.with_messages(messages).with_totals(totals))
+        }
+    }
+}
+
+fn route_from_points(
+    points: Vec<gpx::Waypoint>,
+    name: Option<String>,
+    creator: Option<String>,
+) -> Route {
+    let mut track = gpx::Track::new();
+    track.name = name;
+    track.segments.push(gpx::TrackSegment { points });
+    Route::from(gpx::Gpx {
+        version: gpx::GpxVersion::Gpx11,
+        creator,
+        tracks: vec![track],
+        ..Default::default()
+    })
+}
+
+/// Parse the GeoJSON brouter returns: a feature collection with a single line string feature.
+#[cfg(feature = "geojson")]
+fn parse_geojson(body: &[u8]) -> Result<Route, Error> {
+    use serde_json::Value;
+
+    let invalid = |message: &str| Error::InvalidGeoJson(message.to_string());
+    let root: Value =
+        serde_json::from_slice(body).map_err(|e| Error::InvalidGeoJson(e.to_string()))?;
+    let feature = root
+        .get("features")
+        .and_then(|f| f.get(0))
+        .ok_or_else(|| invalid("no features"))?;
+    let properties = feature.get("properties");
+    let property = |key: &str| {
+        properties
+            .and_then(|p| p.get(key))
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string())
+    };
+    let coordinates = feature
+        .get("geometry")
+        .filter(|g| g.get("type").and_then(|t| t.as_str()) == Some("LineString"))
+        .and_then(|g| g.get("coordinates"))
+        .and_then(|c| c.as_array())
+        .ok_or_else(|| invalid("no line string geometry"))?;
+
+    let points = coordinates
+        .iter()
+        .map(|c| {
+            let c = c
+                .as_array()
+                .ok_or_else(|| invalid("position is not an array"))?;
+            let number = |i: usize| c.get(i).and_then(|v| v.as_f64());
+            let (lon, lat) = number(0)
+                .zip(number(1))
+                .ok_or_else(|| invalid("position without longitude and latitude"))?;
+            let mut waypoint = gpx::Waypoint::new(geo_types::Point::new(lon, lat));
+            waypoint.elevation = number(2);
+            Ok(waypoint)
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let mut route = route_from_points(points, property("name"), property("creator"));
+    if let Some(rows) = properties
+        .and_then(|p| p.get("messages"))
+        .and_then(|m| m.as_array())
+    {
+        let rows = rows.iter().map(|row| {
+            row.as_array()
+                .map(|cells| {
+                    cells
+                        .iter()
+                        .map(|c| match c {
+                            Value::String(s) => s.clone(),
+                            c => c.to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        });
+        route = route.with_messages(SegmentMessage::parse_rows(rows)?);
+    }
+    // brouter writes the totals as strings.
+    let number = |key: &str| {
+        let value = properties.and_then(|p| p.get(key))?;
+        value
+            .as_f64()
+            .or_else(|| value.as_str()?.trim().parse().ok())
+    };
+    Ok(route.with_totals(RouteTotals {
+        track_length_m: number("track-length"),
+        filtered_ascent_m: number("filtered ascend"),
+        plain_ascent_m: number("plain-ascend"),
+        total_time_s: number("total-time"),
+        total_energy_j: number("total-energy"),
+        cost: number("cost"),
+    }))
+}
+
+/// A response from brouter as it was received, see [`crate::Brouter::send_raw`].
+#[derive(Debug, Clone)]
+pub struct RawResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+    pub info: ResponseInfo,
+}
+
+impl RawResponse {
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+    }
+
+    /// Decide whether the response holds a result or an error, as the client does for its
+    /// own requests; see [`classify`].
+    pub fn classify(&self, handling: ResponseHandling) -> Result<(), Error> {
+        classify(self.status, self.content_type(), &self.body, handling)
+    }
+}
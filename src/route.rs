@@ -0,0 +1,1234 @@
+use geo::line_measures::FrechetDistance;
+use geo::{
+    Area, Buffer, Closest, ClosestPoint, Contains, ConvexHull, CoordsIter, Distance, Haversine,
+    InterpolatePoint, MapCoords,
+};
+use geo_types::{Coord, LineString, MultiLineString, MultiPoint, Polygon, Rect};
+use rstar::primitives::{GeomWithData, Line};
+use rstar::RTree;
+use std::sync::OnceLock;
+
+use crate::directions::{English, Locale};
+use crate::instructions::{parse_voice_hints, TurnCommand, TurnInstruction, VoiceHint};
+use crate::response::ResponseInfo;
+use crate::{Error, Point, SegmentMessage};
+
+/// A route as returned by brouter.
+///
+/// With the `serde` feature, routes are serialized as their GPX document and messages. Turn
+/// instructions survive the round trip only if brouter stored them as waypoints rather than in
+/// GPX extensions.
+#[derive(Debug, Clone)]
+pub struct Route {
+    gpx: gpx::Gpx,
+    voice_hints: Vec<VoiceHint>,
+    messages: Vec<SegmentMessage>,
+    index: OnceLock<SegmentIndex>,
+    totals: Option<RouteTotals>,
+    pub(crate) response_info: Option<ResponseInfo>,
+}
+
+/// Serialized form of a [`Route`].
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct RouteDef {
+    gpx: String,
+
+    #[serde(default)]
+    messages: Vec<SegmentMessage>,
+
+    #[serde(default)]
+    totals: Option<RouteTotals>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Route {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error;
+
+        let mut buf = Vec::new();
+        gpx::write(&self.gpx, &mut buf).map_err(S::Error::custom)?;
+        RouteDef {
+            gpx: String::from_utf8(buf).map_err(S::Error::custom)?,
+            messages: self.messages.clone(),
+            totals: self.totals.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Route {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let def = RouteDef::deserialize(deserializer)?;
+        let mut route = Route::from_gpx_bytes(def.gpx.as_bytes())
+            .map_err(D::Error::custom)?
+            .with_messages(def.messages);
+        route.totals = def.totals.or(route.totals);
+        Ok(route)
+    }
+}
+
+/// Summary statistics of a route.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RouteSummary {
+    pub distance_m: f64,
+
+    pub ascent_m: f64,
+
+    pub descent_m: f64,
+
+    /// Number of track points.
+    pub points: usize,
+
+    /// Time between the first and last track point, if the route has timestamps.
+    pub duration_s: Option<f64>,
+}
+
+/// A climb along a route, from a low point to the highest point before the route descends
+/// again; see [`Route::climbs`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Climb {
+    /// Distance along the route at the foot of the climb, in meters.
+    pub start_m: f64,
+
+    /// Distance along the route at the top of the climb, in meters.
+    pub end_m: f64,
+
+    /// Elevation at the foot of the climb, in meters.
+    pub start_elevation_m: f64,
+
+    /// Elevation at the top of the climb, in meters.
+    pub end_elevation_m: f64,
+}
+
+impl Climb {
+    pub fn length_m(&self) -> f64 {
+        self.end_m - self.start_m
+    }
+
+    /// Elevation gained between the foot and the top, in meters.
+    pub fn ascent_m(&self) -> f64 {
+        self.end_elevation_m - self.start_elevation_m
+    }
+
+    /// Average gradient, in percent.
+    pub fn gradient(&self) -> f64 {
+        if self.length_m() > 0.0 {
+            self.ascent_m() / self.length_m() * 100.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// The totals brouter reports for a route, as opposed to those calculated from its points
+/// (see [`RouteSummary`]).
+///
+/// GeoJSON responses have all of them; GPX responses have them in a comment, which lacks the
+/// energy in any useful precision.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RouteTotals {
+    pub track_length_m: Option<f64>,
+
+    /// Ascent after filtering out noise in the elevation data, in meters.
+    pub filtered_ascent_m: Option<f64>,
+
+    /// Difference in elevation between start and finish, in meters.
+    pub plain_ascent_m: Option<f64>,
+
+    /// Estimated travel time, in seconds.
+    pub total_time_s: Option<f64>,
+
+    /// Estimated energy spent by the rider, in joules.
+    pub total_energy_j: Option<f64>,
+
+    /// The cost of the route according to the profile; for CSV responses, the sum of the
+    /// costs of the segments.
+    pub cost: Option<f64>,
+}
+
+impl RouteTotals {
+    /// Parse the comment brouter puts at the top of GPX documents, e.g.
+    /// `track-length = 594 filtered ascend = 2 plain-ascend = 1 cost=821 energy=.0kwh
+    /// time=2m 44s`.
+    fn from_gpx_comment(data: &[u8]) -> Option<RouteTotals> {
+        let head = String::from_utf8_lossy(&data[..data.len().min(crate::ERROR_SNIFF_LIMIT)]);
+        let (_, comment) =
+            lazy_regex::regex_captures!(r"<!--\s*(track-length[^>]*?)\s*-->", &head)?;
+        let number = |value: Option<&str>| value.and_then(|v| v.parse::<f64>().ok());
+        let field = |name: &str| {
+            comment
+                .split_once(name)
+                .map(|(_, rest)| rest.trim_start().trim_start_matches('=').trim_start())
+                .and_then(|rest| rest.split(' ').next())
+        };
+        let total_time_s = comment.split_once("time=").map(|(_, time)| {
+            time.split(' ')
+                .filter_map(|part| {
+                    let unit = match part.chars().last()? {
+                        'h' => 3600.0,
+                        'm' => 60.0,
+                        's' => 1.0,
+                        _ => return None,
+                    };
+                    Some(part[..part.len() - 1].parse::<f64>().ok()? * unit)
+                })
+                .sum()
+        });
+        Some(RouteTotals {
+            track_length_m: number(field("track-length")),
+            filtered_ascent_m: number(field("filtered ascend")),
+            plain_ascent_m: number(field("plain-ascend")),
+            total_time_s,
+            total_energy_j: number(field("energy").and_then(|e| e.strip_suffix("kwh")))
+                .map(|kwh| kwh * 3.6e6),
+            cost: number(field("cost")),
+        })
+    }
+}
+
+/// The cost brouter accumulated along a single segment of a route.
+#[derive(Debug, Clone)]
+pub struct SegmentCost<'a> {
+    pub message: &'a SegmentMessage,
+
+    /// Distance along the route at the end of the segment, in meters.
+    pub distance_m: f64,
+
+    pub cost: f64,
+}
+
+impl From<gpx::Gpx> for Route {
+    fn from(gpx: gpx::Gpx) -> Self {
+        Route {
+            gpx,
+            voice_hints: vec![],
+            messages: vec![],
+            index: OnceLock::new(),
+            totals: None,
+            response_info: None,
+        }
+    }
+}
+
+impl From<Route> for gpx::Gpx {
+    fn from(route: Route) -> Self {
+        route.gpx
+    }
+}
+
+/// See [`Route::multilinestring`].
+impl From<Route> for MultiLineString<f64> {
+    fn from(route: Route) -> Self {
+        route.multilinestring()
+    }
+}
+
+/// See [`Route::multilinestring`].
+impl From<&Route> for MultiLineString<f64> {
+    fn from(route: &Route) -> Self {
+        route.multilinestring()
+    }
+}
+
+// The geo trait implementations below make the algorithms of the geo crate available on routes
+// directly. They treat the track as a multi line string in longitude/latitude degrees, so
+// distances are in degrees rather than meters.
+
+impl CoordsIter for Route {
+    type Iter<'a> = Box<dyn Iterator<Item = Coord<f64>> + 'a>;
+    type ExteriorIter<'a> = Self::Iter<'a>;
+    type Scalar = f64;
+
+    fn coords_iter(&self) -> Self::Iter<'_> {
+        Box::new(self.points().map(|p| p.point().0))
+    }
+
+    fn coords_count(&self) -> usize {
+        self.points().count()
+    }
+
+    fn exterior_coords_iter(&self) -> Self::ExteriorIter<'_> {
+        self.coords_iter()
+    }
+}
+
+impl ClosestPoint<f64> for Route {
+    fn closest_point(&self, p: &geo_types::Point<f64>) -> Closest<f64> {
+        self.multilinestring().closest_point(p)
+    }
+}
+
+impl Contains<geo_types::Point<f64>> for Route {
+    fn contains(&self, p: &geo_types::Point<f64>) -> bool {
+        self.multilinestring().contains(p)
+    }
+}
+
+impl Contains<Coord<f64>> for Route {
+    fn contains(&self, c: &Coord<f64>) -> bool {
+        self.multilinestring().contains(c)
+    }
+}
+
+impl From<LineString<f64>> for Route {
+    fn from(line: LineString<f64>) -> Self {
+        MultiLineString::new(vec![line]).into()
+    }
+}
+
+impl From<MultiLineString<f64>> for Route {
+    fn from(lines: MultiLineString<f64>) -> Self {
+        let mut track = gpx::Track::new();
+        track.segments = lines
+            .into_iter()
+            .map(|line| gpx::TrackSegment {
+                points: line.points().map(gpx::Waypoint::new).collect(),
+            })
+            .collect();
+
+        gpx::Gpx {
+            version: gpx::GpxVersion::Gpx11,
+            tracks: vec![track],
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+impl Route {
+    /// Parse a GPX document as returned by brouter.
+    ///
+    /// Unlike converting from a [`gpx::Gpx`], this also picks up turn instructions that brouter
+    /// stores in GPX extensions.
+    ///
+    /// Documents that fail to parse are retried without their extensions and with a version
+    /// attribute added if it is missing, since older brouter versions produced GPX that is
+    /// slightly off.
+    pub fn from_gpx_bytes(data: &[u8]) -> Result<Route, Error> {
+        Self::from_gpx_response(data, None)
+    }
+
+    pub(crate) fn from_gpx_response(
+        data: &[u8],
+        content_type: Option<&str>,
+    ) -> Result<Route, Error> {
+        let invalid = |message: String| Error::InvalidGpx {
+            message,
+            content_type: content_type.map(|c| c.to_string()),
+            head: String::from_utf8_lossy(&data[..data.len().min(GPX_ERROR_HEAD)]).into_owned(),
+        };
+        let gpx = match gpx::read(data) {
+            Ok(gpx) => gpx,
+            Err(e) => match repair_gpx(data).and_then(|d| gpx::read(d.as_bytes()).ok()) {
+                Some(gpx) => gpx,
+                None => return Err(invalid(e.to_string())),
+            },
+        };
+        let voice_hints = parse_voice_hints(data).map_err(|e| invalid(e.to_string()))?;
+        let mut route = Route::from(gpx);
+        route.voice_hints = voice_hints;
+        route.totals = RouteTotals::from_gpx_comment(data);
+        Ok(route)
+    }
+
+    /// How the server responded to the request for the route, for routes returned by
+    /// [`crate::Brouter::request_route`].
+    ///
+    /// This is not kept when serializing routes.
+    pub fn response_info(&self) -> Option<&ResponseInfo> {
+        self.response_info.as_ref()
+    }
+
+    pub fn gpx(&self) -> &gpx::Gpx {
+        &self.gpx
+    }
+
+    pub fn into_gpx(self) -> gpx::Gpx {
+        self.gpx
+    }
+
+    /// The version of brouter that calculated the route, e.g. "1.7.3", taken from the
+    /// `creator` attribute of the GPX.
+    pub fn brouter_version(&self) -> Option<&str> {
+        let creator = self.gpx.creator.as_deref()?;
+        lazy_regex::regex_captures!(r"(?i)^brouter[- ]?v?(\d[0-9A-Za-z.]*)", creator)
+            .map(|(_, v)| v)
+    }
+
+    /// The totals brouter reported for the route, if it was parsed from a response that has
+    /// them.
+    pub fn totals(&self) -> Option<&RouteTotals> {
+        self.totals.as_ref()
+    }
+
+    /// Length of the route in meters, as reported by brouter or else calculated from its
+    /// points.
+    pub fn total_distance_m(&self) -> f64 {
+        self.totals
+            .as_ref()
+            .and_then(|s| s.track_length_m)
+            .unwrap_or_else(|| self.summary().distance_m)
+    }
+
+    /// Ascent along the route in meters, as reported by brouter (which filters out noise in
+    /// the elevation data) or else calculated from its points.
+    pub fn ascend_m(&self) -> f64 {
+        self.totals
+            .as_ref()
+            .and_then(|s| s.filtered_ascent_m)
+            .unwrap_or_else(|| self.summary().ascent_m)
+    }
+
+    /// The travel time brouter estimated, or else the time between the first and last
+    /// timestamp of the track.
+    pub fn estimated_time(&self) -> Option<std::time::Duration> {
+        self.totals
+            .as_ref()
+            .and_then(|s| s.total_time_s)
+            .or_else(|| self.summary().duration_s)
+            .and_then(|s| std::time::Duration::try_from_secs_f64(s).ok())
+    }
+
+    /// The energy brouter estimated the rider spends, in joules.
+    ///
+    /// GPX responses only have this in kWh rounded to one decimal; request GeoJSON (see
+    /// [`crate::RouteRequest::format`]) for a precise value.
+    pub fn energy_j(&self) -> Option<f64> {
+        self.totals.as_ref().and_then(|s| s.total_energy_j)
+    }
+
+    pub(crate) fn with_totals(mut self, totals: RouteTotals) -> Self {
+        self.totals = Some(totals);
+        self
+    }
+
+    pub fn summary(&self) -> RouteSummary {
+        let mut summary = RouteSummary {
+            distance_m: 0.0,
+            ascent_m: 0.0,
+            descent_m: 0.0,
+            points: 0,
+            duration_s: None,
+        };
+
+        let mut prev: Option<&gpx::Waypoint> = None;
+        for p in self.points() {
+            summary.points += 1;
+            if let Some(prev) = prev {
+                summary.distance_m += Haversine.distance(prev.point(), p.point());
+                if let (Some(a), Some(b)) = (prev.elevation, p.elevation) {
+                    if b > a {
+                        summary.ascent_m += b - a;
+                    } else {
+                        summary.descent_m += a - b;
+                    }
+                }
+            }
+            prev = Some(p);
+        }
+
+        let mut times = self.points().filter_map(|p| p.time);
+        if let (Some(first), Some(last)) = (times.next(), times.last()) {
+            summary.duration_s = Some(
+                (time::OffsetDateTime::from(last) - time::OffsetDateTime::from(first))
+                    .as_seconds_f64(),
+            );
+        }
+
+        summary
+    }
+
+    /// The climbs along the route that gain at least `min_ascent_m` meters.
+    ///
+    /// A climb runs from a low point to the highest point after it, and ends once the route
+    /// descends `min_ascent_m` meters or more below that highest point, so shorter dips don't
+    /// split a climb. Track points without an elevation are skipped.
+    pub fn climbs(&self, min_ascent_m: f64) -> Vec<Climb> {
+        let climb = |foot: (f64, f64), high: (f64, f64)| {
+            (high.1 > foot.1 && high.1 - foot.1 >= min_ascent_m).then_some(Climb {
+                start_m: foot.0,
+                end_m: high.0,
+                start_elevation_m: foot.1,
+                end_elevation_m: high.1,
+            })
+        };
+        let mut climbs = vec![];
+        let mut along = 0.0;
+        let mut prev: Option<geo_types::Point<f64>> = None;
+        // Distance and elevation of the lowest point since the last climb, and of the highest
+        // point after it.
+        let mut low: Option<(f64, f64)> = None;
+        let mut high = (0.0, 0.0);
+        for p in self.points() {
+            if let Some(prev) = prev {
+                along += Haversine.distance(prev, p.point());
+            }
+            prev = Some(p.point());
+            let Some(elevation) = p.elevation else {
+                continue;
+            };
+            let Some(foot) = low else {
+                low = Some((along, elevation));
+                high = (along, elevation);
+                continue;
+            };
+            if elevation > high.1 {
+                high = (along, elevation);
+            } else if elevation < foot.1 || high.1 - elevation >= min_ascent_m {
+                climbs.extend(climb(foot, high));
+                low = Some((along, elevation));
+                high = (along, elevation);
+            }
+        }
+        if let Some(foot) = low {
+            climbs.extend(climb(foot, high));
+        }
+        climbs
+    }
+
+    /// Attach the per-segment messages brouter returned for this route.
+    ///
+    /// See [`crate::Brouter::broute_messages`].
+    pub fn with_messages(mut self, messages: Vec<SegmentMessage>) -> Self {
+        self.messages = messages;
+        self
+    }
+
+    pub fn messages(&self) -> &[SegmentMessage] {
+        &self.messages
+    }
+
+    /// The cost of each segment of the route, in order.
+    ///
+    /// This is empty unless messages have been attached with [`Route::with_messages`].
+    pub fn segment_costs(&self) -> Vec<SegmentCost<'_>> {
+        let mut distance_m = 0.0;
+        self.messages
+            .iter()
+            .map(|message| {
+                distance_m += message.distance_m;
+                SegmentCost {
+                    message,
+                    distance_m,
+                    cost: message.cost(),
+                }
+            })
+            .collect()
+    }
+
+    /// All track points of the route as a single line string, joining its track segments.
+    pub fn linestring(&self) -> LineString<f64> {
+        self.points().map(|p| p.point()).collect()
+    }
+
+    /// The track segments of the route as a multi line string.
+    ///
+    /// Unlike [`Route::linestring`], this keeps segment boundaries; see also
+    /// `MultiLineString::from(route)`.
+    pub fn multilinestring(&self) -> MultiLineString<f64> {
+        self.gpx
+            .tracks
+            .iter()
+            .flat_map(|t| t.segments.iter())
+            .map(|s| s.linestring())
+            .collect()
+    }
+
+    /// The discrete Fréchet distance between the route and `line`, in meters.
+    ///
+    /// This is a measure of how similar two paths are, taking the order of their points into
+    /// account; use [`Route::linestring`] to compare two routes.
+    pub fn frechet_distance(&self, line: &LineString<f64>) -> f64 {
+        Haversine.frechet_distance(&self.linestring(), line)
+    }
+
+    /// Iterate over all track points of the route, in order.
+    pub fn points(&self) -> impl Iterator<Item = &gpx::Waypoint> {
+        self.gpx
+            .tracks
+            .iter()
+            .flat_map(|t| t.segments.iter())
+            .flat_map(|s| s.points.iter())
+    }
+
+    /// The bounding box of the route, or `None` if the route has no points.
+    pub fn bbox(&self) -> Option<Rect<f64>> {
+        let mut coords = self.points().map(|p| Coord::from(p.point()));
+        let first = coords.next()?;
+        let (min, max) = coords.fold((first, first), |(min, max), c| {
+            (
+                Coord {
+                    x: min.x.min(c.x),
+                    y: min.y.min(c.y),
+                },
+                Coord {
+                    x: max.x.max(c.x),
+                    y: max.y.max(c.y),
+                },
+            )
+        });
+        Some(Rect::new(min, max))
+    }
+
+    /// The convex hull of the route, or `None` if the route has no points.
+    pub fn convex_hull(&self) -> Option<Polygon<f64>> {
+        let points: MultiPoint<f64> = self.points().map(|p| p.point()).collect();
+        if points.0.is_empty() {
+            return None;
+        }
+        Some(points.convex_hull())
+    }
+
+    /// A corridor around the route, containing all points within `width_m` meters of it.
+    ///
+    /// The corridor is calculated in an equirectangular projection around the mean latitude of
+    /// the route, which is accurate enough for routes that span a few hundred kilometers. If
+    /// the route consists of disconnected parts, only the largest corridor is returned.
+    ///
+    /// Returns `None` if the route has no points. See [`crate::Nogo::outside`] for keeping
+    /// brouter within the corridor.
+    pub fn buffer(&self, width_m: f64) -> Option<Polygon<f64>> {
+        let (count, lat_sum) = self
+            .points()
+            .fold((0, 0.0), |(n, sum), p| (n + 1, sum + p.point().y()));
+        if count == 0 {
+            return None;
+        }
+        let lon_scale = (lat_sum / count as f64).to_radians().cos();
+        let meters_per_degree = Haversine.radius().to_radians();
+
+        self.multilinestring()
+            .map_coords(|c| Coord {
+                x: c.x * lon_scale,
+                y: c.y,
+            })
+            .buffer(width_m / meters_per_degree)
+            .into_iter()
+            .max_by(|a, b| a.unsigned_area().total_cmp(&b.unsigned_area()))
+            .map(|polygon| {
+                polygon.map_coords(|c| Coord {
+                    x: c.x / lon_scale,
+                    y: c.y,
+                })
+            })
+    }
+
+    /// The point `distance_m` meters along the route.
+    ///
+    /// Returns `None` if the route has no points; distances beyond the end of the route give
+    /// the last point.
+    pub fn point_at(&self, distance_m: f64) -> Option<Point> {
+        let mut points = self.points().map(|p| p.point());
+        let mut prev = points.next()?;
+        let mut remaining = distance_m.max(0.0);
+        for p in points {
+            let length = Haversine.distance(prev, p);
+            if remaining <= length && length > 0.0 {
+                return Some(
+                    Haversine
+                        .point_at_ratio_between(prev, p, remaining / length)
+                        .into(),
+                );
+            }
+            remaining -= length;
+            prev = p;
+        }
+        Some(prev.into())
+    }
+
+    /// Resample the route at fixed intervals.
+    ///
+    /// Returns points spaced `interval_m` meters apart along the route, starting at the first
+    /// point and ending at the last one. Elevations are interpolated linearly between the
+    /// surrounding track points.
+    ///
+    /// Returns no points if `interval_m` is not a positive, finite number.
+    pub fn resample(&self, interval_m: f64) -> Vec<gpx::Waypoint> {
+        if !(interval_m.is_finite() && interval_m > 0.0) {
+            return vec![];
+        }
+
+        let mut points = self.points();
+        let mut prev = match points.next() {
+            Some(p) => p,
+            None => return vec![],
+        };
+
+        let mut ret = vec![prev.clone()];
+        // Distance along the route at which the next sample is due, relative to `prev`.
+        let mut next = interval_m;
+
+        for p in points {
+            let length = Haversine.distance(prev.point(), p.point());
+            while next <= length {
+                let ratio = next / length;
+                let mut wp = gpx::Waypoint::new(Haversine.point_at_ratio_between(
+                    prev.point(),
+                    p.point(),
+                    ratio,
+                ));
+                wp.elevation = match (prev.elevation, p.elevation) {
+                    (Some(a), Some(b)) => Some(a + (b - a) * ratio),
+                    (a, b) => a.or(b),
+                };
+                ret.push(wp);
+                next += interval_m;
+            }
+            next -= length;
+            prev = p;
+        }
+
+        if next < interval_m {
+            ret.push(prev.clone());
+        }
+
+        ret
+    }
+
+    /// Distance along the route and time since the start, in seconds, at points where the
+    /// time is known.
+    ///
+    /// Times come from the messages of kinematic profiles if available, and otherwise from the
+    /// timestamps of the track points.
+    pub(crate) fn time_profile(&self) -> Vec<(f64, f64)> {
+        if self.messages.iter().any(|m| m.time_s.is_some()) {
+            let mut profile = vec![(0.0, 0.0)];
+            for cost in self.segment_costs() {
+                if let Some(time_s) = cost.message.time_s {
+                    profile.push((cost.distance_m, time_s));
+                }
+            }
+            return profile;
+        }
+
+        let mut profile = vec![];
+        let mut along = 0.0;
+        let mut start = None;
+        let mut prev: Option<&gpx::Waypoint> = None;
+        for p in self.points() {
+            if let Some(prev) = prev {
+                along += Haversine.distance(prev.point(), p.point());
+            }
+            prev = Some(p);
+            if let Some(time) = p.time {
+                let time = time::OffsetDateTime::from(time);
+                let start = *start.get_or_insert(time);
+                profile.push((along, (time - start).as_seconds_f64()));
+            }
+        }
+        profile
+    }
+
+    /// The time it takes to travel `distance_m` meters along the route, interpolated between
+    /// the points where the time is known.
+    ///
+    /// Returns `None` if the route has no times, e.g. because it wasn't calculated with a
+    /// kinematic profile, or if `distance_m` is beyond the last known time.
+    pub fn time_at(&self, distance_m: f64) -> Option<std::time::Duration> {
+        interpolate(&self.time_profile(), distance_m, |(d, t)| (d, t))
+            .map(std::time::Duration::from_secs_f64)
+    }
+
+    /// The distance along the route reached after travelling for `duration`, in meters.
+    ///
+    /// See [`Route::time_at`].
+    pub fn distance_at(&self, duration: std::time::Duration) -> Option<f64> {
+        interpolate(&self.time_profile(), duration.as_secs_f64(), |(d, t)| {
+            (t, d)
+        })
+    }
+
+    /// Set the time of each track point to `start` plus the time it takes to get there.
+    ///
+    /// See [`Route::time_at`]; points beyond the last known time keep their time. Returns the
+    /// number of points that were updated.
+    pub fn set_timestamps(&mut self, start: time::OffsetDateTime) -> usize {
+        let profile = self.time_profile();
+        let mut updated = 0;
+        let mut along = 0.0;
+        let mut prev: Option<geo_types::Point<f64>> = None;
+        for p in self
+            .gpx
+            .tracks
+            .iter_mut()
+            .flat_map(|t| t.segments.iter_mut())
+            .flat_map(|s| s.points.iter_mut())
+        {
+            if let Some(prev) = prev {
+                along += Haversine.distance(prev, p.point());
+            }
+            prev = Some(p.point());
+            if let Some(t) = interpolate(&profile, along, |(d, t)| (d, t)) {
+                p.time = Some((start + std::time::Duration::from_secs_f64(t)).into());
+                updated += 1;
+            }
+        }
+        updated
+    }
+
+    /// Drop track points that deviate less than `tolerance_m` meters from the line through
+    /// their neighbours, using the Ramer–Douglas–Peucker algorithm.
+    ///
+    /// Points with turn instructions are always kept. Returns the number of points dropped.
+    pub fn simplify(&mut self, tolerance_m: f64) -> usize {
+        use geo::SimplifyIdx;
+
+        let Some(rect) = self.bbox() else {
+            return 0;
+        };
+        // An equirectangular projection in meters is accurate enough at the scale of a
+        // tolerance.
+        let scale_y = EARTH_RADIUS_M.to_radians();
+        let scale_x = scale_y * rect.center().y.to_radians().cos();
+        let hints = self
+            .voice_hints
+            .iter()
+            .map(|h| h.point.clone())
+            .collect::<Vec<_>>();
+        let mut dropped = 0;
+        for segment in self
+            .gpx
+            .tracks
+            .iter_mut()
+            .flat_map(|t| t.segments.iter_mut())
+        {
+            let line = segment
+                .points
+                .iter()
+                .map(|p| {
+                    let p = p.point();
+                    Coord {
+                        x: p.x() * scale_x,
+                        y: p.y() * scale_y,
+                    }
+                })
+                .collect::<LineString<f64>>();
+            let mut keep = vec![false; segment.points.len()];
+            for i in line.simplify_idx(tolerance_m) {
+                keep[i] = true;
+            }
+            let before = segment.points.len();
+            let mut i = 0;
+            segment.points.retain(|p| {
+                let kept = keep[i] || hints.contains(&Point::from(p.point()));
+                i += 1;
+                kept
+            });
+            dropped += before - segment.points.len();
+        }
+        if dropped > 0 {
+            self.index = OnceLock::new();
+        }
+        dropped
+    }
+
+    /// Find the point on the route closest to `p`.
+    ///
+    /// Returns the snapped point, its distance from `p` in meters and the distance along the
+    /// route in meters, or `None` if the route has no points. A spatial index is built on first
+    /// use, so repeated queries are cheap.
+    pub fn nearest_point(&self, p: &Point) -> Option<(Point, f64, f64)> {
+        self.index
+            .get_or_init(|| SegmentIndex::new(self.points().map(|p| p.point())))
+            .nearest(p.clone().into())
+    }
+
+    /// Add points of interest that lie within `max_distance_m` meters of the route as waypoints.
+    ///
+    /// Afterwards all waypoints are ordered by their distance along the route. Returns the
+    /// number of points of interest that were added.
+    pub fn attach_waypoints(
+        &mut self,
+        pois: impl IntoIterator<Item = gpx::Waypoint>,
+        max_distance_m: f64,
+    ) -> usize {
+        let mut count = 0;
+        for poi in pois {
+            if let Some((_, distance, _)) = self.nearest_point(&poi.point().into()) {
+                if distance <= max_distance_m {
+                    self.gpx.waypoints.push(poi);
+                    count += 1;
+                }
+            }
+        }
+
+        let mut waypoints = std::mem::take(&mut self.gpx.waypoints)
+            .into_iter()
+            .map(|w| {
+                let along = self
+                    .nearest_point(&w.point().into())
+                    .map_or(0.0, |(_, _, along)| along);
+                (along, w)
+            })
+            .collect::<Vec<_>>();
+        waypoints.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.gpx.waypoints = waypoints.into_iter().map(|(_, w)| w).collect();
+
+        count
+    }
+
+    /// Name the exported waypoints closest to the given points.
+    ///
+    /// Turn instructions stored as waypoints are left alone, and each waypoint is named at
+    /// most once.
+    pub fn name_waypoints(&mut self, named: &[(Point, String)]) {
+        let mut candidates = self
+            .gpx
+            .waypoints
+            .iter()
+            .enumerate()
+            .filter(|(_, w)| {
+                w.name
+                    .as_deref()
+                    .and_then(TurnCommand::from_message)
+                    .is_none()
+            })
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+
+        for (point, name) in named {
+            let point = geo_types::Point::from(point.clone());
+            let nearest = candidates
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| {
+                    let a = Haversine.distance(point, self.gpx.waypoints[**a].point());
+                    let b = Haversine.distance(point, self.gpx.waypoints[**b].point());
+                    a.total_cmp(&b)
+                })
+                .map(|(j, _)| j);
+            if let Some(j) = nearest {
+                let i = candidates.remove(j);
+                self.gpx.waypoints[i].name = Some(name.clone());
+            }
+        }
+    }
+
+    /// The turn instructions brouter attached to the route.
+    ///
+    /// These are read from the voice hints that brouter emits when a turn instruction mode is
+    /// requested, whatever the style, ordered by distance along the route. Hints stored in GPX
+    /// extensions are only available for routes created with [`Route::from_gpx_bytes`].
+    pub fn turn_instructions(&self) -> Vec<TurnInstruction> {
+        let hints: Vec<VoiceHint> = if !self.voice_hints.is_empty() {
+            self.voice_hints.clone()
+        } else {
+            self.gpx
+                .waypoints
+                .iter()
+                .filter_map(|w| Some((w, TurnCommand::from_message(w.name.as_deref()?)?)))
+                .chain(
+                    self.gpx
+                        .routes
+                        .iter()
+                        .flat_map(|r| r.points.iter())
+                        .filter_map(|w| {
+                            Some((w, TurnCommand::from_message(w.description.as_deref()?)?))
+                        }),
+                )
+                .map(|(w, command)| VoiceHint {
+                    point: w.point().into(),
+                    command,
+                    angle: None,
+                })
+                .collect()
+        };
+
+        let mut instructions = hints
+            .into_iter()
+            .map(|hint| {
+                let distance_m = self
+                    .nearest_point(&hint.point)
+                    .map_or(0.0, |(_, _, along)| along);
+                TurnInstruction {
+                    point: hint.point,
+                    command: hint.command,
+                    distance_m,
+                    way_name: None,
+                    angle: hint.angle,
+                }
+            })
+            .collect::<Vec<_>>();
+        instructions.sort_by(|a, b| a.distance_m.total_cmp(&b.distance_m));
+        instructions
+    }
+
+    /// Render the turn instructions as English sentences.
+    pub fn directions(&self) -> Vec<String> {
+        self.directions_with(&English)
+    }
+
+    /// Render the turn instructions as sentences, using the given locale.
+    pub fn directions_with(&self, locale: &dyn Locale) -> Vec<String> {
+        let mut previous = 0.0;
+        self.turn_instructions()
+            .iter()
+            .map(|instruction| {
+                let sentence = locale.render(instruction.distance_m - previous, instruction);
+                previous = instruction.distance_m;
+                sentence
+            })
+            .collect()
+    }
+
+    /// Fill in missing track point elevations from a DEM.
+    ///
+    /// Returns the number of points that were updated.
+    #[cfg(feature = "elevation")]
+    pub fn enrich_elevation(
+        &mut self,
+        dem: &crate::elevation::DemCache,
+    ) -> Result<usize, crate::elevation::Error> {
+        self.set_elevations(dem, false)
+    }
+
+    /// Replace all track point elevations with those from a DEM.
+    ///
+    /// Points for which the DEM has no data keep their original elevation. Returns the number
+    /// of points that were updated.
+    #[cfg(feature = "elevation")]
+    pub fn replace_elevation(
+        &mut self,
+        dem: &crate::elevation::DemCache,
+    ) -> Result<usize, crate::elevation::Error> {
+        self.set_elevations(dem, true)
+    }
+
+    #[cfg(feature = "elevation")]
+    fn set_elevations(
+        &mut self,
+        dem: &crate::elevation::DemCache,
+        replace: bool,
+    ) -> Result<usize, crate::elevation::Error> {
+        let mut updated = 0;
+        for p in self
+            .gpx
+            .tracks
+            .iter_mut()
+            .flat_map(|t| t.segments.iter_mut())
+            .flat_map(|s| s.points.iter_mut())
+        {
+            if p.elevation.is_some() && !replace {
+                continue;
+            }
+            let point = p.point();
+            if let Some(elevation) = dem.elevation(&Point::new(point.y(), point.x()))? {
+                p.elevation = Some(elevation);
+                updated += 1;
+            }
+        }
+        Ok(updated)
+    }
+}
+
+/// Linearly interpolate in a profile that increases monotonically in both values, looking up
+/// `x` in the first value of the pairs returned by `axes`.
+fn interpolate(
+    profile: &[(f64, f64)],
+    x: f64,
+    axes: impl Fn((f64, f64)) -> (f64, f64),
+) -> Option<f64> {
+    let (first_x, first_y) = axes(*profile.first()?);
+    if x < first_x {
+        return None;
+    }
+    if x == first_x {
+        return Some(first_y);
+    }
+    profile.windows(2).find_map(|w| {
+        let ((x0, y0), (x1, y1)) = (axes(w[0]), axes(w[1]));
+        if x > x1 || x1 <= x0 {
+            return (x == x1).then_some(y1);
+        }
+        Some(y0 + (y1 - y0) * (x - x0) / (x1 - x0))
+    })
+}
+
+/// A change of state reported by [`OffRouteDetector`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteEvent {
+    LeftRoute,
+    Rejoined,
+}
+
+/// The state of a position relative to a route, as reported by [`OffRouteDetector`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteProgress {
+    pub on_route: bool,
+
+    /// Set if the position changed whether the traveller is on the route.
+    pub event: Option<RouteEvent>,
+
+    /// Distance from the position to the route, in meters.
+    pub distance_from_route_m: f64,
+
+    /// Distance from the nearest point on the route to its end, in meters.
+    pub distance_remaining_m: f64,
+}
+
+/// Tracks whether a stream of positions follows a route, e.g. to decide when to reroute.
+///
+/// A traveller leaves the route when a position is more than the threshold away from it, and
+/// rejoins it only once a position is within the threshold minus the hysteresis, so that GPS
+/// noise around the threshold doesn't cause a flood of events.
+#[derive(Debug, Clone)]
+pub struct OffRouteDetector<'a> {
+    route: &'a Route,
+    length_m: f64,
+    threshold_m: f64,
+    hysteresis_m: f64,
+    on_route: bool,
+}
+
+impl<'a> OffRouteDetector<'a> {
+    /// A detector for `route` that considers positions more than `threshold_m` meters away to
+    /// be off the route.
+    pub fn new(route: &'a Route, threshold_m: f64) -> Self {
+        OffRouteDetector {
+            route,
+            length_m: route.summary().distance_m,
+            threshold_m,
+            hysteresis_m: threshold_m / 2.0,
+            on_route: true,
+        }
+    }
+
+    /// Set how much closer than the threshold a position has to be to rejoin the route; half
+    /// the threshold by default.
+    pub fn hysteresis(mut self, hysteresis_m: f64) -> Self {
+        self.hysteresis_m = hysteresis_m.clamp(0.0, self.threshold_m);
+        self
+    }
+
+    pub fn on_route(&self) -> bool {
+        self.on_route
+    }
+
+    /// Process the next position.
+    pub fn update(&mut self, position: &Point) -> RouteProgress {
+        let (distance_from_route_m, along) = self
+            .route
+            .nearest_point(position)
+            .map_or((f64::INFINITY, 0.0), |(_, distance, along)| {
+                (distance, along)
+            });
+
+        let event = if self.on_route && distance_from_route_m > self.threshold_m {
+            self.on_route = false;
+            Some(RouteEvent::LeftRoute)
+        } else if !self.on_route && distance_from_route_m <= self.threshold_m - self.hysteresis_m {
+            self.on_route = true;
+            Some(RouteEvent::Rejoined)
+        } else {
+            None
+        };
+
+        RouteProgress {
+            on_route: self.on_route,
+            event,
+            distance_from_route_m,
+            distance_remaining_m: (self.length_m - along).max(0.0),
+        }
+    }
+}
+
+/// How much of an invalid GPX document is included in errors, in bytes.
+const GPX_ERROR_HEAD: usize = 200;
+
+/// Mean radius of the earth, in meters.
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// Work around quirks in GPX from older brouter versions, returning `None` if there are none.
+fn repair_gpx(data: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(data).ok()?;
+    let mut repaired = lazy_regex::regex!(r"(?s)<extensions>.*?</extensions>")
+        .replace_all(text, "")
+        .into_owned();
+    if let Some(m) = lazy_regex::regex!(r"<gpx\b[^>]*>").find(&repaired) {
+        if !m.as_str().contains("version=") {
+            repaired.insert_str(m.start() + "<gpx".len(), r#" version="1.1""#);
+        }
+    }
+    (repaired != text).then_some(repaired)
+}
+
+/// Spatial index over the segments of a route.
+///
+/// Coordinates are stored in an equirectangular projection around the mean latitude of the
+/// route, so that euclidean nearest-neighbour queries approximate geodesic ones.
+#[derive(Debug, Clone)]
+struct SegmentIndex {
+    lon_scale: f64,
+    tree: RTree<GeomWithData<Line<[f64; 2]>, usize>>,
+    /// Track points, with the distance along the route at which they occur.
+    points: Vec<(geo_types::Point<f64>, f64)>,
+}
+
+impl SegmentIndex {
+    fn new(points: impl Iterator<Item = geo_types::Point<f64>>) -> Self {
+        let mut along = 0.0;
+        let mut prev: Option<geo_types::Point<f64>> = None;
+        let points = points
+            .map(|p| {
+                if let Some(prev) = prev {
+                    along += Haversine.distance(prev, p);
+                }
+                prev = Some(p);
+                (p, along)
+            })
+            .collect::<Vec<_>>();
+
+        let mean_lat = points.iter().map(|(p, _)| p.y()).sum::<f64>() / points.len().max(1) as f64;
+        let lon_scale = mean_lat.to_radians().cos();
+
+        let segments = points
+            .windows(2)
+            .enumerate()
+            .map(|(i, w)| {
+                GeomWithData::new(
+                    Line::new(project(w[0].0, lon_scale), project(w[1].0, lon_scale)),
+                    i,
+                )
+            })
+            .collect();
+
+        SegmentIndex {
+            lon_scale,
+            tree: RTree::bulk_load(segments),
+            points,
+        }
+    }
+
+    fn nearest(&self, p: geo_types::Point<f64>) -> Option<(Point, f64, f64)> {
+        let (snapped, start) = match self.tree.nearest_neighbor(&project(p, self.lon_scale)) {
+            Some(segment) => {
+                let [x, y] = segment.geom().nearest_point(&project(p, self.lon_scale));
+                (
+                    geo_types::Point::new(x / self.lon_scale, y),
+                    self.points[segment.data],
+                )
+            }
+            None => {
+                let first = *self.points.first()?;
+                (first.0, first)
+            }
+        };
+
+        Some((
+            snapped.into(),
+            Haversine.distance(p, snapped),
+            start.1 + Haversine.distance(start.0, snapped),
+        ))
+    }
+}
+
+fn project(p: geo_types::Point<f64>, lon_scale: f64) -> [f64; 2] {
+    [p.x() * lon_scale, p.y()]
+}
@@ -0,0 +1,533 @@
+//! The segment files brouter routes on.
+//!
+//! brouter's routing data is split into tiles of 5 by 5 degrees, stored in files named after
+//! the south-west corner of the tile, e.g. `E5_N50.rd5`.
+
+use crate::server::ProvisionEvent;
+use crate::transfer::{Throttle, Transfer};
+use crate::{Error, Point, RouteRequest};
+use log::info;
+use reqwest::blocking::Client;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Where brouter.de publishes the segment files.
+pub const DEFAULT_SOURCE: &str = "https://brouter.de/brouter/segments4";
+
+/// Size of a tile, in degrees.
+const TILE_SIZE: f64 = 5.0;
+
+/// How far a route is assumed to stray outside the bounding box of its points, in degrees.
+const MARGIN: f64 = 0.05;
+
+/// Name of the segment file that contains `point`.
+pub fn tile_name(point: &Point) -> String {
+    tile_name_at(
+        (point.lon() / TILE_SIZE).floor() as i32,
+        (point.lat() / TILE_SIZE).floor() as i32,
+    )
+}
+
+fn tile_name_at(x: i32, y: i32) -> String {
+    let (lon, lat) = (x * TILE_SIZE as i32, y * TILE_SIZE as i32);
+    format!(
+        "{}{}_{}{}.rd5",
+        if lon < 0 { 'W' } else { 'E' },
+        lon.abs(),
+        if lat < 0 { 'S' } else { 'N' },
+        lat.abs()
+    )
+}
+
+/// Names of the segment files a route through `points` may need.
+///
+/// These are the tiles that overlap the bounding box of the points, grown slightly since
+/// routes don't stay within it exactly.
+pub fn tiles_for(points: &[Point]) -> Vec<String> {
+    if points.is_empty() {
+        return vec![];
+    }
+    let bound = |f: fn(&Point) -> f64, min: bool| {
+        let values = points.iter().map(f);
+        if min {
+            values.fold(f64::INFINITY, f64::min) - MARGIN
+        } else {
+            values.fold(f64::NEG_INFINITY, f64::max) + MARGIN
+        }
+    };
+    let tile = |v: f64| (v / TILE_SIZE).floor() as i32;
+    let (min_x, max_x) = (
+        tile(bound(Point::lon, true)),
+        tile(bound(Point::lon, false)),
+    );
+    let (min_y, max_y) = (
+        tile(bound(Point::lat, true)),
+        tile(bound(Point::lat, false)),
+    );
+
+    (min_y.max(-18)..=max_y.min(17))
+        .flat_map(|y| (min_x.max(-36)..=max_x.min(35)).map(move |x| tile_name_at(x, y)))
+        .collect()
+}
+
+/// The segment files a request needs, and which of those are missing locally.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    pub required: Vec<String>,
+    pub missing: Vec<String>,
+}
+
+impl Coverage {
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+/// Check which of the segment files needed for `request` are missing from `segments_dir`,
+/// the segment directory of a local brouter server.
+///
+/// This makes it possible to offer downloads before routing, rather than failing with
+/// [`crate::Error::MissingDataFile`].
+pub fn check_coverage(request: &RouteRequest, segments_dir: &Path) -> Coverage {
+    let required = request.segment_tiles();
+    let missing = required
+        .iter()
+        .filter(|name| !segments_dir.join(name).is_file())
+        .cloned()
+        .collect();
+    Coverage { required, missing }
+}
+
+/// Names of all segment files there could be, whether or not they have any roads.
+pub fn all_tiles() -> Vec<String> {
+    (-18..=17)
+        .flat_map(|y| (-36..=35).map(move |x| tile_name_at(x, y)))
+        .collect()
+}
+
+fn is_tile_name(name: &str) -> bool {
+    lazy_regex::regex_is_match!(r"^[EW]\d{1,3}_[NS]\d{1,2}\.rd5$", name)
+}
+
+/// A segment file in the segment directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstalledSegment {
+    pub name: String,
+
+    /// Size in bytes.
+    pub size: u64,
+
+    pub modified: SystemTime,
+}
+
+/// What became of a segment file passed to [`SegmentManager::download_all`].
+#[derive(Debug)]
+pub enum DownloadOutcome {
+    /// The file was present already.
+    Present,
+    Downloaded {
+        size: u64,
+    },
+    /// None of the sources has the file, e.g. because the tile has no roads.
+    Unavailable,
+    Failed(Error),
+}
+
+/// Progress of [`SegmentManager::download_all`], reported after each file.
+#[derive(Debug)]
+pub struct DownloadProgress<'a> {
+    pub name: &'a str,
+    pub outcome: &'a DownloadOutcome,
+
+    /// Number of files handled so far, including this one.
+    pub completed: usize,
+
+    pub total: usize,
+}
+
+/// Manages the segment directory of a local brouter server: lists, downloads, updates and
+/// deletes segment files.
+///
+/// Set one with [`crate::BrouterBuilder::segment_manager`] to have the client fetch the
+/// segment files a request needs before routing.
+///
+/// Several processes can share a segment directory: a file that one of them is downloading
+/// is not downloaded again by the others, which wait for it instead.
+#[derive(Debug, Clone)]
+pub struct SegmentManager {
+    dir: PathBuf,
+    sources: Vec<String>,
+    client: Client,
+    parallelism: usize,
+    events: Option<Sender<ProvisionEvent>>,
+    throttle: Option<Arc<Throttle>>,
+}
+
+impl SegmentManager {
+    /// Create a manager for the segment files in `segments_dir`.
+    pub fn new(segments_dir: &Path) -> Self {
+        SegmentManager {
+            dir: segments_dir.to_path_buf(),
+            sources: vec![DEFAULT_SOURCE.to_string()],
+            client: crate::transfer::client(),
+            parallelism: 4,
+            events: None,
+            throttle: None,
+        }
+    }
+
+    /// The segment directory the manager manages.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Set the base URL to download segment files from.
+    ///
+    /// Files are fetched from `{source}/{E5_N50}.rd5`.
+    pub fn with_source(mut self, source: &str) -> Self {
+        self.sources = vec![source.trim_end_matches('/').to_string()];
+        self
+    }
+
+    /// Add a mirror to download segment files from when the sources before it fail.
+    pub fn with_mirror(mut self, mirror: &str) -> Self {
+        self.sources.push(mirror.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// The client to download segment files with, e.g. one with a proxy.
+    ///
+    /// The default client only limits how long connecting takes. A timeout set on the client
+    /// covers the whole download, so it has to allow for the largest segment files, of
+    /// several hundred megabytes, at the slowest expected speed.
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// How many files [`SegmentManager::download_all`] and [`SegmentManager::outdated`]
+    /// handle at the same time. Defaults to 4.
+    pub fn parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism.max(1);
+        self
+    }
+
+    /// Download at most `bytes_per_second` in total, however many files are downloaded at the
+    /// same time, e.g. to keep a connection usable while a large region is downloaded in the
+    /// background.
+    pub fn bandwidth_limit(mut self, bytes_per_second: u64) -> Self {
+        self.throttle = Some(Arc::new(Throttle::new(bytes_per_second)));
+        self
+    }
+
+    /// Send the progress of downloads to `events`: each download, each file that is
+    /// skipped, and [`ProvisionEvent::Done`] when [`SegmentManager::download_all`],
+    /// [`SegmentManager::update`] or [`SegmentManager::ensure_for`] is done.
+    pub fn events(mut self, events: Sender<ProvisionEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    pub fn segments_dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The segment files in the segment directory, sorted by name.
+    pub fn installed(&self) -> Result<Vec<InstalledSegment>, Error> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        let mut installed = vec![];
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let metadata = entry.metadata()?;
+            if !is_tile_name(&name) || !metadata.is_file() {
+                continue;
+            }
+            installed.push(InstalledSegment {
+                name,
+                size: metadata.len(),
+                modified: metadata.modified()?,
+            });
+        }
+        installed.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(installed)
+    }
+
+    /// The segment files the first source offers, from its directory listing.
+    ///
+    /// Unlike [`all_tiles`], this leaves out the tiles without roads.
+    pub fn available(&self) -> Result<Vec<String>, Error> {
+        let listing = self
+            .client
+            .get(format!("{}/", self.sources[0]))
+            .send()?
+            .error_for_status()?
+            .text()?;
+        let mut names = lazy_regex::regex!(r#"href="([EW]\d{1,3}_[NS]\d{1,2}\.rd5)""#)
+            .captures_iter(&listing)
+            .map(|c| c[1].to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    /// Download the segment file `name`, e.g. `E5_N50.rd5`, unless it is already present.
+    ///
+    /// Returns whether the file is present afterwards; there are no files for tiles without
+    /// any roads, such as those in the middle of an ocean.
+    pub fn download(&self, name: &str) -> Result<bool, Error> {
+        match self.fetch(name, false)? {
+            DownloadOutcome::Failed(e) => Err(e),
+            outcome => Ok(!matches!(outcome, DownloadOutcome::Unavailable)),
+        }
+    }
+
+    /// Download the segment files `names`, [`SegmentManager::parallelism`] at a time,
+    /// calling `progress` after each.
+    ///
+    /// Files that are present already are left alone. Failures don't stop the other
+    /// downloads; the outcome of each file is returned, in the order of `names`.
+    pub fn download_all(
+        &self,
+        names: &[String],
+        progress: impl Fn(&DownloadProgress) + Sync,
+    ) -> Vec<(String, DownloadOutcome)> {
+        self.run_all(names, false, progress)
+    }
+
+    /// Download the segment files `names` again, e.g. those returned by
+    /// [`SegmentManager::outdated`]; see [`SegmentManager::download_all`].
+    pub fn update(
+        &self,
+        names: &[String],
+        progress: impl Fn(&DownloadProgress) + Sync,
+    ) -> Vec<(String, DownloadOutcome)> {
+        self.run_all(names, true, progress)
+    }
+
+    /// The installed segment files that are older than those on the first source, according
+    /// to the `Last-Modified` header the source sends.
+    pub fn outdated(&self) -> Result<Vec<String>, Error> {
+        let installed = self.installed()?;
+        let checks = parallel(&installed, self.parallelism, |segment| {
+            let url = format!("{}/{}", self.sources[0], segment.name);
+            let response = self.client.head(url).send()?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(false);
+            }
+            let response = response.error_for_status()?;
+            let remote = response
+                .headers()
+                .get(reqwest::header::LAST_MODIFIED)
+                .and_then(|v| v.to_str().ok())
+                .and_then(parse_http_date);
+            Ok::<_, Error>(remote.is_some_and(|remote| remote > segment.modified))
+        });
+        let mut outdated = vec![];
+        for (segment, check) in installed.into_iter().zip(checks) {
+            if check? {
+                outdated.push(segment.name);
+            }
+        }
+        Ok(outdated)
+    }
+
+    /// Delete the segment file `name`.
+    pub fn delete(&self, name: &str) -> Result<(), Error> {
+        if !is_tile_name(name) {
+            return Err(Error::Other(format!("invalid segment file name: {}", name)));
+        }
+        Ok(std::fs::remove_file(self.dir.join(name))?)
+    }
+
+    /// Download the segment files `request` may need that are missing, see [`tiles_for`].
+    ///
+    /// Returns the names of the files that were downloaded.
+    pub fn ensure_for(&self, request: &RouteRequest) -> Result<Vec<String>, Error> {
+        let coverage = check_coverage(request, &self.dir);
+        let mut downloaded = vec![];
+        for name in coverage.missing {
+            if self.download(&name)? {
+                downloaded.push(name);
+            }
+        }
+        self.emit(ProvisionEvent::Done);
+        Ok(downloaded)
+    }
+
+    fn run_all(
+        &self,
+        names: &[String],
+        replace: bool,
+        progress: impl Fn(&DownloadProgress) + Sync,
+    ) -> Vec<(String, DownloadOutcome)> {
+        let completed = AtomicUsize::new(0);
+        let outcomes = parallel(names, self.parallelism, |name| {
+            let outcome = self
+                .fetch(name, replace)
+                .unwrap_or_else(DownloadOutcome::Failed);
+            progress(&DownloadProgress {
+                name,
+                outcome: &outcome,
+                completed: completed.fetch_add(1, Ordering::SeqCst) + 1,
+                total: names.len(),
+            });
+            outcome
+        });
+        self.emit(ProvisionEvent::Done);
+        names.iter().cloned().zip(outcomes).collect()
+    }
+
+    fn emit(&self, event: ProvisionEvent) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event);
+        }
+    }
+
+    /// Report that `name` was not downloaded, unless that is because downloading it failed.
+    fn skipped(&self, name: &str, outcome: DownloadOutcome) -> DownloadOutcome {
+        if !matches!(outcome, DownloadOutcome::Failed(_)) {
+            self.emit(ProvisionEvent::SegmentSkipped {
+                name: name.to_string(),
+                present: matches!(outcome, DownloadOutcome::Present),
+            });
+        }
+        outcome
+    }
+
+    fn fetch(&self, name: &str, replace: bool) -> Result<DownloadOutcome, Error> {
+        if !is_tile_name(name) {
+            return Err(Error::Other(format!("invalid segment file name: {}", name)));
+        }
+        let path = self.dir.join(name);
+        if !replace && path.is_file() {
+            return Ok(self.skipped(name, DownloadOutcome::Present));
+        }
+        // Other processes using the same segment directory may be downloading the file too;
+        // wait for them, and use what they downloaded.
+        let _lock = crate::lock_for(&path)?;
+        if !replace && path.is_file() {
+            return Ok(self.skipped(name, DownloadOutcome::Present));
+        }
+
+        let mut outcome = DownloadOutcome::Unavailable;
+        for source in &self.sources {
+            let url = format!("{}/{}", source, name);
+            info!("Downloading {}", url);
+            match self.fetch_from(&url, &path) {
+                Ok(Some(size)) => return Ok(DownloadOutcome::Downloaded { size }),
+                Ok(None) => {}
+                Err(e) => outcome = DownloadOutcome::Failed(e),
+            }
+        }
+        Ok(self.skipped(name, outcome))
+    }
+
+    /// Download `url` to `path`; returns the size, or `None` if the source doesn't have it.
+    fn fetch_from(&self, url: &str, path: &Path) -> Result<Option<u64>, Error> {
+        let response = self.client.get(url).send()?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = response.error_for_status()?;
+        let size = response.content_length();
+        let mut transfer = Transfer::new(
+            response,
+            url,
+            size,
+            self.events.as_ref(),
+            self.throttle.as_deref(),
+        );
+
+        std::fs::create_dir_all(&self.dir)?;
+        // Segment files are large, so they're written as they arrive, to a temporary file
+        // that brouter won't pick up before it is complete.
+        let partial = path.with_extension("rd5.part");
+        let mut write = || -> Result<(), std::io::Error> {
+            let mut file = std::fs::File::create(&partial)?;
+            std::io::copy(&mut transfer, &mut file)?;
+            file.sync_all()
+        };
+        match write() {
+            Ok(()) => {
+                std::fs::rename(&partial, path)?;
+                Ok(Some(transfer.finish()))
+            }
+            Err(e) => {
+                let _ = std::fs::remove_file(&partial);
+                Err(e.into())
+            }
+        }
+    }
+}
+
+/// Apply `f` to each of `items` on up to `parallelism` threads, returning the results in
+/// order.
+fn parallel<T: Sync, R: Send>(
+    items: &[T],
+    parallelism: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let mut results = std::thread::scope(|scope| {
+        let workers = (0..parallelism.min(items.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::SeqCst);
+                        let Some(item) = items.get(i) else {
+                            break;
+                        };
+                        results.push((i, f(item)));
+                    }
+                    results
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                // Only a panic in `f` makes a worker fail; pass it on as is.
+                worker
+                    .join()
+                    .unwrap_or_else(|e| std::panic::resume_unwind(e))
+            })
+            .collect::<Vec<_>>()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+/// Parse a date in the format of HTTP headers, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn parse_http_date(s: &str) -> Option<SystemTime> {
+    let (_, day, month, year, hour, minute, second) = lazy_regex::regex_captures!(
+        r"^\w{3}, (\d{2}) (\w{3}) (\d{4}) (\d{2}):(\d{2}):(\d{2}) GMT$",
+        s.trim()
+    )?;
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let month = MONTHS.iter().position(|m| *m == month)? as u8 + 1;
+    let date = time::Date::from_calendar_date(
+        year.parse().ok()?,
+        time::Month::try_from(month).ok()?,
+        day.parse().ok()?,
+    )
+    .ok()?;
+    let time = time::Time::from_hms(
+        hour.parse().ok()?,
+        minute.parse().ok()?,
+        second.parse().ok()?,
+    )
+    .ok()?;
+    Some(time::PrimitiveDateTime::new(date, time).assume_utc().into())
+}
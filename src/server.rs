@@ -0,0 +1,970 @@
+//! Running a local brouter server.
+//!
+//! [`BrouterServerBuilder`] starts brouter's `RouteServer` from a brouter jar, as the
+//! `server.sh` script in brouter's distribution does, but with the port, heap size and other
+//! settings configurable:
+//!
+//! ```no_run
+//! use brouter_client::server::BrouterServerBuilder;
+//!
+//! let server = BrouterServerBuilder::new("brouter.jar", "segments4")
+//!     .profiles_dir("profiles2")
+//!     .ephemeral_port()
+//!     .heap_mb(1024)
+//!     .start()?;
+//! let router = server.client();
+//! # Ok::<(), brouter_client::Error>(())
+//! ```
+//!
+//! With the `download` feature, [`BrouterServerBuilder::download`] fetches a brouter release
+//! instead of needing a jar at hand.
+//!
+//! Failures are reported as [`Error`], which tells e.g. a missing Java installation apart from
+//! a failed download; it converts into [`crate::Error`] for use with `?`.
+
+use crate::Brouter;
+use log::info;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// An error setting up or running a local brouter server.
+#[derive(Debug)]
+pub enum Error {
+    /// A release could not be downloaded.
+    Download(reqwest::Error),
+    /// A release archive could not be unpacked.
+    Extract(String),
+    /// There is no brouter release in the directory.
+    NoRelease(PathBuf),
+    /// The brouter release in `dir` was not unpacked completely.
+    CorruptRelease {
+        dir: PathBuf,
+        problem: String,
+    },
+    /// The java binary to run the server with was not found.
+    JavaNotFound(PathBuf),
+    /// The JVM could not be started.
+    SpawnFailed(std::io::Error),
+    /// The server exited, or did not accept connections on the address in time.
+    NotServing {
+        address: SocketAddr,
+        reason: String,
+    },
+    Io(std::io::Error),
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Download(e) => write!(f, "Unable to download brouter: {}", e),
+            Error::Extract(e) => write!(f, "Unable to unpack brouter: {}", e),
+            Error::NoRelease(dir) => write!(f, "No brouter release found in {}", dir.display()),
+            Error::CorruptRelease { dir, problem } => {
+                write!(
+                    f,
+                    "Broken brouter release in {}: {}",
+                    dir.display(),
+                    problem
+                )
+            }
+            Error::JavaNotFound(java) => write!(f, "Java not found: {}", java.display()),
+            Error::SpawnFailed(e) => write!(f, "Unable to start brouter: {}", e),
+            Error::NotServing { address, reason } => {
+                write!(f, "brouter is not serving on {}: {}", address, reason)
+            }
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Download(e)
+    }
+}
+
+/// A step in setting up a local server, sent to the channel given to
+/// [`ReleaseInstaller::events`] or [`crate::segments::SegmentManager::events`], e.g. to show
+/// the progress of a first run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProvisionEvent {
+    /// A download started; `size` is the size the server announced, if any.
+    DownloadStarted {
+        url: String,
+        size: Option<u64>,
+    },
+    /// Part of a download arrived; `transferred` is the number of bytes so far.
+    BytesTransferred {
+        url: String,
+        transferred: u64,
+        size: Option<u64>,
+    },
+    DownloadFinished {
+        url: String,
+        size: u64,
+    },
+    /// A release was unpacked into `dir`.
+    Extracted {
+        dir: PathBuf,
+        files: usize,
+    },
+    /// A segment file was not downloaded, because it is `present` already or no source has
+    /// it.
+    SegmentSkipped {
+        name: String,
+        present: bool,
+    },
+    /// A release is installed, or a set of segment files is downloaded.
+    Done,
+}
+
+/// The port brouter's scripts start the server on.
+pub const DEFAULT_PORT: u16 = 17777;
+
+/// How long [`BrouterServerBuilder::start`] waits for the server to accept connections.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to wait for each attempt to connect to a starting server, and between attempts.
+const CONNECT_ATTEMPT_TIMEOUT: Duration = Duration::from_millis(200);
+const CONNECT_ATTEMPT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How much longer than the server's limit per request [`BrouterServer::client`] waits, so
+/// that the server's own error arrives before the client gives up.
+const CLIENT_TIMEOUT_MARGIN: Duration = Duration::from_secs(10);
+
+/// Where brouter publishes its releases.
+pub const DEFAULT_RELEASE_SOURCE: &str = "https://github.com/abrensch/brouter/releases/download";
+
+/// The URL of the archive of brouter release `version`, e.g. "1.7.8".
+pub fn release_url(version: &str) -> String {
+    release_url_at(DEFAULT_RELEASE_SOURCE, version)
+}
+
+fn release_url_at(source: &str, version: &str) -> String {
+    format!("{0}/v{1}/brouter-{1}.zip", source, version)
+}
+
+/// A brouter release unpacked in a directory.
+struct Release {
+    /// The jar to run, unless it is missing.
+    jar: Option<PathBuf>,
+    profiles_dir: PathBuf,
+}
+
+/// The brouter release unpacked in `dir`, if there is one.
+///
+/// Release archives keep everything in a top-level directory, so that is looked in as well.
+/// A directory with profiles but no jar is taken for a release with the jar missing.
+fn locate_release(dir: &Path) -> Result<Option<Release>, Error> {
+    let paths = |dir: &Path| -> Result<Vec<PathBuf>, std::io::Error> {
+        match std::fs::read_dir(dir) {
+            Ok(entries) => entries.map(|e| e.map(|e| e.path())).collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+            Err(e) => Err(e),
+        }
+    };
+    let subdirs = paths(dir)?.into_iter().filter(|p| p.is_dir());
+    let mut without_jar = None;
+    for dir in std::iter::once(dir.to_path_buf()).chain(subdirs) {
+        let mut jars = paths(&dir)?
+            .into_iter()
+            .filter(|p| p.extension().is_some_and(|e| e == "jar"))
+            .collect::<Vec<_>>();
+        // Prefer the jar with the dependencies bundled.
+        jars.sort_by_key(|p| !p.to_string_lossy().ends_with("-all.jar"));
+        let profiles_dir = dir.join("profiles2");
+        if let Some(jar) = jars.into_iter().next() {
+            return Ok(Some(Release {
+                jar: Some(jar),
+                profiles_dir,
+            }));
+        }
+        if without_jar.is_none() && profiles_dir.is_dir() {
+            without_jar = Some(Release {
+                jar: None,
+                profiles_dir,
+            });
+        }
+    }
+    Ok(without_jar)
+}
+
+/// The file listing what was unpacked from a release archive, written next to the release.
+///
+/// Each line holds the CRC-32 in hex, the size and the name of a file, separated by spaces.
+#[cfg(feature = "download")]
+const MANIFEST: &str = "brouter-client-manifest.txt";
+
+#[cfg(feature = "download")]
+fn write_manifest(dir: &Path, files: &[crate::zip::Unpacked]) -> Result<(), std::io::Error> {
+    let manifest = files
+        .iter()
+        .map(|f| format!("{:08x} {} {}\n", f.crc, f.size, f.name))
+        .collect::<String>();
+    std::fs::write(dir.join(MANIFEST), manifest)
+}
+
+/// Check the files of the release in `dir` against its manifest, if it has one; releases
+/// not unpacked by this crate don't.
+#[cfg(feature = "download")]
+fn check_manifest(dir: &Path) -> Result<(), Error> {
+    let manifest = match std::fs::read_to_string(dir.join(MANIFEST)) {
+        Ok(manifest) => manifest,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    let corrupt = |problem: String| Error::CorruptRelease {
+        dir: dir.to_path_buf(),
+        problem,
+    };
+    for line in manifest.lines() {
+        let mut fields = line.splitn(3, ' ');
+        let (Some(crc), Some(size), Some(name)) = (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(corrupt(format!("invalid manifest line {:?}", line)));
+        };
+        let contents = match std::fs::read(dir.join(name)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(corrupt(format!("{} is missing", name)))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if contents.len().to_string() != size
+            || format!("{:08x}", crc32fast::hash(&contents)) != crc
+        {
+            return Err(corrupt(format!("{} differs from the archive", name)));
+        }
+    }
+    Ok(())
+}
+
+/// The jar and profile directory of the brouter release unpacked in `dir`, after checking
+/// that it was unpacked completely: that the jar is there, neither it nor any of the
+/// profiles is empty, and, with the `download` feature, that the files match the manifest
+/// written when the release was unpacked.
+fn check_release(dir: &Path) -> Result<(PathBuf, PathBuf), Error> {
+    let corrupt = |problem: String| Error::CorruptRelease {
+        dir: dir.to_path_buf(),
+        problem,
+    };
+    #[cfg(feature = "download")]
+    check_manifest(dir)?;
+    let release = locate_release(dir)?.ok_or_else(|| Error::NoRelease(dir.to_path_buf()))?;
+    let jar = release
+        .jar
+        .ok_or_else(|| corrupt("the jar is missing".to_string()))?;
+    let mut files = vec![jar.clone()];
+    if release.profiles_dir.is_dir() {
+        for entry in std::fs::read_dir(&release.profiles_dir)? {
+            files.push(entry?.path());
+        }
+    }
+    for file in files {
+        let metadata = std::fs::metadata(&file)?;
+        if metadata.is_file() && metadata.len() == 0 {
+            return Err(corrupt(format!("{} is empty", file.display())));
+        }
+    }
+    Ok((jar, release.profiles_dir))
+}
+
+/// Unpack `archive` in `dir`, calling `check` on the unpacked files before moving them into
+/// place.
+///
+/// The archive is unpacked next to `dir` first, and only moved into place once complete, so
+/// that an interrupted unpack isn't taken for a release. Returns the number of files
+/// unpacked.
+#[cfg(feature = "download")]
+fn unpack_atomically<R: std::io::Read + std::io::Seek>(
+    archive: R,
+    dir: &Path,
+    check: impl FnOnce(&Path, &[crate::zip::Unpacked]) -> Result<(), Error>,
+) -> Result<usize, Error> {
+    let mut partial = dir.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    if partial.exists() {
+        std::fs::remove_dir_all(&partial)?;
+    }
+    let unpacked = crate::zip::unpack(archive, &partial).and_then(|files| {
+        check(&partial, &files)?;
+        Ok(files.len())
+    });
+    let files = match unpacked {
+        Ok(files) => files,
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&partial);
+            return Err(e);
+        }
+    };
+    std::fs::rename(&partial, dir).map_err(|e| {
+        let _ = std::fs::remove_dir_all(&partial);
+        Error::Extract(format!("can't move release into {}: {}", dir.display(), e))
+    })?;
+    Ok(files)
+}
+
+/// Check that the archive unpacked in `dir` holds a complete release.
+#[cfg(feature = "download")]
+fn check_unpacked(dir: &Path) -> Result<(), Error> {
+    match check_release(dir) {
+        Ok(_) => Ok(()),
+        Err(Error::NoRelease(_)) => {
+            Err(Error::Extract("no brouter release in archive".to_string()))
+        }
+        Err(Error::CorruptRelease { problem, .. }) => Err(Error::Extract(format!(
+            "incomplete release in archive: {}",
+            problem
+        ))),
+        Err(e) => Err(e),
+    }
+}
+
+/// Unpack the release archive `archive` in `dir`, with a manifest of its files; see
+/// [`unpack_atomically`].
+#[cfg(feature = "download")]
+fn unpack_release(archive: &[u8], dir: &Path) -> Result<usize, Error> {
+    unpack_atomically(std::io::Cursor::new(archive), dir, |partial, files| {
+        write_manifest(partial, files)?;
+        check_unpacked(partial)
+    })
+}
+
+/// Where snapshots keep the profiles, uploaded profiles and segment files; the jar is at the
+/// top level.
+#[cfg(feature = "download")]
+const SNAPSHOT_PROFILES: &str = "profiles2";
+#[cfg(feature = "download")]
+const SNAPSHOT_CUSTOM_PROFILES: &str = "customprofiles";
+#[cfg(feature = "download")]
+const SNAPSHOT_SEGMENTS: &str = "segments4";
+
+/// Builder for a local brouter server.
+#[derive(Debug, Clone)]
+pub struct BrouterServerBuilder {
+    jar: PathBuf,
+    java: PathBuf,
+    segments_dir: PathBuf,
+    profiles_dir: PathBuf,
+    custom_profiles_dir: PathBuf,
+    bind_address: IpAddr,
+    port: Option<u16>,
+    heap_mb: u32,
+    jvm_options: Vec<String>,
+    max_running_time: Duration,
+    threads: usize,
+}
+
+impl BrouterServerBuilder {
+    /// Configure a server running `jar` on the segment files in `segments_dir`.
+    ///
+    /// The other settings default to those of brouter's `server.sh`: profiles in `profiles2`
+    /// and uploads in `customprofiles` next to the segment directory, port 17777 on
+    /// localhost, a heap of 128 MB, one thread and a limit of 300 seconds per request.
+    pub fn new(jar: impl Into<PathBuf>, segments_dir: impl Into<PathBuf>) -> Self {
+        let segments_dir = segments_dir.into();
+        let sibling = |name: &str| {
+            segments_dir
+                .parent()
+                .map_or_else(|| PathBuf::from(name), |p| p.join(name))
+        };
+        BrouterServerBuilder {
+            jar: jar.into(),
+            java: PathBuf::from("java"),
+            profiles_dir: sibling("profiles2"),
+            custom_profiles_dir: sibling("customprofiles"),
+            segments_dir,
+            bind_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: Some(DEFAULT_PORT),
+            heap_mb: 128,
+            jvm_options: vec![],
+            max_running_time: Duration::from_secs(300),
+            threads: 1,
+        }
+    }
+
+    /// Configure a server running brouter release `version`, e.g. "1.7.8", on the segment
+    /// files in `segments_dir`, with the profiles of the release.
+    ///
+    /// The release is downloaded from GitHub and unpacked in a directory under `install_dir`,
+    /// e.g. [`crate::dirs::cache_dir`], unless it has been before; use [`ReleaseInstaller`] to
+    /// download it with a client of your own. Segment files are not part of releases; see
+    /// [`crate::segments::SegmentManager`] for those.
+    #[cfg(feature = "download")]
+    pub fn download(
+        version: &str,
+        install_dir: impl AsRef<Path>,
+        segments_dir: impl Into<PathBuf>,
+    ) -> Result<Self, Error> {
+        let dir = ReleaseInstaller::new(install_dir.as_ref()).install(version)?;
+        Self::from_release_dir(&dir, segments_dir)
+    }
+
+    /// Configure a server running the brouter release in `archive`, the contents of a release
+    /// zip file, after unpacking it in `dir`; see [`BrouterServerBuilder::download`].
+    ///
+    /// `dir` must not exist yet, or be empty.
+    #[cfg(feature = "download")]
+    pub fn from_release_archive(
+        archive: &[u8],
+        dir: impl AsRef<Path>,
+        segments_dir: impl Into<PathBuf>,
+    ) -> Result<Self, Error> {
+        let _lock = crate::lock_for(dir.as_ref())?;
+        unpack_release(archive, dir.as_ref())?;
+        Self::from_release_dir(dir.as_ref(), segments_dir)
+    }
+
+    /// Configure a server running from a snapshot written by
+    /// [`BrouterServerBuilder::export_snapshot`], after unpacking it in `dir`.
+    ///
+    /// `dir` must not exist yet, or be empty. The server gets the jar, profiles and segment
+    /// files of the snapshot, and the other settings their defaults.
+    #[cfg(feature = "download")]
+    pub fn import_snapshot(path: impl AsRef<Path>, dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let dir = dir.as_ref();
+        let archive = std::io::BufReader::new(std::fs::File::open(path)?);
+        let _lock = crate::lock_for(dir)?;
+        unpack_atomically(archive, dir, |partial, _| check_unpacked(partial))?;
+        Ok(Self::from_release_dir(dir, dir.join(SNAPSHOT_SEGMENTS))?
+            .custom_profiles_dir(dir.join(SNAPSHOT_CUSTOM_PROFILES)))
+    }
+
+    /// Write the jar, the profiles, the uploaded profiles and the segment files `segments`,
+    /// e.g. `E5_N50.rd5`, of the server to a snapshot at `path`.
+    ///
+    /// This is for setting up the same server where nothing can be downloaded, e.g. on a
+    /// machine that will be offline, with [`BrouterServerBuilder::import_snapshot`]. Snapshots
+    /// are zip files, and can't be larger than 4 GiB.
+    #[cfg(feature = "download")]
+    pub fn export_snapshot(
+        &self,
+        path: impl AsRef<Path>,
+        segments: &[String],
+    ) -> Result<(), Error> {
+        let file_name = |path: &Path| {
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .map(str::to_string)
+                .ok_or_else(|| {
+                    Error::Io(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("unsupported file name: {}", path.display()),
+                    ))
+                })
+        };
+
+        // The files to write, with their names in the snapshot and whether to compress them;
+        // segment files don't get much smaller.
+        let mut files = vec![(file_name(&self.jar)?, self.jar.clone(), true)];
+        for (dir, name) in [
+            (&self.profiles_dir, SNAPSHOT_PROFILES),
+            (&self.custom_profiles_dir, SNAPSHOT_CUSTOM_PROFILES),
+        ] {
+            let entries = match std::fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            for entry in entries {
+                let path = entry?.path();
+                if path.is_file() {
+                    files.push((format!("{}/{}", name, file_name(&path)?), path, true));
+                }
+            }
+        }
+        for segment in segments {
+            let path = self.segments_dir.join(segment);
+            if file_name(&path)? != *segment {
+                return Err(Error::Io(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("invalid segment file name: {}", segment),
+                )));
+            }
+            files.push((format!("{}/{}", SNAPSHOT_SEGMENTS, segment), path, false));
+        }
+
+        let path = path.as_ref();
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".part");
+        let partial = PathBuf::from(partial);
+        let write = || -> Result<(), Error> {
+            let out = std::io::BufWriter::new(std::fs::File::create(&partial)?);
+            let mut zip = crate::zip::ZipWriter::new(out);
+            for (name, path, compress) in &files {
+                zip.add(name, &mut std::fs::File::open(path)?, *compress)?;
+            }
+            let out = zip.finish()?.into_inner().map_err(|e| e.into_error())?;
+            Ok(out.sync_all()?)
+        };
+        match write() {
+            Ok(()) => Ok(std::fs::rename(&partial, path)?),
+            Err(e) => {
+                let _ = std::fs::remove_file(&partial);
+                Err(e)
+            }
+        }
+    }
+
+    /// Configure a server running the brouter release unpacked in `dir`.
+    pub fn from_release_dir(dir: &Path, segments_dir: impl Into<PathBuf>) -> Result<Self, Error> {
+        let (jar, profiles_dir) = check_release(dir)?;
+        Ok(Self::new(jar, segments_dir).profiles_dir(profiles_dir))
+    }
+
+    /// The java binary to run, if not `java` on the search path.
+    pub fn java(mut self, java: impl Into<PathBuf>) -> Self {
+        self.java = java.into();
+        self
+    }
+
+    pub fn profiles_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.profiles_dir = dir.into();
+        self
+    }
+
+    /// The directory the server stores uploaded profiles in.
+    pub fn custom_profiles_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.custom_profiles_dir = dir.into();
+        self
+    }
+
+    /// The address to listen on; use `0.0.0.0` to accept connections from other hosts.
+    pub fn bind_address(mut self, address: IpAddr) -> Self {
+        self.bind_address = address;
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Listen on a free port picked when the server starts, e.g. to run several servers in
+    /// parallel tests; see [`BrouterServer::url`] for the port that was picked.
+    pub fn ephemeral_port(mut self) -> Self {
+        self.port = None;
+        self
+    }
+
+    /// The maximum heap size of the JVM, in megabytes. Long routes need more than the default
+    /// of 128.
+    pub fn heap_mb(mut self, heap_mb: u32) -> Self {
+        self.heap_mb = heap_mb;
+        self
+    }
+
+    /// Pass another option to the JVM, e.g. `-DuseRFCMimeType=true`.
+    pub fn jvm_option(mut self, option: &str) -> Self {
+        self.jvm_options.push(option.to_string());
+        self
+    }
+
+    /// How long the server works on a single request before giving up.
+    pub fn max_running_time(mut self, max_running_time: Duration) -> Self {
+        self.max_running_time = max_running_time;
+        self
+    }
+
+    /// How many requests the server handles at the same time.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// The command that runs the server on `port`.
+    fn command(&self, port: u16) -> Command {
+        let mut command = Command::new(&self.java);
+        command
+            .arg(format!("-Xmx{}M", self.heap_mb))
+            .arg(format!(
+                "-DmaxRunningTime={}",
+                self.max_running_time.as_secs()
+            ))
+            .args(&self.jvm_options)
+            .arg("-cp")
+            .arg(&self.jar)
+            .arg("btools.server.RouteServer")
+            .arg(&self.segments_dir)
+            .arg(&self.profiles_dir)
+            .arg(&self.custom_profiles_dir)
+            .arg(port.to_string())
+            .arg(self.threads.to_string())
+            .arg(self.bind_address.to_string())
+            .stdin(Stdio::null());
+        command
+    }
+
+    /// Start the server and wait until it accepts connections.
+    pub fn start(&self) -> Result<BrouterServer, Error> {
+        let mut server = self.spawn()?;
+        server.wait_until_listening(STARTUP_TIMEOUT)?;
+        Ok(server)
+    }
+
+    /// Start the server and wait until it accepts connections, without blocking; see
+    /// [`BrouterServerBuilder::start`]. This needs a tokio runtime.
+    #[cfg(feature = "async")]
+    pub async fn start_async(&self) -> Result<BrouterServer, Error> {
+        let mut server = self.spawn()?;
+        server.wait_until_listening_async(STARTUP_TIMEOUT).await?;
+        Ok(server)
+    }
+
+    /// Start the JVM, without waiting for the server to come up.
+    fn spawn(&self) -> Result<BrouterServer, Error> {
+        let port = match self.port {
+            Some(port) => port,
+            // The port is released again before brouter binds it, so another process could
+            // grab it in between; that is unlikely enough for tests.
+            None => TcpListener::bind((self.bind_address, 0))?
+                .local_addr()?
+                .port(),
+        };
+        std::fs::create_dir_all(&self.custom_profiles_dir)?;
+
+        info!("Starting brouter on port {}", port);
+        let child = self.command(port).spawn().map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => Error::JavaNotFound(self.java.clone()),
+            _ => Error::SpawnFailed(e),
+        })?;
+        Ok(BrouterServer {
+            child,
+            address: SocketAddr::new(self.bind_address, port),
+            // Restarts use the same port, so that clients can keep using the server.
+            builder: self.clone().port(port),
+        })
+    }
+}
+
+/// Downloads brouter releases and unpacks them, to run a local server from.
+///
+/// ```no_run
+/// use brouter_client::server::{BrouterServerBuilder, ReleaseInstaller};
+///
+/// let client = reqwest::blocking::Client::builder()
+///     .proxy(reqwest::Proxy::all("http://proxy.example.com:3128")?)
+///     .build()?;
+/// let dir = ReleaseInstaller::new("releases")
+///     .with_client(client)
+///     .install("1.7.8")?;
+/// let server = BrouterServerBuilder::from_release_dir(&dir, "segments4")?.start()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[cfg(feature = "download")]
+#[derive(Debug, Clone)]
+pub struct ReleaseInstaller {
+    dir: PathBuf,
+    source: String,
+    client: reqwest::blocking::Client,
+    events: Option<std::sync::mpsc::Sender<ProvisionEvent>>,
+    throttle: Option<std::sync::Arc<crate::transfer::Throttle>>,
+}
+
+#[cfg(feature = "download")]
+impl ReleaseInstaller {
+    /// Install releases in directories under `install_dir`, e.g. [`crate::dirs::cache_dir`].
+    pub fn new(install_dir: impl Into<PathBuf>) -> Self {
+        ReleaseInstaller {
+            dir: install_dir.into(),
+            source: DEFAULT_RELEASE_SOURCE.to_string(),
+            client: crate::transfer::client(),
+            events: None,
+            throttle: None,
+        }
+    }
+
+    /// Set the base URL to download releases from.
+    ///
+    /// Releases are fetched from `{source}/v{1.7.8}/brouter-{1.7.8}.zip`.
+    pub fn with_source(mut self, source: &str) -> Self {
+        self.source = source.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// The client to download releases with, e.g. the one given to
+    /// [`crate::segments::SegmentManager::with_client`], whose notes on timeouts apply here as
+    /// well.
+    pub fn with_client(mut self, client: reqwest::blocking::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Download releases at no more than `bytes_per_second`.
+    pub fn bandwidth_limit(mut self, bytes_per_second: u64) -> Self {
+        self.throttle = Some(std::sync::Arc::new(crate::transfer::Throttle::new(
+            bytes_per_second,
+        )));
+        self
+    }
+
+    /// Send the progress of installs to `events`: the download, the unpacking and
+    /// [`ProvisionEvent::Done`] when a release is installed.
+    pub fn events(mut self, events: std::sync::mpsc::Sender<ProvisionEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// The directory release `version` is installed in.
+    pub fn release_dir(&self, version: &str) -> PathBuf {
+        self.dir.join(format!("brouter-{}", version))
+    }
+
+    /// The archive of release `version` is kept in, to unpack it again if needed.
+    fn archive_path(&self, version: &str) -> PathBuf {
+        self.dir.join(format!("brouter-{}.zip", version))
+    }
+
+    /// Download and unpack release `version`, e.g. "1.7.8", unless it has been before.
+    ///
+    /// A release that was not unpacked completely or was changed since, e.g. because its jar
+    /// is missing or empty, is unpacked again from the archive kept next to it, or downloaded
+    /// again if that is gone too. Processes sharing `install_dir` install a release one at a
+    /// time. Returns the directory of the release, for
+    /// [`BrouterServerBuilder::from_release_dir`].
+    pub fn install(&self, version: &str) -> Result<PathBuf, Error> {
+        use std::io::Read;
+
+        let dir = self.release_dir(version);
+        // Other processes may be installing the same release; wait for them, rather than
+        // unpacking it over theirs.
+        let _lock = crate::lock_for(&dir)?;
+        match check_release(&dir) {
+            Ok(_) => {
+                self.emit(ProvisionEvent::Done);
+                return Ok(dir);
+            }
+            Err(Error::NoRelease(_)) => {}
+            Err(Error::CorruptRelease { problem, .. }) => {
+                log::warn!("Reinstalling brouter {}: {}", version, problem);
+                std::fs::remove_dir_all(&dir)?;
+            }
+            Err(e) => return Err(e),
+        }
+
+        let archive_path = self.archive_path(version);
+        match std::fs::read(&archive_path) {
+            Ok(archive) => match self.unpack(&archive, &dir) {
+                Ok(()) => {
+                    self.emit(ProvisionEvent::Done);
+                    return Ok(dir);
+                }
+                Err(e) => log::warn!("Downloading brouter {} again: {}", version, e),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        info!("Downloading brouter {}", version);
+        let url = release_url_at(&self.source, version);
+        let response = self.client.get(&url).send()?.error_for_status()?;
+        let size = response.content_length();
+        let mut transfer = crate::transfer::Transfer::new(
+            response,
+            &url,
+            size,
+            self.events.as_ref(),
+            self.throttle.as_deref(),
+        );
+        let mut archive = vec![];
+        transfer.read_to_end(&mut archive)?;
+        transfer.finish();
+        self.unpack(&archive, &dir)?;
+        let partial = archive_path.with_extension("zip.part");
+        std::fs::write(&partial, &archive)?;
+        std::fs::rename(&partial, &archive_path)?;
+        self.emit(ProvisionEvent::Done);
+        Ok(dir)
+    }
+
+    fn unpack(&self, archive: &[u8], dir: &Path) -> Result<(), Error> {
+        let files = unpack_release(archive, dir)?;
+        self.emit(ProvisionEvent::Extracted {
+            dir: dir.to_path_buf(),
+            files,
+        });
+        Ok(())
+    }
+
+    fn emit(&self, event: ProvisionEvent) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event);
+        }
+    }
+}
+
+/// A running local brouter server, stopped when dropped.
+#[derive(Debug)]
+pub struct BrouterServer {
+    child: Child,
+    address: SocketAddr,
+    builder: BrouterServerBuilder,
+}
+
+impl BrouterServer {
+    /// The URL of the server, e.g. `http://127.0.0.1:17777/`.
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.url_address())
+    }
+
+    /// A client for the server, with access to its profile and segment directories.
+    ///
+    /// The client waits a little longer than the server works on a request (see
+    /// [`BrouterServerBuilder::max_running_time`]), so that the server reports requests that
+    /// take too long.
+    ///
+    /// The client opens a new connection for every request rather than keeping idle ones
+    /// around, which costs little on a local server and means it keeps working after
+    /// [`BrouterServer::restart`].
+    pub fn client(&self) -> Brouter {
+        let client = reqwest::blocking::Client::builder()
+            .pool_max_idle_per_host(0)
+            .build()
+            .expect("failed to initialize the HTTP client");
+        Brouter::builder(&self.url())
+            .client(client)
+            .profiles_dir(&self.builder.profiles_dir)
+            .custom_profiles_dir(&self.builder.custom_profiles_dir)
+            .segments_dir(&self.builder.segments_dir)
+            .timeout(self.builder.max_running_time + CLIENT_TIMEOUT_MARGIN)
+            .build()
+    }
+
+    /// An asynchronous client for the server, with the timeout and connection handling of
+    /// [`BrouterServer::client`].
+    #[cfg(feature = "async")]
+    pub fn async_client(&self) -> crate::async_client::AsyncBrouter {
+        let client = reqwest::Client::builder()
+            .pool_max_idle_per_host(0)
+            .build()
+            .expect("failed to initialize the HTTP client");
+        crate::async_client::AsyncBrouter::builder(&self.url())
+            .client(client)
+            .timeout(self.builder.max_running_time + CLIENT_TIMEOUT_MARGIN)
+            .build()
+    }
+
+    /// Restart the server, e.g. after it has crashed or to pick up new segment files, and wait
+    /// until it accepts connections again.
+    ///
+    /// The server comes back on the same port, so clients from [`BrouterServer::client`] can
+    /// keep being used.
+    pub fn restart(&mut self) -> Result<(), Error> {
+        self.kill()?;
+        info!("Restarting brouter on {}", self.address);
+        let mut server = self.builder.spawn()?;
+        server.wait_until_listening(STARTUP_TIMEOUT)?;
+        // The old process has exited, so dropping it leaves the new one running.
+        *self = server;
+        Ok(())
+    }
+
+    /// The brouter version the server runs, e.g. "1.7.8", from the name of its jar as in
+    /// brouter's releases, e.g. `brouter-1.7.8-all.jar`; `None` for jars named otherwise.
+    ///
+    /// Unlike [`Brouter::server_info`], this needs no routing request.
+    pub fn version(&self) -> Option<&str> {
+        let name = self.builder.jar.file_name()?.to_str()?;
+        lazy_regex::regex_captures!(r"^brouter-(\d[0-9A-Za-z.]*?)(?:-all)?\.jar$", name)
+            .map(|(_, version)| version)
+    }
+
+    /// The process id of the JVM.
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Stop the server and wait for it to exit.
+    pub fn stop(mut self) -> Result<(), Error> {
+        self.kill()
+    }
+
+    fn kill(&mut self) -> Result<(), Error> {
+        if self.child.try_wait()?.is_none() {
+            self.child.kill()?;
+            self.child.wait()?;
+        }
+        Ok(())
+    }
+
+    fn wait_until_listening(&mut self, timeout: Duration) -> Result<(), Error> {
+        let start = Instant::now();
+        let address = self.url_address();
+        loop {
+            self.check_running()?;
+            if TcpStream::connect_timeout(&address, CONNECT_ATTEMPT_TIMEOUT).is_ok() {
+                return Ok(());
+            }
+            self.check_startup_time(start, timeout)?;
+            std::thread::sleep(CONNECT_ATTEMPT_INTERVAL);
+        }
+    }
+
+    #[cfg(feature = "async")]
+    async fn wait_until_listening_async(&mut self, timeout: Duration) -> Result<(), Error> {
+        let start = Instant::now();
+        let address = self.url_address();
+        loop {
+            self.check_running()?;
+            let attempt = tokio::net::TcpStream::connect(address);
+            if let Ok(Ok(_)) = tokio::time::timeout(CONNECT_ATTEMPT_TIMEOUT, attempt).await {
+                return Ok(());
+            }
+            self.check_startup_time(start, timeout)?;
+            tokio::time::sleep(CONNECT_ATTEMPT_INTERVAL).await;
+        }
+    }
+
+    /// Fail if the JVM has exited.
+    fn check_running(&mut self) -> Result<(), Error> {
+        match self.child.try_wait()? {
+            Some(status) => Err(Error::NotServing {
+                address: self.address,
+                reason: format!("exited on startup: {}", status),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Stop the server and fail if it has not come up within `timeout` since `start`.
+    fn check_startup_time(&mut self, start: Instant, timeout: Duration) -> Result<(), Error> {
+        if start.elapsed() <= timeout {
+            return Ok(());
+        }
+        let _ = self.kill();
+        Err(Error::NotServing {
+            address: self.url_address(),
+            reason: format!(
+                "no connections accepted within {} seconds",
+                timeout.as_secs()
+            ),
+        })
+    }
+
+    /// The address to connect to, which differs from the bound one for wildcard addresses.
+    fn url_address(&self) -> SocketAddr {
+        match self.address.ip() {
+            ip if ip.is_unspecified() => {
+                SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), self.address.port())
+            }
+            _ => self.address,
+        }
+    }
+}
+
+impl Drop for BrouterServer {
+    fn drop(&mut self) {
+        let _ = self.kill();
+    }
+}
@@ -0,0 +1,470 @@
+//! Persistent storage for calculated routes.
+//!
+//! Routes are stored in a SQLite database together with their summary, the request they were
+//! calculated from, a name, tags and timestamps. A [`Journal`] records the progress of batch
+//! runs, so that they can be resumed.
+
+use crate::export::GpxWriter;
+use crate::{Brouter, Route, RouteRequest, RouteSummary, TurnInstructionMode};
+use rusqlite::{params, Connection, OptionalExtension, ToSql};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug)]
+pub enum Error {
+    Sqlite(rusqlite::Error),
+    Serde(serde_json::Error),
+    Route(crate::Error),
+    Io(std::io::Error),
+    NotFound(i64),
+    /// The route was stored without the request it was calculated from.
+    MissingRequest(i64),
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Sqlite(e) => write!(f, "SQLite error: {}", e),
+            Error::Serde(e) => write!(f, "Serialization error: {}", e),
+            Error::Route(e) => write!(f, "{}", e),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::NotFound(id) => write!(f, "No stored route with id {}", id),
+            Error::MissingRequest(id) => write!(f, "Stored route {} has no request", id),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Sqlite(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Serde(e)
+    }
+}
+
+impl From<crate::Error> for Error {
+    fn from(e: crate::Error) -> Self {
+        Error::Route(e)
+    }
+}
+
+/// Metadata of a stored route.
+#[derive(Debug, Clone)]
+pub struct StoredRoute {
+    pub id: i64,
+    pub name: Option<String>,
+    pub tags: Vec<String>,
+    pub created: SystemTime,
+    pub updated: SystemTime,
+    pub request: Option<RouteRequest>,
+    pub summary: RouteSummary,
+}
+
+/// Criteria for finding stored routes; all set criteria have to match.
+#[derive(Debug, Clone, Default)]
+pub struct RouteQuery {
+    tag: Option<String>,
+    name: Option<String>,
+    since: Option<SystemTime>,
+    until: Option<SystemTime>,
+    limit: Option<usize>,
+}
+
+impl RouteQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.to_string());
+        self
+    }
+
+    /// Only match routes whose name contains `name`.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Only match routes created at or after `since`.
+    pub fn since(mut self, since: SystemTime) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Only match routes created before `until`.
+    pub fn until(mut self, until: SystemTime) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+fn to_timestamp(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+fn from_timestamp(timestamp: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(timestamp.max(0) as u64)
+}
+
+fn to_gpx(route: &Route) -> Result<Vec<u8>, Error> {
+    // Turn instructions are kept as plain waypoints, so that they survive the round trip
+    // without relying on extensions.
+    let mut writer = GpxWriter::new();
+    if !route.turn_instructions().is_empty() {
+        writer = writer.turn_instructions(TurnInstructionMode::GpsiesStyle);
+    }
+    writer.to_vec(route).map_err(Error::Io)
+}
+
+/// A SQLite-backed store of routes.
+pub struct RouteStore {
+    conn: Connection,
+}
+
+impl RouteStore {
+    /// Open (or create) a route store at `path`.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self, Error> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS routes (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT,
+                created INTEGER NOT NULL,
+                updated INTEGER NOT NULL,
+                request TEXT,
+                summary TEXT NOT NULL,
+                gpx BLOB NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS tags (
+                route_id INTEGER NOT NULL,
+                tag TEXT NOT NULL,
+                PRIMARY KEY (route_id, tag)
+            );",
+        )?;
+        Ok(RouteStore { conn })
+    }
+
+    /// Store a route, returning its id.
+    pub fn save(
+        &self,
+        route: &Route,
+        request: Option<&RouteRequest>,
+        name: Option<&str>,
+        tags: &[&str],
+    ) -> Result<i64, Error> {
+        let now = to_timestamp(SystemTime::now());
+        let request = request.map(serde_json::to_string).transpose()?;
+        self.conn.execute(
+            "INSERT INTO routes (name, created, updated, request, summary, gpx)
+             VALUES (?1, ?2, ?2, ?3, ?4, ?5)",
+            params![
+                name,
+                now,
+                request,
+                serde_json::to_string(&route.summary())?,
+                to_gpx(route)?
+            ],
+        )?;
+        let id = self.conn.last_insert_rowid();
+        for tag in tags {
+            self.add_tag(id, tag)?;
+        }
+        Ok(id)
+    }
+
+    pub fn add_tag(&self, id: i64, tag: &str) -> Result<(), Error> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO tags (route_id, tag) VALUES (?1, ?2)",
+            params![id, tag],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_tag(&self, id: i64, tag: &str) -> Result<(), Error> {
+        self.conn.execute(
+            "DELETE FROM tags WHERE route_id = ?1 AND tag = ?2",
+            params![id, tag],
+        )?;
+        Ok(())
+    }
+
+    fn tags(&self, id: i64) -> Result<Vec<String>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT tag FROM tags WHERE route_id = ?1 ORDER BY tag")?;
+        let tags = stmt
+            .query_map(params![id], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(tags)
+    }
+
+    /// Retrieve the metadata of a stored route.
+    pub fn get(&self, id: i64) -> Result<Option<StoredRoute>, Error> {
+        let row = self
+            .conn
+            .query_row(
+                "SELECT name, created, updated, request, summary FROM routes WHERE id = ?1",
+                params![id],
+                |row| {
+                    Ok((
+                        row.get::<_, Option<String>>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, String>(4)?,
+                    ))
+                },
+            )
+            .optional()?;
+
+        let (name, created, updated, request, summary) = match row {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        Ok(Some(StoredRoute {
+            id,
+            name,
+            tags: self.tags(id)?,
+            created: from_timestamp(created),
+            updated: from_timestamp(updated),
+            request: request.as_deref().map(serde_json::from_str).transpose()?,
+            summary: serde_json::from_str(&summary)?,
+        }))
+    }
+
+    /// Load a stored route.
+    pub fn load(&self, id: i64) -> Result<Route, Error> {
+        let gpx: Vec<u8> = self
+            .conn
+            .query_row("SELECT gpx FROM routes WHERE id = ?1", params![id], |row| {
+                row.get(0)
+            })
+            .optional()?
+            .ok_or(Error::NotFound(id))?;
+        Ok(Route::from_gpx_bytes(&gpx)?)
+    }
+
+    /// Find stored routes matching `query`, most recent first.
+    pub fn find(&self, query: &RouteQuery) -> Result<Vec<StoredRoute>, Error> {
+        let mut sql = "SELECT id FROM routes WHERE 1 = 1".to_string();
+        let mut args: Vec<Box<dyn ToSql>> = vec![];
+
+        if let Some(tag) = &query.tag {
+            args.push(Box::new(tag.clone()));
+            sql.push_str(&format!(
+                " AND id IN (SELECT route_id FROM tags WHERE tag = ?{})",
+                args.len()
+            ));
+        }
+        if let Some(name) = &query.name {
+            args.push(Box::new(format!("%{}%", name)));
+            sql.push_str(&format!(" AND name LIKE ?{}", args.len()));
+        }
+        if let Some(since) = query.since {
+            args.push(Box::new(to_timestamp(since)));
+            sql.push_str(&format!(" AND created >= ?{}", args.len()));
+        }
+        if let Some(until) = query.until {
+            args.push(Box::new(to_timestamp(until)));
+            sql.push_str(&format!(" AND created < ?{}", args.len()));
+        }
+        sql.push_str(" ORDER BY created DESC, id DESC");
+        if let Some(limit) = query.limit {
+            sql.push_str(&format!(" LIMIT {}", limit));
+        }
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let ids = stmt
+            .query_map(
+                rusqlite::params_from_iter(args.iter().map(|a| a.as_ref())),
+                |row| row.get::<_, i64>(0),
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        ids.into_iter()
+            .filter_map(|id| self.get(id).transpose())
+            .collect()
+    }
+
+    pub fn delete(&self, id: i64) -> Result<(), Error> {
+        self.conn
+            .execute("DELETE FROM tags WHERE route_id = ?1", params![id])?;
+        if self
+            .conn
+            .execute("DELETE FROM routes WHERE id = ?1", params![id])?
+            == 0
+        {
+            return Err(Error::NotFound(id));
+        }
+        Ok(())
+    }
+
+    /// Calculate a stored route again from its original request, and update the stored route.
+    pub fn rerun(&self, id: i64, brouter: &Brouter) -> Result<Route, Error> {
+        let stored = self.get(id)?.ok_or(Error::NotFound(id))?;
+        let request = stored.request.ok_or(Error::MissingRequest(id))?;
+        let route = brouter.request_route(&request)?;
+        self.conn.execute(
+            "UPDATE routes SET updated = ?1, summary = ?2, gpx = ?3 WHERE id = ?4",
+            params![
+                to_timestamp(SystemTime::now()),
+                serde_json::to_string(&route.summary())?,
+                to_gpx(&route)?,
+                id
+            ],
+        )?;
+        Ok(route)
+    }
+
+    /// Write a stored route as GPX.
+    pub fn export(
+        &self,
+        id: i64,
+        writer: &GpxWriter,
+        out: impl std::io::Write,
+    ) -> Result<(), Error> {
+        let route = self.load(id)?;
+        writer.write(&route, out).map_err(Error::Io)
+    }
+}
+
+/// The state of a request in a [`Journal`].
+#[derive(Debug)]
+pub enum JournalEntry {
+    /// The request was started, but didn't finish, e.g. because the process crashed.
+    Pending,
+    Done(Box<Route>),
+    /// The request failed, with the error message.
+    Failed(String),
+}
+
+/// A record of the requests of a batch run and their outcomes, so that a run that was
+/// interrupted can resume where it left off.
+///
+/// Requests are identified by their [`RouteRequest::canonical_string`], so equivalent requests
+/// share an entry. Running the same batch again skips requests that completed or failed, and
+/// routes those that never finished.
+pub struct Journal {
+    conn: Connection,
+}
+
+impl Journal {
+    /// Open (or create) a journal at `path`.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self, Error> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS journal (
+                request TEXT PRIMARY KEY,
+                started INTEGER NOT NULL,
+                finished INTEGER,
+                gpx BLOB,
+                error TEXT
+            );",
+        )?;
+        Ok(Journal { conn })
+    }
+
+    /// The recorded state of `request`, if it was ever started.
+    pub fn entry(&self, request: &RouteRequest) -> Result<Option<JournalEntry>, Error> {
+        let row = self
+            .conn
+            .query_row(
+                "SELECT finished, gpx, error FROM journal WHERE request = ?1",
+                params![request.canonical_string()],
+                |row| {
+                    Ok((
+                        row.get::<_, Option<i64>>(0)?,
+                        row.get::<_, Option<Vec<u8>>>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                    ))
+                },
+            )
+            .optional()?;
+
+        Ok(match row {
+            None => None,
+            Some((None, _, _)) => Some(JournalEntry::Pending),
+            Some((Some(_), _, Some(error))) => Some(JournalEntry::Failed(error)),
+            Some((Some(_), gpx, None)) => Some(JournalEntry::Done(Box::new(
+                Route::from_gpx_bytes(&gpx.unwrap_or_default())?,
+            ))),
+        })
+    }
+
+    /// Route all requests that haven't completed or failed yet, recording each outcome as soon
+    /// as it is known.
+    ///
+    /// Failed requests are recorded rather than aborting the run. Returns the number of
+    /// requests that were routed.
+    pub fn run<'a>(
+        &self,
+        brouter: &Brouter,
+        requests: impl IntoIterator<Item = &'a RouteRequest>,
+    ) -> Result<usize, Error> {
+        let mut count = 0;
+        for request in requests {
+            let key = request.canonical_string();
+            let finished: Option<Option<i64>> = self
+                .conn
+                .query_row(
+                    "SELECT finished FROM journal WHERE request = ?1",
+                    params![key],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(Some(_)) = finished {
+                continue;
+            }
+
+            self.conn.execute(
+                "INSERT OR REPLACE INTO journal (request, started) VALUES (?1, ?2)",
+                params![key, to_timestamp(SystemTime::now())],
+            )?;
+            let (gpx, error) = match brouter.request_route(request) {
+                Ok(route) => (Some(to_gpx(&route)?), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            self.conn.execute(
+                "UPDATE journal SET finished = ?1, gpx = ?2, error = ?3 WHERE request = ?4",
+                params![to_timestamp(SystemTime::now()), gpx, error, key],
+            )?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Forget failed requests, so that the next run retries them.
+    pub fn clear_failures(&self) -> Result<usize, Error> {
+        Ok(self
+            .conn
+            .execute("DELETE FROM journal WHERE error IS NOT NULL", [])?)
+    }
+}
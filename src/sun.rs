@@ -0,0 +1,149 @@
+//! Sunrise, sunset and darkness along a route.
+//!
+//! The position of the sun is calculated with the low-precision formulas of the Astronomical
+//! Almanac, which are accurate to about a minute for sunrise and sunset.
+
+use crate::{Point, Route};
+use geo::{Distance, Haversine};
+use std::time::Duration;
+use time::OffsetDateTime;
+
+/// Elevation of the center of the sun at sunrise and sunset, in degrees, allowing for
+/// refraction and the radius of the sun.
+const HORIZON: f64 = -0.833;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SunEventKind {
+    Sunrise,
+    Sunset,
+}
+
+/// The sun rising or setting while on the route.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SunEvent {
+    pub kind: SunEventKind,
+    #[cfg_attr(feature = "serde", serde(with = "time::serde::rfc3339"))]
+    pub time: OffsetDateTime,
+    /// Distance along the route, in meters.
+    pub distance_m: f64,
+    pub point: Point,
+}
+
+/// The result of [`Route::sun_events`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SunEvents {
+    /// Whether the route starts in darkness.
+    pub starts_dark: bool,
+    pub events: Vec<SunEvent>,
+    /// Distance ridden between sunset and sunrise, in meters.
+    pub dark_distance_m: f64,
+    /// Time spent between sunset and sunrise.
+    pub dark_duration: Duration,
+}
+
+/// Elevation of the sun above the horizon at `point` and `time`, in degrees.
+pub fn sun_elevation(point: &Point, time: OffsetDateTime) -> f64 {
+    // Days since J2000.0.
+    let n = (time.unix_timestamp_nanos() as f64 / 1e9) / 86400.0 + 2440587.5 - 2451545.0;
+    let mean_longitude = (280.460 + 0.9856474 * n).rem_euclid(360.0);
+    let mean_anomaly = (357.528 + 0.9856003 * n).rem_euclid(360.0).to_radians();
+    let ecliptic_longitude =
+        (mean_longitude + 1.915 * mean_anomaly.sin() + 0.020 * (2.0 * mean_anomaly).sin())
+            .to_radians();
+    let obliquity = (23.439 - 0.0000004 * n).to_radians();
+
+    let right_ascension = (obliquity.cos() * ecliptic_longitude.sin())
+        .atan2(ecliptic_longitude.cos())
+        .to_degrees();
+    let declination = (obliquity.sin() * ecliptic_longitude.sin()).asin();
+    let sidereal_time = (18.697374558 + 24.06570982441908 * n).rem_euclid(24.0) * 15.0;
+    let hour_angle = (sidereal_time + point.lon() - right_ascension).to_radians();
+
+    let lat = point.lat().to_radians();
+    (lat.sin() * declination.sin() + lat.cos() * declination.cos() * hour_angle.cos())
+        .asin()
+        .to_degrees()
+}
+
+impl Route {
+    /// Find where the sun rises and sets along the route when starting at `start`, and how
+    /// much of it is ridden in darkness.
+    ///
+    /// This uses the times of the route (see [`Route::time_at`]), so it returns `None` for
+    /// routes without times.
+    pub fn sun_events(&self, start: OffsetDateTime) -> Option<SunEvents> {
+        let profile = self.time_profile();
+        if profile.len() < 2 {
+            return None;
+        }
+
+        // Distance, time and sun elevation above the horizon at each track point.
+        let mut samples: Vec<(f64, f64, f64, Point)> = vec![];
+        let mut along = 0.0;
+        let mut prev: Option<geo_types::Point<f64>> = None;
+        let mut index = 0;
+        for p in self.points() {
+            if let Some(prev) = prev {
+                along += Haversine.distance(prev, p.point());
+            }
+            prev = Some(p.point());
+            while index + 2 < profile.len() && profile[index + 1].0 < along {
+                index += 1;
+            }
+            let ((d0, t0), (d1, t1)) = (profile[index], profile[index + 1]);
+            if along < d0 || along > d1 {
+                continue;
+            }
+            let t = if d1 > d0 {
+                t0 + (t1 - t0) * (along - d0) / (d1 - d0)
+            } else {
+                t0
+            };
+            let point = Point::from(p.point());
+            let time = start + Duration::from_secs_f64(t);
+            samples.push((along, t, sun_elevation(&point, time) - HORIZON, point));
+        }
+
+        let starts_dark = samples.first()?.2 < 0.0;
+        let mut events = vec![];
+        let (mut dark_distance_m, mut dark_s) = (0.0, 0.0);
+        for w in samples.windows(2) {
+            let ((d0, t0, e0, p0), (d1, t1, e1, p1)) = (&w[0], &w[1]);
+            if *e0 < 0.0 && *e1 < 0.0 {
+                dark_distance_m += d1 - d0;
+                dark_s += t1 - t0;
+            } else if (*e0 < 0.0) != (*e1 < 0.0) {
+                let f = e0 / (e0 - e1);
+                let (d, t) = (d0 + f * (d1 - d0), t0 + f * (t1 - t0));
+                let kind = if *e0 < 0.0 {
+                    dark_distance_m += d - d0;
+                    dark_s += t - t0;
+                    SunEventKind::Sunrise
+                } else {
+                    dark_distance_m += d1 - d;
+                    dark_s += t1 - t;
+                    SunEventKind::Sunset
+                };
+                events.push(SunEvent {
+                    kind,
+                    time: start + Duration::from_secs_f64(t),
+                    distance_m: d,
+                    point: Point::new(
+                        p0.lat() + f * (p1.lat() - p0.lat()),
+                        p0.lon() + f * (p1.lon() - p0.lon()),
+                    ),
+                });
+            }
+        }
+
+        Some(SunEvents {
+            starts_dark,
+            events,
+            dark_distance_m,
+            dark_duration: Duration::from_secs_f64(dark_s),
+        })
+    }
+}
@@ -0,0 +1,246 @@
+//! Splitting long routes into daily stages.
+//!
+//! A route is split into days that stay within a maximum distance and ascent, or into a given
+//! number of days of roughly equal length. Day ends can be moved to nearby accommodation, taken
+//! from the waypoints of the route.
+
+use crate::Route;
+use geo::{Distance, Haversine};
+
+/// Waypoint types and symbols that mark accommodation.
+const ACCOMMODATION: &[&str] = &[
+    "accommodation",
+    "hotel",
+    "hostel",
+    "guest_house",
+    "camp_site",
+    "campground",
+    "lodging",
+    "alpine_hut",
+];
+
+/// How far accommodation can be from the route, in meters.
+const MAX_ACCOMMODATION_OFF_ROUTE_M: f64 = 500.0;
+
+/// One day of a tour.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stage {
+    /// Day number, starting at 1.
+    pub day: usize,
+
+    /// Distance from the start of the tour to the start of the day, in meters.
+    pub start_m: f64,
+
+    /// Distance from the start of the tour to the end of the day, in meters.
+    pub end_m: f64,
+
+    pub ascent_m: f64,
+
+    /// Name of the accommodation at the end of the day, if the day end was moved there.
+    pub accommodation: Option<String>,
+
+    /// Index of the first track point of the day.
+    pub first_point: usize,
+
+    /// Index of the last track point of the day, which is also the first of the next day.
+    pub last_point: usize,
+}
+
+impl Stage {
+    pub fn distance_m(&self) -> f64 {
+        self.end_m - self.start_m
+    }
+}
+
+fn is_accommodation(waypoint: &gpx::Waypoint) -> bool {
+    [waypoint.type_.as_deref(), waypoint.symbol.as_deref()]
+        .into_iter()
+        .flatten()
+        .any(|t| ACCOMMODATION.contains(&t.to_lowercase().replace(' ', "_").as_str()))
+}
+
+/// Splits routes into daily stages.
+#[derive(Debug, Clone, Default)]
+pub struct TourPlanner {
+    days: Option<usize>,
+    max_distance_m: Option<f64>,
+    max_ascent_m: Option<f64>,
+    accommodation_m: Option<f64>,
+}
+
+impl TourPlanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Aim for `days` days of equal length.
+    ///
+    /// More days are planned if the maximum daily distance or ascent requires it. A tour
+    /// takes at least a day, so 0 leaves the number of days open.
+    pub fn days(mut self, days: usize) -> Self {
+        self.days = (days > 0).then_some(days);
+        self
+    }
+
+    pub fn max_daily_distance_m(mut self, distance_m: f64) -> Self {
+        self.max_distance_m = Some(distance_m);
+        self
+    }
+
+    pub fn max_daily_ascent_m(mut self, ascent_m: f64) -> Self {
+        self.max_ascent_m = Some(ascent_m);
+        self
+    }
+
+    /// Move day ends to accommodation within `distance_m` meters along the route, as long as
+    /// the daily maximums allow it.
+    ///
+    /// Accommodation are the waypoints of the route with a type or symbol such as "hotel" or
+    /// "campground" that lie within 500 m of the route.
+    pub fn snap_to_accommodation(mut self, distance_m: f64) -> Self {
+        self.accommodation_m = Some(distance_m);
+        self
+    }
+
+    /// Split `route` into stages.
+    pub fn plan(&self, route: &Route) -> Vec<Stage> {
+        // Cumulative distance and ascent at each track point.
+        let mut profile = vec![];
+        let (mut distance, mut ascent) = (0.0, 0.0);
+        let mut prev: Option<&gpx::Waypoint> = None;
+        for p in route.points() {
+            if let Some(prev) = prev {
+                distance += Haversine.distance(prev.point(), p.point());
+                if let (Some(a), Some(b)) = (prev.elevation, p.elevation) {
+                    ascent += (b - a).max(0.0);
+                }
+            }
+            profile.push((distance, ascent));
+            prev = Some(p);
+        }
+        if profile.len() < 2 {
+            return vec![];
+        }
+
+        let accommodation = route
+            .gpx()
+            .waypoints
+            .iter()
+            .filter(|w| is_accommodation(w))
+            .filter_map(|w| {
+                let (_, off_route, along) = route.nearest_point(&w.point().into())?;
+                if off_route > MAX_ACCOMMODATION_OFF_ROUTE_M {
+                    return None;
+                }
+                let index = profile.partition_point(|(d, _)| *d < along);
+                Some((index.min(profile.len() - 1), w.name.clone()))
+            })
+            .collect::<Vec<_>>();
+
+        let target_m = self.days.map(|days| distance / days as f64);
+        let mut stages = vec![];
+        let mut first = 0;
+        while first < profile.len() - 1 {
+            let (start_m, start_ascent) = profile[first];
+            let fits = |i: usize| {
+                let (d, a) = profile[i];
+                self.max_distance_m.is_none_or(|max| d - start_m <= max)
+                    && self.max_ascent_m.is_none_or(|max| a - start_ascent <= max)
+            };
+            let last_day = self.days.is_some_and(|days| stages.len() + 1 >= days);
+
+            // The furthest point that the day can end at, but always make progress.
+            let mut last = (first + 1..profile.len())
+                .take_while(|i| fits(*i))
+                .last()
+                .unwrap_or(first + 1);
+            if let (Some(target_m), false) = (target_m, last_day) {
+                let target = profile.partition_point(|(d, _)| *d - start_m < target_m);
+                last = last.min(target.max(first + 1));
+            }
+
+            let mut name = None;
+            if let (Some(window_m), true) = (self.accommodation_m, last < profile.len() - 1) {
+                let end_m = profile[last].0;
+                let nearest = accommodation
+                    .iter()
+                    .filter(|(i, _)| *i > first && fits(*i))
+                    .filter(|(i, _)| (profile[*i].0 - end_m).abs() <= window_m)
+                    .min_by(|a, b| {
+                        let a = (profile[a.0].0 - end_m).abs();
+                        let b = (profile[b.0].0 - end_m).abs();
+                        a.total_cmp(&b)
+                    });
+                if let Some((i, n)) = nearest {
+                    last = *i;
+                    name = n.clone();
+                }
+            }
+
+            stages.push(Stage {
+                day: stages.len() + 1,
+                start_m,
+                end_m: profile[last].0,
+                ascent_m: profile[last].1 - start_ascent,
+                accommodation: name,
+                first_point: first,
+                last_point: last,
+            });
+            first = last;
+        }
+        stages
+    }
+
+    /// One GPX document per stage, each with the track and waypoints of that day.
+    pub fn split(&self, route: &Route) -> Vec<gpx::Gpx> {
+        let points = route.points().cloned().collect::<Vec<_>>();
+        self.plan(route)
+            .iter()
+            .map(|stage| {
+                let range = stage.first_point..=stage.last_point;
+                let mut track = gpx::Track::new();
+                track.name = Some(format!("Day {}", stage.day));
+                track.segments.push(gpx::TrackSegment {
+                    points: points[range.clone()].to_vec(),
+                });
+                let waypoints = route
+                    .gpx()
+                    .waypoints
+                    .iter()
+                    .filter(|w| {
+                        route
+                            .nearest_point(&w.point().into())
+                            .is_some_and(|(_, _, along)| {
+                                along >= stage.start_m && along <= stage.end_m
+                            })
+                    })
+                    .cloned()
+                    .collect();
+                gpx::Gpx {
+                    version: gpx::GpxVersion::Gpx11,
+                    tracks: vec![track],
+                    waypoints,
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+
+    /// The whole route as a single GPX document, with a waypoint at the end of each day.
+    pub fn overview(&self, route: &Route) -> gpx::Gpx {
+        let points = route.points().cloned().collect::<Vec<_>>();
+        let mut gpx = route.gpx().clone();
+        let stages = self.plan(route);
+        let count = stages.len();
+        for stage in stages.into_iter().filter(|s| s.day < count) {
+            let mut waypoint = gpx::Waypoint::new(points[stage.last_point].point());
+            waypoint.name = Some(match stage.accommodation {
+                Some(name) => format!("End of day {}: {}", stage.day, name),
+                None => format!("End of day {}", stage.day),
+            });
+            gpx.waypoints.push(waypoint);
+        }
+        gpx
+    }
+}
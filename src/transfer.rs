@@ -0,0 +1,150 @@
+//! Reading downloads while reporting their progress and limiting their bandwidth.
+
+use crate::server::ProvisionEvent;
+use reqwest::blocking::Client;
+use std::io::Read;
+use std::sync::mpsc::Sender;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// How many bytes arrive between [`ProvisionEvent::BytesTransferred`] events.
+const REPORT_INTERVAL: u64 = 256 * 1024;
+
+/// How long to wait for a connection to the server of a download.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often to check that the server of a download is still there, so that downloads from a
+/// server that went away fail rather than wait forever.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The client to download segment files and releases with by default.
+///
+/// reqwest's default timeout covers reading the whole response, which large files on a slow
+/// link can take much longer than; so only connecting has a time limit.
+pub(crate) fn client() -> Client {
+    Client::builder()
+        .timeout(None)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .tcp_keepalive(KEEPALIVE_INTERVAL)
+        .build()
+        .expect("failed to initialize the HTTP client")
+}
+
+/// A limit on the bandwidth of downloads, shared by all downloads that use it.
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    bytes_per_second: u64,
+    /// When the bytes read so far have been transferred at the limit.
+    until: Mutex<Instant>,
+}
+
+impl Throttle {
+    pub(crate) fn new(bytes_per_second: u64) -> Self {
+        Throttle {
+            bytes_per_second: bytes_per_second.max(1),
+            until: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// The most to read at once, so that reads don't come in bursts: a tenth of a second's
+    /// worth.
+    fn chunk_size(&self) -> usize {
+        (self.bytes_per_second / 10).max(1) as usize
+    }
+
+    /// Wait until `n` more bytes can have been transferred without exceeding the limit.
+    fn wait(&self, n: usize) {
+        let duration = Duration::from_secs_f64(n as f64 / self.bytes_per_second as f64);
+        let until = {
+            let mut until = self.until.lock().unwrap_or_else(PoisonError::into_inner);
+            *until = (*until).max(Instant::now()) + duration;
+            *until
+        };
+        std::thread::sleep(until.saturating_duration_since(Instant::now()));
+    }
+}
+
+/// A download being read from `inner`, reporting its progress to `events` and keeping to
+/// `throttle`.
+pub(crate) struct Transfer<'a, R> {
+    inner: R,
+    url: String,
+    size: Option<u64>,
+    transferred: u64,
+    reported: u64,
+    events: Option<&'a Sender<ProvisionEvent>>,
+    throttle: Option<&'a Throttle>,
+}
+
+impl<'a, R: Read> Transfer<'a, R> {
+    /// Start reading the download of `url`, of `size` bytes if known.
+    pub(crate) fn new(
+        inner: R,
+        url: &str,
+        size: Option<u64>,
+        events: Option<&'a Sender<ProvisionEvent>>,
+        throttle: Option<&'a Throttle>,
+    ) -> Self {
+        let transfer = Transfer {
+            inner,
+            url: url.to_string(),
+            size,
+            transferred: 0,
+            reported: 0,
+            events,
+            throttle,
+        };
+        transfer.emit(ProvisionEvent::DownloadStarted {
+            url: transfer.url.clone(),
+            size,
+        });
+        transfer
+    }
+
+    /// Report that the download is complete, returning its size.
+    pub(crate) fn finish(mut self) -> u64 {
+        if self.reported < self.transferred {
+            self.report();
+        }
+        self.emit(ProvisionEvent::DownloadFinished {
+            url: self.url.clone(),
+            size: self.transferred,
+        });
+        self.transferred
+    }
+
+    fn report(&mut self) {
+        self.reported = self.transferred;
+        self.emit(ProvisionEvent::BytesTransferred {
+            url: self.url.clone(),
+            transferred: self.transferred,
+            size: self.size,
+        });
+    }
+
+    fn emit(&self, event: ProvisionEvent) {
+        if let Some(events) = self.events {
+            // Nobody may be listening anymore, which is no reason to stop downloading.
+            let _ = events.send(event);
+        }
+    }
+}
+
+impl<R: Read> Read for Transfer<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = match self.throttle {
+            Some(throttle) => {
+                let len = buf.len().min(throttle.chunk_size());
+                let n = self.inner.read(&mut buf[..len])?;
+                throttle.wait(n);
+                n
+            }
+            None => self.inner.read(buf)?,
+        };
+        self.transferred += n as u64;
+        if self.transferred - self.reported >= REPORT_INTERVAL {
+            self.report();
+        }
+        Ok(n)
+    }
+}
@@ -0,0 +1,158 @@
+//! Reading and writing points and nogos as WKT (well-known text).
+//!
+//! Only the `POINT`, `LINESTRING` and `POLYGON` geometry types are supported. Extended WKT as
+//! returned by PostGIS (`SRID=4326;POINT(...)`) is accepted; coordinates are assumed to be
+//! WGS84 longitude and latitude, and any Z or M values are ignored.
+
+use crate::{Nogo, Point};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WktError {
+    pub message: String,
+}
+
+impl std::error::Error for WktError {}
+
+impl fmt::Display for WktError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid WKT: {}", self.message)
+    }
+}
+
+fn error<T>(message: impl Into<String>) -> Result<T, WktError> {
+    Err(WktError {
+        message: message.into(),
+    })
+}
+
+enum Geometry {
+    Point(Point),
+    LineString(Vec<Point>),
+    Polygon(Vec<Vec<Point>>),
+}
+
+fn coordinate(text: &str) -> Result<Point, WktError> {
+    let mut values = text.split_whitespace().map(|v| v.parse::<f64>());
+    match (values.next(), values.next()) {
+        (Some(Ok(lon)), Some(Ok(lat))) => Ok(Point::new(lat, lon)),
+        _ => error(format!("invalid coordinate '{}'", text.trim())),
+    }
+}
+
+/// The text between the outer parentheses of `text`.
+fn parenthesized(text: &str) -> Result<&str, WktError> {
+    let text = text.trim();
+    match text.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+        Some(inner) => Ok(inner),
+        None => error(format!("expected parentheses around '{}'", text)),
+    }
+}
+
+fn coordinates(text: &str) -> Result<Vec<Point>, WktError> {
+    parenthesized(text)?.split(',').map(coordinate).collect()
+}
+
+fn parse(text: &str) -> Result<Geometry, WktError> {
+    let text = text.trim();
+    // Strip the SRID of extended WKT.
+    let text = match text.split_once(';') {
+        Some((srid, rest)) if srid.trim().to_uppercase().starts_with("SRID=") => rest.trim(),
+        _ => text,
+    };
+    let start = text.find('(').unwrap_or(text.len());
+    let mut words = text[..start].split_whitespace();
+    let tag = words.next().unwrap_or_default().to_uppercase();
+    match words.next().map(|w| w.to_uppercase()) {
+        None => {}
+        Some(w) if w == "Z" || w == "M" || w == "ZM" => {}
+        Some(w) if w == "EMPTY" => return error("empty geometry"),
+        Some(w) => return error(format!("unexpected '{}'", w)),
+    }
+    let body = &text[start..];
+
+    match tag.as_str() {
+        "POINT" => Ok(Geometry::Point(coordinate(parenthesized(body)?)?)),
+        "LINESTRING" => Ok(Geometry::LineString(coordinates(body)?)),
+        "POLYGON" => {
+            let mut rings = vec![];
+            let mut rest = parenthesized(body)?.trim();
+            while !rest.is_empty() {
+                let end = match rest.find(')') {
+                    Some(end) => end,
+                    None => return error("unclosed ring"),
+                };
+                rings.push(coordinates(&rest[..=end])?);
+                rest = rest[end + 1..].trim_start().trim_start_matches(',').trim();
+            }
+            Ok(Geometry::Polygon(rings))
+        }
+        "" => error("missing geometry type"),
+        other => error(format!("unsupported geometry type '{}'", other)),
+    }
+}
+
+fn coordinate_list(points: &[Point]) -> String {
+    points
+        .iter()
+        .map(|p| format!("{} {}", p.lon(), p.lat()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl Point {
+    /// Parse a WKT `POINT`.
+    pub fn from_wkt(text: &str) -> Result<Point, WktError> {
+        match parse(text)? {
+            Geometry::Point(point) => Ok(point),
+            _ => error("expected a POINT"),
+        }
+    }
+
+    pub fn to_wkt(&self) -> String {
+        format!("POINT({} {})", self.lon(), self.lat())
+    }
+}
+
+impl Nogo {
+    /// Parse a nogo from WKT.
+    ///
+    /// A `POINT` becomes a circular nogo with `radius` in meters. Polygons with holes are not
+    /// supported, since brouter can't represent them.
+    pub fn from_wkt(text: &str, radius: f64, weight: Option<f64>) -> Result<Nogo, WktError> {
+        match parse(text)? {
+            Geometry::Point(point) => Ok(Nogo::Point {
+                point,
+                radius,
+                weight,
+            }),
+            Geometry::LineString(points) => Ok(Nogo::Line { points, weight }),
+            Geometry::Polygon(mut rings) => {
+                if rings.len() > 1 {
+                    return error("polygons with holes are not supported");
+                }
+                match rings.pop() {
+                    Some(points) => Ok(Nogo::Polygon { points, weight }),
+                    None => error("polygon without rings"),
+                }
+            }
+        }
+    }
+
+    /// The geometry of the nogo as WKT.
+    ///
+    /// Point nogos are written as their center; the radius and weight are not included.
+    pub fn to_wkt(&self) -> String {
+        match self {
+            Nogo::Point { point, .. } => point.to_wkt(),
+            Nogo::Line { points, .. } => format!("LINESTRING({})", coordinate_list(points)),
+            Nogo::Polygon { points, .. } => {
+                let mut ring = points.clone();
+                if ring.first() != ring.last() {
+                    ring.push(ring[0].clone());
+                }
+                format!("POLYGON(({}))", coordinate_list(&ring))
+            }
+        }
+    }
+}
@@ -0,0 +1,328 @@
+//! Minimal reading and writing of zip archives: enough to unpack brouter's release archives,
+//! and to write and read snapshots of the files of a local server.
+//!
+//! Only stored and deflated entries are supported, and no ZIP64 or encryption, which limits
+//! archives to 4 GiB; brouter's releases need nothing else. Entries are streamed to and from
+//! files, so archives don't need to fit in memory.
+
+use crate::server::Error;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const CENTRAL_DIRECTORY_ENTRY: u32 = 0x0201_4b50;
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const DATA_DESCRIPTOR: u32 = 0x0807_4b50;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+/// The version of the format needed to extract entries: 2.0, for deflate.
+const VERSION: u16 = 20;
+
+/// Flags of the entries written: sizes and checksum in a data descriptor after the data, and
+/// names in UTF-8.
+const FLAGS: u16 = 0x0808;
+
+/// 1980-01-01, the earliest date zip archives can hold, in MS-DOS format.
+const DOS_EPOCH: u16 = 0x21;
+
+fn invalid(message: &str) -> Error {
+    Error::Extract(format!("invalid zip archive: {}", message))
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, Error> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| invalid("truncated"))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, Error> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| invalid("truncated"))
+}
+
+/// A writer that passes data on to `inner`, counting it and computing its CRC-32.
+struct Checksummed<W> {
+    inner: W,
+    hasher: crc32fast::Hasher,
+    size: u64,
+}
+
+impl<W> Checksummed<W> {
+    fn new(inner: W) -> Self {
+        Checksummed {
+            inner,
+            hasher: crc32fast::Hasher::new(),
+            size: 0,
+        }
+    }
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A file in an archive, as listed in its central directory.
+struct Entry {
+    name: String,
+    method: u16,
+    compressed_size: u64,
+    size: u64,
+    crc: u32,
+    local_offset: u64,
+}
+
+impl Entry {
+    /// Write the contents of the entry in `archive` to `out`, checking their size and
+    /// checksum.
+    fn extract<R: Read + Seek>(&self, archive: &mut R, out: impl Write) -> Result<(), Error> {
+        archive.seek(SeekFrom::Start(self.local_offset))?;
+        let mut header = [0; 30];
+        archive
+            .read_exact(&mut header)
+            .map_err(|_| invalid("truncated"))?;
+        if u32_at(&header, 0)? != LOCAL_HEADER {
+            return Err(invalid(&format!("bad local header for {}", self.name)));
+        }
+        // The local header repeats the name, but may have a different extra field.
+        let skip = u16_at(&header, 26)? as i64 + u16_at(&header, 28)? as i64;
+        archive.seek(SeekFrom::Current(skip))?;
+
+        let data = archive.take(self.compressed_size);
+        let contents: Box<dyn Read + '_> = match self.method {
+            STORED => Box::new(data),
+            DEFLATED => Box::new(DeflateDecoder::new(data)),
+            method => {
+                return Err(invalid(&format!(
+                    "unsupported compression method {} for {}",
+                    method, self.name
+                )))
+            }
+        };
+        // The size comes from the archive, so it's only trusted as far as reading one byte
+        // past it, to tell whether the entry is larger than it claims.
+        let mut out = Checksummed::new(out);
+        std::io::copy(&mut contents.take(self.size + 1), &mut out)?;
+        if out.size != self.size {
+            return Err(invalid(&format!("wrong size of {}", self.name)));
+        }
+        if out.hasher.finalize() != self.crc {
+            return Err(invalid(&format!("wrong checksum of {}", self.name)));
+        }
+        Ok(())
+    }
+}
+
+/// The entries of `archive`, from its central directory.
+fn entries<R: Read + Seek>(archive: &mut R) -> Result<Vec<Entry>, Error> {
+    let len = archive.seek(SeekFrom::End(0))?;
+    // The end of central directory record is at least 22 bytes, followed by a comment of at
+    // most 64 KiB.
+    archive.seek(SeekFrom::Start(len.saturating_sub(22 + 0xffff)))?;
+    let mut tail = vec![];
+    archive.read_to_end(&mut tail)?;
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&i| u32_at(&tail, i).ok() == Some(END_OF_CENTRAL_DIRECTORY))
+        .ok_or_else(|| invalid("no end of central directory"))?;
+    let count = u16_at(&tail, end + 10)? as usize;
+    let directory_size = u32_at(&tail, end + 12)? as u64;
+    let directory_offset = u32_at(&tail, end + 16)? as u64;
+    if directory_offset + directory_size > len {
+        return Err(invalid("truncated"));
+    }
+    archive.seek(SeekFrom::Start(directory_offset))?;
+    let mut directory = vec![];
+    archive.take(directory_size).read_to_end(&mut directory)?;
+
+    let mut entries = Vec::with_capacity(count);
+    let mut offset = 0;
+    for _ in 0..count {
+        if u32_at(&directory, offset)? != CENTRAL_DIRECTORY_ENTRY {
+            return Err(invalid("bad central directory entry"));
+        }
+        let name_length = u16_at(&directory, offset + 28)? as usize;
+        let extra_length = u16_at(&directory, offset + 30)? as usize;
+        let comment_length = u16_at(&directory, offset + 32)? as usize;
+        let name = directory
+            .get(offset + 46..offset + 46 + name_length)
+            .ok_or_else(|| invalid("truncated"))?;
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: u16_at(&directory, offset + 10)?,
+            crc: u32_at(&directory, offset + 16)?,
+            compressed_size: u32_at(&directory, offset + 20)? as u64,
+            size: u32_at(&directory, offset + 24)? as u64,
+            local_offset: u32_at(&directory, offset + 42)? as u64,
+        });
+        offset += 46 + name_length + extra_length + comment_length;
+    }
+    Ok(entries)
+}
+
+/// The path `name` would be written to under `dir`, refusing names that would end up
+/// outside of it.
+fn target_path(dir: &Path, name: &str) -> Result<PathBuf, Error> {
+    let relative = Path::new(name);
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return Err(invalid(&format!("unsafe path {}", name)));
+    }
+    Ok(dir.join(relative))
+}
+
+/// A file unpacked from an archive.
+pub(crate) struct Unpacked {
+    /// The name of the file in the archive, relative to where it was unpacked.
+    pub(crate) name: String,
+    pub(crate) size: u64,
+    pub(crate) crc: u32,
+}
+
+/// Unpack `archive` into `dir`, returning the files written.
+pub(crate) fn unpack<R: Read + Seek>(mut archive: R, dir: &Path) -> Result<Vec<Unpacked>, Error> {
+    let mut written = vec![];
+    for entry in entries(&mut archive)? {
+        let path = target_path(dir, entry.name.trim_end_matches('/'))?;
+        if entry.name.ends_with('/') {
+            std::fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        entry.extract(&mut archive, &mut file)?;
+        file.flush()?;
+        written.push(Unpacked {
+            name: entry.name,
+            size: entry.size,
+            crc: entry.crc,
+        });
+    }
+    Ok(written)
+}
+
+/// Writes a zip archive to `out`, one file at a time.
+pub(crate) struct ZipWriter<W> {
+    out: W,
+    /// How much has been written to `out` so far.
+    offset: u64,
+    directory: Vec<u8>,
+    count: usize,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub(crate) fn new(out: W) -> Self {
+        ZipWriter {
+            out,
+            offset: 0,
+            directory: vec![],
+            count: 0,
+        }
+    }
+
+    /// Add a file named `name`, with the contents read from `contents`, deflated if
+    /// `compress` is set.
+    pub(crate) fn add(
+        &mut self,
+        name: &str,
+        contents: &mut impl Read,
+        compress: bool,
+    ) -> Result<(), Error> {
+        let too_large = || Error::Extract(format!("archive too large at {}", name));
+        let method = if compress { DEFLATED } else { STORED };
+        let local_offset = u32::try_from(self.offset).map_err(|_| too_large())?;
+
+        let mut header = vec![];
+        for value in [VERSION, FLAGS, method, 0, DOS_EPOCH] {
+            header.extend(value.to_le_bytes());
+        }
+        // The sizes and checksum are only known once the contents are written, so they
+        // follow them in a data descriptor.
+        header.extend([0; 12]);
+        header.extend((name.len() as u16).to_le_bytes());
+        header.extend(0u16.to_le_bytes());
+        header.extend(name.as_bytes());
+        self.out.write_all(&LOCAL_HEADER.to_le_bytes())?;
+        self.out.write_all(&header)?;
+        self.offset += 4 + header.len() as u64;
+
+        let mut written = Checksummed::new(&mut self.out);
+        let (crc, size) = if compress {
+            let mut encoder = Checksummed::new(DeflateEncoder::new(
+                &mut written,
+                flate2::Compression::default(),
+            ));
+            std::io::copy(contents, &mut encoder)?;
+            encoder.inner.finish()?;
+            (encoder.hasher.finalize(), encoder.size)
+        } else {
+            std::io::copy(contents, &mut written)?;
+            (written.hasher.clone().finalize(), written.size)
+        };
+        let compressed_size = written.size;
+        self.offset += compressed_size;
+        let compressed_size = u32::try_from(compressed_size).map_err(|_| too_large())?;
+        let size = u32::try_from(size).map_err(|_| too_large())?;
+
+        let mut descriptor = vec![];
+        for value in [DATA_DESCRIPTOR, crc, compressed_size, size] {
+            descriptor.extend(value.to_le_bytes());
+        }
+        self.out.write_all(&descriptor)?;
+        self.offset += descriptor.len() as u64;
+
+        self.directory.extend(CENTRAL_DIRECTORY_ENTRY.to_le_bytes());
+        // Version made by, then the fields of the local header.
+        self.directory.extend(VERSION.to_le_bytes());
+        self.directory.extend(&header[..10]);
+        for value in [crc, compressed_size, size] {
+            self.directory.extend(value.to_le_bytes());
+        }
+        // The lengths of the name, extra field and comment, the disk number and the
+        // attributes.
+        self.directory.extend((name.len() as u16).to_le_bytes());
+        self.directory.extend([0; 12]);
+        self.directory.extend(local_offset.to_le_bytes());
+        self.directory.extend(name.as_bytes());
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Write the central directory, completing the archive, and return `out`.
+    pub(crate) fn finish(mut self) -> Result<W, Error> {
+        let too_large = || Error::Extract("archive too large".to_string());
+        let count = u16::try_from(self.count).map_err(|_| too_large())?;
+        let directory_offset = u32::try_from(self.offset).map_err(|_| too_large())?;
+        self.out.write_all(&self.directory)?;
+
+        let mut end = END_OF_CENTRAL_DIRECTORY.to_le_bytes().to_vec();
+        // The number of this disk and of the one with the directory, then the number of
+        // entries on this disk and in total.
+        end.extend([0; 4]);
+        end.extend(count.to_le_bytes());
+        end.extend(count.to_le_bytes());
+        end.extend((self.directory.len() as u32).to_le_bytes());
+        end.extend(directory_offset.to_le_bytes());
+        // No comment.
+        end.extend(0u16.to_le_bytes());
+        self.out.write_all(&end)?;
+        Ok(self.out)
+    }
+}
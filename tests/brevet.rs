@@ -0,0 +1,107 @@
+//! Opening and closing times of brevet controls.
+
+mod common;
+
+use brouter_client::brevet::{BrevetPlanner, SpeedLimit};
+use brouter_client::Route;
+use common::{route_along, KM};
+use time::Duration;
+use time::OffsetDateTime;
+
+fn planner() -> BrevetPlanner {
+    BrevetPlanner::new(OffsetDateTime::UNIX_EPOCH)
+}
+
+/// Hours and minutes after the start of the planner.
+fn after_start(t: OffsetDateTime) -> (i64, i64) {
+    let elapsed = t - OffsetDateTime::UNIX_EPOCH;
+    (elapsed.whole_hours(), elapsed.whole_minutes() % 60)
+}
+
+/// A route due north, about `km` kilometers long.
+fn route(km: f64) -> Route {
+    route_along((0..=10).map(|i| (km * KM * i as f64 / 10.0, None)))
+}
+
+#[test]
+fn acp_times() {
+    let planner = planner();
+    let (open, close) = planner.times(0.0);
+    assert_eq!((after_start(open), after_start(close)), ((0, 0), (1, 0)));
+    // 100 km at 34 and 15 km/h.
+    let (open, close) = planner.times(100_000.0);
+    assert_eq!((after_start(open), after_start(close)), ((2, 56), (6, 40)));
+    // 300 km: 200 km at 34 km/h and 100 km at 32 km/h.
+    let (open, _) = planner.times(300_000.0);
+    assert_eq!(after_start(open), (9, 0));
+}
+
+#[test]
+fn acp_finish() {
+    let planner = planner();
+    // The minimum speed would give 13:20, but a 200 km brevet closes after 13:30.
+    let (_, close) = planner.times(200_000.0);
+    assert_eq!(after_start(close), (13, 20));
+    let (open, close) = planner.finish_times(200_000.0);
+    assert_eq!((after_start(open), after_start(close)), ((5, 53), (13, 30)));
+    for (km, limit) in [
+        (300.0, (20, 0)),
+        (400.0, (27, 0)),
+        (600.0, (40, 0)),
+        (1000.0, (75, 0)),
+    ] {
+        let (_, close) = planner.finish_times(km * 1000.0);
+        assert_eq!(after_start(close), limit, "{} km", km);
+    }
+    // Routes a little longer than the nominal distance keep its limit.
+    let (_, close) = planner.finish_times(205_000.0);
+    assert_eq!(after_start(close), (13, 30));
+
+    let without = planner.time_limits(vec![]);
+    let (_, close) = without.finish_times(200_000.0);
+    assert_eq!(after_start(close), (13, 20));
+}
+
+#[test]
+fn controls_every() {
+    let route = route(205.0);
+    let controls = planner().controls_every(&route, 100_000.0).unwrap();
+    let distances = controls
+        .iter()
+        .map(|c| (c.distance_m / 1000.0).round())
+        .collect::<Vec<_>>();
+    assert_eq!(distances, [0.0, 100.0, 200.0, 205.0]);
+    // Only the finish closes at the time limit.
+    assert_eq!(after_start(controls[2].close), (13, 20));
+    assert_eq!(after_start(controls[3].close), (13, 30));
+
+    for interval in [0.0, -1.0, f64::NAN] {
+        assert!(planner().controls_every(&route, interval).is_err());
+    }
+}
+
+#[test]
+fn invalid_speed_limits() {
+    let limit = |until_km, min_kmh, max_kmh| SpeedLimit {
+        until_km,
+        min_kmh,
+        max_kmh,
+    };
+    assert!(planner().speed_limits(vec![]).is_err());
+    assert!(planner()
+        .speed_limits(vec![limit(200.0, 0.0, 30.0)])
+        .is_err());
+    assert!(planner()
+        .speed_limits(vec![limit(200.0, 15.0, f64::INFINITY)])
+        .is_err());
+    assert!(planner()
+        .speed_limits(vec![limit(400.0, 15.0, 30.0), limit(200.0, 15.0, 30.0)])
+        .is_err());
+
+    let planner = planner()
+        .speed_limits(vec![limit(f64::INFINITY, 10.0, 20.0)])
+        .unwrap()
+        .start_close(Duration::ZERO);
+    let (open, close) = planner.times(100_000.0);
+    assert_eq!((after_start(open), after_start(close)), ((5, 0), (10, 0)));
+}
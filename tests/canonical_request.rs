@@ -0,0 +1,60 @@
+//! Round trips through the canonical form of requests.
+
+use brouter_client::{Error, Nogo, Point, RouteRequest, TurnInstructionMode};
+
+#[test]
+fn round_trip() {
+    let request = RouteRequest::new(
+        &[Point::new(52.5, 13.4), Point::new(52.6, 13.5)],
+        "trekking",
+    )
+    .nogos(&[Nogo::circle(&Point::new(52.55, 13.45), 100.0)])
+    .alternative(2)
+    .turn_instructions(TurnInstructionMode::OsmandStyle)
+    .point_names(&[Some("Start"), None])
+    .export_waypoints(true);
+    let text = request.canonical_string();
+    let parsed = RouteRequest::from_canonical_string(&text).unwrap();
+    assert_eq!(parsed, request);
+    assert_eq!(parsed.canonical_string(), text);
+}
+
+#[test]
+fn alternative_out_of_range() {
+    let request = RouteRequest::new(
+        &[Point::new(52.5, 13.4), Point::new(52.6, 13.5)],
+        "trekking",
+    )
+    .alternative(3);
+    let text = request.canonical_string();
+    assert!(text.contains("alternative 3\n"), "{}", text);
+    assert_eq!(RouteRequest::from_canonical_string(&text).unwrap(), request);
+
+    for alternative in ["4", "7", "255", "256", "-1"] {
+        let text = text.replace("alternative 3", &format!("alternative {}", alternative));
+        assert!(
+            matches!(
+                RouteRequest::from_canonical_string(&text),
+                Err(Error::InvalidRequest(_))
+            ),
+            "{}",
+            alternative
+        );
+    }
+}
+
+#[test]
+fn invalid_request_not_sent() {
+    // Nothing listens on the discard port; the request is rejected before connecting.
+    let router = brouter_client::Brouter::new("http://127.0.0.1:9/").unwrap();
+    let mut request = RouteRequest::new(
+        &[Point::new(52.5, 13.4), Point::new(52.6, 13.5)],
+        "trekking",
+    );
+    request.alternative = Some(4);
+    assert!(matches!(request.validate(), Err(Error::InvalidRequest(_))));
+    assert!(matches!(
+        router.request_route(&request),
+        Err(Error::InvalidRequest(_))
+    ));
+}
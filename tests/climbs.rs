@@ -0,0 +1,54 @@
+//! Finding climbs in the elevation profile of a route.
+
+mod common;
+
+use brouter_client::Route;
+use common::{route_along, KM};
+
+/// A route due north with a track point every kilometer at the given elevations.
+fn route(elevations: &[f64]) -> Route {
+    route_along(
+        elevations
+            .iter()
+            .enumerate()
+            .map(|(i, elevation)| (i as f64 * KM, Some(*elevation))),
+    )
+}
+
+/// Start and end of the climbs in kilometers, and their ascent.
+fn climbs(route: &Route, min_ascent_m: f64) -> Vec<(f64, f64, f64)> {
+    route
+        .climbs(min_ascent_m)
+        .iter()
+        .map(|c| {
+            (
+                (c.start_m / 1000.0).round(),
+                (c.end_m / 1000.0).round(),
+                c.ascent_m(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn separate_climbs() {
+    let route = route(&[0.0, 50.0, 100.0, 40.0, 0.0, 20.0, 10.0, 80.0, 80.0]);
+    assert_eq!(climbs(&route, 50.0), [(0.0, 2.0, 100.0), (4.0, 7.0, 80.0)]);
+    let climb = &route.climbs(50.0)[0];
+    assert!((climb.gradient() - 5.0).abs() < 0.01);
+}
+
+#[test]
+fn small_dips_dont_split() {
+    let route = route(&[100.0, 150.0, 130.0, 200.0, 190.0]);
+    assert_eq!(climbs(&route, 50.0), [(0.0, 3.0, 100.0)]);
+    assert_eq!(climbs(&route, 10.0), [(0.0, 1.0, 50.0), (2.0, 3.0, 70.0)]);
+}
+
+#[test]
+fn below_threshold() {
+    let route = route(&[0.0, 30.0, 0.0, 30.0]);
+    assert!(route.climbs(50.0).is_empty());
+    assert_eq!(route.climbs(0.0).len(), 2);
+    assert!(self::route(&[10.0; 5]).climbs(0.0).is_empty());
+}
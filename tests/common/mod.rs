@@ -0,0 +1,82 @@
+//! Helpers shared by the tests.
+#![allow(dead_code)]
+
+use brouter_client::Route;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// A server that serves `files`, given as paths and contents, and 404s for anything else.
+///
+/// Returns its URL, without a trailing slash.
+pub fn serve_files(files: Vec<(String, Vec<u8>)>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let files = Arc::new(files);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let files = files.clone();
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                        break;
+                    }
+                }
+                let path = request_line.split_whitespace().nth(1).unwrap();
+                match files.iter().find(|(name, _)| path == format!("/{}", name)) {
+                    Some((_, contents)) => {
+                        write!(
+                            stream,
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            contents.len()
+                        )
+                        .unwrap();
+                        stream.write_all(contents).unwrap();
+                    }
+                    None => write!(
+                        stream,
+                        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    )
+                    .unwrap(),
+                }
+            });
+        }
+    });
+    url
+}
+
+/// An empty directory for `test` to write to.
+pub fn scratch_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("brouter-client-{}-{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Degrees of latitude in a kilometer, as the haversine distance measures it.
+pub const KM: f64 = 1.0 / 111.195_08;
+
+/// A route due north along longitude 5 through `points` of (latitude, elevation).
+pub fn route_along(points: impl IntoIterator<Item = (f64, Option<f64>)>) -> Route {
+    let points = points
+        .into_iter()
+        .map(|(lat, elevation)| {
+            let mut waypoint = gpx::Waypoint::new(geo_types::Point::new(5.0, lat));
+            waypoint.elevation = elevation;
+            waypoint
+        })
+        .collect();
+    let mut track = gpx::Track::new();
+    track.segments.push(gpx::TrackSegment { points });
+    Route::from(gpx::Gpx {
+        version: gpx::GpxVersion::Gpx11,
+        tracks: vec![track],
+        ..Default::default()
+    })
+}
@@ -0,0 +1,104 @@
+//! Writing routes as KML, TCX and FIT.
+
+use brouter_client::export::{FitWriter, KmlWriter, TcxWriter};
+use brouter_client::Route;
+use time::OffsetDateTime;
+
+/// Degrees of latitude in a kilometer, as the haversine distance measures it.
+const KM: f64 = 1.0 / 111.195_08;
+
+/// A route due north of two kilometers, with a track point every kilometer.
+fn route() -> Route {
+    let points = (0..=2)
+        .map(|i| {
+            let mut waypoint = gpx::Waypoint::new(geo_types::Point::new(5.0, i as f64 * KM));
+            waypoint.elevation = Some(10.0 * i as f64);
+            waypoint
+        })
+        .collect();
+    let mut track = gpx::Track::new();
+    track.name = Some("Test & route".to_string());
+    track.segments.push(gpx::TrackSegment { points });
+    Route::from(gpx::Gpx {
+        version: gpx::GpxVersion::Gpx11,
+        tracks: vec![track],
+        ..Default::default()
+    })
+}
+
+/// CRC-16/ARC, computed bit by bit.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for byte in data {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xa001
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+#[test]
+fn kml() {
+    let kml = KmlWriter::new().to_kml(&route());
+    assert!(kml.contains("<name>Test &amp; route</name>"));
+    assert!(kml.contains(&format!(
+        "<coordinates>5,0,0 5,{},10 5,{},20</coordinates>",
+        KM,
+        2.0 * KM
+    )));
+}
+
+#[test]
+fn tcx() {
+    let tcx = TcxWriter::new()
+        .start(OffsetDateTime::UNIX_EPOCH)
+        .speed(5.0)
+        .to_tcx(&route());
+    assert!(tcx.contains("<Name>Test &amp; route</Name>"));
+    assert!(tcx.contains("<TotalTimeSeconds>400</TotalTimeSeconds>"));
+    assert!(tcx.contains("<Time>1970-01-01T00:03:20Z</Time><Position><LatitudeDegrees>"));
+    assert!(
+        tcx.contains("<AltitudeMeters>10</AltitudeMeters><DistanceMeters>1000.0</DistanceMeters>")
+    );
+    assert_eq!(tcx.matches("<Trackpoint>").count(), 3);
+}
+
+#[test]
+fn fit() {
+    let start = OffsetDateTime::UNIX_EPOCH + time::Duration::days(365 * 40);
+    let fit = FitWriter::new().start(start).to_vec(&route());
+
+    assert_eq!(fit[0], 14);
+    assert_eq!(&fit[8..12], b".FIT");
+    assert_eq!(crc16(&fit[..12]), u16::from_le_bytes([fit[12], fit[13]]));
+    let data_size = u32::from_le_bytes([fit[4], fit[5], fit[6], fit[7]]) as usize;
+    assert_eq!(fit.len(), 14 + data_size + 2);
+    // Appending the CRC makes the CRC of the whole file zero.
+    assert_eq!(crc16(&fit), 0);
+
+    // The file_id definition and message, of type course.
+    assert_eq!(fit[14], 0x40);
+    assert_eq!(&fit[17..19], &0u16.to_le_bytes());
+    assert_eq!(fit[32], 0);
+    assert_eq!(fit[33], 6);
+}
+
+#[test]
+fn empty_route() {
+    let route = Route::from(gpx::Gpx {
+        version: gpx::GpxVersion::Gpx11,
+        ..Default::default()
+    });
+    let fit = FitWriter::new()
+        .start(OffsetDateTime::UNIX_EPOCH)
+        .to_vec(&route);
+    assert_eq!(crc16(&fit), 0);
+    assert!(TcxWriter::new()
+        .to_tcx(&route)
+        .contains("<Name>Route</Name>"));
+}
@@ -0,0 +1,104 @@
+//! Failing over to fallback servers.
+
+use brouter_client::{BrouterBuilder, Error};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const PROFILE_UPLOADED: &str = r#"{"profileid":"custom_1"}"#;
+
+/// Read a request, up to the end of its body.
+fn read_request(stream: &TcpStream) {
+    let mut reader = BufReader::new(stream);
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap();
+            }
+        }
+    }
+    reader.take(length).read_to_end(&mut Vec::new()).unwrap();
+}
+
+/// A server that answers requests with `body`, or never answers if `body` is `None`.
+///
+/// Returns its URL and the number of requests it received.
+fn server(body: Option<&'static str>) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let requests = Arc::new(AtomicUsize::new(0));
+    let count = requests.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            count.fetch_add(1, Ordering::SeqCst);
+            read_request(&stream);
+            match body {
+                Some(body) => write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap(),
+                None => std::thread::sleep(Duration::from_secs(10)),
+            }
+        }
+    });
+    (url, requests)
+}
+
+/// The URL of a port nothing listens on.
+fn closed_port() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    format!("http://{}/", listener.local_addr().unwrap())
+}
+
+#[test]
+fn unreachable_primary() {
+    let primary = closed_port();
+    let (fallback, requests) = server(Some(PROFILE_UPLOADED));
+    let brouter = BrouterBuilder::new(&primary).fallback(&fallback).build();
+
+    assert_eq!(
+        brouter.upload_custom_profile(b"".to_vec()).unwrap(),
+        "custom_1"
+    );
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    assert_eq!(brouter.active_url(), fallback);
+
+    // Requests stick to the fallback that answered.
+    brouter.upload_custom_profile(b"".to_vec()).unwrap();
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn slow_primary() {
+    let (primary, primary_requests) = server(None);
+    let (fallback, fallback_requests) = server(Some(PROFILE_UPLOADED));
+    let brouter = BrouterBuilder::new(&primary)
+        .fallback(&fallback)
+        .client(
+            reqwest::blocking::Client::builder()
+                .timeout(Duration::from_millis(300))
+                .build()
+                .unwrap(),
+        )
+        .build();
+
+    // The primary server was reached, so the request isn't sent to the fallback as well.
+    assert!(matches!(
+        brouter.upload_custom_profile(b"".to_vec()),
+        Err(Error::Timeout(_))
+    ));
+    assert_eq!(primary_requests.load(Ordering::SeqCst), 1);
+    assert_eq!(fallback_requests.load(Ordering::SeqCst), 0);
+    assert_eq!(brouter.active_url(), primary);
+}
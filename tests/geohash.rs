@@ -0,0 +1,47 @@
+//! Geohash conversions.
+
+use brouter_client::{Error, Point};
+
+#[test]
+fn known_vectors() {
+    let point = Point::from_geohash("u4pruydqqvj").unwrap();
+    assert!((point.lat() - 57.64911).abs() < 1e-5);
+    assert!((point.lon() - 10.40744).abs() < 1e-5);
+    assert_eq!(Point::new(57.64911, 10.40744).geohash(11), "u4pruydqqvj");
+
+    assert_eq!(Point::new(42.6, -5.6).geohash(5), "ezs42");
+    let point = Point::from_geohash("ezs42").unwrap();
+    assert!((point.lat() - 42.605).abs() < 0.01);
+    assert!((point.lon() - -5.603).abs() < 0.01);
+}
+
+#[test]
+fn round_trip() {
+    let point = Point::new(-33.86882, 151.209296);
+    for precision in 1..=12 {
+        let geohash = point.geohash(precision);
+        assert_eq!(geohash.len(), precision);
+        // The center of the cell lies in the same cell.
+        assert_eq!(
+            Point::from_geohash(&geohash).unwrap().geohash(precision),
+            geohash
+        );
+    }
+    assert_eq!(
+        Point::from_geohash("U4PRUYDQQVJ").unwrap(),
+        Point::from_geohash("u4pruydqqvj").unwrap()
+    );
+}
+
+#[test]
+fn invalid() {
+    assert!(matches!(
+        Point::from_geohash(""),
+        Err(Error::InvalidGeohash(_))
+    ));
+    // 'a', 'i', 'l' and 'o' are not in the alphabet.
+    assert!(matches!(
+        Point::from_geohash("u4pa"),
+        Err(Error::InvalidGeohash(_))
+    ));
+}
@@ -0,0 +1,115 @@
+//! Sending routing requests with long URLs.
+
+use brouter_client::{Brouter, BrouterBuilder, Point};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+
+const ROUTE: &str = include_str!("data/responses/brouter-1.6.3-trekking-osmand.gpx");
+
+/// A request received by the stub server.
+struct Request {
+    method: String,
+    target: String,
+    body: String,
+}
+
+/// A server that answers a single request with a route.
+///
+/// Returns its URL and a receiver for the request it got.
+fn server() -> (String, mpsc::Receiver<Request>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap().to_string();
+        let target = parts.next().unwrap().to_string();
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        let mut body = String::new();
+        reader.take(length).read_to_string(&mut body).unwrap();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            ROUTE.len(),
+            ROUTE
+        )
+        .unwrap();
+        sender
+            .send(Request {
+                method,
+                target,
+                body,
+            })
+            .unwrap();
+    });
+    (url, receiver)
+}
+
+/// Enough points for the URL to be over 8000 characters long.
+fn points() -> Vec<Point> {
+    (0..500)
+        .map(|i| Point::new(52.0 + i as f64 * 0.001, 5.0 + i as f64 * 0.001))
+        .collect()
+}
+
+#[test]
+fn long_urls_are_sent_as_get_by_default() {
+    let (url, requests) = server();
+    let brouter = Brouter::new(&url).unwrap();
+
+    brouter.route(&points()).profile("trekking").send().unwrap();
+
+    let request = requests.recv().unwrap();
+    assert_eq!(request.method, "GET");
+    assert!(request.target.len() > 8000);
+    assert!(request.target.contains("profile=trekking"));
+    assert_eq!(request.body, "");
+}
+
+#[test]
+fn long_urls_are_sent_as_post_when_enabled() {
+    let (url, requests) = server();
+    let brouter = BrouterBuilder::new(&url).max_url_length(8000).build();
+
+    brouter.route(&points()).profile("trekking").send().unwrap();
+
+    let request = requests.recv().unwrap();
+    assert_eq!(request.method, "POST");
+    assert!(!request.target.contains('?'));
+    assert!(request.body.len() > 8000);
+    assert!(request.body.contains("profile=trekking"));
+    assert!(request.body.contains("lonlats="));
+}
+
+#[test]
+fn short_urls_are_sent_as_get_when_enabled() {
+    let (url, requests) = server();
+    let brouter = BrouterBuilder::new(&url).max_url_length(8000).build();
+
+    brouter
+        .route(&points()[..2])
+        .profile("trekking")
+        .send()
+        .unwrap();
+
+    let request = requests.recv().unwrap();
+    assert_eq!(request.method, "GET");
+    assert!(request.target.contains("profile=trekking"));
+    assert_eq!(request.body, "");
+}
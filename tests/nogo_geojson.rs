@@ -0,0 +1,76 @@
+//! Reading nogos from GeoJSON.
+#![cfg(feature = "geojson")]
+
+use brouter_client::{Error, Nogo, Point};
+
+fn read(text: &str) -> Result<Vec<Nogo>, Error> {
+    Nogo::from_geojson(text.as_bytes())
+}
+
+#[test]
+fn round_trip() {
+    let nogos = vec![
+        Nogo::circle(&Point::new(52.5, 13.4), 250.0),
+        Nogo::line(&[Point::new(52.5, 13.4), Point::new(52.6, 13.5)])
+            .penalize(300.0)
+            .unwrap(),
+        Nogo::polygon(&[
+            Point::new(52.5, 13.4),
+            Point::new(52.6, 13.4),
+            Point::new(52.6, 13.5),
+            Point::new(52.5, 13.4),
+        ]),
+    ];
+    assert_eq!(read(&Nogo::to_geojson(&nogos)).unwrap(), nogos);
+}
+
+#[test]
+fn geometries() {
+    let nogos = read(
+        r#"{"type":"Feature","properties":{"radius":50,"weight":100},
+            "geometry":{"type":"MultiPoint","coordinates":[[13.4,52.5],[13.5,52.6]]}}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        nogos,
+        vec![
+            Nogo::circle(&Point::new(52.5, 13.4), 50.0)
+                .penalize(100.0)
+                .unwrap(),
+            Nogo::circle(&Point::new(52.6, 13.5), 50.0)
+                .penalize(100.0)
+                .unwrap(),
+        ]
+    );
+
+    let nogos = read(r#"{"type":"LineString","coordinates":[[13.4,52.5],[13.5,52.6]]}"#).unwrap();
+    assert_eq!(
+        nogos,
+        vec![Nogo::from(geo_types::LineString::from(vec![
+            (13.4, 52.5),
+            (13.5, 52.6)
+        ]))]
+    );
+}
+
+#[test]
+fn invalid() {
+    for text in [
+        r#"{"type":"Point","coordinates":[13.4,52.5]}"#,
+        r#"{"type":"Polygon","coordinates":[[[0,0],[1,0],[1,1],[0,0]],[[0.1,0.1],[0.2,0.1],[0.2,0.2],[0.1,0.1]]]}"#,
+        r#"{"type":"Feature","geometry":null}"#,
+        "[",
+    ] {
+        assert!(
+            matches!(read(text), Err(Error::InvalidGeoJson(_))),
+            "{}",
+            text
+        );
+    }
+    assert!(matches!(
+        read(
+            r#"{"type":"Feature","properties":{"weight":-1},"geometry":{"type":"LineString","coordinates":[[0,0],[1,1]]}}"#
+        ),
+        Err(Error::InvalidRequest(_))
+    ));
+}
@@ -0,0 +1,57 @@
+//! Polygon nogos from geo-types polygons.
+
+use brouter_client::{Error, Nogo, Point, RouteRequest};
+use geo_types::{LineString, Polygon};
+
+fn square(x: f64, y: f64, size: f64) -> LineString<f64> {
+    LineString::from(vec![
+        (x, y),
+        (x + size, y),
+        (x + size, y + size),
+        (x, y + size),
+        (x, y),
+    ])
+}
+
+#[test]
+fn polygon() {
+    let nogo = Nogo::try_from(Polygon::new(square(13.0, 52.0, 1.0), vec![])).unwrap();
+    assert_eq!(
+        nogo,
+        Nogo::Polygon {
+            points: vec![
+                Point::new(52.0, 13.0),
+                Point::new(52.0, 14.0),
+                Point::new(53.0, 14.0),
+                Point::new(53.0, 13.0),
+                Point::new(52.0, 13.0),
+            ],
+            weight: None,
+        }
+    );
+}
+
+#[test]
+fn polygon_with_hole() {
+    let polygon = Polygon::new(square(13.0, 52.0, 1.0), vec![square(13.2, 52.2, 0.1)]);
+    assert!(matches!(
+        Nogo::try_from(polygon),
+        Err(Error::InvalidRequest(_))
+    ));
+}
+
+#[test]
+fn within_corridor_with_hole() {
+    let hole = square(13.2, 52.2, 0.1);
+    let corridor = Polygon::new(square(13.0, 52.0, 1.0), vec![hole.clone()]);
+    let request = RouteRequest::new(
+        &[Point::new(52.1, 13.1), Point::new(52.9, 13.9)],
+        "trekking",
+    )
+    .within(&corridor, 1000.0);
+    assert_eq!(request.nogos.len(), 2);
+    assert_eq!(
+        request.nogos[1],
+        Nogo::try_from(Polygon::new(hole, vec![])).unwrap()
+    );
+}
@@ -0,0 +1,68 @@
+//! Encoding and decoding polylines.
+
+use brouter_client::{Error, Point};
+
+/// The example from Google's description of the algorithm.
+const GOOGLE: &str = "_p~iF~ps|U_ulLnnqC_mqNvxq`@";
+
+fn coordinates(points: &[Point]) -> Vec<(f64, f64)> {
+    points.iter().map(|p| (p.lat(), p.lon())).collect()
+}
+
+#[test]
+fn reference_vector() {
+    let points = Point::from_polyline(GOOGLE, 5).unwrap();
+    assert_eq!(
+        coordinates(&points),
+        [(38.5, -120.2), (40.7, -120.95), (43.252, -126.453)]
+    );
+    assert_eq!(Point::to_polyline(&points, 5), GOOGLE);
+}
+
+#[test]
+fn precision_6_round_trip() {
+    let points = vec![
+        Point::new(52.520008, 13.404954),
+        Point::new(52.516275, 13.377704),
+        Point::new(-33.868820, 151.209296),
+    ];
+    let polyline = Point::to_polyline(&points, 6);
+    assert_eq!(
+        coordinates(&Point::from_polyline(&polyline, 6).unwrap()),
+        coordinates(&points)
+    );
+    // The same polyline read with the wrong precision is off by a factor of ten.
+    assert_eq!(
+        Point::from_polyline(&polyline, 5).unwrap()[0].lat(),
+        525.20008
+    );
+}
+
+#[test]
+fn truncated() {
+    // Cut off in the middle of the last longitude.
+    assert!(matches!(
+        Point::from_polyline(&GOOGLE[..GOOGLE.len() - 1], 5),
+        Err(Error::InvalidPolyline(_))
+    ));
+    // A latitude without a longitude.
+    assert!(matches!(
+        Point::from_polyline("_p~iF", 5),
+        Err(Error::InvalidPolyline(_))
+    ));
+    assert!(matches!(
+        Point::from_polyline("_p~iF ps|U", 5),
+        Err(Error::InvalidPolyline(_))
+    ));
+}
+
+#[test]
+fn overflow() {
+    // Latitudes of 2^62 - 1, three of which don't fit in 64 bits when added up.
+    let value = format!("}}{}F?", "~".repeat(11));
+    assert!(Point::from_polyline(&value.repeat(2), 5).is_ok());
+    assert!(matches!(
+        Point::from_polyline(&value.repeat(3), 5),
+        Err(Error::InvalidPolyline(_))
+    ));
+}
@@ -0,0 +1,99 @@
+use brouter_client::profile::{diff, format, minify, Profile};
+
+const PROFILE: &str = "\
+# A small bike profile
+
+---context:global   # global settings
+assign validForBikes = true
+assign allow_ferries = true # %allow_ferries% | Allow ferries | boolean
+
+
+assign downhillcost 60
+assign uphillcost = 0
+
+---context:way
+# Ways
+assign ferrycost = if allow_ferries then 5.7 else 10000
+assign costfactor
+  switch route=ferry ferrycost
+  switch highway=cycleway 1
+  switch highway=residential|living_street|service|unclassified 1.2
+  switch highway=primary|primary_link ( if bicycle=designated then 1.5 else 3 )
+  switch or highway=motorway|motorway_link highway=trunk|trunk_link 10000 # not for bikes
+  2
+assign turncost = 90
+---context:node
+assign initialcost = switch barrier=gate 100 0
+";
+
+fn parse(text: &str) -> Profile {
+    text.parse().unwrap()
+}
+
+#[test]
+fn test_format_preserves_meaning() {
+    let formatted = format(PROFILE).unwrap();
+    assert_eq!(diff(&parse(PROFILE), &parse(&formatted)), vec![]);
+}
+
+#[test]
+fn test_format_is_idempotent() {
+    let formatted = format(PROFILE).unwrap();
+    assert_eq!(format(&formatted).unwrap(), formatted);
+}
+
+#[test]
+fn test_format_preserves_comments() {
+    let formatted = format(PROFILE).unwrap();
+    for comment in [
+        "# A small bike profile",
+        "# global settings",
+        "# %allow_ferries% | Allow ferries | boolean",
+        "# Ways",
+        "# not for bikes",
+    ] {
+        assert!(formatted.contains(comment), "{} missing", comment);
+    }
+}
+
+#[test]
+fn test_format_layout() {
+    let formatted = format(PROFILE).unwrap();
+    assert!(!formatted.contains("\n\n\n"));
+    assert!(formatted.contains("\n\n---context:node\n"));
+    assert!(formatted.contains("assign costfactor =\n    switch route=ferry ferrycost\n"));
+    assert!(formatted.contains("\n    2\n"));
+    assert!(formatted.contains("assign initialcost = switch barrier=gate 100 0\n"));
+}
+
+#[test]
+fn test_minify_preserves_meaning() {
+    let minified = minify(PROFILE).unwrap();
+    assert_eq!(diff(&parse(PROFILE), &parse(&minified)), vec![]);
+    assert_eq!(minify(&minified).unwrap(), minified);
+}
+
+#[test]
+fn test_minify_strips_comments() {
+    let minified = minify(PROFILE).unwrap();
+    assert!(!minified.contains('#'));
+    assert!(!minified.contains("\n\n"));
+    assert!(minified.len() < PROFILE.len());
+}
+
+#[test]
+fn test_format_minified() {
+    let formatted = format(PROFILE).unwrap();
+    let minified = minify(PROFILE).unwrap();
+    assert_eq!(minify(&formatted).unwrap(), minified);
+    assert_eq!(
+        diff(&parse(&format(&minified).unwrap()), &parse(PROFILE)),
+        vec![]
+    );
+}
+
+#[test]
+fn test_invalid() {
+    assert!(format("---context:global\nassign x = (").is_err());
+    assert!(minify("---context:global\nassign x = (").is_err());
+}
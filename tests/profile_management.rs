@@ -0,0 +1,53 @@
+//! Managing the profiles of a local server.
+
+use brouter_client::{BrouterBuilder, Error};
+use std::path::PathBuf;
+
+/// An empty directory for `test`.
+fn scratch_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("brouter-client-{}-{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn profiles() {
+    let dir = scratch_dir("profiles");
+    let profiles = dir.join("profiles2");
+    let custom = dir.join("customprofiles");
+    std::fs::create_dir_all(&profiles).unwrap();
+    std::fs::create_dir_all(&custom).unwrap();
+    std::fs::write(profiles.join("trekking.brf"), "---context:global\n").unwrap();
+    std::fs::write(custom.join("custom_1.brf"), "---context:way\n").unwrap();
+    let source = dir.join("my-bike.brf");
+    std::fs::write(&source, "---context:node\n").unwrap();
+
+    let brouter = BrouterBuilder::new("http://localhost:17777")
+        .profiles_dir(&profiles)
+        .custom_profiles_dir(&custom)
+        .build();
+    assert_eq!(brouter.list_profiles().unwrap(), ["trekking"]);
+    assert_eq!(
+        brouter.get_profile("trekking").unwrap(),
+        "---context:global\n"
+    );
+
+    assert_eq!(brouter.install_profile(&source).unwrap(), "my-bike");
+    assert_eq!(brouter.list_profiles().unwrap(), ["my-bike", "trekking"]);
+
+    assert_eq!(
+        brouter.get_custom_profile("custom_1").unwrap(),
+        "---context:way\n"
+    );
+    brouter.delete_custom_profile("custom_1").unwrap();
+    assert!(!custom.join("custom_1.brf").exists());
+    // Deleting it again is not an error.
+    brouter.delete_custom_profile("custom_1").unwrap();
+
+    assert!(matches!(
+        brouter.get_profile("../customprofiles/custom_1"),
+        Err(Error::InvalidRequest(_))
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
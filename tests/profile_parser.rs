@@ -0,0 +1,146 @@
+//! Parsing brouter profiles.
+
+use brouter_client::profile::{ContextKind, Expr, ParseError, Profile, Statement};
+
+const TREKKING: &str = include_str!("data/profiles/trekking.brf");
+
+fn error(text: &str) -> ParseError {
+    Profile::parse(text).unwrap_err()
+}
+
+#[test]
+fn stock_profile() {
+    let profile = Profile::parse(TREKKING).unwrap();
+    assert_eq!(
+        profile
+            .contexts
+            .iter()
+            .map(|c| c.kind.clone())
+            .collect::<Vec<_>>(),
+        [ContextKind::Global, ContextKind::Way, ContextKind::Node]
+    );
+    assert_eq!(
+        profile.assignment(&ContextKind::Global, "validForBikes"),
+        Some(&Expr::Bool(true))
+    );
+    assert_eq!(
+        profile.assignment(&ContextKind::Global, "downhillcost"),
+        Some(&Expr::if_then_else(
+            Expr::var("consider_elevation"),
+            60.0,
+            0.0
+        ))
+    );
+    assert_eq!(
+        profile.assignment(&ContextKind::Way, "turncost"),
+        Some(&Expr::if_then_else(Expr::var("is_ldcr"), 0.0, 90.0))
+    );
+    assert!(profile
+        .assignment(&ContextKind::Way, "costfactor")
+        .is_some());
+    assert!(profile
+        .assignment(&ContextKind::Node, "initialcost")
+        .is_some());
+}
+
+#[test]
+fn comments() {
+    let profile = Profile::parse(TREKKING).unwrap();
+    let global = profile.context(&ContextKind::Global).unwrap();
+    assert_eq!(
+        global.comment.as_deref(),
+        Some(" following code refers to global config")
+    );
+    assert_eq!(
+        global.assignment("allow_steps").unwrap().comment.as_deref(),
+        Some(" %allow_steps% | Set false to disallow steps | boolean")
+    );
+    assert_eq!(global.assignment("uphillcost").unwrap().comment, None);
+
+    // The comments before the profile's first context are kept in the preamble.
+    assert_eq!(
+        profile.preamble.first(),
+        Some(&Statement::Comment(
+            " *** The trekking profile is for slow travel".to_string()
+        ))
+    );
+
+    // Comments within a multi-line expression end up in front of the assignment, in order.
+    let way = profile.context(&ContextKind::Way).unwrap();
+    let costfactor = way
+        .statements
+        .iter()
+        .position(|s| matches!(s, Statement::Assign(a) if a.name == "costfactor"))
+        .unwrap();
+    let comments = way.statements[..costfactor]
+        .iter()
+        .rev()
+        .take_while(|s| matches!(s, Statement::Comment(_)))
+        .count();
+    assert_eq!(comments, 13);
+    assert_eq!(
+        way.statements[costfactor - 5..costfactor - 2],
+        [
+            Statement::Comment(" always treated as perfect (=1)".to_string()),
+            Statement::Comment(" a slight penalty for non-cycleroutes".to_string()),
+            Statement::Comment("".to_string()),
+        ]
+    );
+    assert_eq!(way.assignment("costfactor").unwrap().comment, None);
+}
+
+#[test]
+fn truncated_if() {
+    assert_eq!(
+        error("---context:global\nassign x = if a then 1\n"),
+        ParseError {
+            line: 2,
+            column: 23,
+            message: "expected 'else'".to_string(),
+        }
+    );
+    assert_eq!(
+        error("---context:global\nassign x = if a then 1\nassign y = 2\n"),
+        ParseError {
+            line: 3,
+            column: 1,
+            message: "expected 'else'".to_string(),
+        }
+    );
+    assert_eq!(
+        error("---context:global\nassign x = if a 1 else 2\n"),
+        ParseError {
+            line: 2,
+            column: 17,
+            message: "expected 'then'".to_string(),
+        }
+    );
+}
+
+#[test]
+fn unbalanced_parens() {
+    assert_eq!(
+        error("---context:global\nassign x = ( add 1 2\n"),
+        ParseError {
+            line: 2,
+            column: 21,
+            message: "expected ')'".to_string(),
+        }
+    );
+    assert_eq!(
+        error("---context:global\nassign x = ( add 1 2\nassign y = 2\n"),
+        ParseError {
+            line: 3,
+            column: 1,
+            message: "expected ')'".to_string(),
+        }
+    );
+    assert_eq!(
+        error("---context:global\nassign x = add 1 2 )\n"),
+        ParseError {
+            line: 2,
+            column: 20,
+            message: "expected 'assign'".to_string(),
+        }
+    );
+}
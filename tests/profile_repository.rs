@@ -0,0 +1,60 @@
+//! Fetching profiles from collections.
+
+mod common;
+
+use brouter_client::profile::repository::{Collection, Error, ProfileRepository};
+use common::scratch_dir;
+
+#[test]
+fn names_outside_the_cache_are_rejected() {
+    let dir = scratch_dir("repository-names");
+    let repository = ProfileRepository::new(&dir.join("cache"));
+    let collection = Collection::brouter();
+
+    for (version, name) in [
+        (None, "../../.bashrc"),
+        (None, "sub/trekking"),
+        (None, ".hidden"),
+        (Some("../v1.7.8"), "trekking"),
+        (Some(".."), "trekking"),
+    ] {
+        assert!(
+            matches!(
+                repository.fetch(&collection, version, name),
+                Err(Error::InvalidName(_))
+            ),
+            "{:?} {}",
+            version,
+            name
+        );
+        assert!(matches!(
+            repository.install(&collection, version, name, &dir),
+            Err(Error::InvalidName(_))
+        ));
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn cached_profiles() {
+    let dir = scratch_dir("repository-cache");
+    let cache = dir.join("cache");
+    std::fs::create_dir_all(cache.join("brouter/v1.7.8")).unwrap();
+    std::fs::write(
+        cache.join("brouter/v1.7.8/trekking.brf"),
+        "---context:global\n",
+    )
+    .unwrap();
+    let repository = ProfileRepository::new(&cache);
+
+    let path = repository
+        .install(&Collection::brouter(), Some("v1.7.8"), "trekking", &dir)
+        .unwrap();
+
+    assert_eq!(path, dir.join("trekking.brf"));
+    assert_eq!(
+        std::fs::read_to_string(path).unwrap(),
+        "---context:global\n"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
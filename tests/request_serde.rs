@@ -0,0 +1,25 @@
+//! Serializing routing requests.
+#![cfg(feature = "serde")]
+
+use brouter_client::{Point, RouteRequest};
+
+#[test]
+fn fields_added_later_are_optional() {
+    let request = RouteRequest::new(&[Point::new(52.0, 5.0), Point::new(52.1, 5.1)], "trekking");
+    let mut value = serde_json::to_value(&request).unwrap();
+    let fields = value.as_object_mut().unwrap();
+    for name in [
+        "format",
+        "straight_segments",
+        "profile_params",
+        "extra_params",
+        "point_names",
+        "deadline",
+    ] {
+        assert!(fields.remove(name).is_some(), "{}", name);
+    }
+
+    let read: RouteRequest = serde_json::from_value(value).unwrap();
+
+    assert_eq!(read, request);
+}
@@ -0,0 +1,152 @@
+use brouter_client::response::classify;
+use brouter_client::{Error, ResponseHandling};
+use reqwest::StatusCode;
+
+const GPX: &str = "\
+<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<!-- track-length = 1203 filtered ascend = 3 plain-ascend = 0 cost=1523 energy=.0kwh time=4m 1s -->
+<gpx
+ xmlns=\"http://www.topografix.com/GPX/1/1\"
+ creator=\"BRouter-1.7.7\" version=\"1.1\">
+ <trk>
+  <name>brouter_trekking_0</name>
+  <trkseg>
+   <trkpt lon=\"5.123450\" lat=\"52.012340\"><ele>1.25</ele></trkpt>
+   <trkpt lon=\"5.134560\" lat=\"52.014560\"><ele>2.00</ele></trkpt>
+  </trkseg>
+ </trk>
+</gpx>
+";
+
+const GEOJSON: &str = "\
+{
+  \"type\": \"FeatureCollection\",
+  \"features\": [
+    {
+      \"type\": \"Feature\",
+      \"properties\": {
+        \"creator\": \"BRouter-1.7.7\",
+        \"name\": \"brouter_trekking_0\",
+        \"track-length\": \"1203\"
+      },
+      \"geometry\": {
+        \"type\": \"LineString\",
+        \"coordinates\": [[5.123450, 52.012340, 1.25], [5.134560, 52.014560, 2.0]]
+      }
+    }
+  ]
+}
+";
+
+const CSV: &str = "\
+Longitude\tLatitude\tElevation\tDistance\tCostPerKm\tElevCost\tTurnCost\tNodeCost\tInitialCost\tWayTags\tNodeTags\tTime\tEnergy
+5123450\t52012340\t1\t1203\t1100\t0\t0\t0\t0\thighway=cycleway surface=asphalt\t\t241\t0
+";
+
+const PROXY_ERROR: &str = "\
+<html>
+<head><title>502 Bad Gateway</title></head>
+<body>
+<center><h1>502 Bad Gateway</h1></center>
+<hr><center>nginx/1.18.0</center>
+</body>
+</html>
+";
+
+fn lenient(status: u16, content_type: Option<&str>, body: &str) -> Result<(), Error> {
+    classify(
+        StatusCode::from_u16(status).unwrap(),
+        content_type,
+        body.as_bytes(),
+        ResponseHandling::Lenient,
+    )
+}
+
+#[test]
+fn routes_with_success_status() {
+    assert!(lenient(200, Some("application/gpx+xml"), GPX).is_ok());
+    assert!(lenient(200, Some("application/vnd.geo+json"), GEOJSON).is_ok());
+    assert!(lenient(200, Some("text/tab-separated-values"), CSV).is_ok());
+    assert!(lenient(200, None, GPX).is_ok());
+}
+
+#[test]
+fn error_text_with_success_status() {
+    assert!(matches!(
+        lenient(200, Some("text/plain"), "datafile E5_N50.rd5 not found\n"),
+        Err(Error::MissingDataFile(f)) if f == "E5_N50.rd5"
+    ));
+    assert!(matches!(
+        lenient(
+            200,
+            Some("application/gpx+xml"),
+            "no track found at pass=1\n"
+        ),
+        Err(Error::NoRouteFound(1))
+    ));
+    assert!(matches!(
+        lenient(
+            200,
+            None,
+            "from-position not mapped in existing datafile\n"
+        ),
+        Err(Error::PositionNotMapped(p)) if p == "from"
+    ));
+}
+
+#[test]
+fn error_text_with_error_status() {
+    assert!(matches!(
+        lenient(
+            500,
+            Some("text/plain"),
+            "via1-position not mapped in existing datafile\n"
+        ),
+        Err(Error::PositionNotMapped(p)) if p == "via1"
+    ));
+    assert!(matches!(
+        lenient(400, Some("text/plain"), "pass2 timeout after 60 seconds\n"),
+        Err(Error::PassTimeout {
+            pass: 2,
+            timeout: 60
+        })
+    ));
+}
+
+#[test]
+fn routes_with_error_status() {
+    assert!(lenient(500, Some("application/gpx+xml"), GPX).is_ok());
+    assert!(lenient(400, Some("application/vnd.geo+json"), GEOJSON).is_ok());
+
+    let strict = classify(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some("application/gpx+xml"),
+        GPX.as_bytes(),
+        ResponseHandling::Strict,
+    );
+    assert!(matches!(
+        strict,
+        Err(Error::HttpStatus { status, .. }) if status == StatusCode::INTERNAL_SERVER_ERROR
+    ));
+}
+
+#[test]
+fn unrecognized_errors() {
+    assert!(matches!(
+        lenient(502, Some("text/html"), PROXY_ERROR),
+        Err(Error::HttpStatus { status, body }) if status == StatusCode::BAD_GATEWAY && body.contains("nginx")
+    ));
+    assert!(matches!(
+        lenient(500, Some("text/plain"), "java.lang.NullPointerException\n"),
+        Err(Error::HttpStatus { body, .. }) if body == "java.lang.NullPointerException"
+    ));
+    // A captive portal or proxy login page instead of a route.
+    assert!(matches!(
+        lenient(200, None, PROXY_ERROR),
+        Err(Error::Other(m)) if m.starts_with("unexpected HTML response")
+    ));
+    assert!(matches!(
+        lenient(200, Some("application/gpx+xml"), " \n"),
+        Err(Error::Other(m)) if m.starts_with("empty response")
+    ));
+}
@@ -0,0 +1,137 @@
+//! Parses the responses in `tests/data/responses` and compares the result with the `.golden`
+//! file next to each of them.
+//!
+//! Run with `BLESS=1` to write the golden files after an intended change.
+
+use brouter_client::response::{parse_response, ResponseFormat};
+use brouter_client::Route;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+fn responses() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/responses");
+    let mut paths = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e != "golden"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+fn format_of(path: &Path) -> Option<ResponseFormat> {
+    match path.extension()?.to_str()? {
+        // Error messages are recognized whatever the requested format.
+        "gpx" | "txt" => Some(ResponseFormat::Gpx),
+        #[cfg(feature = "geojson")]
+        "geojson" => Some(ResponseFormat::GeoJson),
+        "csv" => Some(ResponseFormat::Csv),
+        _ => None,
+    }
+}
+
+fn describe(route: &Route) -> String {
+    let summary = route.summary();
+    let mut out = String::new();
+    writeln!(out, "version: {:?}", route.brouter_version()).unwrap();
+    writeln!(out, "points: {}", summary.points).unwrap();
+    writeln!(out, "distance_m: {:.1}", summary.distance_m).unwrap();
+    writeln!(out, "ascent_m: {:.1}", summary.ascent_m).unwrap();
+    writeln!(out, "descent_m: {:.1}", summary.descent_m).unwrap();
+    writeln!(out, "totals: {:?}", route.totals()).unwrap();
+    for instruction in route.turn_instructions() {
+        writeln!(
+            out,
+            "turn: {:?} at {:.1} m",
+            instruction.command, instruction.distance_m
+        )
+        .unwrap();
+    }
+    for message in route.messages() {
+        writeln!(
+            out,
+            "message: {:.6},{:.6} {} m, {} per km, time {:?}, highway {:?}",
+            message.point.lat(),
+            message.point.lon(),
+            message.distance_m,
+            message.cost_per_km,
+            message.time_s,
+            message.way_tag("highway"),
+        )
+        .unwrap();
+    }
+    out
+}
+
+#[test]
+fn golden() {
+    let bless = std::env::var_os("BLESS").is_some();
+    let mut failures = vec![];
+    for path in responses() {
+        let Some(format) = format_of(&path) else {
+            continue;
+        };
+        let body = std::fs::read(&path).unwrap();
+        let actual = match parse_response(&body, format) {
+            Ok(route) => describe(&route),
+            Err(e) => format!("error: {:?}\n", e),
+        };
+        let golden = path.with_extension(format!(
+            "{}.golden",
+            path.extension().unwrap().to_str().unwrap()
+        ));
+        if bless {
+            std::fs::write(&golden, &actual).unwrap();
+            continue;
+        }
+        let expected = std::fs::read_to_string(&golden).unwrap_or_default();
+        if actual != expected {
+            failures.push(format!(
+                "{}:\n--- expected\n{}--- actual\n{}",
+                path.display(),
+                expected,
+                actual
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn same_route_in_every_format() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/responses");
+    let parse = |name: &str, format| {
+        parse_response(&std::fs::read(dir.join(name)).unwrap(), format).unwrap()
+    };
+    let locus = parse("brouter-1.7.7-trekking-locus.gpx", ResponseFormat::Gpx);
+    let osmand = parse("brouter-1.6.3-trekking-osmand.gpx", ResponseFormat::Gpx);
+    assert_eq!(locus.summary(), osmand.summary());
+    assert_eq!(
+        locus
+            .turn_instructions()
+            .iter()
+            .map(|i| i.command)
+            .collect::<Vec<_>>(),
+        osmand
+            .turn_instructions()
+            .iter()
+            .map(|i| i.command)
+            .collect::<Vec<_>>()
+    );
+
+    #[cfg(feature = "geojson")]
+    {
+        let geojson = parse("brouter-1.7.7-trekking.geojson", ResponseFormat::GeoJson);
+        let csv = parse("brouter-1.7.7-trekking.csv", ResponseFormat::Csv);
+        assert_eq!(geojson.summary(), locus.summary());
+        assert_eq!(geojson.messages(), csv.messages());
+
+        let length = |route: &Route| route.totals().and_then(|s| s.track_length_m);
+        assert_eq!(length(&geojson), length(&locus));
+        assert_eq!(length(&csv), length(&locus));
+        assert_eq!(
+            geojson.totals().and_then(|s| s.total_time_s),
+            locus.totals().and_then(|s| s.total_time_s)
+        );
+    }
+}
@@ -0,0 +1,21 @@
+//! Converting routes to and from geo-types geometries.
+
+use brouter_client::Route;
+use geo_types::{line_string, MultiLineString};
+
+#[test]
+fn multilinestring_round_trip() {
+    let lines = MultiLineString::new(vec![
+        line_string![(x: 5.0, y: 52.0), (x: 5.1, y: 52.1)],
+        line_string![(x: 5.1, y: 52.1), (x: 5.2, y: 52.0), (x: 5.3, y: 52.2)],
+    ]);
+    let route = Route::from(lines.clone());
+    assert_eq!(route.multilinestring(), lines);
+    assert_eq!(MultiLineString::from(&route), lines);
+    assert_eq!(MultiLineString::from(route.clone()), lines);
+
+    // A single line string joins the segments.
+    let line = route.linestring();
+    assert_eq!(line.0.len(), 5);
+    assert_eq!(Route::from(line).multilinestring().0.len(), 1);
+}
@@ -0,0 +1,65 @@
+//! Resampling routes at fixed intervals.
+
+mod common;
+
+use brouter_client::Route;
+use common::route_along;
+use geo::{Distance, Haversine};
+
+/// A route due north along `points` of (latitude, elevation).
+fn route(points: &[(f64, Option<f64>)]) -> Route {
+    route_along(points.iter().copied())
+}
+
+#[test]
+fn spacing() {
+    let route = route(&[(52.0, None), (52.004, None), (52.01, None)]);
+    let length: f64 = Haversine.distance(
+        geo_types::Point::new(5.0, 52.0),
+        geo_types::Point::new(5.0, 52.01),
+    );
+    let samples = route.resample(100.0);
+    // Every 100 meters along the 1112 m route, and the end.
+    assert_eq!(samples.len(), (length / 100.0).floor() as usize + 2);
+    for pair in samples[..samples.len() - 1].windows(2) {
+        let distance: f64 = Haversine.distance(pair[0].point(), pair[1].point());
+        assert!((distance - 100.0).abs() < 0.01, "{}", distance);
+    }
+    assert_eq!(samples[0].point(), geo_types::Point::new(5.0, 52.0));
+    assert_eq!(
+        samples.last().unwrap().point(),
+        geo_types::Point::new(5.0, 52.01)
+    );
+}
+
+#[test]
+fn interpolated_elevation() {
+    let route = route(&[(52.0, Some(10.0)), (52.01, Some(110.0)), (52.02, None)]);
+    let length: f64 = Haversine.distance(
+        geo_types::Point::new(5.0, 52.0),
+        geo_types::Point::new(5.0, 52.01),
+    );
+    let samples = route.resample(250.0);
+    for (i, sample) in samples.iter().enumerate().take(5) {
+        let expected = 10.0 + 100.0 * (i as f64 * 250.0) / length;
+        let elevation = sample.elevation.unwrap();
+        assert!(
+            (elevation - expected).abs() < 1e-6,
+            "{} {}",
+            elevation,
+            expected
+        );
+    }
+    // Past the last known elevation, the known one is used.
+    assert_eq!(samples.last().unwrap().elevation, None);
+    assert_eq!(samples[samples.len() - 2].elevation, Some(110.0));
+}
+
+#[test]
+fn invalid_interval() {
+    let route = route(&[(52.0, None), (52.01, None)]);
+    for interval in [0.0, -100.0, f64::NAN, f64::INFINITY] {
+        assert!(route.resample(interval).is_empty(), "{}", interval);
+    }
+    assert!(Route::from(gpx::Gpx::default()).resample(100.0).is_empty());
+}
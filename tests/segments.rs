@@ -0,0 +1,182 @@
+//! Managing the segment files of a local server.
+
+mod common;
+
+use brouter_client::segments::{DownloadOutcome, SegmentManager};
+use brouter_client::server::ProvisionEvent;
+use brouter_client::{BrouterBuilder, Point, RouteRequest};
+use common::{scratch_dir, serve_files};
+
+fn files() -> Vec<(String, Vec<u8>)> {
+    ["E5_N50.rd5", "E10_N50.rd5", "E5_N45.rd5"]
+        .into_iter()
+        .map(|name| {
+            let contents = format!("segment {}", name.trim_end_matches(".rd5"));
+            (name.to_string(), contents.into_bytes())
+        })
+        .collect()
+}
+
+#[test]
+fn download_all() {
+    let dir = scratch_dir("download-all");
+    std::fs::write(dir.join("E10_N50.rd5"), b"present").unwrap();
+    let manager = SegmentManager::new(&dir)
+        .with_source(&serve_files(files()))
+        .parallelism(3);
+    let names = ["E5_N50.rd5", "E10_N50.rd5", "W5_S50.rd5", "E5_N45.rd5"]
+        .map(String::from)
+        .to_vec();
+
+    let outcomes = manager.download_all(&names, |_| {});
+
+    // Outcomes are in the order of the names, whichever download finished first.
+    assert_eq!(
+        outcomes.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+        names.iter().collect::<Vec<_>>()
+    );
+    assert!(matches!(
+        outcomes[0].1,
+        DownloadOutcome::Downloaded { size: 14 }
+    ));
+    assert!(matches!(outcomes[1].1, DownloadOutcome::Present));
+    assert!(matches!(outcomes[2].1, DownloadOutcome::Unavailable));
+    assert!(matches!(
+        outcomes[3].1,
+        DownloadOutcome::Downloaded { size: 14 }
+    ));
+    assert_eq!(
+        std::fs::read(dir.join("E5_N50.rd5")).unwrap(),
+        b"segment E5_N50"
+    );
+    assert_eq!(std::fs::read(dir.join("E10_N50.rd5")).unwrap(), b"present");
+    assert!(!dir.join("W5_S50.rd5").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn waits_for_other_downloads() {
+    let dir = scratch_dir("waits-for-other-downloads");
+    // Hold the lock another process downloading the file would have.
+    let lock = std::fs::File::create(dir.join(".E5_N50.rd5.lock")).unwrap();
+    lock.lock().unwrap();
+    let manager = SegmentManager::new(&dir).with_source(&serve_files(files()));
+    let download = std::thread::spawn(move || manager.download("E5_N50.rd5").unwrap());
+
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert!(!download.is_finished());
+    std::fs::write(dir.join("E5_N50.rd5"), b"downloaded elsewhere").unwrap();
+    drop(lock);
+
+    assert!(download.join().unwrap());
+    assert_eq!(
+        std::fs::read(dir.join("E5_N50.rd5")).unwrap(),
+        b"downloaded elsewhere"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn events() {
+    let dir = scratch_dir("segment-events");
+    std::fs::write(dir.join("E10_N50.rd5"), b"present").unwrap();
+    let source = serve_files(files());
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let manager = SegmentManager::new(&dir)
+        .with_source(&source)
+        .parallelism(1)
+        .events(sender);
+    let names = ["E5_N50.rd5", "E10_N50.rd5", "W5_S50.rd5"]
+        .map(String::from)
+        .to_vec();
+
+    manager.download_all(&names, |_| {});
+    drop(manager);
+
+    let url = format!("{}/E5_N50.rd5", source);
+    assert_eq!(
+        receiver.iter().collect::<Vec<_>>(),
+        vec![
+            ProvisionEvent::DownloadStarted {
+                url: url.clone(),
+                size: Some(14)
+            },
+            ProvisionEvent::BytesTransferred {
+                url: url.clone(),
+                transferred: 14,
+                size: Some(14)
+            },
+            ProvisionEvent::DownloadFinished { url, size: 14 },
+            ProvisionEvent::SegmentSkipped {
+                name: "E10_N50.rd5".to_string(),
+                present: true
+            },
+            ProvisionEvent::SegmentSkipped {
+                name: "W5_S50.rd5".to_string(),
+                present: false
+            },
+            ProvisionEvent::Done,
+        ]
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn bandwidth_limit() {
+    let dir = scratch_dir("bandwidth-limit");
+    let source = serve_files(vec![
+        ("E5_N50.rd5".to_string(), vec![0; 1000]),
+        ("E10_N50.rd5".to_string(), vec![0; 1000]),
+    ]);
+    let manager = SegmentManager::new(&dir)
+        .with_source(&source)
+        .parallelism(2)
+        .bandwidth_limit(4000);
+    let names = ["E5_N50.rd5", "E10_N50.rd5"].map(String::from).to_vec();
+
+    let start = std::time::Instant::now();
+    manager.download_all(&names, |_| {});
+
+    // The limit is shared by the parallel downloads: 2000 bytes take half a second.
+    assert!(
+        start.elapsed() >= std::time::Duration::from_millis(450),
+        "{:?}",
+        start.elapsed()
+    );
+    assert_eq!(std::fs::read(dir.join("E10_N50.rd5")).unwrap().len(), 1000);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn check_coverage() {
+    let dir = scratch_dir("check-coverage");
+    std::fs::write(dir.join("E5_N50.rd5"), b"present").unwrap();
+    let request = RouteRequest::new(&[Point::new(52.0, 5.0), Point::new(52.0, 10.0)], "trekking");
+
+    for brouter in [
+        BrouterBuilder::new("http://localhost:17777")
+            .segments_dir(&dir)
+            .build(),
+        BrouterBuilder::new("http://localhost:17777")
+            .segment_manager(SegmentManager::new(&dir))
+            .build(),
+    ] {
+        let coverage = brouter.check_coverage(&request).unwrap();
+        assert_eq!(coverage.required, request.segment_tiles());
+        assert!(coverage.required.contains(&"E5_N50.rd5".to_string()));
+        assert_eq!(
+            coverage.missing,
+            coverage
+                .required
+                .iter()
+                .filter(|name| *name != "E5_N50.rd5")
+                .cloned()
+                .collect::<Vec<_>>()
+        );
+    }
+    assert!(BrouterBuilder::new("http://localhost:17777")
+        .build()
+        .check_coverage(&request)
+        .is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,40 @@
+//! Errors setting up a local server.
+
+use brouter_client::server::{BrouterServerBuilder, Error};
+
+#[test]
+fn java_not_found() {
+    let dir = std::env::temp_dir().join(format!("brouter-client-java-{}", std::process::id()));
+    let result = BrouterServerBuilder::new("brouter.jar", dir.join("segments4"))
+        .custom_profiles_dir(&dir)
+        .java("/nonexistent/java")
+        .ephemeral_port()
+        .start();
+    match result {
+        Err(Error::JavaNotFound(java)) => assert_eq!(java.to_str(), Some("/nonexistent/java")),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn no_release() {
+    let dir = std::env::temp_dir();
+    assert!(matches!(
+        BrouterServerBuilder::from_release_dir(&dir.join("nonexistent"), "segments4"),
+        Err(Error::NoRelease(_))
+    ));
+}
+
+#[test]
+fn converts_to_crate_error() {
+    let e: brouter_client::Error = Error::NoRelease("releases".into()).into();
+    assert!(matches!(
+        e,
+        brouter_client::Error::Server(Error::NoRelease(_))
+    ));
+    assert_eq!(
+        e.to_string(),
+        "Local server: No brouter release found in releases"
+    );
+}
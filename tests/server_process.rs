@@ -0,0 +1,48 @@
+//! Managing the process of a local server.
+//!
+//! These tests run a stand-in for java that just sleeps, while the test itself listens on the
+//! port the server should bind.
+#![cfg(unix)]
+
+mod common;
+
+use brouter_client::server::BrouterServerBuilder;
+use common::scratch_dir;
+use std::net::TcpListener;
+use std::os::unix::fs::PermissionsExt;
+
+#[test]
+fn version_and_restart() {
+    let dir = scratch_dir("server-process");
+    let java = dir.join("java");
+    std::fs::write(&java, "#!/bin/sh\nexec sleep 60\n").unwrap();
+    std::fs::set_permissions(&java, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let mut server =
+        BrouterServerBuilder::new(dir.join("brouter-1.7.8-all.jar"), dir.join("segments4"))
+            .java(&java)
+            .port(port)
+            .start()
+            .unwrap();
+    assert_eq!(server.version(), Some("1.7.8"));
+    let client = server.client();
+    assert_eq!(client.segments_dir(), Some(dir.join("segments4").as_path()));
+
+    let id = server.id();
+    server.restart().unwrap();
+    assert_ne!(server.id(), id);
+    assert_eq!(server.url(), format!("http://127.0.0.1:{}/", port));
+    assert_eq!(client.base_url(), server.url());
+    server.stop().unwrap();
+
+    let other = BrouterServerBuilder::new(dir.join("brouter.jar"), dir.join("segments4"))
+        .java(&java)
+        .port(port)
+        .start()
+        .unwrap();
+    assert_eq!(other.version(), None);
+    drop(other);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,292 @@
+//! Unpacking brouter releases to run a local server from.
+#![cfg(feature = "download")]
+
+mod common;
+
+use brouter_client::server::{BrouterServerBuilder, Error, ProvisionEvent, ReleaseInstaller};
+use common::{scratch_dir, serve_files};
+use std::path::Path;
+
+fn archive(name: &str) -> Vec<u8> {
+    std::fs::read(
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/data/releases")
+            .join(name),
+    )
+    .unwrap()
+}
+
+#[test]
+fn unpack_release() {
+    let dir = scratch_dir("unpack-release");
+    let install = dir.join("brouter-0.0.1");
+    let builder = BrouterServerBuilder::from_release_archive(
+        &archive("brouter-0.0.1.zip"),
+        &install,
+        "segments4",
+    )
+    .unwrap();
+
+    let release = install.join("brouter-0.0.1");
+    assert_eq!(
+        std::fs::read_to_string(release.join("profiles2/shortest.brf")).unwrap(),
+        "---context:global\nassign costfactor 1\n".repeat(20)
+    );
+    assert_eq!(
+        std::fs::read(release.join("brouter-0.0.1-all.jar")).unwrap(),
+        b"not really a jar\n"
+    );
+    let debug = format!("{:?}", builder);
+    assert!(debug.contains("brouter-0.0.1-all.jar"), "{}", debug);
+
+    // The unpacked release is found again without the archive.
+    BrouterServerBuilder::from_release_dir(&install, "segments4").unwrap();
+    assert!(BrouterServerBuilder::from_release_dir(&dir, "segments4").is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unsafe_paths() {
+    let dir = scratch_dir("unsafe-paths");
+    let install = dir.join("release");
+    assert!(BrouterServerBuilder::from_release_archive(
+        &archive("unsafe-path.zip"),
+        &install,
+        "segments4"
+    )
+    .is_err());
+    assert!(!dir.join("escaped.txt").exists());
+    // Nothing is left that could be taken for a release.
+    assert!(!install.exists());
+    assert!(!dir.join("release.partial").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn truncated_archive() {
+    let dir = scratch_dir("truncated-archive");
+    let archive = archive("brouter-0.0.1.zip");
+    assert!(
+        BrouterServerBuilder::from_release_archive(&archive[..archive.len() / 2], &dir, "s")
+            .is_err()
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Change the size and checksum the central directory of `archive` records for `name`.
+fn patch_entry(archive: &mut [u8], name: &str, patch: impl Fn(&mut u32, &mut u32)) {
+    let start = (0..archive.len() - 46)
+        .find(|&i| {
+            let name_length = u16::from_le_bytes([archive[i + 28], archive[i + 29]]) as usize;
+            archive[i..i + 4] == [0x50, 0x4b, 0x01, 0x02]
+                && archive.get(i + 46..i + 46 + name_length) == Some(name.as_bytes())
+        })
+        .unwrap();
+    let field =
+        |archive: &[u8], at: usize| u32::from_le_bytes(archive[at..at + 4].try_into().unwrap());
+    let (mut crc, mut size) = (field(archive, start + 16), field(archive, start + 24));
+    patch(&mut crc, &mut size);
+    archive[start + 16..start + 20].copy_from_slice(&crc.to_le_bytes());
+    archive[start + 24..start + 28].copy_from_slice(&size.to_le_bytes());
+}
+
+#[test]
+fn wrong_sizes() {
+    let dir = scratch_dir("wrong-sizes");
+    for size in [0, 1, u32::MAX] {
+        let mut archive = archive("brouter-0.0.1.zip");
+        patch_entry(
+            &mut archive,
+            "brouter-0.0.1/profiles2/shortest.brf",
+            |_, s| *s = size,
+        );
+        let install = dir.join(size.to_string());
+        assert!(BrouterServerBuilder::from_release_archive(&archive, &install, "s").is_err());
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn wrong_checksum() {
+    let dir = scratch_dir("wrong-checksum");
+    let mut archive = archive("brouter-0.0.1.zip");
+    patch_entry(
+        &mut archive,
+        "brouter-0.0.1/profiles2/shortest.brf",
+        |crc, _| *crc ^= 1,
+    );
+    assert!(
+        BrouterServerBuilder::from_release_archive(&archive, dir.join("release"), "s").is_err()
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn missing_jar() {
+    let dir = scratch_dir("missing-jar");
+    std::fs::create_dir_all(dir.join("brouter-0.0.1/profiles2")).unwrap();
+    assert!(matches!(
+        BrouterServerBuilder::from_release_dir(&dir, "segments4"),
+        Err(Error::CorruptRelease { .. })
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn empty_jar() {
+    let dir = scratch_dir("empty-jar");
+    std::fs::write(dir.join("brouter-0.0.1-all.jar"), b"").unwrap();
+    match BrouterServerBuilder::from_release_dir(&dir, "segments4") {
+        Err(Error::CorruptRelease { problem, .. }) => {
+            assert!(
+                problem.contains("brouter-0.0.1-all.jar is empty"),
+                "{}",
+                problem
+            )
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn install_release() {
+    let dir = scratch_dir("install-release");
+    let source = serve_files(vec![(
+        "v0.0.1/brouter-0.0.1.zip".to_string(),
+        archive("brouter-0.0.1.zip"),
+    )]);
+    let installer = ReleaseInstaller::new(&dir).with_source(&source);
+
+    let release = installer.install("0.0.1").unwrap();
+    assert_eq!(release, dir.join("brouter-0.0.1"));
+    BrouterServerBuilder::from_release_dir(&release, "segments4").unwrap();
+    assert!(matches!(
+        installer.install("0.0.2"),
+        Err(Error::Download(_))
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn repair_release() {
+    let dir = scratch_dir("repair-release");
+    let source = serve_files(vec![(
+        "v0.0.1/brouter-0.0.1.zip".to_string(),
+        archive("brouter-0.0.1.zip"),
+    )]);
+    let installer = ReleaseInstaller::new(&dir).with_source(&source);
+    // As left behind by an unpack that was interrupted.
+    let jar = dir.join("brouter-0.0.1/brouter-0.0.1/brouter-0.0.1-all.jar");
+    std::fs::create_dir_all(jar.parent().unwrap()).unwrap();
+    std::fs::write(&jar, b"").unwrap();
+
+    installer.install("0.0.1").unwrap();
+    assert_eq!(std::fs::read(&jar).unwrap(), b"not really a jar\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn changed_release() {
+    let dir = scratch_dir("changed-release");
+    BrouterServerBuilder::from_release_archive(&archive("brouter-0.0.1.zip"), &dir, "s").unwrap();
+    std::fs::write(
+        dir.join("brouter-0.0.1/profiles2/shortest.brf"),
+        "---context:global\n",
+    )
+    .unwrap();
+
+    match BrouterServerBuilder::from_release_dir(&dir, "s") {
+        Err(Error::CorruptRelease { problem, .. }) => assert_eq!(
+            problem,
+            "brouter-0.0.1/profiles2/shortest.brf differs from the archive"
+        ),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn repair_from_kept_archive() {
+    let dir = scratch_dir("repair-from-kept-archive");
+    let source = serve_files(vec![(
+        "v0.0.1/brouter-0.0.1.zip".to_string(),
+        archive("brouter-0.0.1.zip"),
+    )]);
+    ReleaseInstaller::new(&dir)
+        .with_source(&source)
+        .install("0.0.1")
+        .unwrap();
+    let jar = dir.join("brouter-0.0.1/brouter-0.0.1/brouter-0.0.1-all.jar");
+    std::fs::write(&jar, b"not a jar either\n").unwrap();
+
+    // Nothing listens on the discard port, so this has to make do without downloading.
+    ReleaseInstaller::new(&dir)
+        .with_source("http://127.0.0.1:9")
+        .install("0.0.1")
+        .unwrap();
+    assert_eq!(std::fs::read(&jar).unwrap(), b"not really a jar\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn waits_for_other_installs() {
+    let dir = scratch_dir("waits-for-other-installs");
+    let lock = std::fs::File::create(dir.join(".brouter-0.0.1.lock")).unwrap();
+    lock.lock().unwrap();
+    let installer = ReleaseInstaller::new(&dir).with_source("http://127.0.0.1:9");
+    let install = std::thread::spawn(move || installer.install("0.0.1"));
+
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert!(!install.is_finished());
+    // Another process installs the release in the meantime.
+    let elsewhere = dir.join("elsewhere");
+    BrouterServerBuilder::from_release_archive(&archive("brouter-0.0.1.zip"), &elsewhere, "s")
+        .unwrap();
+    std::fs::rename(&elsewhere, dir.join("brouter-0.0.1")).unwrap();
+    drop(lock);
+
+    assert_eq!(install.join().unwrap().unwrap(), dir.join("brouter-0.0.1"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn install_events() {
+    let dir = scratch_dir("install-events");
+    let archive = archive("brouter-0.0.1.zip");
+    let size = archive.len() as u64;
+    let source = serve_files(vec![("v0.0.1/brouter-0.0.1.zip".to_string(), archive)]);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let installer = ReleaseInstaller::new(&dir)
+        .with_source(&source)
+        .events(sender);
+
+    installer.install("0.0.1").unwrap();
+    installer.install("0.0.1").unwrap();
+    drop(installer);
+
+    let url = format!("{}/v0.0.1/brouter-0.0.1.zip", source);
+    assert_eq!(
+        receiver.iter().collect::<Vec<_>>(),
+        vec![
+            ProvisionEvent::DownloadStarted {
+                url: url.clone(),
+                size: Some(size)
+            },
+            ProvisionEvent::BytesTransferred {
+                url: url.clone(),
+                transferred: size,
+                size: Some(size)
+            },
+            ProvisionEvent::DownloadFinished { url, size },
+            ProvisionEvent::Extracted {
+                dir: dir.join("brouter-0.0.1"),
+                files: 3
+            },
+            ProvisionEvent::Done,
+            // The second time, the release is installed already.
+            ProvisionEvent::Done,
+        ]
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,87 @@
+//! Snapshots of the files of a local server.
+#![cfg(feature = "download")]
+
+mod common;
+
+use brouter_client::server::BrouterServerBuilder;
+use common::scratch_dir;
+use std::path::Path;
+
+/// A server set up in `dir` from the test release, with two segment files and an uploaded
+/// profile.
+fn server(dir: &Path) -> BrouterServerBuilder {
+    let release = std::fs::read(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/releases/brouter-0.0.1.zip"),
+    )
+    .unwrap();
+    std::fs::create_dir_all(dir.join("segments4")).unwrap();
+    std::fs::write(dir.join("segments4/E5_N50.rd5"), vec![1; 100_000]).unwrap();
+    std::fs::write(dir.join("segments4/E10_N50.rd5"), b"E10_N50").unwrap();
+    std::fs::create_dir_all(dir.join("customprofiles")).unwrap();
+    std::fs::write(dir.join("customprofiles/custom_1.brf"), b"custom").unwrap();
+    BrouterServerBuilder::from_release_archive(&release, dir.join("release"), dir.join("segments4"))
+        .unwrap()
+        .custom_profiles_dir(dir.join("customprofiles"))
+}
+
+#[test]
+fn export_and_import() {
+    let dir = scratch_dir("snapshot");
+    let snapshot = dir.join("snapshot.zip");
+    server(&dir.join("original"))
+        .export_snapshot(&snapshot, &["E5_N50.rd5".to_string()])
+        .unwrap();
+
+    let imported = dir.join("imported");
+    let builder = BrouterServerBuilder::import_snapshot(&snapshot, &imported).unwrap();
+
+    assert_eq!(
+        std::fs::read(imported.join("brouter-0.0.1-all.jar")).unwrap(),
+        b"not really a jar\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(imported.join("profiles2/shortest.brf")).unwrap(),
+        "---context:global\nassign costfactor 1\n".repeat(20)
+    );
+    assert_eq!(
+        std::fs::read(imported.join("customprofiles/custom_1.brf")).unwrap(),
+        b"custom"
+    );
+    assert_eq!(
+        std::fs::read(imported.join("segments4/E5_N50.rd5")).unwrap(),
+        vec![1; 100_000]
+    );
+    // Only the segment files asked for are in the snapshot.
+    assert!(!imported.join("segments4/E10_N50.rd5").exists());
+    let debug = format!("{:?}", builder);
+    assert!(debug.contains("segments4"), "{}", debug);
+    assert!(debug.contains("customprofiles"), "{}", debug);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_segment_name() {
+    let dir = scratch_dir("snapshot-invalid-segment");
+    let snapshot = dir.join("snapshot.zip");
+    assert!(server(&dir.join("original"))
+        .export_snapshot(&snapshot, &["../E5_N50.rd5".to_string()])
+        .is_err());
+    assert!(!snapshot.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn truncated_snapshot() {
+    let dir = scratch_dir("snapshot-truncated");
+    let snapshot = dir.join("snapshot.zip");
+    server(&dir.join("original"))
+        .export_snapshot(&snapshot, &["E5_N50.rd5".to_string()])
+        .unwrap();
+    let contents = std::fs::read(&snapshot).unwrap();
+    std::fs::write(&snapshot, &contents[..contents.len() - 100]).unwrap();
+
+    let imported = dir.join("imported");
+    assert!(BrouterServerBuilder::import_snapshot(&snapshot, &imported).is_err());
+    assert!(!imported.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,62 @@
+//! Requests on pooled connections that the server has since closed, e.g. because it was
+//! restarted.
+
+use brouter_client::{Brouter, Point};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+
+const ROUTE: &str = include_str!("data/responses/brouter-1.6.3-trekking-osmand.gpx");
+
+/// Read the head of a request from `reader`, returning whether there was one.
+fn read_request(reader: &mut impl BufRead) -> bool {
+    let mut got = false;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap() == 0 {
+            return false;
+        }
+        if line == "\r\n" {
+            return got;
+        }
+        got = true;
+    }
+}
+
+fn respond(stream: &mut TcpStream) {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+        ROUTE.len(),
+        ROUTE
+    )
+    .unwrap();
+}
+
+#[test]
+fn retries_on_closed_connection() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        // The first connection is kept alive after the first request, and closed without an
+        // answer to the second, as a server that goes away would.
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        assert!(read_request(&mut reader));
+        respond(&mut stream);
+        assert!(read_request(&mut reader));
+        drop(reader);
+        drop(stream);
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        assert!(read_request(&mut reader));
+        respond(&mut stream);
+    });
+    let brouter = Brouter::new(&url).unwrap();
+    let points = [Point::new(52.0, 5.0), Point::new(52.1, 5.1)];
+
+    brouter.route(&points).profile("trekking").send().unwrap();
+    brouter.route(&points).profile("trekking").send().unwrap();
+
+    server.join().unwrap();
+}
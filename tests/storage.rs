@@ -0,0 +1,214 @@
+//! Storing routes and journaling batch runs in SQLite.
+#![cfg(feature = "storage")]
+
+mod common;
+
+use brouter_client::storage::{Journal, JournalEntry, RouteQuery, RouteStore};
+use brouter_client::{Brouter, Point, RouteRequest};
+use common::{route_along, KM};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+const ROUTE: &str = include_str!("data/responses/brouter-1.6.3-trekking-osmand.gpx");
+const NOT_MAPPED: &str = include_str!("data/responses/brouter-1.7.7-to-position-not-mapped.txt");
+
+fn request(profile: &str) -> RouteRequest {
+    RouteRequest::new(&[Point::new(52.0, 5.0), Point::new(52.1, 5.1)], profile)
+}
+
+/// A server that routes requests for the trekking profile and fails all others.
+///
+/// Returns its URL and the number of requests it got.
+fn server() -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let count = Arc::new(AtomicUsize::new(0));
+    let counter = count.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                    break;
+                }
+            }
+            counter.fetch_add(1, Ordering::SeqCst);
+            let (status, body) = if request_line.contains("profile=trekking") {
+                ("200 OK", ROUTE)
+            } else {
+                ("500 Internal Server Error", NOT_MAPPED)
+            };
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+        }
+    });
+    (url, count)
+}
+
+#[test]
+fn save_and_find() {
+    let store = RouteStore::open_in_memory().unwrap();
+    let route = route_along([(52.0, Some(0.0)), (52.0 + KM, Some(10.0))]);
+    let request = request("trekking");
+
+    let id = store
+        .save(&route, Some(&request), Some("to work"), &["commute"])
+        .unwrap();
+    let other = store.save(&route, None, Some("home"), &[]).unwrap();
+
+    let stored = store.get(id).unwrap().unwrap();
+    assert_eq!(stored.name.as_deref(), Some("to work"));
+    assert_eq!(stored.tags, ["commute"]);
+    assert_eq!(stored.request, Some(request));
+    assert_eq!(stored.summary.points, 2);
+    assert_eq!(stored.summary.ascent_m, 10.0);
+    assert!((stored.summary.distance_m - 1000.0).abs() < 0.01);
+    assert_eq!(store.load(id).unwrap().points().count(), 2);
+
+    let ids = |query: &RouteQuery| {
+        store
+            .find(query)
+            .unwrap()
+            .iter()
+            .map(|r| r.id)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(ids(&RouteQuery::new()), [other, id]);
+    assert_eq!(ids(&RouteQuery::new().tag("commute")), [id]);
+    assert_eq!(ids(&RouteQuery::new().name("wor")), [id]);
+    assert_eq!(ids(&RouteQuery::new().limit(1)), [other]);
+
+    store.remove_tag(id, "commute").unwrap();
+    assert!(ids(&RouteQuery::new().tag("commute")).is_empty());
+    store.delete(id).unwrap();
+    assert!(store.get(id).unwrap().is_none());
+    assert!(store.delete(id).is_err());
+}
+
+#[test]
+fn rerun() {
+    let (url, _) = server();
+    let brouter = Brouter::new(&url).unwrap();
+    let store = RouteStore::open_in_memory().unwrap();
+    let route = route_along([(52.0, None), (52.0 + KM, None)]);
+    let with_request = store
+        .save(&route, Some(&request("trekking")), None, &[])
+        .unwrap();
+    let without_request = store.save(&route, None, None, &[]).unwrap();
+
+    let rerouted = store.rerun(with_request, &brouter).unwrap();
+
+    assert_eq!(
+        store.load(with_request).unwrap().points().count(),
+        rerouted.points().count()
+    );
+    assert!(store.rerun(without_request, &brouter).is_err());
+}
+
+#[test]
+fn journal() {
+    let (url, count) = server();
+    let brouter = Brouter::new(&url).unwrap();
+    let journal = Journal::open_in_memory().unwrap();
+    let requests = [request("trekking"), request("unknown")];
+
+    assert!(journal.entry(&requests[0]).unwrap().is_none());
+    assert_eq!(journal.run(&brouter, &requests).unwrap(), 2);
+    assert!(matches!(
+        journal.entry(&requests[0]).unwrap(),
+        Some(JournalEntry::Done(_))
+    ));
+    assert!(matches!(
+        journal.entry(&requests[1]).unwrap(),
+        Some(JournalEntry::Failed(_))
+    ));
+
+    // Finished requests, whether they succeeded or failed, are skipped.
+    assert_eq!(journal.run(&brouter, &requests).unwrap(), 0);
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+
+    // Until the failures are cleared.
+    assert_eq!(journal.clear_failures().unwrap(), 1);
+    assert!(journal.entry(&requests[1]).unwrap().is_none());
+    assert_eq!(journal.run(&brouter, &requests).unwrap(), 1);
+    assert_eq!(count.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn journal_equivalent_requests() {
+    let (url, count) = server();
+    let brouter = Brouter::new(&url).unwrap();
+    let journal = Journal::open_in_memory().unwrap();
+    let request = request("trekking")
+        .profile_param("avoid_steps", "1")
+        .profile_param("consider_elevation", "0");
+    let reordered = self::request("trekking")
+        .profile_param("consider_elevation", "0")
+        .profile_param("avoid_steps", "1");
+
+    assert_eq!(journal.run(&brouter, [&request]).unwrap(), 1);
+
+    assert!(matches!(
+        journal.entry(&reordered).unwrap(),
+        Some(JournalEntry::Done(_))
+    ));
+    assert_eq!(journal.run(&brouter, [&reordered]).unwrap(), 0);
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+}
+
+/// Fails the process while a request is being sent.
+struct Crash;
+
+impl brouter_client::middleware::Middleware for Crash {
+    fn before(&self, _request: &mut reqwest::blocking::Request) {
+        panic!("crash");
+    }
+}
+
+#[test]
+fn journal_pending() {
+    let (url, count) = server();
+    let dir = common::scratch_dir("journal-pending");
+    let path = dir.join("journal.db");
+    let request = request("trekking");
+
+    let crashing = Brouter::builder(&url).middleware(Crash).build();
+    let crashed = {
+        let (path, request) = (path.clone(), request.clone());
+        std::thread::spawn(move || {
+            Journal::open(&path)
+                .unwrap()
+                .run(&crashing, [&request])
+                .unwrap()
+        })
+        .join()
+    };
+    assert!(crashed.is_err());
+
+    let journal = Journal::open(&path).unwrap();
+    assert!(matches!(
+        journal.entry(&request).unwrap(),
+        Some(JournalEntry::Pending)
+    ));
+    // Requests that never finished are routed again.
+    let brouter = Brouter::new(&url).unwrap();
+    assert_eq!(journal.run(&brouter, [&request]).unwrap(), 1);
+    assert!(matches!(
+        journal.entry(&request).unwrap(),
+        Some(JournalEntry::Done(_))
+    ));
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
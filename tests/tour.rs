@@ -0,0 +1,117 @@
+//! Splitting routes into daily stages.
+
+mod common;
+
+use brouter_client::tour::{Stage, TourPlanner};
+use brouter_client::Route;
+use common::{route_along, KM};
+
+/// A route due north of `km` kilometers with a track point every kilometer, climbing
+/// `climb_m` meters per kilometer for the first `climb_km` kilometers.
+fn route(km: usize, climb_km: usize, climb_m: f64) -> Route {
+    route_along((0..=km).map(|i| (i as f64 * KM, Some(i.min(climb_km) as f64 * climb_m))))
+}
+
+/// The kilometers at which the stages end.
+fn ends(stages: &[Stage]) -> Vec<f64> {
+    stages.iter().map(|s| (s.end_m / 1000.0).round()).collect()
+}
+
+#[test]
+fn max_distance() {
+    let route = route(99, 0, 0.0);
+    let stages = TourPlanner::new()
+        .max_daily_distance_m(30_500.0)
+        .plan(&route);
+    assert_eq!(ends(&stages), [30.0, 60.0, 90.0, 99.0]);
+    assert_eq!(
+        stages.iter().map(|s| s.day).collect::<Vec<_>>(),
+        [1, 2, 3, 4]
+    );
+    for pair in stages.windows(2) {
+        assert_eq!(pair[0].end_m, pair[1].start_m);
+        assert_eq!(pair[0].last_point, pair[1].first_point);
+    }
+}
+
+#[test]
+fn days() {
+    // Days of 32.7 km, ending at the first track point past that.
+    let route = route(98, 0, 0.0);
+    assert_eq!(
+        ends(&TourPlanner::new().days(3).plan(&route)),
+        [33.0, 66.0, 98.0]
+    );
+    // A maximum that doesn't fit in the days adds more.
+    assert_eq!(
+        ends(
+            &TourPlanner::new()
+                .days(2)
+                .max_daily_distance_m(40_500.0)
+                .plan(&route)
+        ),
+        [40.0, 80.0, 98.0]
+    );
+    // Zero days leaves the number of days open.
+    assert_eq!(ends(&TourPlanner::new().days(0).plan(&route)), [98.0]);
+}
+
+#[test]
+fn max_ascent() {
+    let route = route(99, 50, 10.0);
+    let stages = TourPlanner::new().max_daily_ascent_m(200.0).plan(&route);
+    assert_eq!(ends(&stages), [20.0, 40.0, 99.0]);
+    assert_eq!(
+        stages
+            .iter()
+            .map(|s| s.ascent_m.round())
+            .collect::<Vec<_>>(),
+        [200.0, 200.0, 100.0]
+    );
+}
+
+#[test]
+fn accommodation() {
+    let mut route = route(99, 0, 0.0).into_gpx();
+    let mut waypoint = |km: f64, east_m: f64, name: &str, type_: &str| {
+        let mut hotel = gpx::Waypoint::new(geo_types::Point::new(
+            5.0 + east_m / 1000.0 * KM / (km * KM).to_radians().cos(),
+            km * KM,
+        ));
+        hotel.name = Some(name.to_string());
+        hotel.type_ = Some(type_.to_string());
+        route.waypoints.push(hotel);
+    };
+    waypoint(27.0, 100.0, "Hotel A", "hotel");
+    // Too far off the route, and not accommodation.
+    waypoint(29.0, 2000.0, "Campground B", "campground");
+    waypoint(58.0, 0.0, "Viewpoint", "viewpoint");
+    // Beyond the maximum daily distance of the second day.
+    waypoint(62.0, 0.0, "Hostel C", "Hostel");
+    let route = Route::from(route);
+
+    let planner = TourPlanner::new()
+        .max_daily_distance_m(30_500.0)
+        .snap_to_accommodation(5_000.0);
+    let stages = planner.plan(&route);
+    assert_eq!(ends(&stages), [27.0, 57.0, 87.0, 99.0]);
+    assert_eq!(stages[0].accommodation.as_deref(), Some("Hotel A"));
+    assert_eq!(stages[1].accommodation, None);
+
+    // Without snapping, the days end at the maximum distance.
+    let unsnapped = TourPlanner::new().max_daily_distance_m(30_500.0);
+    assert_eq!(ends(&unsnapped.plan(&route)), [30.0, 60.0, 90.0, 99.0]);
+
+    assert_eq!(planner.split(&route).len(), 4);
+    let overview = planner.overview(&route);
+    let names = overview
+        .waypoints
+        .iter()
+        .filter_map(|w| w.name.as_deref())
+        .filter(|n| n.starts_with("End of day"))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        ["End of day 1: Hotel A", "End of day 2", "End of day 3"]
+    );
+}
@@ -0,0 +1,50 @@
+use brouter_client::TurnInstructionMode;
+
+#[test]
+fn code_round_trip() {
+    for (code, mode) in TurnInstructionMode::ALL.into_iter().enumerate() {
+        assert_eq!(i32::from(mode), code as i32);
+        assert_eq!(TurnInstructionMode::try_from(code as i32), Ok(mode));
+        assert_eq!(code.to_string().parse(), Ok(mode));
+    }
+    assert!(TurnInstructionMode::try_from(8).is_err());
+    assert!(TurnInstructionMode::try_from(-1).is_err());
+}
+
+#[test]
+fn name_round_trip() {
+    for mode in TurnInstructionMode::ALL {
+        assert_eq!(mode.to_string().parse(), Ok(mode));
+        assert_eq!(mode.name().to_uppercase().parse(), Ok(mode));
+    }
+    assert_eq!("osmand".parse(), Ok(TurnInstructionMode::OsmandStyle));
+    assert_eq!(
+        " locus-old ".parse(),
+        Ok(TurnInstructionMode::LocusOldStyle)
+    );
+    assert!("tomtom".parse::<TurnInstructionMode>().is_err());
+    assert!("".parse::<TurnInstructionMode>().is_err());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_round_trip() {
+    for mode in TurnInstructionMode::ALL {
+        let json = serde_json::to_string(&mode).unwrap();
+        assert_eq!(json, format!("\"{}\"", mode.name()));
+        assert_eq!(
+            serde_json::from_str::<TurnInstructionMode>(&json).unwrap(),
+            mode
+        );
+        assert_eq!(
+            serde_json::from_str::<TurnInstructionMode>(&mode.code().to_string()).unwrap(),
+            mode
+        );
+    }
+    // The form used before modes were serialized by name.
+    assert_eq!(
+        serde_json::from_str::<TurnInstructionMode>("\"GpsiesStyle\"").unwrap(),
+        TurnInstructionMode::GpsiesStyle
+    );
+    assert!(serde_json::from_str::<TurnInstructionMode>("9").is_err());
+}
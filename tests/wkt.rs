@@ -0,0 +1,70 @@
+//! Reading and writing WKT.
+#![cfg(feature = "wkt")]
+
+use brouter_client::{Nogo, Point};
+
+#[test]
+fn point_round_trip() {
+    let point = Point::new(52.3676, 4.9041);
+    assert_eq!(point.to_wkt(), "POINT(4.9041 52.3676)");
+    assert_eq!(Point::from_wkt(&point.to_wkt()).unwrap(), point);
+}
+
+#[test]
+fn srid_prefix() {
+    assert_eq!(
+        Point::from_wkt("SRID=4326;POINT(4.9041 52.3676)").unwrap(),
+        Point::new(52.3676, 4.9041)
+    );
+    assert_eq!(
+        Point::from_wkt("srid=4326; POINT Z (4.9041 52.3676 3)").unwrap(),
+        Point::new(52.3676, 4.9041)
+    );
+}
+
+#[test]
+fn nogo_round_trip() {
+    let line = Nogo::from_wkt("LINESTRING(5 52, 5.1 52.1, 5.2 52)", 0.0, None).unwrap();
+    assert_eq!(Nogo::from_wkt(&line.to_wkt(), 0.0, None).unwrap(), line);
+
+    // Polygons are written with a closed ring.
+    let polygon =
+        Nogo::from_wkt("POLYGON((5 52, 5.1 52, 5.1 52.1, 5 52))", 0.0, Some(2.0)).unwrap();
+    assert_eq!(polygon.to_wkt(), "POLYGON((5 52,5.1 52,5.1 52.1,5 52))");
+    assert_eq!(
+        Nogo::from_wkt(&polygon.to_wkt(), 0.0, Some(2.0)).unwrap(),
+        polygon
+    );
+
+    let point = Nogo::from_wkt("POINT(5 52)", 100.0, None).unwrap();
+    assert_eq!(
+        point,
+        Nogo::Point {
+            point: Point::new(52.0, 5.0),
+            radius: 100.0,
+            weight: None,
+        }
+    );
+}
+
+#[test]
+fn polygon_with_hole() {
+    let err = Nogo::from_wkt(
+        "POLYGON((5 52, 5.3 52, 5.3 52.3, 5 52), (5.1 52.1, 5.2 52.1, 5.2 52.2, 5.1 52.1))",
+        0.0,
+        None,
+    )
+    .unwrap_err();
+    assert_eq!(err.message, "polygons with holes are not supported");
+}
+
+#[test]
+fn empty() {
+    for text in ["POINT EMPTY", "LINESTRING EMPTY", "SRID=4326;POLYGON EMPTY"] {
+        assert_eq!(
+            Nogo::from_wkt(text, 0.0, None).unwrap_err().message,
+            "empty geometry"
+        );
+    }
+    assert!(Point::from_wkt("POINT EMPTY").is_err());
+}